@@ -0,0 +1,393 @@
+use std::{collections::HashSet, fmt};
+
+use crate::maze::{Maze, DEFAULT_HALF_PATH_WIDTH};
+
+const WALL: char = '#';
+const PATH: char = '.';
+const PLAYER: char = 'P';
+const GHOST: char = 'G';
+/// How many units across each cell is, unless the level says otherwise.
+pub const DEFAULT_CELL_SIZE: f32 = 1.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MazeParseError {
+    /// There weren't any path cells at all.
+    NoPaths,
+    /// Every row has to be the same length as the first one.
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownCharacter {
+        row: usize,
+        column: usize,
+        character: char,
+    },
+    /// This path cell can't be reached from the first path cell in the grid.
+    DisconnectedCell { row: usize, column: usize },
+    /// The cells are so small that the paths next to each other would overlap.
+    CellTooSmall { cell_size: f32 },
+}
+
+impl fmt::Display for MazeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MazeParseError::NoPaths => write!(f, "the maze doesn't have any paths"),
+            MazeParseError::RaggedRow {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} is {} cells long, but the rows before it are {} cells long",
+                row, found, expected
+            ),
+            MazeParseError::UnknownCharacter {
+                row,
+                column,
+                character,
+            } => write!(
+                f,
+                "unknown character {:?} at row {}, column {} (expected one of '{}', '{}', '{}' or '{}')",
+                character, row, column, WALL, PATH, PLAYER, GHOST
+            ),
+            MazeParseError::DisconnectedCell { row, column } => write!(
+                f,
+                "the path at row {}, column {} isn't joined to the rest of the maze",
+                row, column
+            ),
+            MazeParseError::CellTooSmall { cell_size } => write!(
+                f,
+                "cells {} units across are too small, since the paths need at least {} units to not overlap",
+                cell_size,
+                DEFAULT_HALF_PATH_WIDTH * 2.0
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MazeParseError {}
+
+/// Everything we can get out of an ASCII maze.
+/// Each cell is `cell_size` units across, with the top left cell at (0, 0), rows going down the y axis and columns going along the x axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsciiLayout {
+    #[allow(clippy::type_complexity)]
+    pub paths: Vec<((f32, f32), (f32, f32))>,
+    /// Where the `P` was, if there was one.
+    pub player_start: Option<(f32, f32)>,
+    /// Where each `G` was, reading the rows from the top.
+    pub ghost_spawns: Vec<(f32, f32)>,
+}
+
+/// Turns a grid of characters into the segments `Maze::new` wants.
+/// `#` is a wall, and `.`, `P` and `G` are all paths (the last two also mark where the player and ghosts start).
+/// Every run of two or more path cells in a row or column becomes a segment, and the maze works out where they cross.
+/// The cells have to be at least as wide as a path, so that the paths next to each other don't overlap.
+pub fn parse_ascii(text: &str, cell_size: f32) -> Result<AsciiLayout, MazeParseError> {
+    if !cell_size.is_finite() || cell_size < DEFAULT_HALF_PATH_WIDTH * 2.0 {
+        return Err(MazeParseError::CellTooSmall { cell_size });
+    }
+    let rows = text
+        .lines()
+        // Leading whitespace is ignored too, so that mazes can be indented in string literals.
+        .map(|line| line.trim().chars().collect::<Vec<_>>())
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+    let width = rows.first().map(|row| row.len()).unwrap_or(0);
+    let mut player_start = None;
+    let mut ghost_spawns = Vec::new();
+    for (row_index, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(MazeParseError::RaggedRow {
+                row: row_index,
+                expected: width,
+                found: row.len(),
+            });
+        }
+        for (column_index, character) in row.iter().enumerate() {
+            let position = (
+                column_index as f32 * cell_size,
+                row_index as f32 * cell_size,
+            );
+            match *character {
+                WALL | PATH => {}
+                PLAYER => player_start = Some(position),
+                GHOST => ghost_spawns.push(position),
+                character => {
+                    return Err(MazeParseError::UnknownCharacter {
+                        row: row_index,
+                        column: column_index,
+                        character,
+                    })
+                }
+            }
+        }
+    }
+    let is_path = |row: usize, column: usize| rows[row][column] != WALL;
+
+    // Flood fill from the first path cell, so that any cell which we can't get to is found (including ones on their own, which wouldn't be part of any segment).
+    let Some(first_cell) = (0..rows.len())
+        .flat_map(|row| (0..width).map(move |column| (row, column)))
+        .find(|(row, column)| is_path(*row, *column))
+    else {
+        return Err(MazeParseError::NoPaths);
+    };
+    let mut reachable = vec![vec![false; width]; rows.len()];
+    reachable[first_cell.0][first_cell.1] = true;
+    let mut to_visit = vec![first_cell];
+    while let Some((row, column)) = to_visit.pop() {
+        let neighbours = [
+            (row.wrapping_sub(1), column),
+            (row + 1, column),
+            (row, column.wrapping_sub(1)),
+            (row, column + 1),
+        ];
+        for (row, column) in neighbours {
+            if row < rows.len() && column < width && is_path(row, column) && !reachable[row][column]
+            {
+                reachable[row][column] = true;
+                to_visit.push((row, column));
+            }
+        }
+    }
+    for (row, reachable_row) in reachable.iter().enumerate() {
+        for (column, reachable) in reachable_row.iter().enumerate() {
+            if is_path(row, column) && !reachable {
+                return Err(MazeParseError::DisconnectedCell { row, column });
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+    // Runs along the rows, then down the columns.
+    for row in 0..rows.len() {
+        add_runs(
+            &mut paths,
+            width,
+            |column| is_path(row, column),
+            |column| (column as f32 * cell_size, row as f32 * cell_size),
+        );
+    }
+    for column in 0..width {
+        add_runs(
+            &mut paths,
+            rows.len(),
+            |row| is_path(row, column),
+            |row| (column as f32 * cell_size, row as f32 * cell_size),
+        );
+    }
+    Ok(AsciiLayout {
+        paths,
+        player_start,
+        ghost_spawns,
+    })
+}
+
+/// Finds every run of at least two path cells in a line of `length` cells, and adds a segment for it.
+#[allow(clippy::type_complexity)]
+fn add_runs(
+    paths: &mut Vec<((f32, f32), (f32, f32))>,
+    length: usize,
+    is_path: impl Fn(usize) -> bool,
+    position: impl Fn(usize) -> (f32, f32),
+) {
+    let mut run_start = None;
+    // Going one past the end means a run which goes right up to the edge still gets finished.
+    for i in 0..=length {
+        match (run_start, i < length && is_path(i)) {
+            (None, true) => run_start = Some(i),
+            (Some(start), false) => {
+                if i - start >= 2 {
+                    paths.push((position(start), position(i - 1)));
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Maze {
+    /// Builds a maze from a grid of characters (see `parse_ascii`), ignoring where things start.
+    /// Levels need the start positions as well (see `Level::from_ascii`), so this is for when only the paths matter, like in the editor.
+    pub fn from_ascii(text: &str, cell_size: f32) -> Result<Maze, MazeParseError> {
+        Ok(Maze::new(&parse_ascii(text, cell_size)?.paths))
+    }
+
+    /// Draws the maze as a grid of characters, which is `from_ascii` the other way around (with the same `cell_size`).
+    /// Every cell along each path is a `.` (rounded to the nearest cell if it isn't on one exactly), and everything else is a `#`.
+    /// The grid is only as big as it needs to be for the paths, so walls around the outside of an ASCII maze don't come back, and neither do tunnels (which go off the sides).
+    pub fn to_ascii(&self, cell_size: f32) -> String {
+        let mut cells = HashSet::new();
+        for (a, b) in self.paths() {
+            let (a, b) = (
+                (a.0 / cell_size, a.1 / cell_size),
+                (b.0 / cell_size, b.1 / cell_size),
+            );
+            let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).round().max(1.0) as usize;
+            for step in 0..=steps {
+                let along = step as f32 / steps as f32;
+                cells.insert((
+                    (a.0 + (b.0 - a.0) * along).round() as i64,
+                    (a.1 + (b.1 - a.1) * along).round() as i64,
+                ));
+            }
+        }
+        let (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) = (
+            cells.iter().map(|cell| cell.0).min(),
+            cells.iter().map(|cell| cell.0).max(),
+            cells.iter().map(|cell| cell.1).min(),
+            cells.iter().map(|cell| cell.1).max(),
+        ) else {
+            return String::new();
+        };
+        (min_y..=max_y)
+            .map(|y| {
+                let row = (min_x..=max_x)
+                    .map(|x| if cells.contains(&(x, y)) { PATH } else { WALL })
+                    .collect::<String>();
+                row + "\n"
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_small_maze() {
+        let text = "
+            .....
+            .#.#.
+            ..P..
+            .#.#.
+            G...G
+        ";
+        let layout = parse_ascii(text, 1.0).unwrap();
+        assert_eq!(layout.player_start, Some((2.0, 2.0)));
+        assert_eq!(layout.ghost_spawns, vec![(0.0, 4.0), (4.0, 4.0)]);
+        assert_eq!(
+            layout.paths,
+            vec![
+                ((0.0, 0.0), (4.0, 0.0)),
+                ((0.0, 2.0), (4.0, 2.0)),
+                ((0.0, 4.0), (4.0, 4.0)),
+                ((0.0, 0.0), (0.0, 4.0)),
+                ((2.0, 0.0), (2.0, 4.0)),
+                ((4.0, 0.0), (4.0, 4.0)),
+            ]
+        );
+        let maze = Maze::from_ascii(text, 1.0).unwrap();
+        assert_eq!(maze, Maze::new(&layout.paths));
+        // Building it again from its own edges should give the same paths (although the intersections can end up in a different order).
+        let sorted_paths = |maze: &Maze| {
+            let mut paths = maze.paths();
+            paths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            paths
+        };
+        assert_eq!(sorted_paths(&Maze::new(&maze.paths())), sorted_paths(&maze));
+        assert_eq!(maze.paths().len(), 12);
+        // Every corner and crossing of the grid is an intersection.
+        assert_eq!(
+            maze.intersections()
+                .iter()
+                .filter(|intersection| intersection.has_paths())
+                .count(),
+            9
+        );
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let text = "\
+.....#....
+.#.#.#.##.
+..........
+##.####.#.
+...#....#.
+";
+        let maze = Maze::from_ascii(text, 1.0).unwrap();
+        assert_eq!(maze.to_ascii(1.0), text);
+        // Drawing it again from what it drew gives the same thing.
+        assert_eq!(
+            Maze::from_ascii(&maze.to_ascii(1.0), 1.0)
+                .unwrap()
+                .to_ascii(1.0),
+            text
+        );
+        // The start positions aren't part of the maze, and the grid shrinks to fit the paths.
+        assert_eq!(
+            Maze::from_ascii("#####\n#P.G#\n#####", 1.0)
+                .unwrap()
+                .to_ascii(1.0),
+            "...\n"
+        );
+        assert_eq!(Maze::new(&[]).to_ascii(1.0), "");
+    }
+
+    #[test]
+    fn bigger_cells() {
+        let text = "
+            ...
+            .#.
+            ...
+        ";
+        let maze = Maze::from_ascii(text, 4.0).unwrap();
+        let mut corners = maze
+            .intersections()
+            .iter()
+            .filter(|intersection| intersection.has_paths())
+            .map(|intersection| intersection.coordinates)
+            .collect::<Vec<_>>();
+        corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            corners,
+            vec![(0.0, 0.0), (0.0, 8.0), (8.0, 0.0), (8.0, 8.0)]
+        );
+        // It is the same maze as with the normal cells, just bigger.
+        let unit = Maze::from_ascii(text, 1.0).unwrap();
+        assert_eq!(maze.paths().len(), unit.paths().len());
+        assert_eq!(maze.route_length(&[0, 1]), Some(8.0));
+        assert_eq!(maze.to_ascii(4.0), "...\n.#.\n...\n");
+
+        // Cells narrower than a path would have the paths on top of each other.
+        assert_eq!(
+            Maze::from_ascii(text, 0.5).unwrap_err(),
+            MazeParseError::CellTooSmall { cell_size: 0.5 }
+        );
+        assert!(Maze::from_ascii(text, f32::NAN).is_err());
+        assert!(Maze::from_ascii(text, DEFAULT_HALF_PATH_WIDTH * 2.0).is_ok());
+    }
+
+    #[test]
+    fn bad_mazes_are_rejected() {
+        assert_eq!(
+            parse_ascii("...\n..\n...", 1.0).unwrap_err(),
+            MazeParseError::RaggedRow {
+                row: 1,
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            parse_ascii("..x", 1.0).unwrap_err(),
+            MazeParseError::UnknownCharacter {
+                row: 0,
+                column: 2,
+                character: 'x'
+            }
+        );
+        assert_eq!(
+            parse_ascii("...#.\n###.#", 1.0).unwrap_err(),
+            MazeParseError::DisconnectedCell { row: 0, column: 4 }
+        );
+        assert_eq!(
+            parse_ascii("###", 1.0).unwrap_err(),
+            MazeParseError::NoPaths
+        );
+    }
+}
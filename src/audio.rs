@@ -0,0 +1,188 @@
+use bevy::{
+    asset::LoadState,
+    audio::{AudioBundle, AudioSink, AudioSinkPlayback, AudioSource, PlaybackSettings},
+    prelude::{
+        AssetServer, Commands, Component, DespawnRecursiveExt, Entity, EventReader, Handle, Local,
+        Query, Res, Resource, With,
+    },
+};
+
+use crate::{
+    ghost::{GhostEaten, PlayerCaught},
+    pause::Paused,
+    pellet::{Pellet, PelletEaten, PowerPelletEaten},
+};
+
+/// How much faster (and so higher) the siren is once every pellet has been eaten, compared to the start.
+const SIREN_PITCH_RISE: f32 = 0.5;
+
+/// Where each of the sounds is loaded from (relative to the assets folder).
+/// Any of them can be left out, in which case that sound just isn't played.
+/// The game doesn't come with any sounds of its own, so they are only heard once they have been put in the assets folder.
+/// Until then the files can't be found, and the game carries on without them (see `playable` and `despawn_failed_sounds`).
+#[derive(Clone, Debug, Resource)]
+pub struct SoundPaths {
+    pub munch: Option<String>,
+    pub power_pellet: Option<String>,
+    pub ghost_eaten: Option<String>,
+    pub death: Option<String>,
+    /// This one plays over and over the whole time the game is being played.
+    pub siren: Option<String>,
+}
+
+impl Default for SoundPaths {
+    fn default() -> Self {
+        Self {
+            munch: Some("sounds/munch.ogg".to_string()),
+            power_pellet: Some("sounds/power_pellet.ogg".to_string()),
+            ghost_eaten: Some("sounds/ghost_eaten.ogg".to_string()),
+            death: Some("sounds/death.ogg".to_string()),
+            siren: Some("sounds/siren.ogg".to_string()),
+        }
+    }
+}
+
+/// The sounds from `SoundPaths`, once they have been asked to load.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct Sounds {
+    munch: Option<Handle<AudioSource>>,
+    power_pellet: Option<Handle<AudioSource>>,
+    ghost_eaten: Option<Handle<AudioSource>>,
+    death: Option<Handle<AudioSource>>,
+    siren: Option<Handle<AudioSource>>,
+}
+
+#[derive(Component)]
+pub struct Siren;
+
+pub fn load_sounds(mut commands: Commands, asset_server: Res<AssetServer>, paths: Res<SoundPaths>) {
+    let load = |path: &Option<String>| path.as_ref().map(|path| asset_server.load(path.as_str()));
+    commands.insert_resource(Sounds {
+        munch: load(&paths.munch),
+        power_pellet: load(&paths.power_pellet),
+        ghost_eaten: load(&paths.ghost_eaten),
+        death: load(&paths.death),
+        siren: load(&paths.siren),
+    });
+}
+
+/// The sound, unless there isn't one or it couldn't be loaded (like when the file isn't there).
+/// Sounds which haven't finished loading yet are still given back, and start playing as soon as they have.
+fn playable(
+    sound: &Option<Handle<AudioSource>>,
+    asset_server: &AssetServer,
+) -> Option<Handle<AudioSource>> {
+    sound
+        .as_ref()
+        .filter(|sound| asset_server.get_load_state(*sound) != LoadState::Failed)
+        .cloned()
+}
+
+fn play_once(
+    commands: &mut Commands,
+    sound: &Option<Handle<AudioSource>>,
+    asset_server: &AssetServer,
+) {
+    if let Some(source) = playable(sound, asset_server) {
+        commands.spawn(AudioBundle {
+            source,
+            settings: PlaybackSettings::DESPAWN,
+        });
+    }
+}
+
+pub fn play_sound_effects(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    sounds: Res<Sounds>,
+    mut pellets: EventReader<PelletEaten>,
+    mut power_pellets: EventReader<PowerPelletEaten>,
+    mut ghosts: EventReader<GhostEaten>,
+    mut caught: EventReader<PlayerCaught>,
+) {
+    // Eating lots of things at once (which happens a lot with pellets) still only plays each sound once, or it would just be noise.
+    for (happened, sound) in [
+        (pellets.iter().count() > 0, &sounds.munch),
+        (power_pellets.iter().count() > 0, &sounds.power_pellet),
+        (ghosts.iter().count() > 0, &sounds.ghost_eaten),
+        (caught.iter().count() > 0, &sounds.death),
+    ] {
+        if happened {
+            play_once(&mut commands, sound, &asset_server);
+        }
+    }
+}
+
+/// Sounds which were started while they were still loading wait for the loading to finish before they play.
+/// If it never does (like when the file isn't there), nothing else would ever get rid of them.
+pub fn despawn_failed_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    sounds: Query<(Entity, &Handle<AudioSource>)>,
+) {
+    for (entity, source) in sounds.iter() {
+        if asset_server.get_load_state(source) == LoadState::Failed {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// How fast the siren plays, which goes up (making it higher) as the pellets run out.
+fn siren_speed(pellets_left: usize, total_pellets: usize) -> f32 {
+    if total_pellets == 0 {
+        return 1.0;
+    }
+    let eaten = 1.0 - pellets_left as f32 / total_pellets as f32;
+    1.0 + eaten.clamp(0.0, 1.0) * SIREN_PITCH_RISE
+}
+
+pub fn start_siren(mut commands: Commands, asset_server: Res<AssetServer>, sounds: Res<Sounds>) {
+    if let Some(source) = playable(&sounds.siren, &asset_server) {
+        commands.spawn((
+            AudioBundle {
+                source,
+                settings: PlaybackSettings::LOOP,
+            },
+            Siren,
+        ));
+    }
+}
+
+pub fn stop_siren(mut commands: Commands, sirens: Query<Entity, With<Siren>>) {
+    for siren in sirens.iter() {
+        commands.entity(siren).despawn_recursive();
+    }
+}
+
+pub fn update_siren(
+    pellets: Query<(), With<Pellet>>,
+    sirens: Query<&AudioSink, With<Siren>>,
+    paused: Res<Paused>,
+    // The most pellets there have been, which is how many there were at the start of the level.
+    mut total_pellets: Local<usize>,
+) {
+    let pellets_left = pellets.iter().count();
+    *total_pellets = (*total_pellets).max(pellets_left);
+    for sink in sirens.iter() {
+        sink.set_speed(siren_speed(pellets_left, *total_pellets));
+        if paused.0 {
+            sink.pause();
+        } else {
+            sink.play();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn siren_gets_higher() {
+        assert_eq!(siren_speed(100, 100), 1.0);
+        assert_eq!(siren_speed(50, 100), 1.0 + SIREN_PITCH_RISE / 2.0);
+        assert_eq!(siren_speed(0, 100), 1.0 + SIREN_PITCH_RISE);
+        // An empty maze doesn't divide by zero.
+        assert_eq!(siren_speed(0, 0), 1.0);
+    }
+}
@@ -0,0 +1,295 @@
+use bevy::{
+    input::mouse::MouseMotion,
+    prelude::{
+        BuildChildren, Commands, Component, DetectChanges, Entity, EventReader, GlobalTransform,
+        Input, KeyCode, Parent, Quat, Query, Res, ResMut, Resource, Time, Transform, Vec2, Vec3,
+        With,
+    },
+};
+
+use crate::{maze::Maze, Player};
+
+/// Marks the camera which normally follows the player around.
+#[derive(Component)]
+pub struct PlayerCamera;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub enum CameraMode {
+    /// The camera is attached to the player, looking the way they are going.
+    #[default]
+    FirstPerson,
+    /// The camera is still attached to the player, but sits up behind them so that they can be seen.
+    ThirdPerson,
+    /// The camera is detached from the player and looks straight down on the whole maze.
+    TopDown,
+    /// The camera is detached from the player and can be flown around the maze (which is handy for looking at big mazes).
+    FreeFly,
+}
+
+#[derive(Clone, Debug, Resource)]
+pub struct FreeFlyConfig {
+    /// Units per second.
+    pub speed: f32,
+    /// Radians per pixel of mouse movement.
+    pub mouse_sensitivity: f32,
+}
+
+impl Default for FreeFlyConfig {
+    fn default() -> Self {
+        Self {
+            speed: 10.0,
+            mouse_sensitivity: 0.003,
+        }
+    }
+}
+
+pub fn toggle_free_fly(keyboard_input: Res<Input<KeyCode>>, mut camera_mode: ResMut<CameraMode>) {
+    if keyboard_input.just_pressed(KeyCode::F) {
+        *camera_mode = match *camera_mode {
+            CameraMode::FreeFly => CameraMode::FirstPerson,
+            _ => CameraMode::FreeFly,
+        };
+    }
+}
+
+impl CameraMode {
+    /// Where the camera goes relative to the player, for the modes where it is attached to them.
+    /// Since the camera is a child of the player, this automatically turns with them.
+    fn player_offset(&self) -> Option<Transform> {
+        match self {
+            CameraMode::FirstPerson => Some(Transform::default().looking_to(-Vec3::Z, Vec3::Y)),
+            CameraMode::ThirdPerson => Some(
+                Transform::from_translation(THIRD_PERSON_OFFSET)
+                    .looking_at(THIRD_PERSON_LOOK_AT, Vec3::Y),
+            ),
+            CameraMode::TopDown | CameraMode::FreeFly => None,
+        }
+    }
+}
+
+/// Where the third person camera sits relative to the player (who faces along -z).
+const THIRD_PERSON_OFFSET: Vec3 = Vec3::new(0.0, 3.0, 5.0);
+/// The third person camera looks a little way in front of the player, so that more of the maze ahead can be seen.
+const THIRD_PERSON_LOOK_AT: Vec3 = Vec3::new(0.0, 0.0, -2.0);
+/// How much further up the top down camera goes than it needs to fit the maze in, so that the walls around the edge aren't cut off.
+const TOP_DOWN_MARGIN: f32 = 5.0;
+
+pub fn cycle_camera_mode(keyboard_input: Res<Input<KeyCode>>, mut camera_mode: ResMut<CameraMode>) {
+    if keyboard_input.just_pressed(KeyCode::C) {
+        *camera_mode = match *camera_mode {
+            CameraMode::FirstPerson => CameraMode::ThirdPerson,
+            CameraMode::ThirdPerson => CameraMode::TopDown,
+            CameraMode::TopDown | CameraMode::FreeFly => CameraMode::FirstPerson,
+        };
+    }
+}
+
+/// Puts the camera above the middle of the maze, high enough to see all of it.
+fn top_down_transform(maze: &Maze) -> Transform {
+    let (min, max) = maze.bounds();
+    let center = (min + max) / 2.0;
+    let size = max - min;
+    // The camera's field of view is 45 degrees, so it sees about as far across as it is high (a bit less, but that is what the margin is for).
+    let height = size.x.max(size.z) + TOP_DOWN_MARGIN;
+    // Forward (-z) is the top of the screen, just like it is the top of the maze.
+    Transform::from_xyz(center.x, height, center.z).looking_to(-Vec3::Y, -Vec3::Z)
+}
+
+/// Attaches the camera to the player or detaches it, depending on the mode.
+/// We check every frame (rather than only when the mode changes) because the camera gets recreated along with the player when the game restarts.
+pub fn apply_camera_mode(
+    camera_mode: Res<CameraMode>,
+    mut cameras: Query<
+        (Entity, &mut Transform, &GlobalTransform, Option<&Parent>),
+        With<PlayerCamera>,
+    >,
+    player: Query<Entity, With<Player>>,
+    maze: Res<Maze>,
+    mut commands: Commands,
+) {
+    for (camera, mut transform, global_transform, parent) in cameras.iter_mut() {
+        match camera_mode.player_offset() {
+            Some(offset) => {
+                if parent.is_none() {
+                    if let Ok(player) = player.get_single() {
+                        *transform = offset;
+                        commands.entity(player).add_child(camera);
+                    }
+                } else if camera_mode.is_changed() {
+                    *transform = offset;
+                }
+            }
+            None => {
+                if parent.is_some() {
+                    // Keep the camera exactly where it was, now that it is no longer relative to the player.
+                    *transform = global_transform.compute_transform();
+                    commands.entity(camera).remove_parent();
+                }
+                if *camera_mode == CameraMode::TopDown
+                    && (camera_mode.is_changed() || parent.is_some())
+                {
+                    *transform = top_down_transform(&maze);
+                }
+            }
+        }
+    }
+}
+
+/// Works out how far the free flying camera should move this frame, relative to the way it is facing.
+fn free_fly_translation(
+    keyboard_input: &Input<KeyCode>,
+    rotation: Quat,
+    speed: f32,
+    delta_seconds: f32,
+) -> Vec3 {
+    let mut direction = Vec3::ZERO;
+    for (key, key_direction) in [
+        (KeyCode::W, -Vec3::Z),
+        (KeyCode::S, Vec3::Z),
+        (KeyCode::A, -Vec3::X),
+        (KeyCode::D, Vec3::X),
+        (KeyCode::Space, Vec3::Y),
+        (KeyCode::ShiftLeft, -Vec3::Y),
+    ] {
+        if keyboard_input.pressed(key) {
+            direction += key_direction;
+        }
+    }
+    rotation * direction.normalize_or_zero() * speed * delta_seconds
+}
+
+pub fn free_fly_movement(
+    camera_mode: Res<CameraMode>,
+    config: Res<FreeFlyConfig>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    time: Res<Time>,
+    mut cameras: Query<&mut Transform, With<PlayerCamera>>,
+) {
+    if *camera_mode != CameraMode::FreeFly {
+        mouse_motion.clear();
+        return;
+    }
+    let mouse_delta = mouse_motion
+        .iter()
+        .fold(Vec2::ZERO, |total, motion| total + motion.delta);
+    for mut transform in cameras.iter_mut() {
+        // Turning left and right happens around the world's up, but looking up and down is relative to the camera.
+        transform.rotate_y(-mouse_delta.x * config.mouse_sensitivity);
+        transform.rotate_local_x(-mouse_delta.y * config.mouse_sensitivity);
+        let translation = free_fly_translation(
+            &keyboard_input,
+            transform.rotation,
+            config.speed,
+            time.delta_seconds(),
+        );
+        transform.translation += translation;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, BuildWorldChildren, IntoSystemConfigs, Update};
+
+    use super::*;
+
+    #[test]
+    fn free_fly_detaches_camera() {
+        let mut app = App::new();
+        app.init_resource::<CameraMode>()
+            .insert_resource(crate::sample_maze())
+            .add_systems(Update, apply_camera_mode);
+        let camera = app
+            .world
+            .spawn((
+                PlayerCamera,
+                Transform::default(),
+                GlobalTransform::from_xyz(1.0, 1.0, 2.0),
+            ))
+            .id();
+        let player = app
+            .world
+            .spawn((Player::default(), Transform::from_xyz(1.0, 1.0, 2.0)))
+            .id();
+        app.world.entity_mut(player).add_child(camera);
+
+        app.update();
+        assert_eq!(app.world.get::<Parent>(camera).unwrap().get(), player);
+
+        *app.world.resource_mut::<CameraMode>() = CameraMode::FreeFly;
+        app.update();
+        assert!(app.world.get::<Parent>(camera).is_none());
+        // It should still be where the player was.
+        assert_eq!(
+            app.world.get::<Transform>(camera).unwrap().translation,
+            Vec3::new(1.0, 1.0, 2.0)
+        );
+
+        let mut keyboard_input = Input::<KeyCode>::default();
+        keyboard_input.press(KeyCode::W);
+        let translation = free_fly_translation(&keyboard_input, Quat::IDENTITY, 10.0, 0.5);
+        assert_eq!(translation, Vec3::new(0.0, 0.0, -5.0));
+
+        *app.world.resource_mut::<CameraMode>() = CameraMode::FirstPerson;
+        app.update();
+        assert_eq!(app.world.get::<Parent>(camera).unwrap().get(), player);
+    }
+
+    #[test]
+    fn camera_modes() {
+        let mut app = App::new();
+        app.init_resource::<CameraMode>()
+            .init_resource::<Input<KeyCode>>()
+            .insert_resource(crate::sample_maze())
+            .add_systems(Update, (cycle_camera_mode, apply_camera_mode).chain());
+        let camera = app
+            .world
+            .spawn((
+                PlayerCamera,
+                Transform::default(),
+                GlobalTransform::default(),
+            ))
+            .id();
+        let player = app
+            .world
+            .spawn((Player::default(), Transform::default()))
+            .id();
+        app.world.entity_mut(player).add_child(camera);
+        let press_c = |app: &mut App| {
+            let mut keyboard_input = app.world.resource_mut::<Input<KeyCode>>();
+            keyboard_input.clear();
+            keyboard_input.press(KeyCode::C);
+            app.update();
+            app.world
+                .resource_mut::<Input<KeyCode>>()
+                .release(KeyCode::C);
+        };
+
+        press_c(&mut app);
+        assert_eq!(*app.world.resource::<CameraMode>(), CameraMode::ThirdPerson);
+        assert_eq!(app.world.get::<Parent>(camera).unwrap().get(), player);
+        let transform = *app.world.get::<Transform>(camera).unwrap();
+        assert_eq!(transform.translation, THIRD_PERSON_OFFSET);
+        // Still looking the same way as the player.
+        assert!(transform.forward().z < 0.0);
+
+        press_c(&mut app);
+        assert_eq!(*app.world.resource::<CameraMode>(), CameraMode::TopDown);
+        assert!(app.world.get::<Parent>(camera).is_none());
+        let transform = *app.world.get::<Transform>(camera).unwrap();
+        assert!(transform.forward().abs_diff_eq(-Vec3::Y, 0.0001));
+        // The sample maze goes from -15 to 15 across and -20 to 20 down (plus the walls on the outside), so the middle is at the origin.
+        assert_eq!(
+            transform.translation,
+            Vec3::new(0.0, 41.0 + TOP_DOWN_MARGIN, 0.0)
+        );
+
+        press_c(&mut app);
+        assert_eq!(*app.world.resource::<CameraMode>(), CameraMode::FirstPerson);
+        assert_eq!(app.world.get::<Parent>(camera).unwrap().get(), player);
+        assert_eq!(
+            *app.world.get::<Transform>(camera).unwrap(),
+            Transform::default().looking_to(-Vec3::Z, Vec3::Y)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+
+use bevy::prelude::{
+    Color, Entity, Gizmos, Input, KeyCode, Local, Or, Query, Res, ResMut, Resource, Transform,
+    Vec3, With,
+};
+use bevy_rapier3d::prelude::Velocity;
+
+use crate::{
+    ghost::{Ghost, PathCache},
+    maze::Maze,
+    step::STEP,
+    Player,
+};
+
+/// Lines drawn just above the ground, so that they don't disappear into it.
+const GIZMO_HEIGHT: f32 = 0.05;
+const INTERSECTION_GIZMO_RADIUS: f32 = 0.2;
+const EDGE_COLOR: Color = Color::CYAN;
+const ACTIVE_EDGE_COLOR: Color = Color::RED;
+const INTERSECTION_COLOR: Color = Color::YELLOW;
+/// The ghosts' paths go above the maze graph, so that they can be seen when both are shown.
+const GHOST_PATH_HEIGHT: f32 = GIZMO_HEIGHT * 2.0;
+
+/// Whether something moving this fast would go further than `limit` in a single step.
+fn exceeds_speed_trap(velocity: Vec3, delta_seconds: f32, limit: f32) -> bool {
+    velocity.length() * delta_seconds > limit
+}
+
+/// Warns about anything moving fast enough that it could tunnel through a wall.
+/// Something in the middle of a path is half a path's width from the walls, so going any further than that in one step could put it on the other side of one.
+/// Everything moves a whole step at a time (see `step.rs`), however long the frames are.
+/// We only warn when something first goes over the limit, since otherwise there would be a warning every frame.
+#[allow(clippy::type_complexity)]
+pub fn speed_trap(
+    actors: Query<(Entity, &Velocity), Or<(With<Player>, With<Ghost>)>>,
+    maze: Res<Maze>,
+    mut speeding: Local<HashSet<Entity>>,
+) {
+    // Anything which has been despawned (like the pellets at the start of a new level) can't be speeding any more.
+    speeding.retain(|entity| actors.contains(*entity));
+    for (entity, velocity) in actors.iter() {
+        if exceeds_speed_trap(velocity.linvel, STEP, maze.half_path_width()) {
+            if speeding.insert(entity) {
+                bevy::log::warn!(
+                    "{:?} is moving at {} units per second, which could take it through a wall",
+                    entity,
+                    velocity.linvel.length()
+                );
+            }
+        } else {
+            speeding.remove(&entity);
+        }
+    }
+}
+
+/// Whether the maze graph is being drawn over the top of the maze.
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct ShowMazeGraph(pub bool);
+
+pub fn toggle_maze_graph(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut show_maze_graph: ResMut<ShowMazeGraph>,
+) {
+    if keyboard_input.just_pressed(KeyCode::G) {
+        show_maze_graph.0 = !show_maze_graph.0;
+    }
+}
+
+/// The edges (with the lower index first) which are part of any of the given paths.
+fn active_edges<'a>(paths: impl Iterator<Item = &'a [usize]>) -> HashSet<(usize, usize)> {
+    paths
+        .flat_map(|path| path.windows(2))
+        .map(|pair| (pair[0].min(pair[1]), pair[0].max(pair[1])))
+        .collect()
+}
+
+fn maze_to_world((x, y): (f32, f32)) -> Vec3 {
+    Vec3::new(x, GIZMO_HEIGHT, y)
+}
+
+/// Draws the intersections and the paths between them, with the ones which the ghosts are planning to go along in a different colour.
+pub fn draw_maze_graph(
+    show_maze_graph: Res<ShowMazeGraph>,
+    maze: Res<Maze>,
+    ghosts: Query<&PathCache, With<Ghost>>,
+    mut gizmos: Gizmos,
+) {
+    if !show_maze_graph.0 {
+        return;
+    }
+    let active = active_edges(ghosts.iter().map(PathCache::path));
+    for (index, intersection) in maze.intersections().iter().enumerate() {
+        let position = maze_to_world(intersection.coordinates);
+        gizmos.sphere(
+            position,
+            Default::default(),
+            INTERSECTION_GIZMO_RADIUS,
+            INTERSECTION_COLOR,
+        );
+        // Tunnels go out of the side of the maze, so a straight line across the middle would just be confusing.
+        for path in intersection.all_paths().filter(|path| !path.wrap) {
+            // Every path is stored at both of its ends, so we only draw it from the lower one.
+            if path.end_index < index {
+                continue;
+            }
+            let color = if active.contains(&(index, path.end_index)) {
+                ACTIVE_EDGE_COLOR
+            } else {
+                EDGE_COLOR
+            };
+            let end = maze_to_world(maze.intersections()[path.end_index].coordinates);
+            gizmos.line(position, end, color);
+        }
+    }
+}
+
+/// Whether the path each ghost is following is drawn (in the ghost's colour).
+#[derive(Clone, Copy, Debug, Default, Resource)]
+pub struct ShowGhostPaths(pub bool);
+
+pub fn toggle_ghost_paths(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut show_ghost_paths: ResMut<ShowGhostPaths>,
+) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        show_ghost_paths.0 = !show_ghost_paths.0;
+    }
+}
+
+/// The lines going from the ghost through each of the intersections on its path.
+/// Going through a tunnel splits the line in two, since the ghost jumps from one side of the maze to the other.
+fn ghost_path_lines(ghost_position: Vec3, path: &[usize], maze: &Maze) -> Vec<Vec<Vec3>> {
+    let mut lines = vec![vec![Vec3::new(
+        ghost_position.x,
+        GHOST_PATH_HEIGHT,
+        ghost_position.z,
+    )]];
+    let mut previous = None;
+    for &index in path {
+        if previous.is_some_and(|previous| maze.wrap_direction(previous, index).is_some()) {
+            lines.push(Vec::new());
+        }
+        let (x, y) = maze.intersections()[index].coordinates;
+        lines
+            .last_mut()
+            .unwrap()
+            .push(Vec3::new(x, GHOST_PATH_HEIGHT, y));
+        previous = Some(index);
+    }
+    lines.retain(|line| line.len() > 1);
+    lines
+}
+
+/// Draws where each ghost is planning to go, which makes it much easier to see why a ghost took a strange route.
+pub fn draw_ghost_paths(
+    show_ghost_paths: Res<ShowGhostPaths>,
+    maze: Res<Maze>,
+    ghosts: Query<(&Ghost, &Transform, &PathCache)>,
+    mut gizmos: Gizmos,
+) {
+    if !show_ghost_paths.0 {
+        return;
+    }
+    for (ghost, transform, path_cache) in ghosts.iter() {
+        for line in ghost_path_lines(transform.translation, path_cache.path(), &maze) {
+            gizmos.linestrip(line, ghost.personality.default_color());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index_of;
+
+    #[test]
+    fn test_speed_trap() {
+        let limit = crate::maze::DEFAULT_HALF_PATH_WIDTH;
+        assert!(!exceeds_speed_trap(Vec3::ZERO, STEP, limit));
+        // Going as fast as the player normally does is fine.
+        assert!(!exceeds_speed_trap(
+            Vec3::new(0.0, 0.0, crate::PlayerConfig::default().speed),
+            STEP,
+            limit
+        ));
+        assert!(exceeds_speed_trap(Vec3::new(0.0, 0.0, 100.0), STEP, limit));
+        // It is the total speed that matters, not the speed along either axis.
+        assert!(exceeds_speed_trap(Vec3::new(24.0, 0.0, 24.0), STEP, limit));
+        assert!(!exceeds_speed_trap(Vec3::new(24.0, 0.0, 0.0), STEP, limit));
+    }
+
+    #[test]
+    fn active_edges_ignore_direction() {
+        let paths: [&[usize]; 3] = [&[3, 1, 2], &[2, 1], &[5]];
+        assert_eq!(
+            active_edges(paths.into_iter()),
+            HashSet::from([(1, 3), (1, 2)])
+        );
+    }
+
+    #[test]
+    fn ghost_paths_split_at_tunnels() {
+        let mut maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((10.0, 0.0), (10.0, 10.0))]);
+        let path = [index_of(&maze, (10.0, 0.0)), index_of(&maze, (10.0, 10.0))];
+        assert_eq!(
+            ghost_path_lines(Vec3::new(5.0, 1.0, 0.0), &path, &maze),
+            vec![vec![
+                Vec3::new(5.0, GHOST_PATH_HEIGHT, 0.0),
+                Vec3::new(10.0, GHOST_PATH_HEIGHT, 0.0),
+                Vec3::new(10.0, GHOST_PATH_HEIGHT, 10.0),
+            ]]
+        );
+
+        maze.add_wrap((0.0, 0.0), (10.0, 10.0), 5.0);
+        let path = [index_of(&maze, (0.0, 0.0)), index_of(&maze, (10.0, 10.0))];
+        assert_eq!(
+            ghost_path_lines(Vec3::new(5.0, 1.0, 0.0), &path, &maze),
+            vec![vec![
+                Vec3::new(5.0, GHOST_PATH_HEIGHT, 0.0),
+                Vec3::new(0.0, GHOST_PATH_HEIGHT, 0.0),
+            ]]
+        );
+        // An empty path (when the ghost is on the same path as the player) has nothing to draw.
+        assert!(ghost_path_lines(Vec3::ZERO, &[], &maze).is_empty());
+    }
+}
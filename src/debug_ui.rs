@@ -0,0 +1,96 @@
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::{Entity, Query, Res, ResMut},
+};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    difficulty::Difficulty,
+    ghost::{FrightenedConfig, Ghost, GhostModeTimer, GhostPersonality, GhostState, PathCache},
+    score::Score,
+    PlayerConfig,
+};
+
+const PERSONALITIES: [GhostPersonality; 4] = [
+    GhostPersonality::Blinky,
+    GhostPersonality::Pinky,
+    GhostPersonality::Inky,
+    GhostPersonality::Clyde,
+];
+
+/// A window for watching what the ghosts are up to, and for changing the tuning while the game is running.
+/// Nothing here is saved, so it is all back to normal the next time the game starts.
+#[allow(clippy::too_many_arguments)]
+pub fn tuning_panel(
+    mut contexts: EguiContexts,
+    diagnostics: Res<DiagnosticsStore>,
+    mut ghosts: Query<(Entity, &mut Ghost, &GhostState, &PathCache)>,
+    mode_timer: Option<Res<GhostModeTimer>>,
+    score: Option<Res<Score>>,
+    mut player_config: ResMut<PlayerConfig>,
+    mut frightened_config: ResMut<FrightenedConfig>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    egui::Window::new("Tuning").show(contexts.ctx_mut(), |ui| {
+        let fps = diagnostics
+            .get(FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.smoothed());
+        match fps {
+            Some(fps) => ui.label(format!("FPS: {:.0}", fps)),
+            None => ui.label("FPS: ..."),
+        };
+        if let Some(score) = score {
+            ui.label(format!("Score: {}", score.current()));
+        }
+        if let Some(mode_timer) = mode_timer {
+            ui.label(format!("Ghost mode: {:?}", mode_timer.mode()));
+        }
+
+        ui.separator();
+        ui.add(egui::Slider::new(&mut player_config.speed, 0.5..=10.0).text("Player speed"));
+        ui.add(
+            egui::Slider::new(&mut frightened_config.duration, 0.0..=20.0)
+                .text("Frightened duration"),
+        );
+        ui.add(
+            egui::Slider::new(&mut frightened_config.freeze_duration, 0.0..=2.0)
+                .text("Frightened freeze"),
+        );
+        // The ghosts' speeds are worked out again every step (see `cruise_elroy`), so the difficulty is the way to change them.
+        ui.horizontal(|ui| {
+            ui.label("Difficulty:");
+            for choice in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+                ui.radio_value(&mut *difficulty, choice, format!("{:?}", choice));
+            }
+        });
+
+        ui.separator();
+        for (entity, mut ghost, ghost_state, path_cache) in ghosts.iter_mut() {
+            ui.horizontal(|ui| {
+                let mut personality = ghost.personality.clone();
+                egui::ComboBox::from_id_source(entity)
+                    .selected_text(format!("{:?}", personality))
+                    .show_ui(ui, |ui| {
+                        for choice in PERSONALITIES {
+                            let name = format!("{:?}", choice);
+                            ui.selectable_value(&mut personality, choice, name);
+                        }
+                    });
+                // Only writing it back when it changes stops every ghost looking changed on every frame.
+                if personality != ghost.personality {
+                    ghost.personality = personality;
+                }
+                let state = match ghost_state {
+                    GhostState::Normal => "normal",
+                    GhostState::Frightened { .. } => "frightened",
+                    GhostState::Eaten { .. } => "eaten",
+                };
+                ui.label(state);
+                match path_cache.target() {
+                    Some(target) => ui.label(format!("heading for {:?}", target)),
+                    None => ui.label("not going anywhere"),
+                };
+            });
+        }
+    });
+}
@@ -0,0 +1,118 @@
+use bevy::prelude::Resource;
+
+use crate::ghost::GhostMode;
+
+/// How hard the game is, which is picked once at the start (with `--difficulty` on the command line).
+/// It doesn't change anything itself, but everything it affects is scaled by its `multipliers`:
+///
+/// | Difficulty | Ghost speed | Player speed | Frightened | Scatter | Chase |
+/// |------------|-------------|--------------|------------|---------|-------|
+/// | Easy       | 0.8         | 1.0          | 1.5        | 1.5     | 0.75  |
+/// | Normal     | 1.0         | 1.0          | 1.0        | 1.0     | 1.0   |
+/// | Hard       | 1.2         | 1.1          | 0.5        | 0.5     | 1.25  |
+///
+/// The last three are how long each of those modes lasts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// How much a `Difficulty` scales each part of the game by, compared to `Normal`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DifficultyMultipliers {
+    pub ghost_speed: f32,
+    pub player_speed: f32,
+    pub frightened_duration: f32,
+    pub scatter_duration: f32,
+    pub chase_duration: f32,
+}
+
+impl Difficulty {
+    /// The numbers from the table above.
+    pub fn multipliers(self) -> DifficultyMultipliers {
+        match self {
+            Difficulty::Easy => DifficultyMultipliers {
+                ghost_speed: 0.8,
+                player_speed: 1.0,
+                frightened_duration: 1.5,
+                scatter_duration: 1.5,
+                chase_duration: 0.75,
+            },
+            Difficulty::Normal => DifficultyMultipliers {
+                ghost_speed: 1.0,
+                player_speed: 1.0,
+                frightened_duration: 1.0,
+                scatter_duration: 1.0,
+                chase_duration: 1.0,
+            },
+            Difficulty::Hard => DifficultyMultipliers {
+                ghost_speed: 1.2,
+                player_speed: 1.1,
+                frightened_duration: 0.5,
+                scatter_duration: 0.5,
+                chase_duration: 1.25,
+            },
+        }
+    }
+
+    /// How much longer (or shorter) the ghosts spend in this mode.
+    pub fn mode_duration(self, mode: GhostMode) -> f32 {
+        let multipliers = self.multipliers();
+        match mode {
+            GhostMode::Scatter => multipliers.scatter_duration,
+            GhostMode::Chase => multipliers.chase_duration,
+        }
+    }
+
+    /// The difficulty with this name (which is how it is written on the command line).
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name.to_lowercase().as_str() {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    /// The other way around to `from_name`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ghost_speed_with, progression::LevelNumber};
+
+    #[test]
+    fn names() {
+        assert_eq!(Difficulty::from_name("easy"), Some(Difficulty::Easy));
+        assert_eq!(Difficulty::from_name("Normal"), Some(Difficulty::Normal));
+        assert_eq!(Difficulty::from_name("HARD"), Some(Difficulty::Hard));
+        assert_eq!(Difficulty::from_name("impossible"), None);
+        for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+            assert_eq!(Difficulty::from_name(difficulty.name()), Some(difficulty));
+        }
+    }
+
+    #[test]
+    fn hard_ghosts_are_faster() {
+        let ghost_speed = |difficulty| ghost_speed_with(difficulty, LevelNumber::default());
+        let (easy, normal, hard) = (
+            ghost_speed(Difficulty::Easy),
+            ghost_speed(Difficulty::Normal),
+            ghost_speed(Difficulty::Hard),
+        );
+        assert!(easy > 0.0);
+        assert!(easy < normal, "{} {}", easy, normal);
+        assert!(normal < hard, "{} {}", normal, hard);
+    }
+}
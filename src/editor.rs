@@ -0,0 +1,315 @@
+use bevy::{
+    prelude::{
+        Assets, Camera, Commands, DespawnRecursiveExt, DetectChanges, Entity, GlobalTransform,
+        Input, KeyCode, MouseButton, NextState, Or, Query, Res, ResMut, Resource, StandardMaterial,
+        State, Vec2, Vec3, With,
+    },
+    window::{PrimaryWindow, Window},
+};
+use bevy_rapier3d::prelude::Velocity;
+
+use crate::{
+    camera::PlayerCamera, ghost::PathCache, maze::Maze, route::RouteGuide, spawn_maze,
+    theme::MazeTheme, GameState, IntersectionComponent, MazeWalls,
+};
+
+/// Everything placed in the editor is snapped to a grid of this size, so that paths actually line up with each other.
+const GRID_SIZE: f32 = 5.0;
+
+#[cfg(feature = "serde")]
+const SAVE_PATH: &str = "maze.json";
+/// An ASCII maze (see `parse_ascii`) which the editor saves to and loads from, with each cell being one square of the grid.
+/// The start positions in it are ignored, since the editor only deals with the paths.
+const ASCII_PATH: &str = "maze.txt";
+
+#[derive(Clone, Debug, Default, Resource)]
+pub struct EditorState {
+    /// Where the mouse was pressed down, if it is being dragged at the moment.
+    drag_start: Option<Vec2>,
+}
+
+fn snap_to_grid(point: Vec2) -> (f32, f32) {
+    let snapped = (point / GRID_SIZE).round() * GRID_SIZE;
+    (snapped.x, snapped.y)
+}
+
+/// Turns a drag of the mouse (in world coordinates on the ground) into a path.
+/// Paths have to be horizontal or vertical, so we only keep whichever way the mouse moved the most.
+/// If the drag is too short to reach another grid point, there is no path.
+#[allow(clippy::type_complexity)]
+fn editor_path(drag_start: Vec2, drag_end: Vec2) -> Option<((f32, f32), (f32, f32))> {
+    let start = snap_to_grid(drag_start);
+    let mut end = snap_to_grid(drag_end);
+    if (end.0 - start.0).abs() >= (end.1 - start.1).abs() {
+        end.1 = start.1;
+    } else {
+        end.0 = start.0;
+    }
+    if start == end {
+        None
+    } else {
+        Some((start, end))
+    }
+}
+
+/// Takes out whichever path is at this point (in world coordinates on the ground).
+/// Clicking on an intersection doesn't do anything, since it isn't clear which of its paths should go.
+fn remove_path_at(maze: &mut Maze, point: Vec2) {
+    if let Some((a, b)) = maze.edge_at((point.x, point.y)) {
+        if a != b {
+            maze.remove_path(a, b);
+        }
+    }
+}
+
+/// Finds where the mouse is pointing on the ground (as x and z coordinates).
+fn cursor_on_ground(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let ray = camera.viewport_to_world(camera_transform, cursor)?;
+    let distance = ray.intersect_plane(Vec3::ZERO, Vec3::Y)?;
+    let point = ray.get_point(distance);
+    Some(Vec2::new(point.x, point.z))
+}
+
+pub fn toggle_editor(
+    keyboard_input: Res<Input<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::E) {
+        next_state.set(match state.get() {
+            GameState::Editor => GameState::Playing,
+            _ => GameState::Editor,
+        });
+    }
+}
+
+/// Nothing should be moving around while the maze is being edited.
+pub fn stop_moving(mut velocities: Query<&mut Velocity>) {
+    for mut velocity in velocities.iter_mut() {
+        *velocity = Velocity::zero();
+    }
+}
+
+pub fn editor_input(
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    // The minimap has a camera as well, but that isn't the one we are looking through.
+    cameras: Query<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    mut editor_state: ResMut<EditorState>,
+    mut maze: ResMut<Maze>,
+) {
+    let cursor_position = windows.get_single().ok().and_then(|window| {
+        cameras
+            .iter()
+            .find(|(camera, _)| camera.is_active)
+            .and_then(|(camera, camera_transform)| {
+                cursor_on_ground(window, camera, camera_transform)
+            })
+    });
+    if mouse_input.just_pressed(MouseButton::Left) {
+        editor_state.drag_start = cursor_position;
+    }
+    if mouse_input.just_released(MouseButton::Left) {
+        if let (Some(drag_start), Some(drag_end)) = (editor_state.drag_start, cursor_position) {
+            if let Some(path) = editor_path(drag_start, drag_end) {
+                maze.add_path(path);
+            }
+        }
+        editor_state.drag_start = None;
+    }
+    // Right clicking rubs paths out again.
+    if mouse_input.just_pressed(MouseButton::Right) {
+        if let Some(point) = cursor_position {
+            remove_path_at(&mut maze, point);
+        }
+    }
+}
+
+/// Reads a maze to replace the one in the editor, which is JSON (from `save_maze`) if the file ends in `.json` and an ASCII maze otherwise.
+/// ASCII mazes don't say how wide the paths are, so those stay the same width as they were.
+/// If it can't be read, the maze is left as it is.
+fn load_maze_file(path: &str, maze: &mut Maze) {
+    let loaded = std::fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| {
+            #[cfg(feature = "serde")]
+            if path.ends_with(".json") {
+                return Maze::from_json(&text).map_err(|error| error.to_string());
+            }
+            Maze::from_ascii(&text, GRID_SIZE)
+                .map(|loaded| loaded.with_half_path_width(maze.half_path_width()))
+                .map_err(|error| error.to_string())
+        });
+    match loaded {
+        Ok(loaded) => *maze = loaded,
+        Err(error) => bevy::log::error!("Failed to load the maze from {}: {}", path, error),
+    }
+}
+
+/// Loads back whatever was last saved, or a hand written ASCII maze if nothing has been.
+pub fn load_maze(keyboard_input: Res<Input<KeyCode>>, mut maze: ResMut<Maze>) {
+    if !keyboard_input.just_pressed(KeyCode::L) {
+        return;
+    }
+    #[cfg(feature = "serde")]
+    if std::path::Path::new(SAVE_PATH).exists() {
+        load_maze_file(SAVE_PATH, &mut maze);
+        return;
+    }
+    load_maze_file(ASCII_PATH, &mut maze);
+}
+
+fn save_maze_file(path: &str, text: String) {
+    if let Err(error) = std::fs::write(path, text) {
+        bevy::log::error!("Failed to save the maze to {}: {}", path, error);
+    }
+}
+
+/// Saves the maze as an ASCII maze, which can be edited by hand and loaded back with `L`.
+/// With serde, it gets saved as JSON as well, which keeps everything (like how wide the paths are) exactly as it was.
+pub fn save_maze(keyboard_input: Res<Input<KeyCode>>, maze: Res<Maze>) {
+    if !keyboard_input.just_pressed(KeyCode::S) {
+        return;
+    }
+    save_maze_file(ASCII_PATH, maze.to_ascii(GRID_SIZE));
+    #[cfg(feature = "serde")]
+    save_maze_file(SAVE_PATH, maze.to_json());
+}
+
+/// Replaces the walls and intersection sensors whenever the maze is edited.
+/// Editing can change the indices of the intersections, so anything holding onto them has to start again.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn rebuild_maze(
+    maze: Res<Maze>,
+    old_maze: Query<Entity, Or<(With<MazeWalls>, With<IntersectionComponent>)>>,
+    mut path_caches: Query<&mut PathCache>,
+    route: Option<ResMut<RouteGuide>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<bevy::prelude::Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    theme: Res<MazeTheme>,
+) {
+    if !maze.is_changed() {
+        return;
+    }
+    for entity in old_maze.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for mut path_cache in path_caches.iter_mut() {
+        *path_cache = PathCache::default();
+    }
+    // The route would need to be made again for the new maze, so it is safer to just get rid of it.
+    if let Some(mut route) = route {
+        if !route.0.is_empty() {
+            route.0.clear();
+        }
+    }
+    spawn_maze(&maze, &theme, &mut commands, &mut meshes, &mut materials);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index_of;
+
+    #[test]
+    fn clicks_snap_to_grid() {
+        let mut maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0))]);
+        // A slightly wonky drag upwards from near the end of the existing path.
+        let path = editor_path(Vec2::new(9.2, 0.7), Vec2::new(11.4, 6.1));
+        assert_eq!(path, Some(((10.0, 0.0), (10.0, 5.0))));
+        maze.add_path(path.unwrap());
+        let corner = &maze.intersections()[index_of(&maze, (10.0, 0.0))];
+        assert!(corner.left().is_some());
+        assert!(corner.forward().is_some());
+        assert!(maze
+            .intersections()
+            .iter()
+            .any(|intersection| intersection.coordinates == (10.0, 5.0)));
+        // Drags which don't reach another grid point don't do anything.
+        assert_eq!(editor_path(Vec2::new(1.0, 1.0), Vec2::new(2.0, 1.5)), None);
+    }
+
+    #[test]
+    fn loading_ascii_mazes() {
+        let path = std::env::temp_dir().join(format!("pacman3d-editor-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "...\n.#.\n...").unwrap();
+        let mut maze = Maze::new(&[((0.0, 0.0), (5.0, 0.0))]).with_half_path_width(1.0);
+        load_maze_file(path, &mut maze);
+        // Each cell is a square of the grid.
+        assert_eq!(maze.paths().len(), 4);
+        assert!(maze.paths().contains(&((0.0, 0.0), (10.0, 0.0))));
+        assert_eq!(maze.half_path_width(), 1.0);
+        // Anything which isn't a maze leaves the old one alone.
+        std::fs::write(path, "..\n.").unwrap();
+        load_maze_file(path, &mut maze);
+        assert_eq!(maze.paths().len(), 4);
+        std::fs::remove_file(path).unwrap();
+        load_maze_file(path, &mut maze);
+        assert_eq!(maze.paths().len(), 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn loading_saved_mazes() {
+        let path =
+            std::env::temp_dir().join(format!("pacman3d-editor-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let saved = crate::sample_maze().with_half_path_width(0.8);
+        std::fs::write(path, saved.to_json()).unwrap();
+        let mut maze = Maze::new(&[((0.0, 0.0), (5.0, 0.0))]);
+        load_maze_file(path, &mut maze);
+        std::fs::remove_file(path).unwrap();
+        // Everything comes back, including how wide the paths are.
+        assert_eq!(maze, saved);
+        assert_eq!(maze.edge_at((0.0, 0.7)), saved.edge_at((0.0, 0.7)));
+    }
+
+    #[test]
+    fn right_clicks_remove_paths() {
+        let mut maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((10.0, 0.0), (10.0, 5.0))]);
+        // Clicking on the corner itself doesn't do anything.
+        remove_path_at(&mut maze, Vec2::new(10.1, 0.2));
+        assert_eq!(maze.paths().len(), 2);
+        remove_path_at(&mut maze, Vec2::new(10.2, 3.0));
+        assert_eq!(maze.paths(), vec![((0.0, 0.0), (10.0, 0.0))]);
+        // Neither does clicking off the paths.
+        remove_path_at(&mut maze, Vec2::new(5.0, 3.0));
+        assert_eq!(maze.paths().len(), 1);
+    }
+
+    #[test]
+    fn editing_forgets_old_indices() {
+        use bevy::prelude::{in_state, IntoSystemConfigs, Update};
+
+        let mut app = crate::build_test_app(crate::level::Level::default());
+        app.insert_resource(RouteGuide(vec![0, 1]))
+            .add_systems(Update, rebuild_maze.run_if(in_state(GameState::Editor)));
+        // Long enough for Blinky to have worked out where to go.
+        for _ in 0..10 {
+            app.update();
+        }
+        let mut path_caches = app.world.query::<&PathCache>();
+        assert!(path_caches
+            .iter(&app.world)
+            .any(|path_cache| !path_cache.path().is_empty()));
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Editor);
+        app.update();
+        app.world
+            .resource_mut::<Maze>()
+            .add_path(((100.0, 0.0), (105.0, 0.0)));
+        app.update();
+        assert!(path_caches
+            .iter(&app.world)
+            .all(|path_cache| path_cache.path().is_empty()));
+        assert!(app.world.resource::<RouteGuide>().0.is_empty());
+    }
+}
@@ -1,263 +1,1838 @@
-use std::collections::HashMap;
+use std::time::Duration;
 
 use bevy::prelude::{
-    Assets, Color, Commands, Component, Entity, Quat, Query, Res, ResMut, StandardMaterial,
-    Transform, Vec3, Without,
+    Assets, Color, Commands, Component, Entity, Event, EventReader, EventWriter, Query, Res,
+    ResMut, Resource, StandardMaterial, Timer, TimerMode, Transform, Vec3, With, Without,
 };
-use bevy_rapier3d::prelude::{LockedAxes, RigidBody, Velocity};
+use bevy_rapier3d::prelude::{CollisionGroups, Group, LockedAxes, RigidBody, Velocity};
 
 use crate::{
-    maze::{Maze, HALF_PATH_WIDTH},
+    difficulty::Difficulty,
+    intersection::CurrentIntersection,
+    math::euclidean,
+    maze::Maze,
     object::{GameObject, Mesh, Shape},
-    Player,
+    pellet::{Pellet, PelletEaten, PowerPelletEaten},
+    progression::LevelNumber,
+    step::{Interpolated, StepTime},
+    CharacterSize, Direction, Player, PLAYER_SPEED,
 };
 
-#[derive(Clone, Debug)]
-pub enum GhostType {
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GhostPersonality {
     Blinky,
     Pinky,
     Inky,
     Clyde,
 }
 
+impl GhostPersonality {
+    /// The colours the ghosts have in the arcade game.
+    pub fn default_color(&self) -> Color {
+        match self {
+            GhostPersonality::Blinky => Color::RED,
+            GhostPersonality::Pinky => Color::PINK,
+            GhostPersonality::Inky => Color::CYAN,
+            GhostPersonality::Clyde => Color::ORANGE,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Component)]
-pub struct Ghost(pub GhostType);
+pub struct Ghost {
+    pub personality: GhostPersonality,
+    /// How fast (in units per second) the ghost goes when it isn't frightened or eaten.
+    pub speed: f32,
+    /// How much faster than `speed` the ghost is going at the moment (see `cruise_elroy`).
+    /// This is kept separate so that it doesn't wipe out whatever `speed` the ghost was given.
+    pub speed_multiplier: f32,
+}
+
+impl Ghost {
+    pub fn new(personality: GhostPersonality) -> Self {
+        Self {
+            personality,
+            speed: GHOST_SPEED,
+            speed_multiplier: 1.0,
+        }
+    }
+
+    /// How fast the ghost is going at the moment when it isn't frightened or eaten.
+    pub fn current_speed(&self) -> f32 {
+        self.speed * self.speed_multiplier
+    }
+}
+
+/// All of the ghosts start out going the same speed, like in the arcade game.
+/// There the player goes at 80% of full speed and the ghosts at 75%, so we keep the same ratio to the player's speed.
+pub const GHOST_SPEED: f32 = PLAYER_SPEED * 75.0 / 80.0;
+
+/// When Blinky speeds up (as long as there are no more than this many pellets left) and how much faster he goes.
+/// In the arcade game he goes 80% of full speed with 20 dots left and 85% with 10 left, instead of the normal 75%, which is where these ratios come from.
+/// He is nicknamed "Cruise Elroy" when he does this.
+const CRUISE_ELROY_STAGES: [(usize, f32); 2] = [(10, 85.0 / 75.0), (20, 80.0 / 75.0)];
+
+/// How much faster than normal a ghost should be going with this many pellets left to eat.
+fn speed_multiplier(personality: &GhostPersonality, pellets_left: usize) -> f32 {
+    if *personality != GhostPersonality::Blinky {
+        return 1.0;
+    }
+    CRUISE_ELROY_STAGES
+        .iter()
+        .find(|(pellets, _)| pellets_left <= *pellets)
+        .map_or(1.0, |(_, ratio)| *ratio)
+}
+
+/// Speeds Blinky up as the pellets run out.
+pub fn cruise_elroy(pellets: Query<(), With<Pellet>>, mut ghosts: Query<&mut Ghost>) {
+    let pellets_left = pellets.iter().count();
+    for mut ghost in ghosts.iter_mut() {
+        let multiplier = speed_multiplier(&ghost.personality, pellets_left);
+        if ghost.speed_multiplier != multiplier {
+            ghost.speed_multiplier = multiplier;
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Component)]
+pub enum GhostState {
+    #[default]
+    Normal,
+    /// The ghost can be eaten until the timer runs out.
+    /// It stays still until the freeze timer has finished, and only then does the main timer start.
+    Frightened { freeze: Timer, timer: Timer },
+    /// The ghost has been eaten, so it hurries back home (to its spawn point) and then carries on as normal.
+    /// The player can't be caught by it on the way.
+    Eaten { home: Vec3 },
+}
+
+impl GhostState {
+    /// Whether the ghost is frightened but hasn't started running away yet.
+    pub fn is_frozen(&self) -> bool {
+        match self {
+            GhostState::Frightened { freeze, .. } => !freeze.remaining().is_zero(),
+            GhostState::Normal | GhostState::Eaten { .. } => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Resource)]
+pub struct FrightenedConfig {
+    /// How long (in seconds) the ghosts can be eaten for, not counting the freeze.
+    pub duration: f32,
+    /// How long (in seconds) the ghosts stop still for when they first become frightened, like in the arcade game.
+    pub freeze_duration: f32,
+}
+
+impl Default for FrightenedConfig {
+    fn default() -> Self {
+        Self {
+            duration: 8.0,
+            freeze_duration: 0.5,
+        }
+    }
+}
+
+/// Makes the ghost frightened, or keeps it frightened for longer if it already was.
+/// A ghost which is already frozen keeps its original freeze, so eating two power pellets in a row doesn't hold the ghosts still for twice as long.
+/// Ghosts which have been eaten are still on their way home, so they don't get frightened again.
+pub fn frighten(ghost_state: &mut GhostState, config: &FrightenedConfig) {
+    let timer = Timer::from_seconds(config.duration, TimerMode::Once);
+    match ghost_state {
+        GhostState::Frightened {
+            timer: old_timer, ..
+        } => *old_timer = timer,
+        GhostState::Normal => {
+            *ghost_state = GhostState::Frightened {
+                freeze: Timer::from_seconds(config.freeze_duration, TimerMode::Once),
+                timer,
+            }
+        }
+        GhostState::Eaten { .. } => {}
+    }
+}
+
+/// What the ghosts are doing when they aren't frightened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GhostMode {
+    /// Each ghost goes back to its own corner of the maze.
+    Scatter,
+    /// The ghosts go after the player (each in their own way).
+    Chase,
+}
+
+/// How long (in seconds) each mode lasts, in order, like in the arcade game.
+/// After the last one the ghosts chase forever.
+const GHOST_MODE_SCHEDULE: [(GhostMode, f32); 4] = [
+    (GhostMode::Scatter, 7.0),
+    (GhostMode::Chase, 20.0),
+    (GhostMode::Scatter, 7.0),
+    (GhostMode::Chase, 20.0),
+];
+
+/// Switches all of the ghosts between scattering and chasing.
+#[derive(Clone, Debug, Resource)]
+pub struct GhostModeTimer {
+    /// Which part of `GHOST_MODE_SCHEDULE` we are up to.
+    phase: usize,
+    timer: Timer,
+}
+
+impl Default for GhostModeTimer {
+    fn default() -> Self {
+        Self {
+            phase: 0,
+            timer: Timer::from_seconds(GHOST_MODE_SCHEDULE[0].1, TimerMode::Once),
+        }
+    }
+}
+
+impl GhostModeTimer {
+    pub fn mode(&self) -> GhostMode {
+        GHOST_MODE_SCHEDULE
+            .get(self.phase)
+            .map(|(mode, _)| *mode)
+            .unwrap_or(GhostMode::Chase)
+    }
+
+    pub fn tick(&mut self, mut delta: Duration) {
+        // A long frame could go past the end of a phase, so whatever is left over counts towards the next one.
+        while self.phase < GHOST_MODE_SCHEDULE.len() {
+            let remaining = self.timer.remaining();
+            if delta < remaining {
+                self.timer.tick(delta);
+                return;
+            }
+            delta -= remaining;
+            self.phase += 1;
+            if let Some((_, duration)) = GHOST_MODE_SCHEDULE.get(self.phase) {
+                self.timer = Timer::from_seconds(*duration, TimerMode::Once);
+            }
+        }
+    }
+}
+
+/// The scatter and chase timer doesn't run while the ghosts are frightened.
+pub fn update_ghost_mode(
+    ghosts: Query<&GhostState>,
+    mut mode_timer: ResMut<GhostModeTimer>,
+    time: Res<StepTime>,
+    difficulty: Res<Difficulty>,
+) {
+    if ghosts
+        .iter()
+        .any(|ghost_state| matches!(ghost_state, GhostState::Frightened { .. }))
+    {
+        return;
+    }
+    // Running the timer slower makes the mode last longer, which is easier than changing every duration in the schedule.
+    let duration = difficulty.mode_duration(mode_timer.mode());
+    mode_timer.tick(time.delta().div_f32(duration));
+}
+
+/// How many points eating a frightened ghost is worth.
+pub const GHOST_POINTS: u32 = 200;
+
+/// Sent whenever the player eats a frightened ghost.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct GhostEaten {
+    pub ghost: Entity,
+    pub points: u32,
+}
+
+/// Sent whenever a ghost catches the player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct PlayerCaught;
+
+/// Where a ghost goes back to when it gets eaten.
+#[derive(Clone, Copy, Debug, Component)]
+pub struct SpawnPoint(pub Vec3);
+
+/// A ghost which is still waiting in the ghost house.
+/// It bobs up and down where it started, and doesn't go anywhere until `release_ghosts` lets it out.
+#[derive(Clone, Copy, Debug, Component)]
+pub struct Penned;
+
+/// How high above and below its spawn point a penned ghost bobs.
+const BOB_HEIGHT: f32 = 0.15;
+/// How fast (in radians per second) penned ghosts bob up and down.
+const BOB_SPEED: f32 = 5.0;
+
+/// When a ghost gets to leave the ghost house.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GhostRelease {
+    /// How long (in seconds) after the level started...
+    pub delay: f32,
+    /// ...or how many pellets the player has eaten by then, whichever happens first.
+    pub pellets: usize,
+}
+
+/// When each ghost leaves the ghost house, in the order they are waiting.
+/// Any ghosts past the end of this go at the same time as the last one.
+#[derive(Clone, Debug, PartialEq, Resource)]
+pub struct GhostReleaseSchedule(pub Vec<GhostRelease>);
+
+impl Default for GhostReleaseSchedule {
+    /// Blinky comes straight out, and the rest follow a few seconds apart (or sooner if the player is quick with the pellets).
+    /// The pellet counts are the arcade game's, except that Pinky doesn't come out straight away.
+    fn default() -> Self {
+        Self(vec![
+            GhostRelease {
+                delay: 0.0,
+                pellets: 0,
+            },
+            GhostRelease {
+                delay: 4.0,
+                pellets: 10,
+            },
+            GhostRelease {
+                delay: 8.0,
+                pellets: 30,
+            },
+            GhostRelease {
+                delay: 12.0,
+                pellets: 60,
+            },
+        ])
+    }
+}
+
+/// The ghosts which haven't been let out yet, and how long they have been waiting.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct GhostHouse {
+    /// Where the ghost house is, which is the intersections the ghosts start on.
+    pub intersections: Vec<usize>,
+    /// Every ghost which starts in the house, in the order they come out.
+    ghosts: Vec<Entity>,
+    /// How many of `ghosts` have been let out.
+    released: usize,
+    elapsed: f32,
+    pellets_eaten: usize,
+}
+
+impl GhostHouse {
+    pub fn new(intersections: Vec<usize>, ghosts: Vec<Entity>) -> Self {
+        Self {
+            intersections,
+            ghosts,
+            ..Default::default()
+        }
+    }
+
+    /// The ghosts still inside, in the order they come out.
+    pub fn waiting(&self) -> &[Entity] {
+        &self.ghosts[self.released..]
+    }
+
+    /// Whether the next ghost should come out now.
+    fn next_is_due(&self, schedule: &GhostReleaseSchedule) -> bool {
+        match schedule.0.get(self.released).or(schedule.0.last()) {
+            Some(release) => self.elapsed >= release.delay || self.pellets_eaten >= release.pellets,
+            // Without a schedule there's no reason to keep anyone waiting.
+            None => true,
+        }
+    }
+
+    /// Lets the next ghost out, whether it is time or not, and gives it back so that it can be unpenned.
+    pub fn release_next_ghost(&mut self) -> Option<Entity> {
+        let ghost = *self.ghosts.get(self.released)?;
+        self.released += 1;
+        Some(ghost)
+    }
+
+    /// Puts every ghost back inside, and starts the schedule again (for when the player gets caught).
+    pub fn reset(&mut self) {
+        self.released = 0;
+        self.elapsed = 0.0;
+        self.pellets_eaten = 0;
+    }
+}
+
+/// Lets the ghosts out of the ghost house one at a time, following the `GhostReleaseSchedule`.
+pub fn release_ghosts(
+    mut commands: Commands,
+    mut house: ResMut<GhostHouse>,
+    mut pellets: EventReader<PelletEaten>,
+    mut ghosts: Query<(&SpawnPoint, &mut Transform), With<Penned>>,
+    schedule: Res<GhostReleaseSchedule>,
+    time: Res<StepTime>,
+) {
+    house.elapsed += time.delta_seconds();
+    house.pellets_eaten += pellets.iter().count();
+    while !house.waiting().is_empty() && house.next_is_due(&schedule) {
+        let Some(ghost) = house.release_next_ghost() else {
+            break;
+        };
+        // It shouldn't come out halfway through a bob, or it would be floating (or sunk into the floor) for the rest of the game.
+        if let Ok((spawn_point, mut transform)) = ghosts.get_mut(ghost) {
+            transform.translation = spawn_point.0;
+        }
+        commands.entity(ghost).remove::<Penned>();
+    }
+}
+
+/// Penned ghosts stay where they started, bobbing up and down while they wait.
+pub fn bob_penned_ghosts(
+    mut ghosts: Query<(&SpawnPoint, &mut Transform, &mut Velocity), With<Penned>>,
+    time: Res<StepTime>,
+) {
+    let bob = (time.elapsed_seconds() * BOB_SPEED).sin() * BOB_HEIGHT;
+    for (spawn_point, mut transform, mut velocity) in ghosts.iter_mut() {
+        transform.translation = spawn_point.0 + Vec3::Y * bob;
+        *velocity = Velocity::zero();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionOutcome {
+    GhostEaten,
+    PlayerCaught,
+    /// The ghost has already been eaten, so it just goes past.
+    Nothing,
+}
+
+/// Whether a ghost at `ghost_position` has got to the player, which is when they are no more than `radius` apart.
+/// Only the distance across the ground counts, since the player and the ghosts aren't the same height (and penned ghosts bob up and down).
+pub fn is_caught(player_position: Vec3, ghost_position: Vec3, radius: f32) -> bool {
+    euclidean(
+        (player_position.x, player_position.z),
+        (ghost_position.x, ghost_position.z),
+    ) <= radius
+}
+
+/// Works out what happens when the player touches a ghost in this state.
+pub fn collision_outcome(ghost_state: &GhostState) -> CollisionOutcome {
+    match ghost_state {
+        // This is still the case on the frame where the timer runs out, since the collision is checked before the ghost goes back to normal.
+        // That way the player never gets caught by a ghost which was frightened when they touched it.
+        GhostState::Frightened { .. } => CollisionOutcome::GhostEaten,
+        GhostState::Normal => CollisionOutcome::PlayerCaught,
+        GhostState::Eaten { .. } => CollisionOutcome::Nothing,
+    }
+}
+
+/// Works out whether the player has eaten a ghost or been caught by one, leaving what happens next to `eat_ghosts` and `catch_player`.
+/// This only goes by how far apart they are (see `is_caught`), so it doesn't matter what the physics thinks (the physics is only there for the walls).
+#[allow(clippy::type_complexity)]
+pub fn ghost_reached_player(
+    player: Query<&Transform, With<Player>>,
+    // Ghosts waiting in the ghost house aren't in the game yet, so the player can go straight past them.
+    ghosts: Query<(Entity, &Transform, &GhostState), (Without<Player>, Without<Penned>)>,
+    mut ghosts_eaten: EventWriter<GhostEaten>,
+    mut player_caught: EventWriter<PlayerCaught>,
+    maze: Res<Maze>,
+    character_size: Res<CharacterSize>,
+) {
+    let Ok(player_transform) = player.get_single() else {
+        return;
+    };
+    // They are touching as soon as their edges meet.
+    let radius = character_size.player_radius(&maze) + character_size.ghost_radius(&maze);
+    let mut caught = false;
+    for (ghost_entity, ghost_transform, ghost_state) in ghosts.iter() {
+        if !is_caught(
+            player_transform.translation,
+            ghost_transform.translation,
+            radius,
+        ) {
+            continue;
+        }
+        match collision_outcome(ghost_state) {
+            CollisionOutcome::GhostEaten => ghosts_eaten.send(GhostEaten {
+                ghost: ghost_entity,
+                points: GHOST_POINTS,
+            }),
+            CollisionOutcome::PlayerCaught => caught = true,
+            CollisionOutcome::Nothing => {}
+        }
+    }
+    // Getting caught by two ghosts at once still only loses one life.
+    if caught {
+        player_caught.send(PlayerCaught);
+    }
+}
+
+fn tick_frightened(ghost_state: &mut GhostState, delta: Duration) {
+    let frozen = ghost_state.is_frozen();
+    if let GhostState::Frightened { freeze, timer } = ghost_state {
+        if frozen {
+            freeze.tick(delta);
+        } else if timer.tick(delta).finished() {
+            *ghost_state = GhostState::Normal;
+        }
+    }
+}
+
+pub fn update_frightened(mut ghosts: Query<&mut GhostState>, time: Res<StepTime>) {
+    for mut ghost_state in ghosts.iter_mut() {
+        tick_frightened(&mut ghost_state, time.delta());
+    }
+}
+
+/// Sends the ghost back to where it started once the player has eaten it.
+pub fn eat_ghosts(
+    mut eaten: EventReader<GhostEaten>,
+    mut ghosts: Query<(&mut GhostState, &SpawnPoint, &mut PathCache)>,
+) {
+    for eaten in eaten.iter() {
+        if let Ok((mut ghost_state, spawn_point, mut path_cache)) = ghosts.get_mut(eaten.ghost) {
+            // The ghost makes its own way back to where it started, and then carries on as normal.
+            *ghost_state = GhostState::Eaten {
+                home: spawn_point.0,
+            };
+            *path_cache = PathCache::default();
+        }
+    }
+}
+
+/// Makes every ghost frightened when the player eats a power pellet.
+pub fn frighten_ghosts(
+    mut power_pellets: EventReader<PowerPelletEaten>,
+    mut ghosts: Query<(&mut GhostState, &mut PathCache)>,
+    config: Res<FrightenedConfig>,
+    difficulty: Res<Difficulty>,
+    level_number: Res<LevelNumber>,
+) {
+    // Eating two at once is no different to eating one.
+    if power_pellets.iter().count() == 0 {
+        return;
+    }
+    let config = FrightenedConfig {
+        duration: config.duration
+            * difficulty.multipliers().frightened_duration
+            * level_number.multipliers().frightened_duration,
+        ..config.clone()
+    };
+    // Later on the ghosts aren't frightened at all, so they shouldn't stop and turn around either.
+    if config.duration <= 0.0 {
+        return;
+    }
+    for (mut ghost_state, mut path_cache) in ghosts.iter_mut() {
+        frighten(&mut ghost_state, &config);
+        // The ghosts should start running away straight away, rather than finishing the path they were on.
+        *path_cache = PathCache::default();
+    }
+}
+
+/// Picks the intersection a frightened ghost should run to next.
+/// On an intersection, this is whichever neighbouring intersection is furthest from the player; otherwise it is whichever end of the current path is further away.
+fn flee_target(
+    ghost_position: (f32, f32),
+    player_position: (f32, f32),
+    maze: &Maze,
+) -> Option<usize> {
+    // A ghost which has been knocked off the paths still has somewhere to run to, rather than just waiting there to be eaten.
+    let edge = maze.nearest_path(ghost_position)?;
+    let candidates = if edge.0 == edge.1 {
+        let intersection = &maze.intersections()[edge.0];
+        intersection
+            .all_paths()
+            .map(|path| path.end_index)
+            .collect::<Vec<_>>()
+    } else {
+        vec![edge.0, edge.1]
+    };
+    let distance_to_player =
+        |index: &usize| euclidean(maze.intersections()[*index].coordinates, player_position);
+    candidates
+        .into_iter()
+        .max_by(|a, b| distance_to_player(a).total_cmp(&distance_to_player(b)))
+}
+
+/// How long a ghost can go without working out its path again, for each unit it is away from the player.
+const RECOMPUTE_SECONDS_PER_UNIT: f32 = 0.02;
+/// Even the furthest away ghosts need to notice where the player has gone eventually.
+const MAX_RECOMPUTE_INTERVAL: f32 = 0.5;
+
+/// The edges which the ghost and its target were on when a path was worked out (each with the smaller index first).
+/// The path can only really change when one of these does.
+type PathKey = ((usize, usize), (usize, usize));
+
+/// `target_edge` is the edge the target is on, which the caller might already know (like the player's `current_path`).
+fn path_key(
+    ghost_position: (f32, f32),
+    target_edge: Option<(usize, usize)>,
+    maze: &Maze,
+) -> Option<PathKey> {
+    let sorted = |edge: (usize, usize)| (edge.0.min(edge.1), edge.0.max(edge.1));
+    Some((sorted(maze.edge_at(ghost_position)?), sorted(target_edge?)))
+}
+
+/// Ghosts don't need to work out their path every frame, so we remember it until the ghost or its target moves onto a different edge.
+/// The path is also worked out again every now and then anyway, since which end of the target's edge is closer can change as the target moves along it.
+/// That matters less for ghosts which are far away, so they wait longer.
+#[derive(Clone, Debug, Default, Component)]
+pub struct PathCache {
+    path: Vec<usize>,
+    key: Option<PathKey>,
+    /// When (in seconds since the game started) the path should be worked out again, even if the key hasn't changed.
+    next_recompute: f32,
+    /// The last intersection the ghost was on, and the one before that.
+    /// Ghosts aren't allowed to turn around, so we need to know which way they came from.
+    last_intersection: Option<usize>,
+    previous_intersection: Option<usize>,
+    /// The mode the ghost was in when the path was worked out.
+    /// Ghosts are allowed to turn around when it changes.
+    mode: Option<GhostMode>,
+    /// Where the ghost is trying to get to, and which edge that is on (frightened ghosts don't have anywhere).
+    target: Option<(f32, f32)>,
+    target_edge: Option<(usize, usize)>,
+}
+
+/// Limits how much path finding the ghosts can do each frame, so that lots of ghosts on a huge maze can't make the game stutter.
+/// Ghosts which don't get a turn keep following their old paths until the next frame.
+#[derive(Clone, Debug, Resource)]
+pub struct PathfindingBudget {
+    /// How many intersections can be expanded each frame (across all of the ghosts).
+    pub nodes_per_frame: usize,
+}
+
+impl Default for PathfindingBudget {
+    fn default() -> Self {
+        Self {
+            nodes_per_frame: 2000,
+        }
+    }
+}
+
+/// Everything we need to work out a new path for one ghost.
+struct PathRequest<'a> {
+    cache: &'a mut PathCache,
+    key: Option<PathKey>,
+    target: (f32, f32),
+    ghost_position: (f32, f32),
+    distance_to_player: f32,
+    /// The intersection which the ghost can't go straight back to.
+    came_from: Option<usize>,
+    mode: GhostMode,
+}
+
+/// Works out new paths for as many ghosts as the budget allows, starting with the ones which have been waiting the longest.
+/// Returns how many of them got new paths.
+fn recompute_paths(
+    mut requests: Vec<PathRequest>,
+    now: f32,
+    node_budget: usize,
+    maze: &Maze,
+) -> usize {
+    requests.sort_by(|a, b| a.cache.next_recompute.total_cmp(&b.cache.next_recompute));
+    let mut remaining_budget = node_budget;
+    let mut recomputed = 0;
+    for request in requests {
+        if remaining_budget == 0 {
+            break;
+        }
+        let (path, expansions) = find_shortest_path(
+            request.target,
+            request.ghost_position,
+            maze,
+            straight_line_heuristic(request.target, maze),
+            request.came_from,
+        );
+        // This happens far too often to be on by default, but `RUST_LOG=pacman3d::ghost=trace` shows it.
+        bevy::log::trace!(
+            "Ghost at {:?} heading for {:?} in {:?} mode: {:?} ({:?} long, {} expansions)",
+            request.ghost_position,
+            request.target,
+            request.mode,
+            path,
+            maze.route_length(&path),
+            expansions
+        );
+        request
+            .cache
+            .update(path, request.key, now, request.distance_to_player);
+        request.cache.mode = Some(request.mode);
+        // The last search is allowed to go over, since there is no use in stopping half way through.
+        remaining_budget = remaining_budget.saturating_sub(expansions);
+        recomputed += 1;
+    }
+    recomputed
+}
+
+impl PathCache {
+    /// The intersections the ghost is currently planning to go through, in order.
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    /// Where the ghost is actually trying to get to, which is nowhere while it is frightened.
+    pub fn target(&self) -> Option<(f32, f32)> {
+        self.target
+    }
+
+    /// Where to go once there aren't any intersections left on the path.
+    /// That is straight for the target if it is on the same path as the ghost (or one coming out of the intersection the ghost is on).
+    /// Anywhere else would mean going through the walls, which happens when the target has moved and the ghost is still waiting for its turn to find a new path (see `PathfindingBudget`).
+    /// Until then it sticks with the path it was on, and waits at whichever end of it is closest to the target.
+    fn heading_for(&self, ghost_position: (f32, f32), maze: &Maze) -> Option<(f32, f32)> {
+        let target = self.target()?;
+        let ghost_edge = find_edge(ghost_position, maze)?;
+        let joins_target_edge = |index: usize| {
+            self.target_edge
+                .is_some_and(|(a, b)| a == index || b == index)
+        };
+        if Some(ghost_edge) == self.target_edge
+            || (ghost_edge.0 == ghost_edge.1 && joins_target_edge(ghost_edge.0))
+        {
+            return Some(target);
+        }
+        [ghost_edge.0, ghost_edge.1]
+            .into_iter()
+            .map(|index| maze.intersections()[index].coordinates)
+            .min_by(|a, b| euclidean(*a, target).total_cmp(&euclidean(*b, target)))
+    }
+
+    fn needs_recompute(&self, key: Option<PathKey>, now: f32) -> bool {
+        // An empty path means we are on the same path as the player, and that changes far too quickly to rely on.
+        // If we don't have a key then someone is off the paths, so the best we can do is go by the time.
+        self.path.is_empty() || key.is_none() || key != self.key || now >= self.next_recompute
+    }
+
+    fn update(
+        &mut self,
+        path: Vec<usize>,
+        key: Option<PathKey>,
+        now: f32,
+        distance_to_player: f32,
+    ) {
+        self.path = path;
+        self.key = key;
+        self.next_recompute =
+            now + (distance_to_player * RECOMPUTE_SECONDS_PER_UNIT).min(MAX_RECOMPUTE_INTERVAL);
+    }
+
+    /// Drops the intersection at the front of the path once we have reached it, and keeps track of where the ghost has been.
+    fn advance(&mut self, ghost_position: (f32, f32), maze: &Maze) {
+        let edge = maze.edge_at(ghost_position);
+        if let Some(next_index) = self.path.first().copied() {
+            if edge == Some((next_index, next_index)) {
+                self.path.remove(0);
+            }
+        }
+        if let Some((a, b)) = edge {
+            if a == b && self.last_intersection != Some(a) {
+                self.previous_intersection = self.last_intersection;
+                self.last_intersection = Some(a);
+            }
+        }
+    }
+
+    /// The intersection which going back to would mean turning around.
+    /// On an intersection that is the one before it, and between intersections it is the one we have just left.
+    fn came_from(&self, ghost_position: (f32, f32), maze: &Maze) -> Option<usize> {
+        match maze.edge_at(ghost_position)? {
+            (a, b) if a == b => self.previous_intersection,
+            (a, b) => self
+                .last_intersection
+                .filter(|index| *index == a || *index == b),
+        }
+    }
+}
+
+/// How far apart the ghosts have to start.
+/// They are nearly as wide as the paths, so any closer than this and their colliders would overlap (which makes the physics throw them apart).
+pub fn min_spawn_spacing(maze: &Maze) -> f32 {
+    maze.half_path_width() * 3.0
+}
+/// How many steps of `min_spacing` we will go looking for a free spot before giving up.
+const MAX_SPAWN_SEARCH_STEPS: usize = 20;
+
+/// Moves the requested spawn points apart so that no two ghosts start on top of each other.
+/// Each one is snapped onto the maze first (using the nearest intersection if it isn't on a path), then slid along the paths until it is far enough away from the others.
+pub fn spread_spawn_points(
+    requested: &[(f32, f32)],
+    maze: &Maze,
+    min_spacing: f32,
+) -> Vec<(f32, f32)> {
+    let mut spawn_points: Vec<(f32, f32)> = Vec::new();
+    for position in requested {
+        let position = if maze.on_path(*position) {
+            *position
+        } else {
+            match maze.nearest_intersection(*position) {
+                Some(index) => maze.intersections()[index].coordinates,
+                None => *position,
+            }
+        };
+        let far_enough = |candidate: (f32, f32)| {
+            spawn_points
+                .iter()
+                .all(|other| euclidean(*other, candidate) >= min_spacing)
+        };
+        let spawn_point = (0..=MAX_SPAWN_SEARCH_STEPS)
+            .flat_map(|step| {
+                let offset = step as f32 * min_spacing;
+                [
+                    (position.0, position.1 - offset),
+                    (position.0, position.1 + offset),
+                    (position.0 - offset, position.1),
+                    (position.0 + offset, position.1),
+                ]
+            })
+            .find(|candidate| maze.on_path(*candidate) && far_enough(*candidate))
+            .unwrap_or_else(|| {
+                bevy::log::warn!("Couldn't find room to spawn a ghost near {:?}", position);
+                position
+            });
+        spawn_points.push(spawn_point);
+    }
+    spawn_points
+}
+
+/// How tall a ghost is compared to how wide it is.
+const GHOST_HEIGHT_RATIO: f32 = 1.5;
+
+/// How tall a ghost with this radius is, from the bottom of it to the top.
+pub fn ghost_height(radius: f32) -> f32 {
+    radius * 2.0 * GHOST_HEIGHT_RATIO
+}
 
 pub fn create_ghost(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<bevy::prelude::Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
     initial_position: Vec3,
-    ghost_type: GhostType,
+    color: Color,
+    personality: GhostPersonality,
+    radius: f32,
 ) -> Entity {
-    let mut game_object = GameObject::default();
-    game_object.add_mesh(Mesh {
-        color: match ghost_type {
-            GhostType::Blinky => Color::RED,
-            GhostType::Pinky => Color::PINK,
-            GhostType::Inky => Color::CYAN,
-            GhostType::Clyde => Color::ORANGE,
-        },
-        position: Vec3::default(),
-        rotation: Quat::default(),
-        shape: Shape::Cylinder {
-            radius: HALF_PATH_WIDTH,
-            height: HALF_PATH_WIDTH * 2.0,
+    let (ghost, mut entity_commands) = GameObject::with_mesh(Mesh {
+        color,
+        // Being rounded means the ghosts slide past the corners of walls, rather than catching on them like cylinders do.
+        // They're a bit taller than they are wide, so that there is a straight bit in the middle and they don't just look like balls.
+        shape: Shape::Capsule {
+            radius,
+            height: ghost_height(radius),
         },
+        ..Default::default()
+    })
+    .spawn_with_id(
+        Transform::from_translation(initial_position),
+        RigidBody::KinematicVelocityBased,
+        commands,
+        meshes,
+        materials,
+    );
+    entity_commands
+        .insert(Ghost::new(personality))
+        .insert(GhostState::default())
+        .insert(SpawnPoint(initial_position))
+        .insert(PathCache::default())
+        .insert(CurrentIntersection::default())
+        .insert(LockedAxes::ROTATION_LOCKED)
+        // Whether a ghost has got the player is down to how far apart they are (see `is_caught`), so the ghosts go straight through the player rather than pushing them around.
+        // Being kinematic, they never hit the walls either, so they don't need to touch anything.
+        .insert(CollisionGroups::new(Group::NONE, Group::NONE))
+        .insert(Interpolated::new(initial_position));
+    ghost
+}
+
+/// Spawns a ghost for each of these, with its own spawn point, colour and personality.
+/// The radius should come from `CharacterSize::ghost_radius`, so that the ghosts fit between the walls.
+pub fn spawn_ghosts(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<bevy::prelude::Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    ghosts: &[(Vec3, Color, GhostPersonality)],
+    radius: f32,
+) -> Vec<Entity> {
+    ghosts
+        .iter()
+        .map(|(spawn_point, color, personality)| {
+            create_ghost(
+                commands,
+                meshes,
+                materials,
+                *spawn_point,
+                *color,
+                personality.clone(),
+                radius,
+            )
+        })
+        .collect()
+}
+
+/// Mazes loaded from files can have all sorts of rubbish in them, including coordinates which aren't numbers at all.
+fn is_finite(point: (f32, f32)) -> bool {
+    point.0.is_finite() && point.1.is_finite()
+}
+
+/// The A* heuristic: how far it is to the target in a straight line.
+/// This finds a path just as short as plain Dijkstra, but usually has to look at a lot fewer intersections to do it.
+/// The straight line distance can never be more than the distance along the paths, so we can't miss the shortest path because of it.
+fn straight_line_heuristic(target: (f32, f32), maze: &Maze) -> impl Fn((f32, f32)) -> f32 {
+    // Tunnels can make the distance along the paths shorter than the straight line, so mazes with them have to fall back to Dijkstra.
+    let use_heuristic = !maze.has_wraps();
+    move |coordinates| {
+        if use_heuristic {
+            euclidean(coordinates, target)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// How far from an intersection something can be and still count as being on it, even if it has strayed off the paths.
+/// The ghosts can easily end up a little way off the middle of the paths, especially when they cut across a corner.
+fn off_path_tolerance(maze: &Maze) -> f32 {
+    maze.half_path_width() * 2.0
+}
+
+/// Like `Maze::edge_at`, but if the position is just off the paths it counts as being on the nearest intersection instead.
+fn find_edge(position: (f32, f32), maze: &Maze) -> Option<(usize, usize)> {
+    maze.edge_at(position).or_else(|| {
+        let nearest = maze.nearest_intersection(position)?;
+        (euclidean(maze.intersections()[nearest].coordinates, position) <= off_path_tolerance(maze))
+            .then_some((nearest, nearest))
+    })
+}
+
+/// Finds the intersections the ghost has to go through to get to the player, along with how many intersections had to be expanded to find them.
+/// The number of expansions is how we keep track of how much work the ghosts are doing.
+/// The heuristic guesses how far it is from the intersection at the given coordinates to the player (or is always zero for plain Dijkstra).
+/// Like in the arcade game, ghosts can't turn around, so the path won't go straight back to `came_from` unless there is no other way (like at a dead end).
+pub fn find_shortest_path(
+    player_position: (f32, f32),
+    current_ghost_position: (f32, f32),
+    maze: &Maze,
+    heuristic: impl Fn((f32, f32)) -> f32,
+    came_from: Option<usize>,
+) -> (Vec<usize>, usize) {
+    let (path, expansions) = search_path(
+        player_position,
+        current_ghost_position,
+        maze,
+        &heuristic,
+        came_from,
+    );
+    match path {
+        Some(path) => (path, expansions),
+        None if came_from.is_some() => {
+            let (path, more_expansions) = search_path(
+                player_position,
+                current_ghost_position,
+                maze,
+                &heuristic,
+                None,
+            );
+            (path.unwrap_or_default(), expansions + more_expansions)
+        }
+        // If there is no way at all then there is nothing sensible to do, so we just try again next frame (which is what an empty path tells the cache to do).
+        None => (vec![], expansions),
+    }
+}
+
+/// Does the actual searching for `find_shortest_path`, giving `None` if there isn't a way to the player.
+fn search_path(
+    player_position: (f32, f32),
+    current_ghost_position: (f32, f32),
+    maze: &Maze,
+    heuristic: &impl Fn((f32, f32)) -> f32,
+    came_from: Option<usize>,
+) -> (Option<Vec<usize>>, usize) {
+    if !is_finite(player_position) || !is_finite(current_ghost_position) {
+        return (None, 0);
+    }
+    let (Some(player_path), Some(ghost_path)) = (
+        find_edge(player_position, maze),
+        find_edge(current_ghost_position, maze),
+    ) else {
+        return (None, 0);
+    };
+    if player_path == ghost_path {
+        // We are already on the right path, so we don't actually have to do anythign but chase the player down by moving in their direction.
+        // `edge_at` always gives the ends of a path the same way round, whereever along it we are, but the two sides of a tunnel come out the opposite way round to each other.
+        // So the same edge really does mean there's nothing but a straight open path between us, and never a tunnel mouth at either side of the maze.
+        // This is the one time a ghost can still turn around, but only until the player gets off the path.
+        return (Some(vec![]), 1);
+    }
+    let on_intersection = ghost_path.0 == ghost_path.1;
+    // Whether going to this intersection first would mean turning around.
+    let is_reverse = |index: usize| {
+        came_from.is_some_and(|came_from| {
+            if on_intersection {
+                // Heading straight to the player would take us along the path we came in on.
+                index == ghost_path.0
+                    && (player_path == (index, came_from) || player_path == (came_from, index))
+            } else {
+                index == came_from
+            }
+        })
+    };
+    // There is an edge case where the player is standing exactly on an intersection and we are on a path joining to it.
+    // The code below, in fact, doesn't check if the ghost's current path is joining to the player's.
+    // As this is due Tomorrow, I won't actually fix the code, but I will put in this special case to make it work.
+    if (ghost_path.0 == player_path.0 || ghost_path.0 == player_path.1) && !is_reverse(ghost_path.0)
+    {
+        return (Some(vec![ghost_path.0]), 1);
+    } else if (ghost_path.1 == player_path.0 || ghost_path.1 == player_path.1)
+        && !is_reverse(ghost_path.1)
+    {
+        return (Some(vec![ghost_path.1]), 1);
+    }
+    // We start from both ends of the ghost's path at once, and finish at whichever end of the player's path gets us to them soonest.
+    let starts = [ghost_path.0, ghost_path.1]
+        .into_iter()
+        .filter(|index| on_intersection || !is_reverse(*index))
+        .map(|index| {
+            let coordinates = maze.intersections()[index].coordinates;
+            (index, euclidean(coordinates, current_ghost_position))
+        })
+        .collect::<Vec<_>>();
+    let goals = [player_path.0, player_path.1].map(|index| {
+        let coordinates = maze.intersections()[index].coordinates;
+        (index, euclidean(coordinates, player_position))
     });
-    game_object
-        .spawn(
-            Transform::from_translation(initial_position),
-            RigidBody::KinematicVelocityBased,
-            commands,
-            meshes,
-            materials,
+    let (shortest_path, expansions) = maze.search(&starts, &goals, heuristic, came_from);
+    // The player is on a bit of the maze which we can't get to from here (at least not without turning around).
+    let Some(shortest_path) = shortest_path else {
+        return (None, expansions);
+    };
+    // If the ghost is already on an intersection, then we must exclude it from the path.
+    // This is because the path finding needs to find the paths which the ghost must reach, not the ones it is already on.
+    if on_intersection {
+        (Some(shortest_path[1..].to_vec()), expansions)
+    } else {
+        (Some(shortest_path), expansions)
+    }
+}
+
+/// The length of a "tile" in the original game, which is what the ghosts' targeting is measured in.
+/// A path is one tile wide, so wider paths mean the ghosts aim further ahead.
+fn tile(maze: &Maze) -> f32 {
+    maze.half_path_width() * 2.0
+}
+const PINKY_TILES_AHEAD: f32 = 4.0;
+const INKY_TILES_AHEAD: f32 = 2.0;
+/// Clyde gives up on chasing the player and goes back to his corner when he gets this many tiles away.
+const CLYDE_RETREAT_TILES: f32 = 8.0;
+
+/// Finds the corner of the maze which belongs to each ghost, like in the arcade game (see `Maze::corners`).
+pub fn home_corner(personality: &GhostPersonality, maze: &Maze) -> (f32, f32) {
+    let corner = match personality {
+        GhostPersonality::Pinky => 0,
+        GhostPersonality::Blinky => 1,
+        GhostPersonality::Clyde => 2,
+        GhostPersonality::Inky => 3,
+    };
+    maze.corners()
+        .map(|corners| maze.intersections()[corners[corner]].coordinates)
+        .unwrap_or_default()
+}
+
+/// Moves a target onto the maze if it isn't already on a path, since we can't find a path to somewhere off the maze.
+fn snap_to_maze(target: (f32, f32), maze: &Maze) -> (f32, f32) {
+    if maze.on_path(target) {
+        return target;
+    }
+    match maze.nearest_intersection(target) {
+        Some(index) => maze.intersections()[index].coordinates,
+        None => target,
+    }
+}
+
+/// Finds the position which this ghost should be heading for.
+/// Inky needs to know where Blinky is; if there isn't a Blinky then he just chases the player.
+fn ghost_target(
+    personality: &GhostPersonality,
+    player_position: (f32, f32),
+    player_direction: Direction,
+    ghost_position: (f32, f32),
+    blinky_position: Option<(f32, f32)>,
+    maze: &Maze,
+) -> (f32, f32) {
+    let ahead_of_player = |tiles: f32| {
+        (
+            player_position.0 + player_direction.x_velocity() * tiles * tile(maze),
+            player_position.1 + player_direction.z_velocity() * tiles * tile(maze),
         )
-        .insert(Ghost(ghost_type))
-        .insert(LockedAxes::ROTATION_LOCKED)
-        .id()
+    };
+    let target = match personality {
+        GhostPersonality::Blinky => player_position,
+        GhostPersonality::Pinky => ahead_of_player(PINKY_TILES_AHEAD),
+        GhostPersonality::Inky => match blinky_position {
+            // Take the line from Blinky to just ahead of the player, and keep going for the same distance again.
+            Some(blinky_position) => {
+                let pivot = ahead_of_player(INKY_TILES_AHEAD);
+                (
+                    pivot.0 * 2.0 - blinky_position.0,
+                    pivot.1 * 2.0 - blinky_position.1,
+                )
+            }
+            None => player_position,
+        },
+        GhostPersonality::Clyde => {
+            if euclidean(ghost_position, player_position) > CLYDE_RETREAT_TILES * tile(maze) {
+                player_position
+            } else {
+                home_corner(personality, maze)
+            }
+        }
+    };
+    snap_to_maze(target, maze)
+}
+
+/// The velocity which gets us to something `offset` away, going at `speed`.
+/// If we would get there within the next `delta` seconds then we slow down so that we end up exactly on it, rather than going past and having to turn around (which made the ghosts jitter back and forth at every corner).
+fn approach_velocity(offset: Vec3, speed: f32, delta: f32) -> Vec3 {
+    if delta > 0.0 && offset.length() < speed * delta {
+        offset / delta
+    } else {
+        offset.normalize_or_zero() * speed
+    }
+}
+
+/// Works out which way (and how fast) a ghost should go to follow its path, or to get to `target` once there is nothing left on the path (see `PathCache::heading_for`).
+/// This doesn't touch any of the ECS, so `ghost_movement` just has to hand it the positions and use the velocity it gives back.
+fn steer(
+    ghost_position: (f32, f32),
+    path: &[usize],
+    target: Option<(f32, f32)>,
+    speed: f32,
+    delta: f32,
+    maze: &Maze,
+) -> Vec3 {
+    let Some(&next_index) = path.first() else {
+        // Just head straight for the target, since there is nothing in the way (and frightened ghosts without anywhere to go just wait).
+        return match target {
+            Some(target) => approach_velocity(
+                Vec3::new(
+                    target.0 - ghost_position.0,
+                    0.0,
+                    target.1 - ghost_position.1,
+                ),
+                speed,
+                delta,
+            ),
+            None => Vec3::ZERO,
+        };
+    };
+    let next_intersection = &maze.intersections()[next_index];
+    // Going through a tunnel means heading out of the side of the maze, rather than straight across it to the other end.
+    let tunnel_direction = maze.edge_at(ghost_position).and_then(|(a, b)| {
+        let from = if b == next_index { a } else { b };
+        maze.wrap_direction(from, next_index)
+    });
+    match tunnel_direction {
+        // The other end of the tunnel is a long way away, so there is no chance of overshooting it.
+        Some(direction) => Vec3::new(direction.0, 0.0, direction.1) * speed,
+        None => approach_velocity(
+            Vec3::new(
+                next_intersection.coordinates.0 - ghost_position.0,
+                0.0,
+                next_intersection.coordinates.1 - ghost_position.1,
+            ),
+            speed,
+            delta,
+        ),
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn ghost_movement(
+    player: Query<(&Transform, &Player)>,
+    mut ghosts: Query<
+        (
+            &Ghost,
+            &mut GhostState,
+            &mut Transform,
+            &mut Velocity,
+            &mut PathCache,
+        ),
+        // Penned ghosts wait where they are until they are let out.
+        (Without<Player>, Without<Penned>),
+    >,
+    maze: Res<Maze>,
+    time: Res<StepTime>,
+    budget: Res<PathfindingBudget>,
+    mode_timer: Res<GhostModeTimer>,
+    difficulty: Res<Difficulty>,
+    level_number: Res<LevelNumber>,
+) {
+    // The player doesn't exist while we are waiting to restart after they die.
+    let Ok((player_transform, player)) = player.get_single() else {
+        return;
+    };
+    let player_position = (
+        player_transform.translation.x,
+        player_transform.translation.z,
+    );
+    let now = time.elapsed_seconds();
+    let delta = time.delta_seconds();
+    // Working out the paths has to be done one ghost at a time, so that they can share the budget.
+    let mut all_ghosts = ghosts.iter_mut().collect::<Vec<_>>();
+    let blinky_position = all_ghosts
+        .iter()
+        .find(|(ghost, ..)| ghost.personality == GhostPersonality::Blinky)
+        .map(|(_, _, transform, ..)| (transform.translation.x, transform.translation.z));
+    let mut requests = Vec::new();
+    for (ghost, ghost_state, ghost_transform, _, path_cache) in all_ghosts.iter_mut() {
+        if let Some(wrapped) =
+            maze.wrap_position((ghost_transform.translation.x, ghost_transform.translation.z))
+        {
+            ghost_transform.translation.x = wrapped.0;
+            ghost_transform.translation.z = wrapped.1;
+        }
+        let ghost_position = (ghost_transform.translation.x, ghost_transform.translation.z);
+        path_cache.advance(ghost_position, &maze);
+        if let GhostState::Frightened { .. } = **ghost_state {
+            // Frightened ghosts just run away one intersection at a time, so there is no path finding to do.
+            if path_cache.path.is_empty() {
+                if let Some(flee_index) = flee_target(ghost_position, player_position, &maze) {
+                    path_cache.path = vec![flee_index];
+                }
+                // Without a key, the ghost will work out a proper path as soon as it stops being frightened.
+                path_cache.key = None;
+            }
+            path_cache.target = None;
+            path_cache.target_edge = None;
+            continue;
+        }
+        if let GhostState::Eaten { home } = **ghost_state {
+            if euclidean(ghost_position, (home.x, home.z)) < maze.half_path_width() {
+                // Home at last, so the ghost can start over (including turning whichever way it likes).
+                **ghost_state = GhostState::Normal;
+                **path_cache = PathCache::default();
+            }
+        }
+        let mode = mode_timer.mode();
+        let target = match (&**ghost_state, mode) {
+            (GhostState::Eaten { home }, _) => (home.x, home.z),
+            (_, GhostMode::Scatter) => home_corner(&ghost.personality, &maze),
+            (_, GhostMode::Chase) => ghost_target(
+                &ghost.personality,
+                player_position,
+                player.current_direction,
+                ghost_position,
+                blinky_position,
+                &maze,
+            ),
+        };
+        // When the ghost is going straight for the player, `player_movement` has already worked out which path they are on.
+        let target_edge = player
+            .current_path()
+            .filter(|_| target == player_position)
+            .or_else(|| maze.edge_at(target));
+        path_cache.target = Some(target);
+        path_cache.target_edge = target_edge;
+        let key = path_key(ghost_position, target_edge, &maze);
+        // When the mode changes, the ghosts get a chance to turn around (which is the only time they can).
+        let mode_changed = path_cache.mode.is_some_and(|path_mode| path_mode != mode);
+        if mode_changed || path_cache.needs_recompute(key, now) {
+            let came_from = if mode_changed {
+                None
+            } else {
+                path_cache.came_from(ghost_position, &maze)
+            };
+            requests.push(PathRequest {
+                cache: path_cache,
+                key,
+                target,
+                ghost_position,
+                distance_to_player: player_transform
+                    .translation
+                    .distance(ghost_transform.translation),
+                came_from,
+                mode,
+            });
+        }
+    }
+    recompute_paths(requests, now, budget.nodes_per_frame, &maze);
+    // Following the paths doesn't depend on any of the other ghosts, though.
+    ghosts.par_iter_mut().for_each_mut(
+        |(ghost, ghost_state, ghost_transform, mut ghost_velocity, path_cache)| {
+            // Eaten ghosts rush back home.
+            let speed = match *ghost_state {
+                GhostState::Eaten { .. } => ghost.current_speed() * 2.0,
+                _ => ghost.current_speed(),
+            } * difficulty.multipliers().ghost_speed
+                * level_number.multipliers().ghost_speed;
+            let ghost_position = (ghost_transform.translation.x, ghost_transform.translation.z);
+            ghost_velocity.linvel = if ghost_state.is_frozen() {
+                Vec3::ZERO
+            } else {
+                steer(
+                    ghost_position,
+                    &path_cache.path,
+                    path_cache.heading_for(ghost_position, &maze),
+                    speed,
+                    delta,
+                    &maze,
+                )
+            };
+        },
+    );
 }
 
-/// Finds the indices of the two intersections which this position is between.
-/// If it is slightly off the path (i.e. within the width of the path), it will round to the nearest path.
-fn find_path(position: (f32, f32), maze: &Maze) -> Option<(usize, usize)> {
-    fn within_range(a: f32, b: f32) -> bool {
-        (a - b).abs() < HALF_PATH_WIDTH
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, Events, IntoSystemConfigs, Update};
+
+    use super::*;
+    use crate::{index_of, maze::DEFAULT_HALF_PATH_WIDTH};
+
+    #[test]
+    fn penned_ghosts_cant_catch_the_player() {
+        let mut app = App::new();
+        app.add_event::<GhostEaten>()
+            .add_event::<PlayerCaught>()
+            .insert_resource(crate::sample_maze())
+            .init_resource::<CharacterSize>()
+            .add_systems(Update, ghost_reached_player);
+        // Right in the middle of one of the corridors, where the ghosts wait in `Level::default`.
+        let ghost = app
+            .world
+            .spawn((
+                GhostState::Normal,
+                Transform::from_xyz(5.0, 1.0, 20.0),
+                Penned,
+            ))
+            .id();
+        let player = app
+            .world
+            .spawn((Player::default(), Transform::default()))
+            .id();
+        let caught_walking_past = |app: &mut App| {
+            (0..=20).any(|step| {
+                app.world.get_mut::<Transform>(player).unwrap().translation =
+                    Vec3::new(step as f32 * 0.5, 1.0, 20.0);
+                app.update();
+                !app.world.resource::<Events<PlayerCaught>>().is_empty()
+            })
+        };
+        assert!(!caught_walking_past(&mut app));
+        // Once it is let out, it's as dangerous as any other ghost.
+        app.world.entity_mut(ghost).remove::<Penned>();
+        assert!(caught_walking_past(&mut app));
+    }
+
+    #[test]
+    fn blinky_speeds_up_near_the_end() {
+        assert_eq!(speed_multiplier(&GhostPersonality::Blinky, 200), 1.0);
+        assert_eq!(speed_multiplier(&GhostPersonality::Blinky, 21), 1.0);
+        let first_stage = speed_multiplier(&GhostPersonality::Blinky, 20);
+        let second_stage = speed_multiplier(&GhostPersonality::Blinky, 10);
+        assert!(first_stage > 1.0);
+        assert!(second_stage > first_stage);
+        assert_eq!(speed_multiplier(&GhostPersonality::Blinky, 0), second_stage);
+        // The others don't.
+        assert_eq!(speed_multiplier(&GhostPersonality::Pinky, 0), 1.0);
+        assert_eq!(speed_multiplier(&GhostPersonality::Clyde, 10), 1.0);
+
+        let mut app = App::new();
+        app.add_systems(Update, cruise_elroy);
+        // A ghost which has been given its own speed keeps it, with the speed up on top.
+        let blinky = app
+            .world
+            .spawn(Ghost {
+                speed: 1.5,
+                ..Ghost::new(GhostPersonality::Blinky)
+            })
+            .id();
+        let inky = app.world.spawn(Ghost::new(GhostPersonality::Inky)).id();
+        let pellets = (0..25)
+            .map(|_| app.world.spawn(Pellet).id())
+            .collect::<Vec<_>>();
+        app.update();
+        assert_eq!(app.world.get::<Ghost>(blinky).unwrap().current_speed(), 1.5);
+        for pellet in &pellets[..15] {
+            app.world.despawn(*pellet);
+        }
+        app.update();
+        let blinky = app.world.get::<Ghost>(blinky).unwrap();
+        assert_eq!(blinky.speed, 1.5);
+        assert_eq!(blinky.current_speed(), 1.5 * second_stage);
+        assert_eq!(
+            app.world.get::<Ghost>(inky).unwrap().current_speed(),
+            GHOST_SPEED
+        );
+    }
+
+    /// Plain Dijkstra, which is allowed to turn around, as most of these tests want.
+    fn dijkstra(
+        player_position: (f32, f32),
+        ghost_position: (f32, f32),
+        maze: &Maze,
+    ) -> Vec<usize> {
+        find_shortest_path(player_position, ghost_position, maze, |_| 0.0, None).0
+    }
+
+    /// The same, but with the heuristic the ghosts actually use.
+    fn astar(player_position: (f32, f32), ghost_position: (f32, f32), maze: &Maze) -> Vec<usize> {
+        find_shortest_path(
+            player_position,
+            ghost_position,
+            maze,
+            straight_line_heuristic(player_position, maze),
+            None,
+        )
+        .0
+    }
+
+    #[test]
+    fn test_find_shortest_path() {
+        let maze = Maze::new(&[
+            ((1.0, 0.0), (-1.0, 0.0)),
+            ((0.0, 1.0), (0.0, -1.0)),
+            ((1.0, 1.0), (1.0, -1.0)),
+            ((1.0, 1.0), (-1.0, 1.0)),
+            ((-1.0, 1.0), (-1.0, -1.0)),
+            ((-1.0, -1.0), (1.0, -1.0)),
+        ]);
+        let shortest_path = dijkstra((0.0, 0.0), (0.0, 0.0), &maze);
+        assert!(shortest_path.is_empty());
+        let shortest_path = dijkstra((0.0, 0.0), (-1.0, 0.5), &maze);
+        // It's a bit nasty, but I don't want to have to worry about what indices the intersections are at.
+        assert_eq!(
+            maze.intersections()[shortest_path[0]].coordinates,
+            (-1.0, 0.0)
+        );
+        assert_eq!(
+            maze.intersections()[shortest_path[1]].coordinates,
+            (0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn shortest_paths_around_the_sample_maze() {
+        let maze = crate::sample_maze();
+        let path = |player_position, ghost_position| {
+            dijkstra(player_position, ghost_position, &maze)
+                .into_iter()
+                .map(|index| maze.intersections()[index].coordinates)
+                .collect::<Vec<_>>()
+        };
+        // Every place where the lines of the maze cross is an intersection, even if the path just carries straight on through it.
+        // These start from Blinky's spawn point, in the corner of the bottom right loop.
+        let blinky = (5.0, 20.0);
+        // Straight up the middle of the maze.
+        assert_eq!(path((0.0, 7.5), blinky), vec![(5.0, 10.0), (0.0, 10.0)]);
+        assert_eq!(
+            path((0.0, -2.5), blinky),
+            vec![(5.0, 10.0), (0.0, 10.0), (0.0, 5.0), (0.0, 0.0)]
+        );
+        // Into the bottom left loop, which is quicker to get into from the right hand side of it.
+        assert_eq!(
+            path((-12.5, 20.0), blinky),
+            vec![
+                (5.0, 10.0),
+                (0.0, 10.0),
+                (-5.0, 10.0),
+                (-5.0, 20.0),
+                (-10.0, 20.0)
+            ]
+        );
+        // All the way from Pinky's spawn point to the top right loop.
+        assert_eq!(
+            path((12.5, -20.0), (-5.0, 20.0)),
+            vec![
+                (-5.0, 10.0),
+                (0.0, 10.0),
+                (0.0, 5.0),
+                (0.0, 0.0),
+                (0.0, -5.0),
+                (0.0, -10.0),
+                (5.0, -10.0),
+                (5.0, -20.0),
+                (10.0, -20.0)
+            ]
+        );
+        // Along the long path on the left of the bottom left loop, there is nothing in the way.
+        assert_eq!(path((-15.0, 18.0), (-15.0, 12.0)), vec![]);
+    }
+
+    #[test]
+    fn ghosts_off_the_paths_dont_panic() {
+        let maze = Maze::new(&[((-5.0, 0.0), (5.0, 0.0)), ((0.0, 0.0), (0.0, 5.0))]);
+        // Just off the corner of the crossing, where neither path quite reaches.
+        let path = dijkstra((-4.0, 0.0), (0.6, 0.6), &maze);
+        assert_eq!(maze.intersections()[path[0]].coordinates, (0.0, 0.0));
+        // Too far away to count as being anywhere in particular.
+        assert!(dijkstra((-4.0, 0.0), (3.0, 3.0), &maze).is_empty());
+        assert!(dijkstra((3.0, 3.0), (-4.0, 0.0), &maze).is_empty());
+        // The player is on a loop which can't be reached from the ghost's.
+        let maze = Maze::new(&[((0.0, 0.0), (5.0, 0.0)), ((0.0, 10.0), (5.0, 10.0))]);
+        assert!(dijkstra((2.0, 10.0), (2.0, 0.0), &maze).is_empty());
     }
-    // We need to ensure that if we are on an intersection, we prioritise that over being on a path.
-    // The easiest way I can think of is an initial pass which checks if we are on any of the intersections.
-    for (intersection_index, intersection) in maze.intersections().iter().enumerate() {
-        if within_range(intersection.coordinates.0, position.0)
-            && within_range(intersection.coordinates.1, position.1)
-        {
-            return Some((intersection_index, intersection_index));
+
+    #[test]
+    fn ghosts_slow_down_to_stop_on_intersections() {
+        // A long way off, we go at full speed.
+        assert_eq!(
+            approach_velocity(Vec3::new(10.0, 0.0, 0.0), 2.0, 0.1),
+            Vec3::new(2.0, 0.0, 0.0)
+        );
+        // But if we would go past it this frame, we only go as far as it.
+        let velocity = approach_velocity(Vec3::new(0.0, 0.0, -0.1), 2.0, 0.1);
+        assert!(velocity.abs_diff_eq(Vec3::new(0.0, 0.0, -1.0), 0.0001));
+        assert_eq!(approach_velocity(Vec3::ZERO, 2.0, 0.1), Vec3::ZERO);
+        // Without a frame time to go by, there isn't anything to clamp to.
+        assert_eq!(
+            approach_velocity(Vec3::new(0.1, 0.0, 0.0), 2.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn broken_coordinates_dont_panic() {
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (10.0, 0.0)),
+            ((10.0, 0.0), (10.0, 10.0)),
+            ((10.0, 0.0), (f32::NAN, 0.0)),
+            ((0.0, 0.0), (0.0, f32::INFINITY)),
+        ]);
+        // The broken bits of the maze are left out, but the rest of it still works.
+        assert_eq!(
+            dijkstra((10.0, 5.0), (2.0, 0.0), &maze),
+            vec![index_of(&maze, (10.0, 0.0))]
+        );
+        assert_eq!(
+            astar((10.0, 5.0), (2.0, 0.0), &maze),
+            vec![index_of(&maze, (10.0, 0.0))]
+        );
+        for position in [(f32::NAN, 0.0), (0.0, f32::INFINITY), (f32::NAN, f32::NAN)] {
+            assert_eq!(dijkstra(position, (2.0, 0.0), &maze), Vec::<usize>::new());
+            assert_eq!(dijkstra((10.0, 5.0), position, &maze), Vec::<usize>::new());
         }
     }
 
-    for (intersection_index, intersection) in maze.intersections().iter().enumerate() {
-        if within_range(intersection.coordinates.0, position.0) {
-            if intersection.coordinates.1 < position.1 {
-                let distance = position.1 - intersection.coordinates.1;
-                if let Some(forward_path) = intersection
-                    .forward
-                    .as_ref()
-                    .filter(|forward_path| distance < forward_path.length)
-                {
-                    return Some((intersection_index, forward_path.end_index));
-                }
-            } else if intersection.coordinates.1 > position.1 {
-                let distance = intersection.coordinates.1 - position.1;
-                if let Some(backward_path) = intersection
-                    .backward
-                    .as_ref()
-                    .filter(|backward_path| distance < backward_path.length)
-                {
-                    return Some((intersection_index, backward_path.end_index));
-                }
-            }
+    #[test]
+    fn equal_paths_are_chosen_the_same_way() {
+        // The ghost is in the middle of the bottom of a loop and the player is in the middle of the top, so going either way round is just as far.
+        let sides = [
+            ((-5.0, 0.0), (5.0, 0.0)),
+            ((5.0, 0.0), (5.0, 10.0)),
+            ((5.0, 10.0), (-5.0, 10.0)),
+            ((-5.0, 10.0), (-5.0, 0.0)),
+        ];
+        let mut backwards = sides;
+        backwards.reverse();
+        for maze in [Maze::new(&sides), Maze::new(&backwards)] {
+            let (left, right) = (index_of(&maze, (-5.0, 0.0)), index_of(&maze, (5.0, 0.0)));
+            // Whichever way has the lowest index first wins.
+            let expected = if left < right {
+                vec![left, index_of(&maze, (-5.0, 10.0))]
+            } else {
+                vec![right, index_of(&maze, (5.0, 10.0))]
+            };
+            assert_eq!(dijkstra((0.0, 10.0), (0.0, 0.0), &maze), expected);
+            assert_eq!(astar((0.0, 10.0), (0.0, 0.0), &maze), expected);
         }
-        // We don't do an else here because there is a chance that the player isn't on any path.
-        // This being the case, the first if might not return anything but this one will.
-        // We get panics if we don't do this.
-        if within_range(intersection.coordinates.1, position.1) {
-            if intersection.coordinates.0 < position.0 {
-                let distance = position.0 - intersection.coordinates.0;
-                if let Some(right_path) = intersection
-                    .right
-                    .as_ref()
-                    .filter(|right_path| distance < right_path.length)
-                {
-                    return Some((intersection_index, right_path.end_index));
-                }
-            } else if intersection.coordinates.0 > position.0 {
-                let distance = intersection.coordinates.0 - position.0;
-                if let Some(left_path) = intersection
-                    .left
-                    .as_ref()
-                    .filter(|left_path| distance < left_path.length)
-                {
-                    return Some((intersection_index, left_path.end_index));
-                }
+    }
+
+    #[test]
+    fn steering() {
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((0.0, 0.0), (0.0, 10.0))]);
+        // Following the path.
+        assert_eq!(
+            steer(
+                (4.0, 0.0),
+                &[index_of(&maze, (0.0, 0.0))],
+                None,
+                2.0,
+                0.1,
+                &maze
+            ),
+            Vec3::new(-2.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            steer(
+                (0.0, 0.0),
+                &[index_of(&maze, (0.0, 10.0))],
+                Some((10.0, 0.0)),
+                2.0,
+                0.1,
+                &maze
+            ),
+            Vec3::new(0.0, 0.0, 2.0)
+        );
+        // Already on the same path as the target.
+        assert_eq!(
+            steer((4.0, 0.0), &[], Some((9.0, 0.0)), 2.0, 0.1, &maze),
+            Vec3::new(2.0, 0.0, 0.0)
+        );
+        // Nowhere to go.
+        assert_eq!(steer((4.0, 0.0), &[], None, 2.0, 0.1, &maze), Vec3::ZERO);
+    }
+
+    /// Every edge between neighbouring points of a 4 by 4 grid (5 apart), which random mazes are made out of.
+    fn grid_edges() -> Vec<((f32, f32), (f32, f32))> {
+        let mut edges = Vec::new();
+        for a in 0..4 {
+            for b in 0..3 {
+                let (a, b) = (a as f32 * 5.0, b as f32 * 5.0);
+                edges.push(((b, a), (b + 5.0, a)));
+                edges.push(((a, b), (a, b + 5.0)));
             }
         }
+        edges
     }
-    None
-}
 
-/// We need to find the distance between two points so often that I made this little utility function.
-/// TODO: Maybe this should be moved to some sort of math utility file?
-fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
-    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
-}
+    proptest::proptest! {
+        #[test]
+        fn shortest_paths_are_connected(
+            edges in proptest::sample::subsequence(grid_edges(), 1..=24),
+            player in (0..24usize, 0.0f32..=1.0),
+            ghost in (0..24usize, 0.0f32..=1.0),
+        ) {
+            let maze = Maze::new(&edges);
+            // Somewhere along one of the edges.
+            let position = |(edge, along): (usize, f32)| {
+                let (a, b) = edges[edge % edges.len()];
+                (a.0 + (b.0 - a.0) * along, a.1 + (b.1 - a.1) * along)
+            };
+            let (player_position, ghost_position) = (position(player), position(ghost));
+            let path = dijkstra(player_position, ghost_position, &maze);
+            proptest::prop_assert!(maze.route_length(&path).is_some(), "{:?}", path);
+            if let (Some(first), Some(last)) = (path.first(), path.last()) {
+                // It starts from one of the ends of the ghost's path (or next to the intersection it is on), and gets to the player's path.
+                let ghost_edge = find_edge(ghost_position, &maze).unwrap();
+                proptest::prop_assert!(
+                    [ghost_edge.0, ghost_edge.1].contains(first)
+                        || maze.route_length(&[ghost_edge.0, *first]).is_some()
+                );
+                let player_edge = find_edge(player_position, &maze).unwrap();
+                proptest::prop_assert!([player_edge.0, player_edge.1].contains(last));
+            }
+        }
+    }
 
-pub fn find_shortest_path(
-    player_position: (f32, f32),
-    current_ghost_position: (f32, f32),
-    maze: &Maze,
-) -> Vec<usize> {
-    let player_path = find_path(player_position, maze).expect("Player not on a path");
-    let ghost_path = find_path(current_ghost_position, maze).expect("Ghost not on a path");
-    if player_path == ghost_path {
-        // We are already on the right path, so we don't actually have to do anythign but chase the player down by moving in their direction.
-        return vec![];
+    #[test]
+    fn ghosts_on_the_boundaries_are_still_found() {
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((10.0, 0.0), (10.0, 10.0))]);
+        let coordinates = |edge: Option<(usize, usize)>| {
+            edge.map(|(a, b)| {
+                (
+                    maze.intersections()[a].coordinates,
+                    maze.intersections()[b].coordinates,
+                )
+            })
+        };
+        // Exactly halfway along a path.
+        let halfway = coordinates(find_edge((5.0, 0.0), &maze)).unwrap();
+        assert!(halfway == ((0.0, 0.0), (10.0, 0.0)) || halfway == ((10.0, 0.0), (0.0, 0.0)));
+        assert!(!dijkstra((10.0, 10.0), (5.0, 0.0), &maze).is_empty());
+        // Exactly as far off the end of the dead end as a ghost is allowed to stray.
+        assert_eq!(
+            coordinates(find_edge((-off_path_tolerance(&maze), 0.0), &maze)),
+            Some(((0.0, 0.0), (0.0, 0.0)))
+        );
+        assert!(!dijkstra((10.0, 10.0), (-off_path_tolerance(&maze), 0.0), &maze).is_empty());
+        assert_eq!(
+            find_edge((-off_path_tolerance(&maze) - 0.01, 0.0), &maze),
+            None
+        );
     }
-    // There is an edge case where the player is standing exactly on an intersection and we are on a path joining to it.
-    // The code below, in fact, doesn't check if the ghost's current path is joining to the player's.
-    // As this is due Tomorrow, I won't actually fix the code, but I will put in this special case to make it work.
-    if ghost_path.0 == player_path.0 || ghost_path.0 == player_path.1 {
-        return vec![ghost_path.0];
-    } else if ghost_path.1 == player_path.0 || ghost_path.1 == player_path.1 {
-        return vec![ghost_path.1];
-    }
-    let mut tried_indices_to_distances = HashMap::new();
-    let mut potential_paths = {
-        let initial_ghost_intersections = (
-            &maze.intersections()[ghost_path.0],
-            &maze.intersections()[ghost_path.1],
-        );
-        let initial_ghost_distances = (
-            distance(
-                initial_ghost_intersections.0.coordinates,
-                current_ghost_position,
-            ),
-            distance(
-                initial_ghost_intersections.1.coordinates,
-                current_ghost_position,
-            ),
+
+    #[test]
+    fn ghosts_dont_turn_around() {
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (10.0, 0.0)),
+            ((10.0, 0.0), (10.0, 10.0)),
+            ((10.0, 10.0), (0.0, 10.0)),
+            ((0.0, 10.0), (0.0, 0.0)),
+        ]);
+        let coordinates = |path: Vec<usize>| {
+            path.into_iter()
+                .map(|index| maze.intersections()[index].coordinates)
+                .collect::<Vec<_>>()
+        };
+        let path = |target, ghost_position, came_from| {
+            coordinates(find_shortest_path(target, ghost_position, &maze, |_| 0.0, came_from).0)
+        };
+        // Going right from the bottom left corner, the target is just behind us, so we have to go all the way round the loop.
+        assert_eq!(path((0.0, 5.0), (4.0, 0.0), None), vec![(0.0, 0.0)]);
+        assert_eq!(
+            path((0.0, 5.0), (4.0, 0.0), Some(index_of(&maze, (0.0, 0.0)))),
+            vec![(10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]
         );
-        if ghost_path.0 != ghost_path.1 {
-            vec![
-                (initial_ghost_distances.0, vec![ghost_path.0]),
-                (initial_ghost_distances.1, vec![ghost_path.1]),
-            ]
-        } else {
-            vec![(initial_ghost_distances.0, vec![ghost_path.0])]
+        // The same goes for when we are on an intersection.
+        assert_eq!(
+            path((5.0, 0.0), (10.0, 0.0), Some(index_of(&maze, (10.0, 10.0)))),
+            vec![(10.0, 0.0)]
+        );
+        assert_eq!(
+            path((5.0, 0.0), (10.0, 0.0), Some(index_of(&maze, (0.0, 0.0)))),
+            vec![(10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]
+        );
+
+        // But at a dead end, turning around is the only way to go.
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((0.0, 0.0), (0.0, 10.0))]);
+        let dead_end = index_of(&maze, (10.0, 0.0));
+        let corner = index_of(&maze, (0.0, 0.0));
+        let (path, _) = find_shortest_path((0.0, 5.0), (10.0, 0.0), &maze, |_| 0.0, Some(corner));
+        assert_eq!(path, vec![corner]);
+        assert_eq!(
+            search_path((0.0, 5.0), (10.0, 0.0), &maze, &|_| 0.0, Some(corner)).0,
+            None
+        );
+        // The cache remembers which way the ghost came from as it goes.
+        let mut cache = PathCache::default();
+        cache.advance((0.0, 0.0), &maze);
+        cache.advance((5.0, 0.0), &maze);
+        assert_eq!(cache.came_from((5.0, 0.0), &maze), Some(corner));
+        cache.advance((10.0, 0.0), &maze);
+        assert_eq!(cache.came_from((10.0, 0.0), &maze), Some(corner));
+        assert_eq!(cache.last_intersection, Some(dead_end));
+    }
+
+    #[test]
+    fn shortest_path_through_tunnel() {
+        let mut maze = Maze::new(&[
+            ((-10.0, 0.0), (10.0, 0.0)),
+            ((-5.0, 0.0), (-5.0, 5.0)),
+            ((5.0, 0.0), (5.0, 5.0)),
+        ]);
+        maze.add_wrap((-10.0, 0.0), (10.0, 0.0), 1.0);
+        let coordinates = |path: Vec<usize>| {
+            path.into_iter()
+                .map(|index| maze.intersections()[index].coordinates)
+                .collect::<Vec<_>>()
+        };
+        // It is 16 units along the maze, but only 5 through the tunnel.
+        for path in [
+            dijkstra((8.0, 0.0), (-8.0, 0.0), &maze),
+            astar((8.0, 0.0), (-8.0, 0.0), &maze),
+        ] {
+            assert_eq!(coordinates(path), vec![(-10.0, 0.0), (10.0, 0.0)]);
         }
-    };
-    let mut completed_paths = Vec::new();
-    while !potential_paths.is_empty() {
-        // Will terminate if we run out of paths to try.
-        let mut new_potential_paths = Vec::new();
-        for (accumulating_distance, potential_path) in potential_paths.iter() {
-            let current_index = *potential_path.last().unwrap();
-            // We don't want to try the same index twice unless we have found a faster way of getting there.
-            // If we have, it would already have been registered by the code which created this potential path.
-            if let Some(distance) = tried_indices_to_distances.get(&current_index) {
-                if accumulating_distance > distance {
-                    continue;
-                }
-            } else {
-                tried_indices_to_distances.insert(current_index, *accumulating_distance);
-            }
-            let current_intersection = &maze.intersections()[current_index];
-            let joining_paths = current_intersection
-                .forward
-                .iter()
-                .chain(current_intersection.backward.iter())
-                .chain(current_intersection.left.iter())
-                .chain(current_intersection.right.iter());
-            for joining_path in joining_paths {
-                let new_distance = accumulating_distance + joining_path.length;
-                // We don't want to add a path if it is covering an index which can be reached by a faster route.
-                if tried_indices_to_distances
-                    .get(&joining_path.end_index)
-                    .filter(|other_distance| **other_distance <= new_distance)
-                    .is_none()
-                {
-                    let mut new_path = potential_path.clone();
-                    new_path.push(joining_path.end_index);
-                    if joining_path.end_index == player_path.0
-                        || joining_path.end_index == player_path.1
-                    {
-                        // We have found a path which will lead us to the player, so we can stop looking.
-                        let joined_intersection = &maze.intersections()[joining_path.end_index];
-                        completed_paths.push((
-                            new_distance
-                                + distance(joined_intersection.coordinates, player_position),
-                            new_path,
-                        ));
-                    } else {
-                        new_potential_paths.push((new_distance, new_path));
-                    }
-                }
+        // But not when the long way is shorter.
+        assert_eq!(
+            coordinates(dijkstra((-2.0, 0.0), (-8.0, 0.0), &maze)),
+            vec![(-5.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn chasing_along_the_same_path() {
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((0.0, 0.0), (0.0, 10.0))]);
+        // The ghost behind the player just keeps going.
+        assert!(dijkstra((7.0, 0.0), (2.0, 0.0), &maze).is_empty());
+        assert_eq!(
+            steer((2.0, 0.0), &[], Some((7.0, 0.0)), 2.0, 0.1, &maze),
+            Vec3::new(2.0, 0.0, 0.0)
+        );
+        // And the player behind the ghost gets the ghost to turn around.
+        assert!(dijkstra((2.0, 0.0), (7.0, 0.0), &maze).is_empty());
+        assert_eq!(
+            steer((7.0, 0.0), &[], Some((2.0, 0.0)), 2.0, 0.1, &maze),
+            Vec3::new(-2.0, 0.0, 0.0)
+        );
+        // The same going along the other path, in the other direction.
+        assert!(dijkstra((0.0, 1.0), (0.0, 9.0), &maze).is_empty());
+        assert_eq!(
+            steer((0.0, 9.0), &[], Some((0.0, 1.0)), 2.0, 0.1, &maze),
+            Vec3::new(0.0, 0.0, -2.0)
+        );
+
+        let mut maze = Maze::new(&[((-10.0, 0.0), (10.0, 0.0))]);
+        maze.add_wrap((-10.0, 0.0), (10.0, 0.0), 1.0);
+        // Both in the same side of the tunnel is still a straight line.
+        assert!(dijkstra((-10.9, 0.0), (-10.6, 0.0), &maze).is_empty());
+        // But either side of the maze isn't, even though it's the same tunnel.
+        assert!(!dijkstra((10.6, 0.0), (-10.6, 0.0), &maze).is_empty());
+    }
+
+    #[test]
+    fn astar_finds_equally_short_paths() {
+        let maze = crate::sample_maze();
+        // The total distance the ghost has to go to get to the player along this path.
+        let path_length = |ghost_position: (f32, f32), path: &[usize], player_position| {
+            let mut position = ghost_position;
+            let mut length = 0.0;
+            for index in path {
+                let coordinates = maze.intersections()[*index].coordinates;
+                length += euclidean(position, coordinates);
+                position = coordinates;
             }
+            length + euclidean(position, player_position)
+        };
+        for (player_position, ghost_position) in [
+            ((0.0, 0.0), (15.0, 20.0)),
+            ((-15.0, -12.0), (15.0, 12.0)),
+            ((10.0, 2.5), (-5.0, -20.0)),
+            ((-12.5, 20.0), (12.5, -20.0)),
+            ((0.0, 7.5), (-10.0, 0.0)),
+        ] {
+            let dijkstra_path = dijkstra(player_position, ghost_position, &maze);
+            let astar_path = astar(player_position, ghost_position, &maze);
+            assert!(
+                (path_length(ghost_position, &dijkstra_path, player_position)
+                    - path_length(ghost_position, &astar_path, player_position))
+                .abs()
+                    < 0.001,
+                "{:?} and {:?}",
+                dijkstra_path,
+                astar_path
+            );
         }
-        potential_paths = new_potential_paths;
     }
 
-    let shortest_path = completed_paths
-        .into_iter()
-        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
-        .expect("No path to player found")
-        .1;
-    // If the ghost is already on an intersection, then we must exclude it from the path.
-    // This is because the path finding needs to find the paths which the ghost must reach, not the ones it is already on.
-    if ghost_path.0 == ghost_path.1 {
-        shortest_path[1..].to_vec()
-    } else {
-        shortest_path
+    #[test]
+    fn far_ghosts_recompute_less_often() {
+        let count_recomputes = |distance_to_player: f32| {
+            let mut path_cache = PathCache::default();
+            let mut recomputes = 0;
+            // A couple of seconds at 60 frames per second.
+            for frame in 0..120 {
+                let now = frame as f32 / 60.0;
+                if path_cache.needs_recompute(Some(((0, 1), (2, 3))), now) {
+                    path_cache.update(vec![0], Some(((0, 1), (2, 3))), now, distance_to_player);
+                    recomputes += 1;
+                }
+            }
+            recomputes
+        };
+        let near_recomputes = count_recomputes(0.5);
+        let far_recomputes = count_recomputes(20.0);
+        assert_eq!(near_recomputes, 120);
+        assert!(far_recomputes < near_recomputes);
+        // It should still happen every now and then though.
+        assert!(far_recomputes >= 4);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn path_cache_waits_for_a_new_edge() {
+        let maze = Maze::new(&[((-5.0, 0.0), (5.0, 0.0)), ((0.0, -5.0), (0.0, 5.0))]);
+        let mut path_cache = PathCache::default();
+        let key = path_key((-3.0, 0.0), maze.edge_at((0.0, 3.0)), &maze);
+        assert!(path_cache.needs_recompute(key, 0.0));
+        path_cache.update(vec![0], key, 0.0, 20.0);
+        // Both of them moving along the same edges doesn't make any difference.
+        assert_eq!(path_key((-2.0, 0.0), maze.edge_at((0.0, 4.0)), &maze), key);
+        assert!(!path_cache.needs_recompute(key, 0.1));
+        // But as soon as either of them is somewhere else, the path is worked out again.
+        let new_key = path_key((-2.0, 0.0), maze.edge_at((0.0, -3.0)), &maze);
+        assert_ne!(new_key, key);
+        assert!(path_cache.needs_recompute(new_key, 0.1));
+        let new_key = path_key((0.0, 0.0), maze.edge_at((0.0, 4.0)), &maze);
+        assert!(path_cache.needs_recompute(new_key, 0.1));
+    }
 
     #[test]
-    fn test_find_path() {
-        let maze = Maze::new(&[((1.0, 0.0), (-1.0, 0.0)), ((0.0, 1.0), (0.0, -1.0))]);
-        let left_path = find_path((-0.5, 0.0), &maze);
-        assert_eq!(left_path, Some((0, 1)));
-        let right_path = find_path((0.5, 0.0), &maze);
-        assert_eq!(right_path, Some((0, 2)));
-        let forward_path = find_path((0.0, 0.5), &maze);
-        assert_eq!(forward_path, Some((0, 4)));
-        let backward_path = find_path((0.0, -0.5), &maze);
-        assert_eq!(backward_path, Some((0, 3)));
+    fn waiting_ghosts_stay_on_the_paths() {
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((0.0, 0.0), (0.0, 10.0))]);
+        let mut cache = PathCache {
+            target: Some((8.0, 0.0)),
+            target_edge: maze.edge_at((8.0, 0.0)),
+            ..Default::default()
+        };
+        // On the same path, and on an intersection at the end of it.
+        assert_eq!(cache.heading_for((4.0, 0.0), &maze), Some((8.0, 0.0)));
+        assert_eq!(cache.heading_for((0.0, 0.0), &maze), Some((8.0, 0.0)));
+        // The target has gone round the corner, but the ghost hasn't had its turn to find a new path yet.
+        cache.target = Some((0.0, 8.0));
+        cache.target_edge = maze.edge_at((0.0, 8.0));
+        assert_eq!(cache.heading_for((4.0, 0.0), &maze), Some((0.0, 0.0)));
+        assert_eq!(cache.heading_for((0.0, 4.0), &maze), Some((0.0, 8.0)));
+        cache.target = None;
+        assert_eq!(cache.heading_for((4.0, 0.0), &maze), None);
     }
 
     #[test]
-    fn test_find_shortest_path() {
+    fn pathfinding_budget_spreads_ghosts_over_frames() {
         let maze = Maze::new(&[
             ((1.0, 0.0), (-1.0, 0.0)),
             ((0.0, 1.0), (0.0, -1.0)),
@@ -266,107 +1841,568 @@ mod test {
             ((-1.0, 1.0), (-1.0, -1.0)),
             ((-1.0, -1.0), (1.0, -1.0)),
         ]);
-        let shortest_path = find_shortest_path((0.0, 0.0), (0.0, 0.0), &maze);
-        assert!(shortest_path.is_empty());
-        let shortest_path = find_shortest_path((0.0, 0.0), (-1.0, 0.5), &maze);
-        // It's a bit nasty, but I don't want to have to worry about what indices the intersections are at.
+        let ghost_positions = [(-1.0, 0.5), (1.0, -0.5), (0.5, 1.0)];
+        let mut caches = vec![PathCache::default(); ghost_positions.len()];
+        let mut recomputed_per_frame = Vec::new();
+        for frame in 0..3 {
+            let now = (frame + 1) as f32 / 60.0;
+            let requests = caches
+                .iter_mut()
+                .zip(ghost_positions)
+                .filter(|(cache, ghost_position)| {
+                    cache.needs_recompute(
+                        path_key(*ghost_position, maze.edge_at((0.0, 0.0)), &maze),
+                        now,
+                    )
+                })
+                .map(|(cache, ghost_position)| PathRequest {
+                    cache,
+                    key: path_key(ghost_position, maze.edge_at((0.0, 0.0)), &maze),
+                    target: (0.0, 0.0),
+                    ghost_position,
+                    // Far enough away that they won't need to recompute again for a while.
+                    distance_to_player: 20.0,
+                    came_from: None,
+                    mode: GhostMode::Chase,
+                })
+                .collect();
+            recomputed_per_frame.push(recompute_paths(requests, now, 1, &maze));
+        }
+        assert_eq!(recomputed_per_frame, vec![1, 1, 1]);
+        assert!(caches.iter().all(|cache| !cache.path.is_empty()));
+    }
+
+    #[test]
+    fn caught_right_on_the_edge() {
+        let player = Vec3::new(0.0, 1.0, 0.0);
+        assert!(is_caught(player, Vec3::new(0.0, 0.4, 0.0), 0.8));
+        // Exactly touching counts, in any direction.
+        assert!(is_caught(player, Vec3::new(0.8, 0.4, 0.0), 0.8));
+        assert!(is_caught(player, Vec3::new(0.0, 0.4, -0.8), 0.8));
+        assert!(is_caught(player, Vec3::new(0.6, 0.4, 0.8), 1.0));
+        // Just too far away doesn't.
+        assert!(!is_caught(player, Vec3::new(0.81, 0.4, 0.0), 0.8));
+        assert!(!is_caught(player, Vec3::new(0.6, 0.4, 0.81), 1.0));
+        // Being higher up or lower down doesn't make any difference.
+        assert!(is_caught(player, Vec3::new(0.8, 10.0, 0.0), 0.8));
+    }
+
+    #[test]
+    fn test_collision_outcome() {
         assert_eq!(
-            maze.intersections()[shortest_path[0]].coordinates,
-            (-1.0, 0.0)
+            collision_outcome(&GhostState::Normal),
+            CollisionOutcome::PlayerCaught
         );
+        let mut ghost_state = GhostState::Frightened {
+            freeze: Timer::default(),
+            timer: Timer::from_seconds(8.0, TimerMode::Once),
+        };
         assert_eq!(
-            maze.intersections()[shortest_path[1]].coordinates,
-            (0.0, 0.0)
+            collision_outcome(&ghost_state),
+            CollisionOutcome::GhostEaten
+        );
+        // This is the frame where the timer is about to run out.
+        tick_frightened(&mut ghost_state, Duration::from_millis(7990));
+        assert_eq!(
+            collision_outcome(&ghost_state),
+            CollisionOutcome::GhostEaten
+        );
+        // Once the frame is over the ghost goes back to normal.
+        tick_frightened(&mut ghost_state, Duration::from_millis(10));
+        assert!(matches!(ghost_state, GhostState::Normal));
+        assert_eq!(
+            collision_outcome(&ghost_state),
+            CollisionOutcome::PlayerCaught
         );
     }
-}
 
-pub fn ghost_movement(
-    player: Query<(&Transform, &Player)>,
-    mut ghosts: Query<(&Ghost, &Transform, &mut Velocity), Without<Player>>,
-    maze: Res<Maze>,
-) {
-    ghosts
-        .par_iter_mut()
-        .for_each_mut(|(ghost, ghost_transform, mut ghost_velocity)| {
-            let (player_transform, player) = player.get_single().unwrap();
-            let shortest_path = find_shortest_path(
-                match ghost.0 {
-                    GhostType::Blinky => (
-                        player_transform.translation.x,
-                        player_transform.translation.z,
-                    ),
-                    GhostType::Pinky => {
-                        let player_path = find_path(
+    #[test]
+    fn frightened_ghosts_freeze_first() {
+        let maze = Maze::new(&[((-5.0, 0.0), (5.0, 0.0))]);
+        let config = FrightenedConfig {
+            duration: 8.0,
+            freeze_duration: 0.5,
+        };
+        let mut app = App::new();
+        app.insert_resource(maze)
+            .init_resource::<StepTime>()
+            .init_resource::<PathfindingBudget>()
+            .init_resource::<GhostModeTimer>()
+            .init_resource::<Difficulty>()
+            .init_resource::<LevelNumber>()
+            .add_systems(Update, ghost_movement);
+        app.world
+            .spawn((Player::default(), Transform::from_xyz(-4.0, 0.0, 0.0)));
+        let mut ghost_state = GhostState::Normal;
+        frighten(&mut ghost_state, &config);
+        let ghost = app
+            .world
+            .spawn((
+                Ghost::new(GhostPersonality::Blinky),
+                ghost_state,
+                Transform::from_xyz(4.0, 0.0, 0.0),
+                Velocity::zero(),
+                PathCache::default(),
+            ))
+            .id();
+        let ghost_velocity = |app: &App| app.world.get::<Velocity>(ghost).unwrap().linvel;
+
+        app.update();
+        assert_eq!(ghost_velocity(&app), Vec3::ZERO);
+
+        let mut ghost_state = app.world.get_mut::<GhostState>(ghost).unwrap();
+        tick_frightened(&mut ghost_state, Duration::from_millis(300));
+        // Eating another power pellet now shouldn't make the freeze any longer.
+        frighten(&mut ghost_state, &config);
+        tick_frightened(&mut ghost_state, Duration::from_millis(200));
+        assert!(!ghost_state.is_frozen());
+        assert!(matches!(*ghost_state, GhostState::Frightened { .. }));
+
+        app.update();
+        assert_ne!(ghost_velocity(&app), Vec3::ZERO);
+    }
+
+    #[test]
+    fn eating_a_ghost() {
+        let mut app = App::new();
+        app.add_event::<GhostEaten>()
+            .add_systems(Update, eat_ghosts);
+        let home = Vec3::new(-4.0, 0.0, 0.0);
+        let mut frightened = GhostState::Normal;
+        frighten(&mut frightened, &FrightenedConfig::default());
+        let mut spawn = || {
+            app.world
+                .spawn((frightened.clone(), SpawnPoint(home), PathCache::default()))
+                .id()
+        };
+        let (eaten, other) = (spawn(), spawn());
+        app.world.send_event(GhostEaten {
+            ghost: eaten,
+            points: GHOST_POINTS,
+        });
+        app.update();
+        assert!(matches!(
+            app.world.get::<GhostState>(eaten).unwrap(),
+            GhostState::Eaten { home: eaten_home } if *eaten_home == home
+        ));
+        assert!(matches!(
+            app.world.get::<GhostState>(other).unwrap(),
+            GhostState::Frightened { .. }
+        ));
+    }
+
+    #[test]
+    fn eaten_ghosts_go_home() {
+        let maze = Maze::new(&[((-5.0, 0.0), (5.0, 0.0))]);
+        let mut app = App::new();
+        app.insert_resource(maze)
+            .init_resource::<StepTime>()
+            .init_resource::<PathfindingBudget>()
+            .init_resource::<GhostModeTimer>()
+            .init_resource::<Difficulty>()
+            .init_resource::<LevelNumber>()
+            .add_systems(Update, ghost_movement);
+        app.world
+            .spawn((Player::default(), Transform::from_xyz(5.0, 0.0, 0.0)));
+        let home = Vec3::new(-4.0, 0.0, 0.0);
+        let ghost = app
+            .world
+            .spawn((
+                Ghost::new(GhostPersonality::Blinky),
+                GhostState::Eaten { home },
+                Transform::from_xyz(4.0, 0.0, 0.0),
+                Velocity::zero(),
+                PathCache::default(),
+            ))
+            .id();
+        assert_eq!(
+            collision_outcome(app.world.get::<GhostState>(ghost).unwrap()),
+            CollisionOutcome::Nothing
+        );
+        // Power pellets don't make any difference to a ghost which is already on its way home.
+        frighten(
+            &mut app.world.get_mut::<GhostState>(ghost).unwrap(),
+            &FrightenedConfig::default(),
+        );
+
+        app.update();
+        // Away from the player and towards home, twice as fast as usual.
+        assert_eq!(
+            app.world.get::<Velocity>(ghost).unwrap().linvel,
+            Vec3::new(-GHOST_SPEED * 2.0, 0.0, 0.0)
+        );
+        assert!(matches!(
+            app.world.get::<GhostState>(ghost).unwrap(),
+            GhostState::Eaten { .. }
+        ));
+
+        app.world.get_mut::<Transform>(ghost).unwrap().translation = Vec3::new(-3.8, 0.0, 0.0);
+        app.update();
+        assert!(matches!(
+            app.world.get::<GhostState>(ghost).unwrap(),
+            GhostState::Normal
+        ));
+        // Now it carries on as normal (which is scattering this early on), at the normal speed.
+        assert_eq!(
+            app.world.get::<Velocity>(ghost).unwrap().linvel,
+            Vec3::new(GHOST_SPEED, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn spawn_points_are_spread_out() {
+        let maze = Maze::new(&[((-5.0, 0.0), (5.0, 0.0)), ((0.0, -5.0), (0.0, 5.0))]);
+        // All crammed into the middle, with a couple exactly on top of each other and one which is off the paths completely.
+        let requested = [(0.0, 0.0), (0.0, 0.0), (0.3, 0.2), (0.5, 0.6), (-0.2, 0.0)];
+        let spawn_points = spread_spawn_points(&requested, &maze, min_spawn_spacing(&maze));
+        assert_eq!(spawn_points.len(), requested.len());
+        for (i, a) in spawn_points.iter().enumerate() {
+            assert!(maze.edge_at(*a).is_some());
+            for b in spawn_points.iter().skip(i + 1) {
+                assert!(
+                    euclidean(*a, *b) >= min_spawn_spacing(&maze),
+                    "{:?} and {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+        // The first one doesn't have to move at all.
+        assert_eq!(spawn_points[0], (0.0, 0.0));
+    }
+
+    #[test]
+    fn frightened_ghosts_flee() {
+        let maze = Maze::new(&[((-5.0, 0.0), (5.0, 0.0)), ((0.0, -5.0), (0.0, 5.0))]);
+        // In the middle, the ghost should go whichever way takes it furthest from the player.
+        assert_eq!(
+            flee_target((0.0, 0.0), (-4.0, 0.0), &maze),
+            Some(index_of(&maze, (5.0, 0.0)))
+        );
+        assert_eq!(
+            flee_target((0.0, 0.0), (0.0, 3.0), &maze),
+            Some(index_of(&maze, (0.0, -5.0)))
+        );
+        // Part way along a path, it can only go one way or the other.
+        assert_eq!(
+            flee_target((0.0, 2.0), (0.0, 4.0), &maze),
+            Some(index_of(&maze, (0.0, 0.0)))
+        );
+    }
+
+    #[test]
+    fn ghost_mode_schedule() {
+        let mut mode_timer = GhostModeTimer::default();
+        let mut modes = Vec::new();
+        // Tick in quarter seconds, and note down every time the mode changes.
+        let mut mode = mode_timer.mode();
+        modes.push((0.0, mode));
+        for tick in 1..=300 {
+            mode_timer.tick(Duration::from_millis(250));
+            if mode_timer.mode() != mode {
+                mode = mode_timer.mode();
+                modes.push((tick as f32 / 4.0, mode));
+            }
+        }
+        assert_eq!(
+            modes,
+            vec![
+                (0.0, GhostMode::Scatter),
+                (7.0, GhostMode::Chase),
+                (27.0, GhostMode::Scatter),
+                (34.0, GhostMode::Chase),
+            ]
+        );
+        // A single long tick should go through several phases at once.
+        let mut mode_timer = GhostModeTimer::default();
+        mode_timer.tick(Duration::from_secs(30));
+        assert_eq!(mode_timer.mode(), GhostMode::Scatter);
+        mode_timer.tick(Duration::from_secs(3));
+        assert_eq!(mode_timer.mode(), GhostMode::Scatter);
+        mode_timer.tick(Duration::from_secs(1));
+        assert_eq!(mode_timer.mode(), GhostMode::Chase);
+    }
+
+    #[test]
+    fn frightened_ghosts_pause_the_mode_timer() {
+        let mut app = App::new();
+        app.init_resource::<StepTime>()
+            .init_resource::<GhostModeTimer>()
+            .init_resource::<Difficulty>()
+            .add_systems(Update, update_ghost_mode);
+        let ghost = app.world.spawn(GhostState::Normal).id();
+        let advance = |app: &mut App, seconds: u64| {
+            app.world
+                .resource_mut::<StepTime>()
+                .advance(Duration::from_secs(seconds));
+            app.update();
+        };
+        advance(&mut app, 5);
+        frighten(
+            &mut app.world.get_mut::<GhostState>(ghost).unwrap(),
+            &FrightenedConfig::default(),
+        );
+        advance(&mut app, 5);
+        assert_eq!(
+            app.world.resource::<GhostModeTimer>().mode(),
+            GhostMode::Scatter
+        );
+        *app.world.get_mut::<GhostState>(ghost).unwrap() = GhostState::Normal;
+        advance(&mut app, 3);
+        assert_eq!(
+            app.world.resource::<GhostModeTimer>().mode(),
+            GhostMode::Chase
+        );
+    }
+
+    #[test]
+    fn ghosts_leave_the_house_in_turn() {
+        let mut app = App::new();
+        app.add_event::<PelletEaten>()
+            .init_resource::<StepTime>()
+            .init_resource::<GhostReleaseSchedule>()
+            .add_systems(Update, (bob_penned_ghosts, release_ghosts).chain());
+        let spawn_point = Vec3::new(5.0, 0.5, 20.0);
+        let ghosts = (0..4)
+            .map(|_| {
+                app.world
+                    .spawn((
+                        Penned,
+                        SpawnPoint(spawn_point),
+                        Transform::from_translation(spawn_point),
+                        Velocity::linear(Vec3::X),
+                    ))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+        app.insert_resource(GhostHouse::new(vec![0], ghosts.clone()));
+        let penned = |app: &App| {
+            ghosts
+                .iter()
+                .map(|ghost| app.world.get::<Penned>(*ghost).is_some())
+                .collect::<Vec<_>>()
+        };
+        let advance = |app: &mut App, seconds: f32| {
+            app.world
+                .resource_mut::<StepTime>()
+                .advance(Duration::from_secs_f32(seconds));
+            app.update();
+        };
+
+        // The first one comes straight out, and the rest bob on the spot.
+        advance(&mut app, 0.1);
+        assert_eq!(penned(&app), [false, true, true, true]);
+        let waiting = app.world.get::<Transform>(ghosts[1]).unwrap().translation;
+        assert_ne!(waiting, spawn_point);
+        assert!((waiting - spawn_point).length() <= BOB_HEIGHT);
+        assert_eq!(
+            *app.world.get::<Velocity>(ghosts[1]).unwrap(),
+            Velocity::zero()
+        );
+
+        advance(&mut app, 4.0);
+        assert_eq!(penned(&app), [false, false, true, true]);
+        // Coming out puts the ghost back on the floor, rather than wherever it was in its bob.
+        assert_eq!(
+            app.world.get::<Transform>(ghosts[1]).unwrap().translation,
+            spawn_point
+        );
+
+        // Eating enough pellets lets the next one out early.
+        app.world
+            .send_event_batch((0..30).map(|_| PelletEaten { points: 10 }));
+        advance(&mut app, 0.1);
+        assert_eq!(penned(&app), [false, false, false, true]);
+
+        // The scheduler can be skipped ahead for testing, even when it isn't time yet.
+        let mut house = app.world.resource_mut::<GhostHouse>();
+        assert_eq!(house.release_next_ghost(), Some(ghosts[3]));
+        assert_eq!(house.release_next_ghost(), None);
+        assert!(house.waiting().is_empty());
+        house.reset();
+        assert_eq!(house.waiting(), &ghosts[..]);
+    }
+
+    #[test]
+    fn spawned_ghosts_chase_independently() {
+        use bevy::{
+            asset::{AddAsset, AssetPlugin},
+            prelude::{MinimalPlugins, Startup},
+        };
+
+        let mut mode_timer = GhostModeTimer::default();
+        mode_timer.tick(Duration::from_secs(7));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<bevy::prelude::Mesh>()
+            .add_asset::<StandardMaterial>()
+            .insert_resource(crate::sample_maze())
+            .insert_resource(mode_timer)
+            .init_resource::<Difficulty>()
+            .init_resource::<LevelNumber>()
+            .init_resource::<PathfindingBudget>()
+            .init_resource::<StepTime>()
+            .add_systems(
+                Startup,
+                |mut commands: Commands,
+                 mut meshes: ResMut<Assets<bevy::prelude::Mesh>>,
+                 mut materials: ResMut<Assets<StandardMaterial>>| {
+                    // Both start in the same place, so any difference is down to their personalities.
+                    spawn_ghosts(
+                        &mut commands,
+                        &mut meshes,
+                        &mut materials,
+                        &[
                             (
-                                player_transform.translation.x,
-                                player_transform.translation.z,
+                                Vec3::new(-10.0, DEFAULT_HALF_PATH_WIDTH, 0.0),
+                                Color::RED,
+                                GhostPersonality::Blinky,
                             ),
-                            &maze,
-                        )
-                        .expect("Player not on a path");
-                        let ghost_path = find_path(
-                            (ghost_transform.translation.x, ghost_transform.translation.z),
-                            &maze,
-                        )
-                        .expect("Ghost not on a path");
-                        if player_path == ghost_path {
-                            // We are on their path, so we just try to get to them (essentially what Blinky does all the time).
                             (
-                                player_transform.translation.x,
-                                player_transform.translation.z,
-                            )
-                        } else {
-                            let path_intersections = (
-                                &maze.intersections()[player_path.0],
-                                &maze.intersections()[player_path.1],
-                            );
-                            match player.current_direction {
-                                crate::Direction::Forward | crate::Direction::Right => (
-                                    path_intersections
-                                        .0
-                                        .coordinates
-                                        .0
-                                        .max(path_intersections.1.coordinates.0),
-                                    path_intersections
-                                        .0
-                                        .coordinates
-                                        .1
-                                        .max(path_intersections.1.coordinates.1),
-                                ),
-                                crate::Direction::Backward | crate::Direction::Left => (
-                                    path_intersections
-                                        .0
-                                        .coordinates
-                                        .0
-                                        .min(path_intersections.1.coordinates.0),
-                                    path_intersections
-                                        .0
-                                        .coordinates
-                                        .1
-                                        .min(path_intersections.1.coordinates.1),
-                                ),
-                            }
-                        }
-                    }
-                    _ => panic!("Only Blinky and Pinky are implemented"),
+                                Vec3::new(-10.0, DEFAULT_HALF_PATH_WIDTH, 0.0),
+                                Color::PINK,
+                                GhostPersonality::Pinky,
+                            ),
+                        ],
+                        DEFAULT_HALF_PATH_WIDTH,
+                    );
                 },
-                (ghost_transform.translation.x, ghost_transform.translation.z),
+            )
+            .add_systems(Update, ghost_movement);
+        app.world.spawn((
+            Player {
+                current_direction: Direction::Forward,
+                ..Default::default()
+            },
+            Transform::from_xyz(0.0, 1.0, 7.5),
+        ));
+        app.update();
+
+        let mut ghosts = app.world.query::<(&Ghost, &PathCache)>();
+        let paths = ghosts
+            .iter(&app.world)
+            .map(|(ghost, path_cache)| (ghost.personality.clone(), path_cache.path.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|(_, path)| !path.is_empty()));
+        assert_ne!(paths[0].0, paths[1].0);
+        assert_ne!(paths[0].1, paths[1].1);
+    }
+
+    #[test]
+    fn test_ghost_target_after_turn() {
+        let maze = Maze::new(&[((1.0, 0.0), (-1.0, 0.0)), ((0.0, 1.0), (0.0, -1.0))]);
+        // The player has come down the vertical path and been snapped onto the middle intersection.
+        let player_position = (0.0, 0.0);
+        let ghost_position = (1.0, 0.0);
+        let target = ghost_target(
+            &GhostPersonality::Pinky,
+            player_position,
+            Direction::Forward,
+            ghost_position,
+            None,
+            &maze,
+        );
+        assert_eq!(target, (0.0, -1.0));
+        // Now they turn left, so Pinky should be heading for the end of the left path instead.
+        let target = ghost_target(
+            &GhostPersonality::Pinky,
+            player_position,
+            Direction::Left,
+            ghost_position,
+            None,
+            &maze,
+        );
+        assert_eq!(target, (-1.0, 0.0));
+    }
+
+    #[test]
+    fn test_personality_targets() {
+        let maze = crate::sample_maze();
+        let player_position = (0.0, 0.0);
+        // Pinky goes for four tiles ahead of the player.
+        assert_eq!(
+            ghost_target(
+                &GhostPersonality::Pinky,
+                player_position,
+                Direction::Right,
+                (10.0, 10.0),
+                None,
                 &maze,
-            );
-            const SPEED: f32 = 2.0;
-            if shortest_path.is_empty() {
-                // Just head in the direction of the player, since we are on the same path.
-                let direction = player_transform.translation - ghost_transform.translation;
-                ghost_velocity.linvel = direction.normalize() * SPEED;
-            } else {
-                let next_intersection = &maze.intersections()[shortest_path[0]];
-                let direction = (
-                    next_intersection.coordinates.0 - ghost_transform.translation.x,
-                    next_intersection.coordinates.1 - ghost_transform.translation.z,
-                );
-                ghost_velocity.linvel =
-                    Vec3::new(direction.0, 0.0, direction.1).normalize() * SPEED;
-            }
-        });
+            ),
+            (4.0, 0.0)
+        );
+        // Tiles are as wide as the paths, so with paths twice as wide she aims twice as far ahead.
+        assert_eq!(
+            ghost_target(
+                &GhostPersonality::Pinky,
+                player_position,
+                Direction::Right,
+                (10.0, 10.0),
+                None,
+                &crate::sample_maze().with_half_path_width(DEFAULT_HALF_PATH_WIDTH * 2.0),
+            ),
+            (8.0, 0.0)
+        );
+        // Inky goes for the point two tiles ahead of the player, reflected through Blinky.
+        assert_eq!(
+            ghost_target(
+                &GhostPersonality::Inky,
+                player_position,
+                Direction::Forward,
+                (10.0, 10.0),
+                Some((0.0, 6.0)),
+                &maze,
+            ),
+            (0.0, -10.0)
+        );
+        // If that is off the maze, he goes to the nearest intersection instead.
+        assert_eq!(
+            ghost_target(
+                &GhostPersonality::Inky,
+                player_position,
+                Direction::Forward,
+                (10.0, 10.0),
+                Some((2.0, 20.0)),
+                &maze,
+            ),
+            (-5.0, -20.0)
+        );
+        // Clyde chases the player from far away, but goes back to his corner up close.
+        assert_eq!(
+            ghost_target(
+                &GhostPersonality::Clyde,
+                player_position,
+                Direction::Forward,
+                (10.0, 10.0),
+                None,
+                &maze,
+            ),
+            player_position
+        );
+        assert_eq!(
+            ghost_target(
+                &GhostPersonality::Clyde,
+                player_position,
+                Direction::Forward,
+                (5.0, 0.0),
+                None,
+                &maze,
+            ),
+            home_corner(&GhostPersonality::Clyde, &maze)
+        );
+        let corner = home_corner(&GhostPersonality::Clyde, &maze);
+        assert!(corner.0 < 0.0 && corner.1 > 0.0);
+    }
+
+    #[test]
+    fn home_corners() {
+        let maze = crate::sample_maze();
+        assert_eq!(home_corner(&GhostPersonality::Blinky, &maze), (15.0, -20.0));
+        assert_eq!(home_corner(&GhostPersonality::Clyde, &maze), (-15.0, 20.0));
+        // The ends of the corridor go straight on into the tunnel, so there aren't any corners to go to.
+        let mut maze = Maze::new(&[((-10.0, 0.0), (10.0, 0.0))]);
+        maze.add_wrap((-10.0, 0.0), (10.0, 0.0), 1.0);
+        assert_eq!(home_corner(&GhostPersonality::Blinky, &maze), (10.0, 0.0));
+    }
 }
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::{Res, ResMut, Resource};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::score::Score;
+
+/// The best score there has been so far, kept between games.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub struct HighScore(pub u32);
+
+/// Where the high score is saved.
+#[derive(Clone, Debug, Resource)]
+pub struct HighScorePath(pub PathBuf);
+
+/// What actually goes in the file, so that there is room for more in it later.
+#[derive(Serialize, Deserialize)]
+struct HighScoreFile {
+    high_score: u32,
+}
+
+/// `high_score.json` in the user's config folder, if they have one.
+pub fn high_score_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "pacman3d").map(|dirs| dirs.config_dir().join("high_score.json"))
+}
+
+/// Nobody has a high score the first time they play, so a missing file just means zero.
+/// A file which can't be read properly is treated the same way, rather than stopping the game from starting.
+pub fn load_high_score(path: impl AsRef<Path>) -> HighScore {
+    let path = path.as_ref();
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return HighScore::default();
+    };
+    match serde_json::from_str::<HighScoreFile>(&text) {
+        Ok(file) => HighScore(file.high_score),
+        Err(error) => {
+            bevy::log::warn!("Couldn't read the high score from {:?}: {}", path, error);
+            HighScore::default()
+        }
+    }
+}
+
+pub fn save_high_score(path: impl AsRef<Path>, score: u32) -> std::io::Result<()> {
+    let path = path.as_ref();
+    // The config folder isn't there until something has been saved in it.
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let text = serde_json::to_string(&HighScoreFile { high_score: score })?;
+    std::fs::write(path, text)
+}
+
+/// Saves the score once the game is over, but only if it beats the high score.
+pub fn record_high_score(
+    score: Res<Score>,
+    mut high_score: ResMut<HighScore>,
+    path: Res<HighScorePath>,
+) {
+    if score.current() <= high_score.0 {
+        return;
+    }
+    high_score.0 = score.current();
+    if let Err(error) = save_high_score(&path.0, high_score.0) {
+        bevy::log::error!("Couldn't save the high score to {:?}: {}", path.0, error);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, Update};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pacman3d-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn saving_and_loading() {
+        let path = temp_path("high-score");
+        assert_eq!(load_high_score(&path), HighScore(0));
+        save_high_score(&path, 1230).unwrap();
+        assert_eq!(load_high_score(&path), HighScore(1230));
+        std::fs::write(&path, "not a high score").unwrap();
+        assert_eq!(load_high_score(&path), HighScore(0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn only_better_scores_are_saved() {
+        let path = temp_path("better-score");
+        let mut app = App::new();
+        app.insert_resource(Score(500))
+            .insert_resource(HighScore(1000))
+            .insert_resource(HighScorePath(path.clone()))
+            .add_systems(Update, record_high_score);
+        app.update();
+        assert_eq!(*app.world.resource::<HighScore>(), HighScore(1000));
+        assert!(!path.exists());
+        app.world.resource_mut::<Score>().0 = 1500;
+        app.update();
+        assert_eq!(*app.world.resource::<HighScore>(), HighScore(1500));
+        assert_eq!(load_high_score(&path), HighScore(1500));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
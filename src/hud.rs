@@ -0,0 +1,146 @@
+use bevy::{
+    asset::LoadState,
+    prelude::{
+        AssetServer, Color, Commands, Component, Handle, Query, Res, Resource, TextBundle,
+        TextStyle, Val, With, Without,
+    },
+    text::{Font, Text},
+    ui::{PositionType, Style},
+};
+
+use crate::{
+    minimap::{MINIMAP_MARGIN, MINIMAP_SIZE},
+    score::{Lives, Score},
+};
+
+const HUD_FONT_SIZE: f32 = 32.0;
+const HUD_COLOR: Color = Color::WHITE;
+/// How far the text is from the edges of the screen (in pixels).
+const HUD_MARGIN: f32 = 10.0;
+
+/// Where the font for the HUD is loaded from (relative to the assets folder).
+/// Without one, or if it can't be loaded, Bevy's own font is used instead so that the HUD still shows up.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct HudFont(pub Option<String>);
+
+#[derive(Component)]
+pub struct ScoreText;
+
+#[derive(Component)]
+pub struct LivesText;
+
+fn score_text(score: &Score) -> String {
+    format!("Score: {}", score.current())
+}
+
+fn lives_text(lives: &Lives) -> String {
+    format!("Lives: {}", lives.0)
+}
+
+pub fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>, font: Res<HudFont>) {
+    let style = TextStyle {
+        font: font
+            .0
+            .as_ref()
+            .map(|path| asset_server.load(path.as_str()))
+            .unwrap_or_default(),
+        font_size: HUD_FONT_SIZE,
+        color: HUD_COLOR,
+    };
+    // The minimap is already in the top left corner, so the score goes just underneath it.
+    commands.spawn((
+        TextBundle::from_section("", style.clone()).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px((MINIMAP_MARGIN + MINIMAP_SIZE) as f32 + HUD_MARGIN),
+            left: Val::Px(HUD_MARGIN),
+            ..Default::default()
+        }),
+        ScoreText,
+    ));
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(HUD_MARGIN),
+            right: Val::Px(HUD_MARGIN),
+            ..Default::default()
+        }),
+        LivesText,
+    ));
+}
+
+pub fn update_hud(
+    score: Res<Score>,
+    lives: Res<Lives>,
+    asset_server: Res<AssetServer>,
+    mut score_texts: Query<&mut Text, (With<ScoreText>, Without<LivesText>)>,
+    mut lives_texts: Query<&mut Text, (With<LivesText>, Without<ScoreText>)>,
+) {
+    for (mut text, value) in score_texts
+        .iter_mut()
+        .map(|text| (text, score_text(&score)))
+        .chain(
+            lives_texts
+                .iter_mut()
+                .map(|text| (text, lives_text(&lives))),
+        )
+    {
+        // Changing the text (even to the same thing) makes it get laid out again, so we only touch it when something is different.
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        if asset_server.get_load_state(&text.sections[0].style.font) == LoadState::Failed {
+            text.sections[0].style.font = Handle::<Font>::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{
+        asset::{AddAsset, AssetPlugin},
+        prelude::{App, MinimalPlugins, Startup, Update},
+    };
+
+    use super::*;
+
+    #[test]
+    fn hud_shows_score_and_lives() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Font>()
+            .init_resource::<HudFont>()
+            .insert_resource(Score(120))
+            .insert_resource(Lives(2))
+            .add_systems(Startup, setup_hud)
+            .add_systems(Update, update_hud);
+        app.update();
+        let texts = |app: &mut App| {
+            let score = app
+                .world
+                .query_filtered::<&Text, With<ScoreText>>()
+                .single(&app.world)
+                .sections[0]
+                .value
+                .clone();
+            let lives = app
+                .world
+                .query_filtered::<&Text, With<LivesText>>()
+                .single(&app.world)
+                .sections[0]
+                .value
+                .clone();
+            (score, lives)
+        };
+        assert_eq!(
+            texts(&mut app),
+            ("Score: 120".to_string(), "Lives: 2".to_string())
+        );
+        app.world.resource_mut::<Score>().0 += 10;
+        app.world.resource_mut::<Lives>().lose();
+        app.update();
+        assert_eq!(
+            texts(&mut app),
+            ("Score: 130".to_string(), "Lives: 1".to_string())
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{
+        Axis, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads, Input,
+        KeyCode, Local, Res, ResMut, Resource,
+    },
+};
+
+use crate::replay::{ReplayPlayer, ReplayRecorder};
+
+/// Everything the player can do.
+/// These are all relative to the way the player is facing, so going straight means carrying on the way they are going.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerAction {
+    GoStraight,
+    TurnLeft,
+    TurnRight,
+    /// Turns right around and goes back the way the player came.
+    Reverse,
+}
+
+/// Which keys and gamepad buttons do each of the player's actions.
+/// More than one key (or button) can do the same thing.
+#[derive(Clone, Debug, Resource)]
+pub struct InputBindings {
+    pub keys: Vec<(KeyCode, PlayerAction)>,
+    pub gamepad_buttons: Vec<(GamepadButtonType, PlayerAction)>,
+    /// How far the left stick has to be pushed (from 0 to 1) before it counts.
+    pub stick_threshold: f32,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            keys: vec![
+                (KeyCode::Up, PlayerAction::GoStraight),
+                (KeyCode::Down, PlayerAction::Reverse),
+                (KeyCode::Left, PlayerAction::TurnLeft),
+                (KeyCode::Right, PlayerAction::TurnRight),
+            ],
+            gamepad_buttons: vec![
+                (GamepadButtonType::DPadUp, PlayerAction::GoStraight),
+                (GamepadButtonType::DPadDown, PlayerAction::Reverse),
+                (GamepadButtonType::DPadLeft, PlayerAction::TurnLeft),
+                (GamepadButtonType::DPadRight, PlayerAction::TurnRight),
+            ],
+            stick_threshold: 0.5,
+        }
+    }
+}
+
+/// Works out which action the left stick is asking for, going by whichever way it is pushed furthest.
+fn stick_action(x: f32, y: f32, threshold: f32) -> Option<PlayerAction> {
+    if x.abs().max(y.abs()) < threshold {
+        None
+    } else if x.abs() >= y.abs() {
+        Some(if x < 0.0 {
+            PlayerAction::TurnLeft
+        } else {
+            PlayerAction::TurnRight
+        })
+    } else {
+        // Up is positive on gamepads.
+        Some(if y > 0.0 {
+            PlayerAction::GoStraight
+        } else {
+            PlayerAction::Reverse
+        })
+    }
+}
+
+/// Everything needed to work out what the player wants to do, from the keyboard and any gamepads.
+#[derive(SystemParam)]
+pub struct PlayerInput<'w, 's> {
+    bindings: Res<'w, InputBindings>,
+    keyboard_input: Res<'w, Input<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    /// What the stick was asking for last frame, so that holding it in one place only does that once (like holding a key down).
+    stick_action: Local<'s, Option<PlayerAction>>,
+}
+
+impl PlayerInput<'_, '_> {
+    /// The actions which were started this frame.
+    pub fn just_pressed(&mut self) -> Vec<PlayerAction> {
+        let mut actions = Vec::new();
+        let mut add = |action: PlayerAction| {
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        };
+        for (key, action) in &self.bindings.keys {
+            if self.keyboard_input.just_pressed(*key) {
+                add(*action);
+            }
+        }
+        for (button_type, action) in &self.bindings.gamepad_buttons {
+            if self
+                .gamepad_buttons
+                .get_just_pressed()
+                .any(|button| button.button_type == *button_type)
+            {
+                add(*action);
+            }
+        }
+        let stick = self.gamepads.iter().find_map(|gamepad| {
+            let x = self
+                .gamepad_axes
+                .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))?;
+            let y = self
+                .gamepad_axes
+                .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))?;
+            stick_action(x, y, self.bindings.stick_threshold)
+        });
+        if stick != *self.stick_action {
+            if let Some(action) = stick {
+                add(action);
+            }
+        }
+        *self.stick_action = stick;
+        actions
+    }
+}
+
+/// The actions which have been asked for, but which the player hasn't done yet.
+/// The input is read every frame, but the player only moves on the game's steps, which might come more or less often than that.
+/// Keeping them here means an action is never missed (or done twice) because there wasn't exactly one step in the frame it was pressed.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct PendingActions(Vec<PlayerAction>);
+
+pub fn read_player_input(mut input: PlayerInput, mut pending: ResMut<PendingActions>) {
+    for action in input.just_pressed() {
+        if !pending.0.contains(&action) {
+            pending.0.push(action);
+        }
+    }
+}
+
+/// What the player does on each step of the game.
+#[derive(SystemParam)]
+pub struct PlayerActions<'w> {
+    pending: ResMut<'w, PendingActions>,
+    /// Replays take over from the keyboard and gamepads completely.
+    replay: Option<ResMut<'w, ReplayPlayer>>,
+    recorder: Option<ResMut<'w, ReplayRecorder>>,
+}
+
+impl PlayerActions<'_> {
+    /// The actions to do this step.
+    /// This should only be called once a step, since each call is a step of any replay being recorded or played back.
+    pub fn take(&mut self) -> Vec<PlayerAction> {
+        let pending = std::mem::take(&mut self.pending.0);
+        let actions = match &mut self.replay {
+            Some(replay) => replay.next_actions(),
+            None => pending,
+        };
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&actions);
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, ResMut, Update};
+
+    use super::*;
+
+    #[test]
+    fn stick_directions() {
+        assert_eq!(stick_action(0.0, 0.0, 0.5), None);
+        assert_eq!(stick_action(0.3, -0.4, 0.5), None);
+        assert_eq!(stick_action(-0.9, 0.2, 0.5), Some(PlayerAction::TurnLeft));
+        assert_eq!(stick_action(0.6, 0.6, 0.5), Some(PlayerAction::TurnRight));
+        assert_eq!(stick_action(0.1, 0.8, 0.5), Some(PlayerAction::GoStraight));
+        assert_eq!(stick_action(-0.1, -0.8, 0.5), Some(PlayerAction::Reverse));
+    }
+
+    #[derive(Default, Resource)]
+    struct Pressed(Vec<PlayerAction>);
+
+    #[test]
+    fn keys_and_buttons_do_the_same_thing() {
+        let mut app = App::new();
+        app.init_resource::<InputBindings>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Gamepads>()
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .init_resource::<Pressed>()
+            .add_systems(
+                Update,
+                |mut input: PlayerInput, mut pressed: ResMut<Pressed>| {
+                    pressed.0 = input.just_pressed();
+                },
+            );
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Down);
+        app.update();
+        assert_eq!(
+            app.world.resource::<Pressed>().0,
+            vec![PlayerAction::Reverse]
+        );
+
+        app.world.resource_mut::<Input<KeyCode>>().clear();
+        app.world
+            .resource_mut::<Input<GamepadButton>>()
+            .press(GamepadButton::new(
+                bevy::input::gamepad::Gamepad::new(0),
+                GamepadButtonType::DPadLeft,
+            ));
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Left);
+        app.update();
+        // Doing it both ways at once still only turns once.
+        assert_eq!(
+            app.world.resource::<Pressed>().0,
+            vec![PlayerAction::TurnLeft]
+        );
+    }
+}
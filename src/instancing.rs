@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::load_internal_asset,
+    core::cast_slice,
+    core_pipeline::core_3d::Opaque3d,
+    ecs::{
+        change_detection::DetectChanges,
+        system::{
+            lifetimeless::{Read, SRes},
+            SystemParamItem,
+        },
+    },
+    pbr::{MeshPipeline, MeshPipelineKey, MeshUniform, SetMeshBindGroup, SetMeshViewBindGroup},
+    prelude::{
+        App, Color, Commands, Component, Entity, FromWorld, Handle, HandleUntyped,
+        IntoSystemConfigs, Msaa, Plugin, Query, Ref, Res, ResMut, Resource, Shader, Vec3, With,
+        World,
+    },
+    reflect::TypeUuid,
+    render::{
+        mesh::{GpuBufferInfo, MeshVertexBufferLayout},
+        render_asset::RenderAssets,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, RenderCommand, RenderCommandResult,
+            RenderPhase, SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::{
+            Buffer, BufferInitDescriptor, BufferUsages, PipelineCache, RenderPipelineDescriptor,
+            SpecializedMeshPipeline, SpecializedMeshPipelineError, SpecializedMeshPipelines,
+            VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
+        },
+        renderer::RenderDevice,
+        view::ExtractedView,
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+};
+
+const INSTANCING_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x3d9a_c0de_5e11_e7a5);
+
+/// Where the instance data starts in the shader, well out of the way of everything a mesh could have (see `MeshPipeline::specialize`).
+const FIRST_INSTANCE_LOCATION: u32 = 10;
+
+/// One copy of an instanced mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instance {
+    pub position: Vec3,
+    /// How many times bigger this copy is than the mesh.
+    pub scale: f32,
+    pub color: Color,
+    /// The colour it glows, even in the dark.
+    pub emissive: Color,
+}
+
+impl Instance {
+    /// How the instance is laid out in the instance buffer, which has to match the shader.
+    fn to_floats(self) -> [f32; 12] {
+        let [x, y, z] = self.position.to_array();
+        let [r, g, b, a] = self.color.as_linear_rgba_f32();
+        let [emissive_r, emissive_g, emissive_b, emissive_a] = self.emissive.as_linear_rgba_f32();
+        [
+            x, y, z, self.scale, r, g, b, a, emissive_r, emissive_g, emissive_b, emissive_a,
+        ]
+    }
+}
+
+/// Draws the entity's mesh once for every one of these, all at once.
+/// The entity itself should stay where it is, and also needs `NoFrustumCulling` (since the mesh alone doesn't say how far the copies spread out).
+#[derive(Clone, Debug, Default, PartialEq, Component)]
+pub struct Instances(pub Vec<Instance>);
+
+/// Bevy draws every mesh separately, which is really slow when there are hundreds of the same thing (like the pellets).
+/// This draws all of the copies of a mesh in one go instead, which is called instancing.
+pub struct InstancingPlugin;
+
+impl Plugin for InstancingPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            INSTANCING_SHADER_HANDLE,
+            "instancing.wgsl",
+            Shader::from_wgsl
+        );
+        app.sub_app_mut(RenderApp)
+            .add_render_command::<Opaque3d, DrawInstanced>()
+            .init_resource::<SpecializedMeshPipelines<InstancingPipeline>>()
+            .init_resource::<ChangedInstances>()
+            .init_resource::<InstanceBuffers>()
+            .add_systems(ExtractSchedule, extract_instances)
+            .add_systems(
+                Render,
+                (
+                    queue_instanced.in_set(RenderSet::Queue),
+                    prepare_instance_buffers.in_set(RenderSet::Prepare),
+                ),
+            );
+    }
+
+    // The mesh pipeline isn't there until everything has been built.
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<InstancingPipeline>();
+    }
+}
+
+/// Marks the entities in the render world which have instances to draw.
+#[derive(Component)]
+struct Instanced;
+
+/// The instances which have changed since the last frame (laid out for the instance buffer), for each entity.
+/// Most frames nothing changes, so there is nothing to copy.
+#[derive(Default, Resource)]
+struct ChangedInstances(HashMap<Entity, Vec<[f32; 12]>>);
+
+/// The instances, sent to the GPU.
+struct InstanceBuffer {
+    buffer: Buffer,
+    length: usize,
+}
+
+/// Everything in the render world is thrown away at the end of each frame, so the buffers are kept here instead (by entity) to be used again until the instances change.
+#[derive(Default, Resource)]
+struct InstanceBuffers(HashMap<Entity, InstanceBuffer>);
+
+fn extract_instances(
+    mut commands: Commands,
+    instances: Extract<Query<(Entity, Ref<Instances>)>>,
+    mut changed: ResMut<ChangedInstances>,
+    mut buffers: ResMut<InstanceBuffers>,
+) {
+    for (entity, entity_instances) in instances.iter() {
+        commands.get_or_spawn(entity).insert(Instanced);
+        if entity_instances.is_changed() {
+            changed.0.insert(
+                entity,
+                entity_instances
+                    .0
+                    .iter()
+                    .map(|instance| instance.to_floats())
+                    .collect(),
+            );
+        }
+    }
+    // The buffers for anything which has gone (or isn't instanced any more) would otherwise stay around forever.
+    buffers.0.retain(|entity, _| instances.contains(*entity));
+}
+
+/// Makes new instance buffers for the instances which have changed, so that they always match the `Instances` (even when some have been taken away).
+fn prepare_instance_buffers(
+    mut changed: ResMut<ChangedInstances>,
+    mut buffers: ResMut<InstanceBuffers>,
+    render_device: Res<RenderDevice>,
+) {
+    for (entity, floats) in changed.0.drain() {
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("instance buffer"),
+            contents: cast_slice(&floats),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        });
+        buffers.0.insert(
+            entity,
+            InstanceBuffer {
+                buffer,
+                length: floats.len(),
+            },
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_instanced(
+    opaque_3d_draw_functions: Res<DrawFunctions<Opaque3d>>,
+    instancing_pipeline: Res<InstancingPipeline>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<InstancingPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<bevy::prelude::Mesh>>,
+    instanced_meshes: Query<(Entity, &MeshUniform, &Handle<bevy::prelude::Mesh>), With<Instanced>>,
+    mut views: Query<(&ExtractedView, &mut RenderPhase<Opaque3d>)>,
+) {
+    let draw_instanced = opaque_3d_draw_functions.read().id::<DrawInstanced>();
+    let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples());
+    for (view, mut opaque_phase) in views.iter_mut() {
+        let view_key = msaa_key | MeshPipelineKey::from_hdr(view.hdr);
+        let rangefinder = view.rangefinder3d();
+        for (entity, mesh_uniform, mesh_handle) in instanced_meshes.iter() {
+            let Some(mesh) = meshes.get(mesh_handle) else {
+                continue;
+            };
+            let key = view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+            let pipeline = match pipelines.specialize(
+                &pipeline_cache,
+                &instancing_pipeline,
+                key,
+                &mesh.layout,
+            ) {
+                Ok(pipeline) => pipeline,
+                Err(error) => {
+                    bevy::log::error!("Couldn't make the instancing pipeline: {}", error);
+                    continue;
+                }
+            };
+            opaque_phase.add(Opaque3d {
+                entity,
+                pipeline,
+                draw_function: draw_instanced,
+                distance: rangefinder.distance(&mesh_uniform.transform),
+            });
+        }
+    }
+}
+
+/// Bevy's normal mesh pipeline, but with our own shader and the instance buffer added on.
+#[derive(Resource)]
+struct InstancingPipeline {
+    mesh_pipeline: MeshPipeline,
+}
+
+impl FromWorld for InstancingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for InstancingPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+        // The mesh's bind group is normally the second one, after the material, but there isn't a material here.
+        descriptor
+            .vertex
+            .shader_defs
+            .push("MESH_BINDGROUP_1".into());
+        descriptor.vertex.shader = INSTANCING_SHADER_HANDLE.typed();
+        let vec4_size = VertexFormat::Float32x4.size();
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; 12]>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: (0..3)
+                .map(|i| VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: vec4_size * i as u64,
+                    shader_location: FIRST_INSTANCE_LOCATION + i,
+                })
+                .collect(),
+        });
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader = INSTANCING_SHADER_HANDLE.typed();
+        }
+        Ok(descriptor)
+    }
+}
+
+type DrawInstanced = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    DrawMeshInstanced,
+);
+
+struct DrawMeshInstanced;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawMeshInstanced {
+    type Param = (
+        SRes<RenderAssets<bevy::prelude::Mesh>>,
+        SRes<InstanceBuffers>,
+    );
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = Read<Handle<bevy::prelude::Mesh>>;
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        mesh_handle: &'w Handle<bevy::prelude::Mesh>,
+        (meshes, buffers): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(gpu_mesh) = meshes.into_inner().get(mesh_handle) else {
+            return RenderCommandResult::Failure;
+        };
+        let Some(instance_buffer) = buffers.into_inner().0.get(&item.entity()) else {
+            return RenderCommandResult::Failure;
+        };
+        // There's nothing to draw, and zero sized buffers can't be bound.
+        if instance_buffer.length == 0 {
+            return RenderCommandResult::Success;
+        }
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, instance_buffer.buffer.slice(..));
+        let instances = 0..instance_buffer.length as u32;
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, instances);
+            }
+            GpuBufferInfo::NonIndexed => {
+                pass.draw(0..gpu_mesh.vertex_count, instances);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn instance_layout() {
+        let instance = Instance {
+            position: Vec3::new(1.0, 2.0, 3.0),
+            scale: 0.5,
+            color: Color::WHITE,
+            emissive: Color::BLACK,
+        };
+        assert_eq!(
+            instance.to_floats(),
+            [1.0, 2.0, 3.0, 0.5, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+        );
+    }
+}
@@ -0,0 +1,38 @@
+// Draws one mesh lots of times over, with the position, size and colours of each copy coming from the instance buffer (see `instancing.rs`).
+#import bevy_pbr::mesh_functions mesh_position_local_to_clip, mesh_normal_local_to_world
+#import bevy_pbr::mesh_bindings mesh
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+
+    // These are the same for the whole of each copy.
+    @location(10) instance_position_scale: vec4<f32>,
+    @location(11) instance_color: vec4<f32>,
+    @location(12) instance_emissive: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) emissive: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let position = vertex.position * vertex.instance_position_scale.w + vertex.instance_position_scale.xyz;
+    var out: VertexOutput;
+    out.clip_position = mesh_position_local_to_clip(mesh.model, vec4<f32>(position, 1.0));
+    out.normal = mesh_normal_local_to_world(vertex.normal);
+    out.color = vertex.instance_color;
+    out.emissive = vertex.instance_emissive;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // This doesn't go through Bevy's lights at all, so we just pretend the light is straight above, with the undersides only half as bright.
+    let light = 0.75 + 0.25 * normalize(in.normal).y;
+    return vec4<f32>(in.color.rgb * light + in.emissive.rgb, in.color.a);
+}
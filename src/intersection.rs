@@ -0,0 +1,126 @@
+use bevy::prelude::{Component, Entity, Event, EventWriter, Query, Res, Transform};
+
+use crate::maze::Maze;
+
+/// Sent when an entity with a `CurrentIntersection` moves onto an intersection (identified by its index in the maze).
+#[derive(Clone, Copy, Debug, PartialEq, Event)]
+pub struct IntersectionEntered(pub Entity, pub usize);
+
+/// Sent when an entity with a `CurrentIntersection` leaves the intersection it was on.
+#[derive(Clone, Copy, Debug, PartialEq, Event)]
+pub struct IntersectionExited(pub Entity, pub usize);
+
+/// Keeps track of which intersection (if any) an entity is currently standing on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub struct CurrentIntersection(pub Option<usize>);
+
+/// How much further than half the path width an entity has to go before we say it has left an intersection.
+/// Without this, something sitting right on the edge would flicker in and out (and send a stream of events) as physics nudges it around.
+const EXIT_TOLERANCE: f32 = 0.05;
+
+fn distance_to_intersection(maze: &Maze, index: usize, position: (f32, f32)) -> f32 {
+    let coordinates = maze.intersections()[index].coordinates;
+    // We treat the intersection as a square (like the rest of the maze), so we only care about the furthest axis.
+    (coordinates.0 - position.0)
+        .abs()
+        .max((coordinates.1 - position.1).abs())
+}
+
+/// Works out which intersection something at this position is on, given the one it was on last time.
+pub fn update_current_intersection(
+    current: Option<usize>,
+    position: (f32, f32),
+    maze: &Maze,
+) -> Option<usize> {
+    if let Some(current) = current {
+        if distance_to_intersection(maze, current, position)
+            <= maze.half_path_width() + EXIT_TOLERANCE
+        {
+            return Some(current);
+        }
+    }
+    (0..maze.intersections().len())
+        .find(|index| distance_to_intersection(maze, *index, position) < maze.half_path_width())
+}
+
+pub fn intersection_events(
+    mut entities: Query<(Entity, &Transform, &mut CurrentIntersection)>,
+    maze: Res<Maze>,
+    mut entered: EventWriter<IntersectionEntered>,
+    mut exited: EventWriter<IntersectionExited>,
+) {
+    for (entity, transform, mut current_intersection) in entities.iter_mut() {
+        let new_intersection = update_current_intersection(
+            current_intersection.0,
+            (transform.translation.x, transform.translation.z),
+            &maze,
+        );
+        if new_intersection != current_intersection.0 {
+            if let Some(old_intersection) = current_intersection.0 {
+                exited.send(IntersectionExited(entity, old_intersection));
+            }
+            if let Some(new_intersection) = new_intersection {
+                entered.send(IntersectionEntered(entity, new_intersection));
+            }
+            current_intersection.0 = new_intersection;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, Events, Update};
+
+    use super::*;
+
+    #[test]
+    fn test_intersection_events() {
+        let maze = Maze::new(&[((-2.0, 0.0), (2.0, 0.0)), ((0.0, -2.0), (0.0, 2.0))]);
+        let mut app = App::new();
+        app.add_event::<IntersectionEntered>()
+            .add_event::<IntersectionExited>()
+            .insert_resource(maze)
+            .add_systems(Update, intersection_events);
+        let entity = app
+            .world
+            .spawn((
+                Transform::from_xyz(-1.0, 0.0, 0.0),
+                CurrentIntersection::default(),
+            ))
+            .id();
+        let mut entered_reader = app
+            .world
+            .resource::<Events<IntersectionEntered>>()
+            .get_reader();
+        let mut exited_reader = app
+            .world
+            .resource::<Events<IntersectionExited>>()
+            .get_reader();
+        let mut entered = Vec::new();
+        let mut exited = Vec::new();
+        // Some of these wobble back and forth over the edges of the intersection, like the physics sometimes does.
+        for x in [
+            -1.0, -0.55, -0.45, -0.52, -0.48, -0.2, 0.0, 0.3, 0.52, 0.48, 0.53, 0.6, 1.0,
+        ] {
+            app.world
+                .entity_mut(entity)
+                .get_mut::<Transform>()
+                .unwrap()
+                .translation
+                .x = x;
+            app.update();
+            entered.extend(
+                entered_reader
+                    .iter(app.world.resource::<Events<IntersectionEntered>>())
+                    .copied(),
+            );
+            exited.extend(
+                exited_reader
+                    .iter(app.world.resource::<Events<IntersectionExited>>())
+                    .copied(),
+            );
+        }
+        assert_eq!(entered, vec![IntersectionEntered(entity, 0)]);
+        assert_eq!(exited, vec![IntersectionExited(entity, 0)]);
+    }
+}
@@ -0,0 +1,426 @@
+use std::fmt;
+
+use bevy::prelude::Resource;
+
+use crate::{
+    ascii::{parse_ascii, MazeParseError},
+    ghost::GhostPersonality,
+    maze::{BridgeError, Maze, MazeBuildError, MazeBuilder},
+    pellet::{pellet_positions, PelletLayout, PelletLayoutError},
+};
+
+/// Everything needed to set up a game: the maze itself and where everything starts.
+#[derive(Clone, Debug, PartialEq, Resource)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Level {
+    /// The segments which make up the maze, as passed to `Maze::new`.
+    #[allow(clippy::type_complexity)]
+    pub paths: Vec<((f32, f32), (f32, f32))>,
+    /// Pairs of paths (as indices into `paths`) which go over each other where they cross, rather than joining (see `Maze::with_bridges`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bridges: Vec<(usize, usize)>,
+    pub player_start: (f32, f32),
+    pub pellets: PelletLayout,
+    pub ghosts: Vec<GhostSpawn>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GhostSpawn {
+    /// This used to be called `ghost_type`, and older level files still use that name.
+    #[cfg_attr(feature = "serde", serde(alias = "ghost_type"))]
+    pub personality: GhostPersonality,
+    pub position: (f32, f32),
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    #[cfg(feature = "serde")]
+    Parse(serde_json::Error),
+    /// Paths have to be horizontal, vertical or at 45 degrees.
+    SlantedPath(((f32, f32), (f32, f32))),
+    DuplicatePath(((f32, f32), (f32, f32))),
+    Bridge {
+        paths: (usize, usize),
+        error: BridgeError,
+    },
+    StartOffPath((f32, f32)),
+    Pellets(PelletLayoutError),
+    /// Some of the paths aren't joined to the rest of the maze.
+    Disconnected,
+    Ascii(MazeParseError),
+    /// ASCII levels need a `P` to say where the player starts.
+    MissingPlayerStart,
+    /// The ghost is either off the paths or on a bit of the maze which isn't joined to where the player starts.
+    GhostUnreachable {
+        personality: GhostPersonality,
+        position: (f32, f32),
+    },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Io(error) => write!(f, "couldn't read the level: {}", error),
+            #[cfg(feature = "serde")]
+            LevelError::Parse(error) => write!(f, "couldn't parse the level: {}", error),
+            LevelError::SlantedPath(path) => write!(
+                f,
+                "the path from {:?} to {:?} isn't horizontal, vertical or at 45 degrees",
+                path.0, path.1
+            ),
+            LevelError::DuplicatePath(path) => write!(
+                f,
+                "the path from {:?} to {:?} is in the level twice",
+                path.0, path.1
+            ),
+            LevelError::Bridge { paths, error } => write!(
+                f,
+                "there can't be a bridge between paths {} and {}: {}",
+                paths.0, paths.1, error
+            ),
+            LevelError::StartOffPath(position) => {
+                write!(
+                    f,
+                    "the player starts at {:?}, which isn't on a path",
+                    position
+                )
+            }
+            LevelError::Pellets(error) => write!(f, "{}", error),
+            LevelError::Disconnected => {
+                write!(f, "some of the paths aren't joined to the rest of the maze")
+            }
+            LevelError::Ascii(error) => write!(f, "{}", error),
+            LevelError::MissingPlayerStart => {
+                write!(f, "there isn't a 'P' to say where the player starts")
+            }
+            LevelError::GhostUnreachable {
+                personality,
+                position,
+            } => write!(
+                f,
+                "{:?} starts at {:?}, where it can't get to the player",
+                personality, position
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<MazeBuildError> for LevelError {
+    fn from(error: MazeBuildError) -> Self {
+        match error {
+            MazeBuildError::SlantedPath(path) => LevelError::SlantedPath(path),
+            MazeBuildError::DuplicatePath(path) => LevelError::DuplicatePath(path),
+            MazeBuildError::Bridge { paths, error } => LevelError::Bridge { paths, error },
+        }
+    }
+}
+
+impl Default for Level {
+    /// The level which the game has always started with.
+    fn default() -> Self {
+        Self {
+            paths: MazeBuilder::new()
+                .horizontal(0.0, -10.0, 10.0)
+                .vertical(0.0, -10.0, 10.0)
+                .horizontal(10.0, -10.0, 10.0)
+                .horizontal(-10.0, -10.0, 10.0)
+                .vertical(10.0, -10.0, 10.0)
+                .vertical(-10.0, -10.0, 10.0)
+                // The bottom left loop.
+                .vertical(-5.0, 10.0, 20.0)
+                .horizontal(20.0, -5.0, -15.0)
+                .vertical(-15.0, 20.0, 5.0)
+                .horizontal(5.0, -15.0, -10.0)
+                // The bottom right loop.
+                .vertical(5.0, 10.0, 20.0)
+                .horizontal(20.0, 5.0, 15.0)
+                .vertical(15.0, 20.0, 5.0)
+                .horizontal(5.0, 15.0, 10.0)
+                // The top right loop.
+                .vertical(5.0, -10.0, -20.0)
+                .horizontal(-20.0, 5.0, 15.0)
+                .vertical(15.0, -20.0, -5.0)
+                .horizontal(-5.0, 15.0, 10.0)
+                // The top left loop.
+                .vertical(-5.0, -10.0, -20.0)
+                .horizontal(-20.0, -5.0, -15.0)
+                .vertical(-15.0, -20.0, -5.0)
+                .horizontal(-5.0, -15.0, -10.0)
+                // Joining the left and right loops together down the sides.
+                .vertical(-15.0, 5.0, -5.0)
+                .vertical(15.0, 5.0, -5.0)
+                .into_paths()
+                .expect("The default level should be valid"),
+            bridges: Vec::new(),
+            player_start: (0.0, 0.0),
+            pellets: PelletLayout::Spacing(1.0),
+            ghosts: vec![
+                GhostSpawn {
+                    personality: GhostPersonality::Blinky,
+                    position: (5.0, 20.0),
+                },
+                GhostSpawn {
+                    personality: GhostPersonality::Pinky,
+                    position: (-5.0, 20.0),
+                },
+                GhostSpawn {
+                    personality: GhostPersonality::Inky,
+                    position: (5.0, 20.0),
+                },
+                GhostSpawn {
+                    personality: GhostPersonality::Clyde,
+                    position: (-5.0, 20.0),
+                },
+            ],
+        }
+    }
+}
+
+impl Level {
+    /// Checks that the level actually makes sense, and builds the maze for it.
+    pub fn validate(&self) -> Result<Maze, LevelError> {
+        let builder = self.paths.iter().fold(MazeBuilder::new(), |builder, path| {
+            builder.segment(path.0, path.1)
+        });
+        let maze = self
+            .bridges
+            .iter()
+            .fold(builder, |builder, &(a, b)| builder.bridge(a, b))
+            .build()?;
+        if !maze.is_fully_connected() {
+            return Err(LevelError::Disconnected);
+        }
+        let Some(player_edge) = maze.edge_at(self.player_start) else {
+            return Err(LevelError::StartOffPath(self.player_start));
+        };
+        pellet_positions(&maze, self.pellets).map_err(LevelError::Pellets)?;
+        for ghost in &self.ghosts {
+            if !maze.is_reachable(player_edge, ghost.position) {
+                return Err(LevelError::GhostUnreachable {
+                    personality: ghost.personality.clone(),
+                    position: ghost.position,
+                });
+            }
+        }
+        Ok(maze)
+    }
+
+    /// Makes a level out of an ASCII maze (see `parse_ascii`), with each cell `cell_size` units across.
+    /// The ghosts get the four personalities in order, starting again from Blinky if there are more than four of them.
+    pub fn from_ascii(text: &str, cell_size: f32) -> Result<Level, LevelError> {
+        let layout = parse_ascii(text, cell_size).map_err(LevelError::Ascii)?;
+        let personalities = [
+            GhostPersonality::Blinky,
+            GhostPersonality::Pinky,
+            GhostPersonality::Inky,
+            GhostPersonality::Clyde,
+        ];
+        let level = Level {
+            paths: layout.paths,
+            player_start: layout.player_start.ok_or(LevelError::MissingPlayerStart)?,
+            ghosts: layout
+                .ghost_spawns
+                .into_iter()
+                .zip(personalities.into_iter().cycle())
+                .map(|(position, personality)| GhostSpawn {
+                    personality,
+                    position,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        level.validate()?;
+        Ok(level)
+    }
+
+    /// Reads a level from a file, making sure it is valid.
+    /// JSON files (with the serde feature) are loaded as they are, and anything else is treated as an ASCII maze with cells `cell_size` units across.
+    pub fn load(path: impl AsRef<std::path::Path>, cell_size: f32) -> Result<Level, LevelError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(LevelError::Io)?;
+        #[cfg(feature = "serde")]
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let level: Level = serde_json::from_str(&text).map_err(LevelError::Parse)?;
+            level.validate()?;
+            return Ok(level);
+        }
+        Level::from_ascii(&text, cell_size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_level_is_valid() {
+        assert!(Level::default().validate().is_ok());
+    }
+
+    #[test]
+    fn ascii_level() {
+        let text = "
+            G...G
+            .#.#.
+            ..P..
+        ";
+        let level = Level::from_ascii(text, 1.0).unwrap();
+        assert_eq!(level.player_start, (2.0, 2.0));
+        assert_eq!(
+            level.ghosts,
+            vec![
+                GhostSpawn {
+                    personality: GhostPersonality::Blinky,
+                    position: (0.0, 0.0),
+                },
+                GhostSpawn {
+                    personality: GhostPersonality::Pinky,
+                    position: (4.0, 0.0),
+                },
+            ]
+        );
+        assert!(matches!(
+            Level::from_ascii("...\n.#.\n...", 1.0),
+            Err(LevelError::MissingPlayerStart)
+        ));
+        // Everything is further apart with bigger cells, including where everyone starts.
+        let level = Level::from_ascii(text, 4.0).unwrap();
+        assert_eq!(level.player_start, (8.0, 8.0));
+        assert_eq!(level.ghosts[1].position, (16.0, 0.0));
+        assert!(level.paths.contains(&((0.0, 0.0), (16.0, 0.0))));
+    }
+
+    #[test]
+    fn start_off_path_is_rejected() {
+        let level = Level {
+            player_start: (2.0, 3.0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            level.validate(),
+            Err(LevelError::StartOffPath((2.0, 3.0)))
+        ));
+        let mut level = Level {
+            paths: vec![((0.0, 0.0), (10.0, 0.0)), ((0.0, 5.0), (10.0, 5.0))],
+            ghosts: vec![GhostSpawn {
+                personality: GhostPersonality::Pinky,
+                position: (5.0, 5.0),
+            }],
+            ..Default::default()
+        };
+        // The ghost's path isn't joined to the player's, which is caught before we get to the ghosts.
+        assert!(matches!(level.validate(), Err(LevelError::Disconnected)));
+        level.paths.pop();
+        assert!(matches!(
+            level.validate(),
+            Err(LevelError::GhostUnreachable { .. })
+        ));
+    }
+
+    #[test]
+    fn bridges() {
+        let mut level = Level {
+            paths: vec![
+                ((-5.0, 0.0), (5.0, 0.0)),
+                ((0.0, -5.0), (0.0, 5.0)),
+                ((-5.0, 5.0), (5.0, 5.0)),
+                ((-5.0, 0.0), (-5.0, 5.0)),
+            ],
+            bridges: vec![(0, 1)],
+            player_start: (-5.0, 0.0),
+            ghosts: Vec::new(),
+            ..Default::default()
+        };
+        let maze = level.validate().unwrap();
+        assert!(!maze
+            .intersections()
+            .iter()
+            .any(|intersection| intersection.coordinates == (0.0, 0.0)));
+        // A bad bridge in a level file shouldn't crash the game.
+        level.bridges = vec![(1, 2)];
+        assert!(matches!(
+            level.validate(),
+            Err(LevelError::Bridge {
+                paths: (1, 2),
+                error: BridgeError::AtEnd
+            })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn load_level() {
+        use bevy::{
+            asset::{AddAsset, AssetPlugin},
+            prelude::{
+                App, Mesh, MinimalPlugins, StandardMaterial, Startup, Transform, With, Without,
+            },
+        };
+
+        use crate::{
+            ghost::Ghost,
+            pellet::{Pellet, PowerPellet},
+            Player,
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("pacman3d-test-level-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{
+                "paths": [[[-5.0, 0.0], [5.0, 0.0]], [[0.0, -5.0], [0.0, 5.0]]],
+                "player_start": [0.0, 4.0],
+                "pellets": "Midpoints",
+                "ghosts": [
+                    { "personality": "Blinky", "position": [-5.0, 0.0] },
+                    { "ghost_type": "Pinky", "position": [5.0, 0.0] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let level = Level::load(&path, 1.0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // The old name for a ghost's personality still works.
+        assert_eq!(level.ghosts[1].personality, GhostPersonality::Pinky);
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .insert_resource(level.clone())
+            .init_resource::<crate::theme::MazeTheme>()
+            .init_resource::<crate::CharacterSize>()
+            .add_systems(Startup, crate::setup_world);
+        app.update();
+
+        assert_eq!(*app.world.resource::<Maze>(), Maze::new(&level.paths));
+        let player_position = app
+            .world
+            .query_filtered::<&Transform, With<Player>>()
+            .single(&app.world)
+            .translation;
+        assert_eq!((player_position.x, player_position.z), (0.0, 4.0));
+        let mut ghost_positions = app
+            .world
+            .query_filtered::<&Transform, (With<Ghost>, Without<Player>)>()
+            .iter(&app.world)
+            .map(|transform| (transform.translation.x, transform.translation.z))
+            .collect::<Vec<_>>();
+        ghost_positions.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(ghost_positions, vec![(-5.0, 0.0), (5.0, 0.0)]);
+        // The two paths are split into four by the intersection in the middle.
+        let pellets = app
+            .world
+            .query_filtered::<(), (With<Pellet>, Without<PowerPellet>)>()
+            .iter(&app.world)
+            .count();
+        assert_eq!(pellets, 4);
+    }
+}
@@ -1,24 +1,463 @@
-use std::f32::consts::PI;
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
 
+use ascii::DEFAULT_CELL_SIZE;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use ghost::{ghost_movement, Ghost};
-use maze::{Intersection, Maze, Path, HALF_PATH_WIDTH};
-use object::{GameObject, MeshComponent};
+use camera::{
+    apply_camera_mode, cycle_camera_mode, free_fly_movement, toggle_free_fly, CameraMode,
+    FreeFlyConfig, PlayerCamera,
+};
+use difficulty::Difficulty;
+use editor::{
+    editor_input, load_maze, rebuild_maze, save_maze, stop_moving, toggle_editor, EditorState,
+};
+use ghost::{
+    bob_penned_ghosts, cruise_elroy, eat_ghosts, frighten_ghosts, ghost_movement,
+    ghost_reached_player, release_ghosts, update_frightened, update_ghost_mode, FrightenedConfig,
+    GhostEaten, GhostHouse, GhostModeTimer, GhostReleaseSchedule, GhostState, PathCache,
+    PathfindingBudget, Penned, PlayerCaught, SpawnPoint,
+};
+use input::{read_player_input, InputBindings, PendingActions, PlayerAction, PlayerActions};
+use intersection::{
+    intersection_events, CurrentIntersection, IntersectionEntered, IntersectionExited,
+};
+use level::Level;
+use math::euclidean;
+use maze::{Intersection, Maze, Path, DEFAULT_HALF_PATH_WIDTH};
+use object::{ColliderStrategy, GameObject};
+use pause::{not_paused, toggle_pause, Paused, PausedVelocities};
+use pellet::{
+    all_pellets_collected, pellet_collection, spawn_pellets, update_pellet_instances, Pellet,
+    PelletEaten, PelletLayout, PowerPelletEaten,
+};
+use progression::LevelNumber;
+use replay::{save_recording, ReplayHeader, ReplayPlayer, ReplayRecorder, ReplaySavePath};
+use route::{spawn_route_guides, RouteGuide};
+use score::{update_score, Lives, Score};
+use step::{
+    advance_step_time, interpolate_positions, limit_steps_per_frame, record_positions,
+    restore_positions, Interpolated, StepTime, STEP,
+};
+use theme::MazeTheme;
 
-use crate::ghost::{create_ghost, GhostType};
+use crate::ghost::{ghost_height, min_spawn_spacing, spawn_ghosts, spread_spawn_points};
 
+mod ascii;
+mod audio;
+mod camera;
+#[cfg(debug_assertions)]
+mod debug;
+#[cfg(feature = "debug-ui")]
+mod debug_ui;
+mod difficulty;
+mod editor;
 mod ghost;
+#[cfg(feature = "high-score")]
+mod high_score;
+mod hud;
+mod input;
+mod instancing;
+mod intersection;
+mod level;
+mod math;
 mod maze;
+mod minimap;
 mod object;
+mod pause;
+mod pellet;
+mod progression;
+mod replay;
+mod route;
+mod score;
+mod step;
+mod theme;
+
+/// Each step of the physics is split up into this many, so that nothing can go through the walls.
+const PHYSICS_SUBSTEPS: usize = 4;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
-        .add_systems(Startup, setup_graphics)
-        .add_systems(Update, (player_movement, ghost_movement, death))
-        .run();
+    let command_line = parse_command_line(std::env::args().skip(1));
+    let mut app = App::new();
+    app.add_plugins((DefaultPlugins, instancing::InstancingPlugin))
+        .insert_resource(choose_level(
+            command_line.level.as_deref(),
+            command_line.cell_size.as_deref(),
+            command_line.pellets.as_deref(),
+        ))
+        .insert_resource(choose_difficulty(command_line.difficulty.as_deref()))
+        .init_resource::<EditorState>()
+        .init_resource::<CameraMode>()
+        .init_resource::<FreeFlyConfig>()
+        .init_resource::<hud::HudFont>()
+        .add_systems(
+            Startup,
+            (
+                // The camera goes on the player, so the player has to actually be there first.
+                (setup_world, apply_deferred, setup_rendering).chain(),
+                minimap::setup_minimap,
+                hud::setup_hud,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_editor,
+                toggle_free_fly,
+                cycle_camera_mode,
+                apply_camera_mode
+                    .after(toggle_free_fly)
+                    .after(cycle_camera_mode),
+                free_fly_movement.after(apply_camera_mode),
+                spawn_route_guides.run_if(resource_exists_and_changed::<RouteGuide>()),
+                minimap::draw_minimap_maze.run_if(resource_exists_and_changed::<Maze>()),
+                minimap::update_minimap_markers.run_if(resource_exists::<Maze>()),
+                update_pellet_instances,
+                hud::update_hud,
+                theme::cycle_theme,
+                theme::apply_theme
+                    .after(theme::cycle_theme)
+                    .run_if(resource_changed::<MazeTheme>()),
+            ),
+        )
+        .add_systems(OnEnter(GameState::Editor), stop_moving)
+        .add_systems(
+            Update,
+            (
+                editor_input,
+                load_maze,
+                save_maze,
+                rebuild_maze.after(editor_input).after(load_maze),
+            )
+                .run_if(in_state(GameState::Editor)),
+        );
+    add_gameplay(&mut app);
+    add_replays(&mut app, &command_line);
+    // The sounds are left out of the tests, which don't have anything to play them on.
+    app.init_resource::<audio::SoundPaths>()
+        .init_resource::<audio::Sounds>()
+        .add_systems(Startup, audio::load_sounds)
+        .add_systems(
+            Update,
+            (
+                audio::play_sound_effects,
+                audio::update_siren.after(audio::play_sound_effects),
+                audio::despawn_failed_sounds,
+            ),
+        )
+        .add_systems(OnEnter(GameState::Playing), audio::start_siren)
+        .add_systems(OnExit(GameState::Playing), audio::stop_siren);
+    #[cfg(feature = "high-score")]
+    if let Some(path) = high_score::high_score_path() {
+        app.insert_resource(high_score::load_high_score(&path))
+            .insert_resource(high_score::HighScorePath(path))
+            .add_systems(OnEnter(GameState::Won), high_score::record_high_score)
+            .add_systems(OnEnter(GameState::Lost), high_score::record_high_score);
+    }
+    #[cfg(feature = "debug-ui")]
+    app.add_plugins((
+        bevy_egui::EguiPlugin,
+        bevy::diagnostic::FrameTimeDiagnosticsPlugin,
+    ))
+    .add_systems(Update, debug_ui::tuning_panel);
+    #[cfg(debug_assertions)]
+    app.init_resource::<debug::ShowMazeGraph>()
+        .init_resource::<debug::ShowGhostPaths>()
+        .add_systems(
+            Update,
+            (
+                debug::speed_trap,
+                debug::toggle_maze_graph,
+                debug::draw_maze_graph.after(debug::toggle_maze_graph),
+                debug::toggle_ghost_paths,
+                debug::draw_ghost_paths
+                    .after(debug::toggle_ghost_paths)
+                    .after(ghost_movement),
+            ),
+        );
+    app.run();
+}
+
+/// Everything needed to actually play the game, leaving out the drawing and the editor.
+/// The tests use this as well (see `build_test_app`), so that they are playing the same game.
+fn add_gameplay(app: &mut App) {
+    app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false))
+        .insert_resource(FixedTime::new_from_secs(STEP))
+        // Rapier steps once every time `FixedUpdate` runs, so it has to step by exactly as long as `FixedUpdate` does.
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: STEP,
+                substeps: PHYSICS_SUBSTEPS,
+            },
+            ..Default::default()
+        })
+        .add_event::<IntersectionEntered>()
+        .add_event::<IntersectionExited>()
+        .add_event::<PelletEaten>()
+        .add_event::<PowerPelletEaten>()
+        .add_event::<GhostEaten>()
+        .add_event::<PlayerCaught>()
+        .init_resource::<StepTime>()
+        .init_resource::<PlayerConfig>()
+        .init_resource::<CharacterSize>()
+        .init_resource::<Difficulty>()
+        .init_resource::<LevelNumber>()
+        .init_resource::<InputBindings>()
+        .init_resource::<PendingActions>()
+        .init_resource::<PathfindingBudget>()
+        .init_resource::<FrightenedConfig>()
+        .init_resource::<GhostReleaseSchedule>()
+        .init_resource::<Paused>()
+        .init_resource::<PausedVelocities>()
+        // The maze is coloured in as it is spawned, so this is needed even when nothing is being drawn.
+        .init_resource::<MazeTheme>()
+        .add_state::<GameState>()
+        // Everything which moves (including the physics) does it on fixed steps, so that the game plays out the same however fast it is drawn.
+        // Rapier would normally do its own thing in `PostUpdate`, so we put its systems in the steps ourselves.
+        .configure_sets(
+            FixedUpdate,
+            (
+                PhysicsSet::SyncBackend,
+                PhysicsSet::SyncBackendFlush,
+                // Pausing stops the physics too, so that nothing drifts or falls while everything else is stopped.
+                PhysicsSet::StepSimulation.run_if(not_paused),
+                PhysicsSet::Writeback,
+            )
+                .chain(),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                limit_steps_per_frame,
+                restore_positions,
+                (
+                    advance_step_time,
+                    (
+                        player_movement,
+                        // Turning snaps the player's position, so the ghosts need to see where they ended up.
+                        ghost_movement.after(player_movement),
+                        // The ghosts' states need to be looked at before they change, so that a ghost which stops being frightened this frame can still be eaten.
+                        ghost_reached_player.before(update_frightened),
+                        // Everything which happens because of the events is done in the same step they are sent, so that none of them are missed.
+                        eat_ghosts.after(ghost_reached_player),
+                        // Getting caught puts every ghost back, even one which was just eaten.
+                        catch_player.after(eat_ghosts),
+                        update_frightened,
+                        update_ghost_mode.before(ghost_movement),
+                        cruise_elroy.before(ghost_movement),
+                        // The ghosts which are let out this step still bob one last time, since they are only unpenned at the end of the step.
+                        bob_penned_ghosts.before(release_ghosts),
+                        // This counts this step's pellets too, and getting caught puts everyone back in the house before anyone can be let out.
+                        release_ghosts
+                            .after(pellet_collection)
+                            .after(catch_player)
+                            .before(ghost_movement),
+                        pellet_collection,
+                        update_score
+                            .after(pellet_collection)
+                            .after(ghost_reached_player),
+                        frighten_ghosts.after(pellet_collection),
+                        check_win.after(pellet_collection),
+                    )
+                        .run_if(in_state(GameState::Playing)),
+                )
+                    .chain()
+                    .run_if(not_paused),
+                intersection_events,
+            )
+                .chain()
+                .before(PhysicsSet::SyncBackend),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend)
+                    .in_set(PhysicsSet::SyncBackend),
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackendFlush)
+                    .in_set(PhysicsSet::SyncBackendFlush),
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation)
+                    .in_set(PhysicsSet::StepSimulation),
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback)
+                    .in_set(PhysicsSet::Writeback),
+                record_positions.after(PhysicsSet::Writeback),
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_pause.run_if(in_state(GameState::Playing)),
+                // The keys are read every frame, and wait there for the next step.
+                read_player_input
+                    .after(toggle_pause)
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(not_paused),
+            ),
+        )
+        .add_systems(
+            PostUpdate,
+            interpolate_positions.before(bevy::transform::TransformSystem::TransformPropagate),
+        )
+        .add_systems(
+            OnEnter(GameState::Won),
+            (stop_moving, advance_level.after(stop_moving)),
+        )
+        .add_systems(OnEnter(GameState::Lost), stop_moving)
+        .add_systems(Update, new_game.run_if(in_state(GameState::Lost)));
+}
+
+/// Makes an app which plays the level without a window or anything being drawn, for testing the game as a whole.
+/// Every update is exactly one step of the game (however long it actually takes), so the tests come out the same every time.
+/// The very first update doesn't have any time in it though, so it doesn't step at all.
+#[cfg(test)]
+fn build_test_app(level: Level) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        bevy::input::InputPlugin,
+        // Rapier needs this, even though there aren't any scenes.
+        bevy::scene::ScenePlugin,
+    ))
+    // The meshes and materials are still made, but nothing ever draws them.
+    .add_asset::<Mesh>()
+    .add_asset::<StandardMaterial>()
+    .insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(
+        std::time::Duration::from_secs_f32(STEP),
+    ))
+    .insert_resource(level)
+    .add_systems(Startup, setup_world);
+    add_gameplay(&mut app);
+    app
+}
+
+/// How fast a ghost starts going after the player on this difficulty and level, on the same maze each time.
+#[cfg(test)]
+fn ghost_speed_with(difficulty: Difficulty, level: LevelNumber) -> f32 {
+    let mut app = App::new();
+    app.insert_resource(Maze::new(&[((-10.0, 0.0), (10.0, 0.0))]))
+        .insert_resource(difficulty)
+        .insert_resource(level)
+        .init_resource::<StepTime>()
+        .init_resource::<PathfindingBudget>()
+        .init_resource::<GhostModeTimer>()
+        .add_systems(Update, ghost_movement);
+    app.world
+        .spawn((Player::default(), Transform::from_xyz(10.0, 0.0, 0.0)));
+    let ghost = app
+        .world
+        .spawn((
+            ghost::Ghost::new(ghost::GhostPersonality::Blinky),
+            GhostState::Normal,
+            Transform::from_xyz(-5.0, 0.0, 0.0),
+            Velocity::zero(),
+            PathCache::default(),
+        ))
+        .id();
+    app.world
+        .resource_mut::<StepTime>()
+        .advance(std::time::Duration::from_secs_f32(STEP));
+    app.update();
+    app.world.get::<Velocity>(ghost).unwrap().linvel.length()
+}
+
+/// What was asked for on the command line.
+/// That is a level to load, along with `--record <file>` to record the game or `--replay <file>` to play a recording back.
+/// `--difficulty <easy|normal|hard>` picks the `Difficulty`, and `--cell-size <units>` is how big each cell of an ASCII level is.
+/// `--pellets <layout>` lays the pellets out differently to how the level says to (see `PelletLayout::from_name`).
+#[derive(Clone, Debug, Default, PartialEq)]
+struct CommandLine {
+    level: Option<String>,
+    record: Option<String>,
+    replay: Option<String>,
+    difficulty: Option<String>,
+    cell_size: Option<String>,
+    pellets: Option<String>,
+}
+
+fn parse_command_line(args: impl IntoIterator<Item = String>) -> CommandLine {
+    let mut command_line = CommandLine::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => command_line.record = args.next(),
+            "--replay" => command_line.replay = args.next(),
+            "--difficulty" => command_line.difficulty = args.next(),
+            "--cell-size" => command_line.cell_size = args.next(),
+            "--pellets" => command_line.pellets = args.next(),
+            _ => command_line.level = Some(arg),
+        }
+    }
+    command_line
+}
+
+/// Sets up recording or playing back a replay, if the command line asked for it.
+/// The `Difficulty` has to have been picked already, since it goes in the replay along with the level.
+fn add_replays(app: &mut App, command_line: &CommandLine) {
+    let header = ReplayHeader {
+        level: command_line.level.clone(),
+        difficulty: *app.world.resource::<Difficulty>(),
+    };
+    if let Some(path) = &command_line.record {
+        app.insert_resource(ReplayRecorder::new(header.clone()))
+            .insert_resource(ReplaySavePath(path.into()))
+            .add_systems(OnEnter(GameState::Won), save_recording)
+            .add_systems(OnEnter(GameState::Lost), save_recording);
+    }
+    if let Some(path) = &command_line.replay {
+        match ReplayPlayer::load_replay(path, &header) {
+            Ok(replay) => {
+                app.insert_resource(replay);
+            }
+            Err(error) => error!("Couldn't load the replay from {}: {}", path, error),
+        }
+    }
+}
+
+/// Loads the level given on the command line, falling back to the built in one.
+/// A different pellet layout is only used if it actually works with the level's maze.
+fn choose_level(path: Option<&str>, cell_size: Option<&str>, pellets: Option<&str>) -> Level {
+    let cell_size = match cell_size.map(str::parse::<f32>) {
+        Some(Ok(cell_size)) => cell_size,
+        Some(Err(error)) => {
+            error!("The cell size has to be a number: {}", error);
+            DEFAULT_CELL_SIZE
+        }
+        None => DEFAULT_CELL_SIZE,
+    };
+    let mut level = path
+        .and_then(|path| match Level::load(path, cell_size) {
+            Ok(level) => Some(level),
+            Err(error) => {
+                error!("Couldn't load the level from {}: {}", path, error);
+                None
+            }
+        })
+        .unwrap_or_default();
+    if let Some(name) = pellets {
+        match PelletLayout::from_name(name) {
+            Some(layout) => {
+                let original = std::mem::replace(&mut level.pellets, layout);
+                if let Err(error) = level.validate() {
+                    error!("Can't lay the pellets out like {:?}: {}", name, error);
+                    level.pellets = original;
+                }
+            }
+            None => error!("There isn't a pellet layout called {:?}", name),
+        }
+    }
+    level
+}
+
+/// The difficulty given on the command line, or `Normal` if there wasn't one.
+fn choose_difficulty(name: Option<&str>) -> Difficulty {
+    let Some(name) = name else {
+        return Difficulty::default();
+    };
+    Difficulty::from_name(name).unwrap_or_else(|| {
+        error!("There isn't a difficulty called {:?}", name);
+        Difficulty::default()
+    })
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
@@ -28,13 +467,66 @@ enum Direction {
     #[default]
     Forward,
     Backward,
+    /// The diagonals, for going along the paths at 45 degrees.
+    ForwardLeft,
+    ForwardRight,
+    BackwardLeft,
+    BackwardRight,
 }
 
 impl Direction {
+    /// The four straight directions.
+    fn all() -> [Direction; 4] {
+        [
+            Direction::Forward,
+            Direction::Backward,
+            Direction::Left,
+            Direction::Right,
+        ]
+    }
+
+    /// The four straight directions, followed by the diagonals.
+    fn all_with_diagonals() -> [Direction; 8] {
+        let [forward, backward, left, right] = Self::all();
+        [
+            forward,
+            backward,
+            left,
+            right,
+            Direction::ForwardLeft,
+            Direction::ForwardRight,
+            Direction::BackwardLeft,
+            Direction::BackwardRight,
+        ]
+    }
+
+    /// Going round anticlockwise (looking down from above), an eighth of a turn at a time.
+    const ROUND: [Direction; 8] = [
+        Direction::Forward,
+        Direction::ForwardLeft,
+        Direction::Left,
+        Direction::BackwardLeft,
+        Direction::Backward,
+        Direction::BackwardRight,
+        Direction::Right,
+        Direction::ForwardRight,
+    ];
+
+    /// Turns anticlockwise (looking down from above) by this many eighths of a turn, or clockwise if it is negative.
+    fn rotate(&self, eighths: i32) -> Self {
+        let index = Self::ROUND
+            .iter()
+            .position(|direction| direction == self)
+            .unwrap() as i32;
+        Self::ROUND[(index + eighths).rem_euclid(8) as usize]
+    }
+
     fn x_velocity(&self) -> f32 {
         match self {
             Direction::Left => -1.0,
             Direction::Right => 1.0,
+            Direction::ForwardLeft | Direction::BackwardLeft => -FRAC_1_SQRT_2,
+            Direction::ForwardRight | Direction::BackwardRight => FRAC_1_SQRT_2,
             _ => 0.0,
         }
     }
@@ -43,56 +535,44 @@ impl Direction {
         match self {
             Direction::Forward => -1.0,
             Direction::Backward => 1.0,
+            Direction::ForwardLeft | Direction::ForwardRight => -FRAC_1_SQRT_2,
+            Direction::BackwardLeft | Direction::BackwardRight => FRAC_1_SQRT_2,
             _ => 0.0,
         }
     }
 
+    fn is_diagonal(&self) -> bool {
+        self.x_velocity() != 0.0 && self.z_velocity() != 0.0
+    }
+
+    /// The angle of this direction in the maze's coordinates (which are the same as x and z), like `maze::angle_of` gives.
+    fn angle(&self) -> f32 {
+        maze::angle_of((self.x_velocity(), self.z_velocity()))
+    }
+
     fn get_rotation(&self) -> Quat {
-        match self {
-            Direction::Forward => Quat::from_rotation_y(0.0),
-            Direction::Left => Quat::from_rotation_y(PI / 2.0),
-            Direction::Backward => Quat::from_rotation_y(PI),
-            Direction::Right => Quat::from_rotation_y(PI * 1.5),
-        }
+        let eighths = Self::ROUND
+            .iter()
+            .position(|direction| direction == self)
+            .unwrap();
+        Quat::from_rotation_y(PI / 4.0 * eighths as f32)
     }
 
     fn rotate_left(&self) -> Self {
-        match self {
-            Direction::Forward => Direction::Left,
-            Direction::Left => Direction::Backward,
-            Direction::Backward => Direction::Right,
-            Direction::Right => Direction::Forward,
-        }
+        self.rotate(2)
     }
 
     fn rotate_right(&self) -> Self {
-        match self {
-            Direction::Forward => Direction::Right,
-            Direction::Right => Direction::Backward,
-            Direction::Backward => Direction::Left,
-            Direction::Left => Direction::Forward,
-        }
+        self.rotate(-2)
     }
 
     fn rotate_backward(&self) -> Self {
-        match self {
-            Direction::Forward => Direction::Backward,
-            Direction::Backward => Direction::Forward,
-            Direction::Left => Direction::Right,
-            Direction::Right => Direction::Left,
-        }
+        self.rotate(4)
     }
 
-    fn intersection_path<'a>(&self, intersection: &'a Intersection) -> &'a Option<Path> {
-        // The catch here is that our notion of forward is the opposite of the intersection's.
-        // We say forward is the negative z direction (which is how Bevy does it).
-        // The intersection says forward is the positive y direction though, so this may look a bit strange.
-        match self {
-            Direction::Forward => &intersection.backward,
-            Direction::Backward => &intersection.forward,
-            Direction::Left => &intersection.left,
-            Direction::Right => &intersection.right,
-        }
+    fn intersection_path<'a>(&self, intersection: &'a Intersection) -> Option<&'a Path> {
+        // Our notion of forward is the negative z direction (which is how Bevy does it), which is the intersection's backward, but going by the x and z velocities sorts that out.
+        intersection.path_at(self.angle())
     }
 }
 
@@ -100,159 +580,488 @@ impl Direction {
 pub struct Player {
     current_direction: Direction,
     queued_direction: Option<Direction>,
+    /// When (in seconds since the game started) the turn was queued.
+    queued_at: f32,
+    /// Which way the player should be facing.
+    /// Turns happen straight away as far as movement goes, but the player (and the camera with them) turns to face the new way over a short time.
+    target_rotation: Quat,
+    /// The two intersections at the ends of the path the player is on (both the same one when they are right on it).
+    current_path: Option<(usize, usize)>,
+}
+
+impl Player {
+    /// Where the player was as of the last `player_movement`, so that everything else doesn't have to look it up again.
+    pub fn current_path(&self) -> Option<(usize, usize)> {
+        self.current_path
+    }
+
+    fn queue_turn(&mut self, direction: Direction, now: f32) {
+        self.queued_direction = Some(direction);
+        self.queued_at = now;
+    }
+
+    /// Forgets about the queued turn if it has been waiting for longer than `expiry` seconds.
+    /// Otherwise a turn pressed long before the player gets anywhere near an intersection would still happen, which feels wrong.
+    fn expire_queued_turn(&mut self, now: f32, expiry: f32) {
+        if now - self.queued_at > expiry {
+            self.queued_direction = None;
+        }
+    }
 }
 
 #[derive(Component)]
 struct IntersectionComponent(pub Intersection);
 
-fn setup_graphics(
+/// Marks the game object holding the walls of the maze, so that it can be found and rebuilt if the maze changes.
+#[derive(Component)]
+struct MazeWalls;
+
+/// The ground under the maze, which is found again to colour it in when the theme changes.
+#[derive(Component)]
+struct Floor;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, States)]
+pub enum GameState {
+    #[default]
+    Playing,
+    Editor,
+    /// Every pellet has been eaten.
+    Won,
+    /// The player has run out of lives.
+    Lost,
+}
+
+/// Spawns the walls of the maze, along with the sensors we use to tell when something is on an intersection.
+fn spawn_maze(
+    maze: &Maze,
+    theme: &MazeTheme,
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+) {
+    let mut walls = maze.create_game_object(theme);
+    // There are hundreds of little walls, so drawing them one by one is very slow.
+    walls.set_batched(true);
+    // For the same reason, one collider made of all of their triangles is much cheaper for the physics than hundreds of boxes.
+    walls.set_collider_strategy(ColliderStrategy::TriMesh);
+    walls
+        .spawn(
+            Default::default(),
+            RigidBody::Fixed,
+            commands,
+            meshes,
+            materials,
+        )
+        .insert(MazeWalls);
+
+    // We need to detect when the player is intersecting with an intersection, since they can only move when this is the case.
+    for intersection in maze.intersections() {
+        let mut sensor = GameObject::with_mesh(object::Mesh {
+            shape: object::Shape::Sphere {
+                radius: maze.half_path_width(),
+            },
+            color: Color::NONE,
+            ..Default::default()
+        });
+        sensor.set_sensor(true);
+        sensor
+            .spawn(
+                Transform::from_xyz(intersection.coordinates.0, 0.0, intersection.coordinates.1),
+                RigidBody::Fixed,
+                commands,
+                meshes,
+                materials,
+            )
+            .insert(IntersectionComponent(intersection.clone()));
+    }
+}
+
+/// The maze from the built in level, which is handy for tests.
+#[cfg(test)]
+fn sample_maze() -> Maze {
+    Maze::new(&Level::default().paths)
+}
+
+/// Finds the intersection at these coordinates, since tests usually know where an intersection is rather than its index.
+/// The coordinates only have to be close, so that ones which have been added up from other numbers still match.
+#[cfg(test)]
+fn index_of(maze: &Maze, coordinates: (f32, f32)) -> usize {
+    maze.intersections()
+        .iter()
+        .position(|intersection| euclidean(intersection.coordinates, coordinates) < 0.0001)
+        .unwrap_or_else(|| panic!("No intersection at {:?}", coordinates))
+}
+
+/// Spawns everything in the game (and the resources that go with it), which is all that is needed to play it.
+fn setup_world(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    level: Res<Level>,
+    theme: Res<MazeTheme>,
+    character_size: Res<CharacterSize>,
 ) {
-    commands.insert_resource(AmbientLight {
-        color: Color::WHITE,
-        brightness: 1.0,
-    });
-    let camera = commands
-        .spawn(Camera3dBundle {
-            transform: Transform::default().looking_to(-Vec3::Z, Vec3::Y),
-            ..Default::default()
-        })
-        .id();
-
-    let mut ground = GameObject::default();
-    ground.add_mesh(object::Mesh {
-        color: Color::YELLOW,
+    commands.insert_resource(Score::default());
+    commands.insert_resource(Lives::default());
+    // The ghosts start the game scattering, even after the player dies.
+    commands.insert_resource(GhostModeTimer::default());
+    let player_position = Vec3::new(level.player_start.0, 1.0, level.player_start.1);
+    let floor = GameObject::with_mesh(object::Mesh {
+        color: theme.floor,
         shape: object::Shape::Box {
             width: 100.0,
             height: 1.0,
             depth: 100.0,
         },
-        position: Default::default(),
-        rotation: Default::default(),
-    });
-    ground.spawn(
+        ..Default::default()
+    })
+    .spawn_static(
         Transform::default(),
-        RigidBody::Fixed,
         &mut commands,
         &mut meshes,
         &mut materials,
     );
+    commands.entity(floor).insert(Floor);
 
-    const PLAYER_RADIUS: f32 = HALF_PATH_WIDTH - 0.1;
+    let maze = level
+        .validate()
+        .expect("The level should have been checked when it was loaded");
+    let player_radius = character_size.player_radius(&maze);
+    let ghost_radius = character_size.ghost_radius(&maze);
 
-    let mut player = GameObject::default();
-    player.add_mesh(object::Mesh {
+    GameObject::with_mesh(object::Mesh {
         shape: object::Shape::Cylinder {
-            radius: PLAYER_RADIUS,
+            radius: player_radius,
             height: 1.0,
         },
         color: Color::BLUE,
-        position: Default::default(),
-        rotation: Default::default(),
-    });
-    player
-        .spawn(
-            Transform::from_xyz(0.0, 1.0, 0.0),
-            RigidBody::Dynamic,
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-        )
-        .insert(Player::default())
-        .insert(LockedAxes::ROTATION_LOCKED)
-        .add_child(camera);
-
-    let maze = Maze::new(&[
-        ((-10.0, 0.0), (10.0, 0.0)),
-        ((0.0, -10.0), (0.0, 10.0)),
-        ((-10.0, 10.0), (10.0, 10.0)),
-        ((-10.0, -10.0), (10.0, -10.0)),
-        ((10.0, -10.0), (10.0, 10.0)),
-        ((-10.0, -10.0), (-10.0, 10.0)),
-        ((-5.0, 10.0), (-5.0, 20.0)),
-        ((-5.0, 20.0), (-15.0, 20.0)),
-        ((-15.0, 20.0), (-15.0, 5.0)),
-        ((-15.0, 5.0), (-10.0, 5.0)),
-        ((5.0, 10.0), (5.0, 20.0)),
-        ((5.0, 20.0), (15.0, 20.0)),
-        ((15.0, 20.0), (15.0, 5.0)),
-        ((15.0, 5.0), (10.0, 5.0)),
-        ((5.0, -10.0), (5.0, -20.0)),
-        ((5.0, -20.0), (15.0, -20.0)),
-        ((15.0, -20.0), (15.0, -5.0)),
-        ((15.0, -5.0), (10.0, -5.0)),
-        ((-5.0, -10.0), (-5.0, -20.0)),
-        ((-5.0, -20.0), (-15.0, -20.0)),
-        ((-15.0, -20.0), (-15.0, -5.0)),
-        ((-15.0, -5.0), (-10.0, -5.0)),
-        ((-15.0, 5.0), (-15.0, -5.0)),
-        ((15.0, 5.0), (15.0, -5.0)),
-    ]);
-    maze.create_game_object().spawn(
-        Default::default(),
-        RigidBody::Fixed,
+        metallic: Some(0.6),
+        perceptual_roughness: Some(0.4),
+        ..Default::default()
+    })
+    .spawn(
+        Transform::from_translation(player_position),
+        RigidBody::Dynamic,
         &mut commands,
         &mut meshes,
         &mut materials,
-    );
-
-    // We need to detect when the player is intersecting with an intersection, since they can only move when this is the case.
-    for intersection in maze.intersections() {
-        commands
-            .spawn(Collider::ball(HALF_PATH_WIDTH))
-            .insert(Sensor)
-            .insert(Transform::from_xyz(
-                intersection.coordinates.0,
-                0.0,
-                intersection.coordinates.1,
-            ))
-            .insert(GlobalTransform::default())
-            .insert(IntersectionComponent(intersection.clone()));
-    }
+    )
+    .insert(Player::default())
+    .insert(CurrentIntersection::default())
+    .insert(LockedAxes::ROTATION_LOCKED)
+    .insert(Interpolated::new(player_position));
 
-    commands.insert_resource(maze);
+    spawn_maze(&maze, &theme, &mut commands, &mut meshes, &mut materials);
+    spawn_pellets(&maze, level.pellets, &mut commands, &mut meshes);
 
-    create_ghost(
-        &mut commands,
-        &mut meshes,
-        &mut materials,
-        Vec3::new(5.0, HALF_PATH_WIDTH, 20.0),
-        GhostType::Blinky,
-    );
-    create_ghost(
-        &mut commands,
-        &mut meshes,
-        &mut materials,
-        Vec3::new(-5.0, HALF_PATH_WIDTH, 20.0),
-        GhostType::Pinky,
+    // Every ghost needs to be able to get to the player from where it starts (even after being moved away from the others).
+    let player_edge = maze
+        .edge_at(level.player_start)
+        .expect("Player doesn't start on a path");
+    let spawn_points = spread_spawn_points(
+        &level
+            .ghosts
+            .iter()
+            .map(|ghost| ghost.position)
+            .collect::<Vec<_>>(),
+        &maze,
+        min_spawn_spacing(&maze),
     );
-    create_ghost(
+    let ghosts = level
+        .ghosts
+        .iter()
+        .zip(spawn_points)
+        .map(|(ghost, position)| {
+            debug_assert!(
+                maze.is_reachable(player_edge, position),
+                "{:?} can't reach the player from {:?}",
+                ghost.personality,
+                position
+            );
+            (
+                // This puts the bottom of the ghost on the ground.
+                Vec3::new(position.0, ghost_height(ghost_radius) / 2.0, position.1),
+                ghost.personality.default_color(),
+                ghost.personality.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let ghost_entities = spawn_ghosts(
         &mut commands,
         &mut meshes,
         &mut materials,
-        Vec3::new(5.0, HALF_PATH_WIDTH, 20.0),
-        GhostType::Blinky,
+        &ghosts,
+        ghost_radius,
     );
+    // Every ghost starts in the ghost house, and they are let out in the same order as they are in the level.
+    for ghost in ghost_entities.iter() {
+        commands.entity(*ghost).insert(Penned);
+    }
+    let mut house_intersections = ghosts
+        .iter()
+        .filter_map(|(position, ..)| maze.nearest_intersection((position.x, position.z)))
+        .collect::<Vec<_>>();
+    house_intersections.sort();
+    house_intersections.dedup();
+    commands.insert_resource(GhostHouse::new(house_intersections, ghost_entities));
+
+    commands.insert_resource(maze);
+}
+
+/// Sets up the things which are only needed to see the game: the lights, and the camera which rides along with the player.
+fn setup_rendering(mut commands: Commands, player: Query<Entity, With<Player>>) {
+    commands.insert_resource(AmbientLight {
+        color: Color::WHITE,
+        brightness: 1.0,
+    });
+    let camera = commands
+        .spawn(Camera3dBundle {
+            transform: Transform::default().looking_to(-Vec3::Z, Vec3::Y),
+            ..Default::default()
+        })
+        .insert(PlayerCamera)
+        .id();
+    if let Ok(player) = player.get_single() {
+        commands.entity(player).add_child(camera);
+    }
 }
 
 fn can_go_that_way(intersection: &Intersection, direction: Direction) -> bool {
     direction.intersection_path(intersection).is_some()
 }
 
+/// Works out which way the player actually goes if they turn towards `turn` (a quarter turn from `current`) at this intersection.
+/// If there isn't a path that way, a diagonal half way round (or failing that, one further round) will do instead, which is how the player gets onto and off the diagonal paths.
+fn available_turn(
+    intersection: &Intersection,
+    current: Direction,
+    turn: Direction,
+) -> Option<Direction> {
+    let options = if turn == current.rotate_left() {
+        [turn, current.rotate(1), current.rotate(3)]
+    } else if turn == current.rotate_right() {
+        [turn, current.rotate(-1), current.rotate(-3)]
+    } else {
+        [turn; 3]
+    };
+    options
+        .into_iter()
+        .find(|direction| can_go_that_way(intersection, *direction))
+}
+
+/// How big the player and the ghosts are, as a fraction of how wide the paths are.
+/// Both of them are a little bit thinner than the paths, so that they don't scrape along the walls or catch on the corners.
+#[derive(Clone, Copy, Debug, PartialEq, Resource)]
+pub struct CharacterSize {
+    pub player: f32,
+    pub ghost: f32,
+}
+
+impl Default for CharacterSize {
+    fn default() -> Self {
+        // On the default paths this leaves a gap of 0.1 on each side, which is what the player has always had.
+        Self {
+            player: 0.8,
+            ghost: 0.8,
+        }
+    }
+}
+
+impl CharacterSize {
+    fn player_radius(&self, maze: &Maze) -> f32 {
+        maze.half_path_width() * self.player
+    }
+
+    fn ghost_radius(&self, maze: &Maze) -> f32 {
+        maze.half_path_width() * self.ghost
+    }
+}
+
+/// How fast (in units per second) the player goes, unless it has been changed in the `PlayerConfig`.
+pub const PLAYER_SPEED: f32 = 3.0;
+
+/// Tuning for how the player moves around the maze.
+#[derive(Clone, Debug, Resource)]
+pub struct PlayerConfig {
+    pub speed: f32,
+    /// How far away from an intersection the player starts slowing down if they have a turn queued for it.
+    pub turn_slowdown_distance: f32,
+    /// The slowest the player will go while coming into a queued turn, as a fraction of `speed`.
+    pub min_turn_speed_fraction: f32,
+    /// If the player is already this close to the middle of an intersection when they turn, we don't bother snapping them onto it.
+    pub snap_tolerance: f32,
+    /// How much of the way towards the middle of the intersection the player gets moved along their new direction when they turn.
+    pub along_axis_nudge: f32,
+    /// How fast the player turns to face their new direction, in radians per second.
+    pub turn_speed: f32,
+    /// How long (in seconds) a turn stays queued before it is forgotten about.
+    pub queued_turn_expiry: f32,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            speed: PLAYER_SPEED,
+            turn_slowdown_distance: DEFAULT_HALF_PATH_WIDTH * 2.0,
+            min_turn_speed_fraction: 0.5,
+            snap_tolerance: 0.05,
+            along_axis_nudge: 0.25,
+            // A quarter turn takes 0.15 seconds.
+            turn_speed: PI / 2.0 / 0.15,
+            queued_turn_expiry: 0.5,
+        }
+    }
+}
+
+/// Works out how fast the player should be going.
+/// If they have queued a turn for the intersection they are heading towards, we slow them down as they get close so that they don't overshoot it before the turn happens.
+fn approach_speed(
+    player: &Player,
+    position: (f32, f32),
+    maze: &Maze,
+    config: &PlayerConfig,
+) -> f32 {
+    match queued_turn_ahead(player, position, maze) {
+        Some((_, distance)) if distance < config.turn_slowdown_distance => {
+            let fraction = config.min_turn_speed_fraction
+                + (1.0 - config.min_turn_speed_fraction) * distance / config.turn_slowdown_distance;
+            config.speed * fraction
+        }
+        _ => config.speed,
+    }
+}
+
+/// Finds the intersection which the player is heading towards, and how far away it is, as long as they can take their queued turn there.
+fn queued_turn_ahead(player: &Player, position: (f32, f32), maze: &Maze) -> Option<(usize, f32)> {
+    let queued_direction = player.queued_direction?;
+    let edge = maze.edge_at(position)?;
+    // We only care about the end of the path which we are moving towards.
+    let distance_ahead = |index: usize| {
+        let coordinates = maze.intersections()[index].coordinates;
+        (coordinates.0 - position.0) * player.current_direction.x_velocity()
+            + (coordinates.1 - position.1) * player.current_direction.z_velocity()
+    };
+    let target = if distance_ahead(edge.0) >= distance_ahead(edge.1) {
+        edge.0
+    } else {
+        edge.1
+    };
+    let distance = distance_ahead(target);
+    let can_turn = available_turn(
+        &maze.intersections()[target],
+        player.current_direction,
+        queued_direction,
+    )
+    .is_some();
+    (distance >= 0.0 && can_turn).then_some((target, distance))
+}
+
+/// Finds the intersection the player has reached if there is nowhere to go from it in the direction they are facing.
+/// Without this the player would keep pushing into the wall at the end of the path, and the physics would make them jitter against it.
+/// `edge` is the path the player is on (see `Player::current_path`), and only its ends count, since the nearest intersection could be on a different path running alongside.
+fn dead_end_reached(
+    direction: Direction,
+    position: (f32, f32),
+    edge: Option<(usize, usize)>,
+    maze: &Maze,
+) -> Option<usize> {
+    let (start, end) = edge?;
+    let distance = |index: usize| euclidean(maze.intersections()[index].coordinates, position);
+    let index = if distance(start) <= distance(end) {
+        start
+    } else {
+        end
+    };
+    let intersection = &maze.intersections()[index];
+    // If the intersection is still ahead of the player they haven't got there yet, so they can keep going.
+    let distance_ahead = (intersection.coordinates.0 - position.0) * direction.x_velocity()
+        + (intersection.coordinates.1 - position.1) * direction.z_velocity();
+    (distance_ahead <= 0.0 && !can_go_that_way(intersection, direction)).then_some(index)
+}
+
+/// Turns from `current` towards `target`, by no more than `max_angle` radians.
+fn rotate_towards(current: Quat, target: Quat, max_angle: f32) -> Quat {
+    let angle = current.angle_between(target);
+    if angle <= max_angle {
+        target
+    } else {
+        current.slerp(target, max_angle / angle)
+    }
+}
+
+/// Moves the player onto the middle of the path they are turning onto.
+/// Only the axis across the new path has to line up exactly, so the other one just gets nudged towards the middle, which stops the turn from pulling the player backwards.
+fn snap_to_intersection(
+    transform: &mut Transform,
+    intersection: &Intersection,
+    new_direction: Direction,
+    config: &PlayerConfig,
+) {
+    let offset = Vec2::new(
+        transform.translation.x - intersection.coordinates.0,
+        transform.translation.z - intersection.coordinates.1,
+    );
+    if offset.length() <= config.snap_tolerance {
+        return;
+    }
+    if new_direction.is_diagonal() {
+        // Diagonal paths don't line up with either axis, so we keep however far along the new path the player is and take away how far across it they are.
+        let along = Vec2::new(new_direction.x_velocity(), new_direction.z_velocity());
+        let position = along * offset.dot(along) * (1.0 - config.along_axis_nudge);
+        transform.translation.x = intersection.coordinates.0 + position.x;
+        transform.translation.z = intersection.coordinates.1 + position.y;
+    } else if new_direction.x_velocity() != 0.0 {
+        transform.translation.z = intersection.coordinates.1;
+        transform.translation.x -= offset.x * config.along_axis_nudge;
+    } else {
+        transform.translation.x = intersection.coordinates.0;
+        transform.translation.z -= offset.y * config.along_axis_nudge;
+    }
+}
+
+/// Carries on straight ahead, rather than taking whatever turn was queued up.
+/// On an intersection this only works if there is actually a path ahead; otherwise nothing happens.
+fn go_straight(
+    player: &mut Player,
+    transform: &mut Transform,
+    current_intersection: Option<&Intersection>,
+    config: &PlayerConfig,
+) {
+    match current_intersection {
+        Some(intersection) => {
+            if can_go_that_way(intersection, player.current_direction) {
+                player.queued_direction = None;
+                snap_to_intersection(transform, intersection, player.current_direction, config);
+            }
+        }
+        None => player.queued_direction = None,
+    }
+}
+
 #[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
 fn player_movement(
     mut player: Query<(&mut Player, &mut Velocity, &mut Transform, Entity)>,
     intersections: Query<
         (&IntersectionComponent, Entity),
         (With<IntersectionComponent>, Without<Player>),
     >,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut actions: PlayerActions,
     rapier_context: Res<RapierContext>,
+    maze: Res<Maze>,
+    config: Res<PlayerConfig>,
+    time: Res<StepTime>,
+    difficulty: Res<Difficulty>,
 ) {
+    let config = PlayerConfig {
+        speed: config.speed * difficulty.multipliers().player_speed,
+        ..config.clone()
+    };
+    let now = time.elapsed_seconds();
+    let actions = actions.take();
     for (mut player, mut velocity, mut transform, entity) in player.iter_mut() {
-        if keyboard_input.just_pressed(KeyCode::Down) {
+        player.expire_queued_turn(now, config.queued_turn_expiry);
+        if actions.contains(&PlayerAction::Reverse) {
             player.current_direction = player.current_direction.rotate_backward();
         }
         let current_intersection = intersections
@@ -264,88 +1073,811 @@ fn player_movement(
             })
             .map(|(intersection, _)| intersection.0.clone())
             .next();
-        if keyboard_input.just_pressed(KeyCode::Left) {
-            let new_direction = player.current_direction.rotate_left();
-            if let Some(current_intersection) = current_intersection
-                .as_ref()
-                .filter(|intersection| can_go_that_way(intersection, new_direction))
+        if actions.contains(&PlayerAction::GoStraight) {
+            go_straight(
+                &mut player,
+                &mut transform,
+                current_intersection.as_ref(),
+                &config,
+            );
+        }
+        for (action, rotate) in [
+            (
+                PlayerAction::TurnLeft,
+                Direction::rotate_left as fn(&Direction) -> Direction,
+            ),
+            (PlayerAction::TurnRight, Direction::rotate_right),
+        ] {
+            if !actions.contains(&action) {
+                continue;
+            }
+            let current_direction = player.current_direction;
+            let turn = rotate(&current_direction);
+            if let Some((current_intersection, new_direction)) =
+                current_intersection.as_ref().and_then(|intersection| {
+                    Some((
+                        intersection,
+                        available_turn(intersection, current_direction, turn)?,
+                    ))
+                })
             {
                 player.current_direction = new_direction;
-                transform.translation.x = current_intersection.coordinates.0;
-                transform.translation.z = current_intersection.coordinates.1;
+                snap_to_intersection(&mut transform, current_intersection, new_direction, &config);
             } else {
-                player.queued_direction = Some(new_direction);
+                player.queue_turn(turn, now);
             }
         }
-        if keyboard_input.just_pressed(KeyCode::Right) {
-            let new_direction = player.current_direction.rotate_right();
-            if let Some(current_intersection) = current_intersection
-                .as_ref()
-                .filter(|intersection| can_go_that_way(intersection, new_direction))
-            {
+        if let Some(current_intersection) = &current_intersection {
+            if let Some(new_direction) = player.queued_direction.and_then(|queued_direction| {
+                available_turn(
+                    current_intersection,
+                    player.current_direction,
+                    queued_direction,
+                )
+            }) {
                 player.current_direction = new_direction;
-                transform.translation.x = current_intersection.coordinates.0;
-                transform.translation.z = current_intersection.coordinates.1;
-            } else {
-                player.queued_direction = Some(new_direction);
+                player.queued_direction = None;
+                snap_to_intersection(&mut transform, current_intersection, new_direction, &config);
             }
         }
-        if let Some(current_intersection) = &current_intersection {
-            if let Some(queued_direction) = player.queued_direction {
-                if can_go_that_way(current_intersection, queued_direction) {
-                    player.current_direction = queued_direction;
-                    player.queued_direction = None;
-                    transform.translation.x = current_intersection.coordinates.0;
-                    transform.translation.z = current_intersection.coordinates.1;
+        let mut speed = approach_speed(
+            &player,
+            (transform.translation.x, transform.translation.z),
+            &maze,
+            &config,
+        );
+        // At low frame rates the player can go right over an intersection between one frame and the next, without the sensor ever seeing them on it.
+        // So if we would get to the turn before the next frame, we take it now rather than missing it.
+        if let Some((index, distance)) = queued_turn_ahead(
+            &player,
+            (transform.translation.x, transform.translation.z),
+            &maze,
+        ) {
+            if distance <= speed * time.delta_seconds() {
+                let intersection = &maze.intersections()[index];
+                transform.translation.x = intersection.coordinates.0;
+                transform.translation.z = intersection.coordinates.1;
+                let queued_direction = player.queued_direction.take().unwrap();
+                // `queued_turn_ahead` has already checked that there is a way to go.
+                if let Some(new_direction) =
+                    available_turn(intersection, player.current_direction, queued_direction)
+                {
+                    player.current_direction = new_direction;
                 }
+                speed = config.speed;
             }
         }
-        const SPEED: f32 = 3.0;
-        velocity.linvel.x = player.current_direction.x_velocity() * SPEED;
-        velocity.linvel.z = player.current_direction.z_velocity() * SPEED;
-        transform.rotation = player.current_direction.get_rotation();
+        let position = (transform.translation.x, transform.translation.z);
+        // Turning might have just moved the player onto a different path, so this can't wait for the end of the step.
+        player.current_path = maze.edge_at(position);
+        if let Some(index) = dead_end_reached(
+            player.current_direction,
+            position,
+            player.current_path(),
+            &maze,
+        ) {
+            // Stop right on the intersection, in case we went a little bit past it.
+            let coordinates = maze.intersections()[index].coordinates;
+            if player.current_direction.x_velocity() != 0.0 {
+                transform.translation.x = coordinates.0;
+            }
+            if player.current_direction.z_velocity() != 0.0 {
+                transform.translation.z = coordinates.1;
+            }
+            speed = 0.0;
+        }
+        velocity.linvel.x = player.current_direction.x_velocity() * speed;
+        velocity.linvel.z = player.current_direction.z_velocity() * speed;
+        player.target_rotation = player.current_direction.get_rotation();
+        transform.rotation = rotate_towards(
+            transform.rotation,
+            player.target_rotation,
+            config.turn_speed * time.delta_seconds(),
+        );
+        if let Some(wrapped) =
+            maze.wrap_position((transform.translation.x, transform.translation.z))
+        {
+            transform.translation.x = wrapped.0;
+            transform.translation.z = wrapped.1;
+        }
+        player.current_path = maze.edge_at((transform.translation.x, transform.translation.z));
     }
 }
 
-#[derive(Resource)]
-struct DeathTimer(Timer);
+fn check_win(pellets: Query<(), With<Pellet>>, mut next_state: ResMut<NextState<GameState>>) {
+    if all_pellets_collected(&pellets) {
+        next_state.set(GameState::Won);
+    }
+}
+
+/// Everything about the ghosts which goes back to how it started when the player does.
+type ResettableGhosts<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut GhostState,
+        &'static mut Transform,
+        &'static SpawnPoint,
+        &'static mut PathCache,
+    ),
+    Without<Player>,
+>;
 
+/// Puts the player and the ghosts back where they started, with the ghosts waiting their turn in the ghost house again.
+fn send_everyone_back(
+    commands: &mut Commands,
+    player: &mut Query<(&mut Player, &mut Transform, &mut Velocity)>,
+    ghosts: &mut ResettableGhosts,
+    house: &mut GhostHouse,
+    mode_timer: &mut GhostModeTimer,
+    level: &Level,
+) {
+    for (mut player, mut player_transform, mut player_velocity) in player.iter_mut() {
+        *player = Player::default();
+        player_transform.translation = Vec3::new(level.player_start.0, 1.0, level.player_start.1);
+        *player_velocity = Velocity::zero();
+    }
+    for (ghost, mut ghost_state, mut ghost_transform, spawn_point, mut path_cache) in
+        ghosts.iter_mut()
+    {
+        *ghost_state = GhostState::Normal;
+        ghost_transform.translation = spawn_point.0;
+        *path_cache = PathCache::default();
+        // They all have to wait their turn to come out again.
+        commands.entity(ghost).insert(Penned);
+    }
+    house.reset();
+    *mode_timer = GhostModeTimer::default();
+}
+
+/// Takes away one of the player's lives when they get caught, and starts them again (or ends the game if that was the last one).
 #[allow(clippy::too_many_arguments)]
-fn death(
-    player: Query<Entity, With<Player>>,
-    ghosts: Query<Entity, With<Ghost>>,
-    // While this falls somewhat short of "everything", we don't actually want to destroy *literally* everything.
-    // What we really want to do is to remove all game objects and the camera.
-    everything: Query<Entity, AnyOf<(With<Camera>, With<GameObject>, With<MeshComponent>)>>,
+fn catch_player(
     mut commands: Commands,
-    death_timer: Option<ResMut<DeathTimer>>,
-    time: Res<Time>,
-    rapier_context: Res<RapierContext>,
-    // Other things we need to provide to call the startup system.
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<StandardMaterial>>,
+    mut caught: EventReader<PlayerCaught>,
+    mut player: Query<(&mut Player, &mut Transform, &mut Velocity)>,
+    mut ghosts: ResettableGhosts,
+    mut lives: ResMut<Lives>,
+    mut mode_timer: ResMut<GhostModeTimer>,
+    mut house: ResMut<GhostHouse>,
+    mut next_state: ResMut<NextState<GameState>>,
+    level: Res<Level>,
 ) {
-    if let Some(mut death_timer) = death_timer {
-        if death_timer.0.tick(time.delta()).just_finished() {
-            // This is when we restart the game.
-            commands.remove_resource::<DeathTimer>();
-            setup_graphics(commands, meshes, materials);
+    if caught.iter().count() == 0 {
+        return;
+    }
+    if lives.lose() {
+        next_state.set(GameState::Lost);
+        return;
+    }
+    // Everyone goes back to the start, but the pellets stay eaten.
+    send_everyone_back(
+        &mut commands,
+        &mut player,
+        &mut ghosts,
+        &mut house,
+        &mut mode_timer,
+        &level,
+    );
+}
+
+/// Once every pellet has been eaten, the next level starts straight away with all of the pellets back.
+/// The maze stays the same, but the ghosts get faster and don't stay frightened for as long (see `LevelNumber::multipliers`).
+#[allow(clippy::too_many_arguments)]
+fn advance_level(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut player: Query<(&mut Player, &mut Transform, &mut Velocity)>,
+    mut ghosts: ResettableGhosts,
+    mut level_number: ResMut<LevelNumber>,
+    mut mode_timer: ResMut<GhostModeTimer>,
+    mut house: ResMut<GhostHouse>,
+    mut next_state: ResMut<NextState<GameState>>,
+    level: Res<Level>,
+    maze: Res<Maze>,
+) {
+    *level_number = level_number.next();
+    spawn_pellets(&maze, level.pellets, &mut commands, &mut meshes);
+    send_everyone_back(
+        &mut commands,
+        &mut player,
+        &mut ghosts,
+        &mut house,
+        &mut mode_timer,
+        &level,
+    );
+    next_state.set(GameState::Playing);
+}
+
+/// Once the game is over, pressing enter starts a new one from the very beginning.
+#[allow(clippy::too_many_arguments)]
+fn new_game(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    pellets: Query<Entity, With<Pellet>>,
+    mut player: Query<(&mut Player, &mut Transform, &mut Velocity)>,
+    mut ghosts: ResettableGhosts,
+    mut score: ResMut<Score>,
+    mut lives: ResMut<Lives>,
+    mut level_number: ResMut<LevelNumber>,
+    mut mode_timer: ResMut<GhostModeTimer>,
+    mut house: ResMut<GhostHouse>,
+    mut next_state: ResMut<NextState<GameState>>,
+    level: Res<Level>,
+    maze: Res<Maze>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Return) {
+        return;
+    }
+    score.reset();
+    *lives = Lives::default();
+    *level_number = LevelNumber::default();
+    // Whatever pellets were left go, so that there aren't two of them anywhere.
+    for pellet in pellets.iter() {
+        commands.entity(pellet).despawn_recursive();
+    }
+    spawn_pellets(&maze, level.pellets, &mut commands, &mut meshes);
+    send_everyone_back(
+        &mut commands,
+        &mut player,
+        &mut ghosts,
+        &mut house,
+        &mut mode_timer,
+        &level,
+    );
+    next_state.set(GameState::Playing);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn getting_caught() {
+        let mut app = App::new();
+        app.add_event::<PlayerCaught>()
+            .add_state::<GameState>()
+            .insert_resource(Level::default())
+            .insert_resource(Lives(2))
+            .init_resource::<GhostModeTimer>()
+            .init_resource::<GhostHouse>()
+            .add_systems(Update, catch_player);
+        let player = app
+            .world
+            .spawn((
+                Player::default(),
+                Transform::from_xyz(5.0, 1.0, 5.0),
+                Velocity::linear(Vec3::X),
+            ))
+            .id();
+        let ghost = app
+            .world
+            .spawn((
+                GhostState::Eaten { home: Vec3::ZERO },
+                Transform::from_xyz(3.0, 1.0, 3.0),
+                SpawnPoint(Vec3::new(5.0, 1.0, 20.0)),
+                PathCache::default(),
+            ))
+            .id();
+        // Nothing happens until something actually catches the player.
+        app.update();
+        assert_eq!(*app.world.resource::<Lives>(), Lives(2));
+
+        app.world.send_event(PlayerCaught);
+        app.update();
+        assert_eq!(*app.world.resource::<Lives>(), Lives(1));
+        let start = Level::default().player_start;
+        assert_eq!(
+            app.world.get::<Transform>(player).unwrap().translation,
+            Vec3::new(start.0, 1.0, start.1)
+        );
+        assert_eq!(
+            *app.world.get::<Velocity>(player).unwrap(),
+            Velocity::zero()
+        );
+        assert_eq!(
+            app.world.get::<Transform>(ghost).unwrap().translation,
+            Vec3::new(5.0, 1.0, 20.0)
+        );
+        assert!(matches!(
+            app.world.get::<GhostState>(ghost).unwrap(),
+            GhostState::Normal
+        ));
+        assert!(app.world.get::<Penned>(ghost).is_some());
+
+        app.world.send_event(PlayerCaught);
+        app.update();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<GameState>>().get(),
+            GameState::Lost
+        );
+    }
+
+    #[test]
+    fn eating_every_pellet_wins() {
+        let mut app = App::new();
+        app.add_state::<GameState>()
+            .add_systems(Update, check_win)
+            .add_systems(OnEnter(GameState::Won), stop_moving);
+        let pellet = app.world.spawn(Pellet).id();
+        let ghost = app
+            .world
+            .spawn(Velocity::linear(Vec3::new(1.0, 0.0, 0.0)))
+            .id();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<GameState>>().get(),
+            GameState::Playing
+        );
+
+        app.world.despawn(pellet);
+        // The state only changes on the frame after it is asked to.
+        app.update();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<GameState>>().get(),
+            GameState::Won
+        );
+        assert_eq!(app.world.get::<Velocity>(ghost).unwrap().linvel, Vec3::ZERO);
+    }
+
+    #[test]
+    fn winning_starts_the_next_level() {
+        let level = Level::default();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .add_state::<GameState>()
+            .insert_resource(level.validate().unwrap())
+            .insert_resource(level.clone())
+            .init_resource::<LevelNumber>()
+            .init_resource::<GhostModeTimer>()
+            .init_resource::<GhostHouse>()
+            .add_systems(OnEnter(GameState::Won), advance_level);
+        let player = app
+            .world
+            .spawn((
+                Player::default(),
+                Transform::from_xyz(10.0, 1.0, 0.0),
+                Velocity::linear(Vec3::new(1.0, 0.0, 0.0)),
+            ))
+            .id();
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Won);
+        app.update();
+        assert_eq!(*app.world.resource::<LevelNumber>(), LevelNumber(2));
+        assert!(app.world.query::<&Pellet>().iter(&app.world).count() > 0);
+        assert_eq!(
+            app.world.get::<Transform>(player).unwrap().translation,
+            Vec3::new(level.player_start.0, 1.0, level.player_start.1)
+        );
+        // And then the game carries on.
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<GameState>>().get(),
+            GameState::Playing
+        );
+    }
+
+    #[test]
+    fn starting_a_new_game() {
+        let level = Level::default();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .add_state::<GameState>()
+            .init_resource::<Input<KeyCode>>()
+            .insert_resource(level.validate().unwrap())
+            .insert_resource(level.clone())
+            .insert_resource(Score(1230))
+            .insert_resource(Lives(0))
+            .insert_resource(LevelNumber(3))
+            .init_resource::<GhostModeTimer>()
+            .init_resource::<GhostHouse>()
+            .add_systems(Update, new_game.run_if(in_state(GameState::Lost)));
+        let leftover = app.world.spawn(Pellet).id();
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Lost);
+        app.update();
+        // Nothing happens until enter is pressed.
+        assert_eq!(*app.world.resource::<Score>(), Score(1230));
+
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Return);
+        app.update();
+        assert_eq!(*app.world.resource::<Score>(), Score(0));
+        assert_eq!(*app.world.resource::<Lives>(), Lives::default());
+        assert_eq!(*app.world.resource::<LevelNumber>(), LevelNumber(1));
+        assert!(app.world.get_entity(leftover).is_none());
+        assert!(app.world.query::<&Pellet>().iter(&app.world).count() > 0);
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<GameState>>().get(),
+            GameState::Playing
+        );
+    }
+
+    #[test]
+    fn slows_down_into_queued_turn() {
+        let maze = Maze::new(&[((0.0, 0.0), (0.0, -10.0)), ((-5.0, -10.0), (5.0, -10.0))]);
+        let config = PlayerConfig::default();
+        let mut player = Player {
+            current_direction: Direction::Forward,
+            queued_direction: None,
+            ..Default::default()
+        };
+        // Without a queued turn we just go at full speed.
+        assert_eq!(
+            approach_speed(&player, (0.0, -9.5), &maze, &config),
+            config.speed
+        );
+        player.queued_direction = Some(Direction::Left);
+        let far_speed = approach_speed(&player, (0.0, -5.0), &maze, &config);
+        let near_speed = approach_speed(&player, (0.0, -9.5), &maze, &config);
+        assert_eq!(far_speed, config.speed);
+        assert!(near_speed < far_speed);
+        assert!(near_speed >= config.speed * config.min_turn_speed_fraction);
+        // Heading the other way, the intersection at the top has nowhere to turn left, so there is no reason to slow down.
+        let mut walled_player = player.clone();
+        walled_player.current_direction = Direction::Backward;
+        walled_player.queued_direction = Some(Direction::Left);
+        assert_eq!(
+            approach_speed(&walled_player, (0.0, -0.5), &maze, &config),
+            config.speed
+        );
+    }
+
+    #[test]
+    fn headless_game() {
+        let mut app = build_test_app(Level::default());
+        // A second's worth of frames.
+        for _ in 0..60 {
+            app.update();
         }
-    } else {
-        // What we want to do is to check if the player is intersecting with any ghosts.
-        // If they are, we go and delete everything and set up the timer.
-        let player_entity = player.iter().next().expect("Player not found");
-        if ghosts.iter().any(|ghost_entity| {
-            rapier_context
-                .contact_pair(ghost_entity, player_entity)
-                .map(|contact| contact.has_any_active_contacts())
-                .unwrap_or(false)
-        }) {
-            // We need to delete everything.
-            for entity in everything.iter() {
-                commands.entity(entity).despawn();
+        // The player starts in the middle of the maze facing forwards, and there is a path straight ahead.
+        let player_position = app
+            .world
+            .query_filtered::<&Transform, With<Player>>()
+            .single(&app.world)
+            .translation;
+        assert!(player_position.x.abs() < 0.01, "{:?}", player_position);
+        assert!(player_position.z < -2.0, "{:?}", player_position);
+        // Blinky has left where it started, and the rest are still waiting their turn in the ghost house.
+        let mut ghosts = app
+            .world
+            .query_filtered::<(&Transform, &SpawnPoint, Option<&Penned>), Without<Player>>();
+        assert_eq!(ghosts.iter(&app.world).count(), 4);
+        assert_eq!(
+            ghosts
+                .iter(&app.world)
+                .filter(|(.., penned)| penned.is_some())
+                .count(),
+            3
+        );
+        for (transform, spawn_point, penned) in ghosts.iter(&app.world) {
+            let distance = transform.translation.distance(spawn_point.0);
+            if penned.is_some() {
+                assert!(
+                    distance < 1.0,
+                    "{:?} {:?}",
+                    transform.translation,
+                    spawn_point.0
+                );
+            } else {
+                assert!(
+                    distance > 1.0,
+                    "{:?} {:?}",
+                    transform.translation,
+                    spawn_point.0
+                );
             }
-            commands.insert_resource(DeathTimer(Timer::from_seconds(3.0, TimerMode::Once)));
         }
     }
+
+    #[test]
+    fn replays_play_out_the_same() {
+        // Where the player and all the ghosts are after a couple of seconds of the game.
+        let positions = |app: &mut App| {
+            app.world
+                .query_filtered::<&Transform, Or<(With<Player>, With<SpawnPoint>)>>()
+                .iter(&app.world)
+                .map(|transform| transform.translation)
+                .collect::<Vec<_>>()
+        };
+        let mut app = build_test_app(Level::default());
+        app.init_resource::<ReplayRecorder>();
+        for frame in 0..120 {
+            let mut keyboard_input = app.world.resource_mut::<Input<KeyCode>>();
+            keyboard_input.clear();
+            match frame {
+                30 => keyboard_input.press(KeyCode::Left),
+                31 => keyboard_input.release(KeyCode::Left),
+                70 => keyboard_input.press(KeyCode::Down),
+                71 => keyboard_input.release(KeyCode::Down),
+                _ => {}
+            }
+            app.update();
+        }
+        let recorded = positions(&mut app);
+        let path = std::env::temp_dir().join(format!("pacman3d-game-{}", std::process::id()));
+        app.world
+            .resource::<ReplayRecorder>()
+            .save_replay(&path)
+            .unwrap();
+
+        let mut app = build_test_app(Level::default());
+        app.insert_resource(ReplayPlayer::load_replay(&path, &ReplayHeader::default()).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        for _ in 0..120 {
+            app.update();
+        }
+        assert_eq!(positions(&mut app), recorded);
+    }
+
+    #[test]
+    fn current_path_follows_the_player() {
+        let mut app = build_test_app(Level::default());
+        let current_path = |app: &mut App| {
+            let (a, b) = app
+                .world
+                .query::<&Player>()
+                .single(&app.world)
+                .current_path()
+                .unwrap();
+            let maze = app.world.resource::<Maze>();
+            let mut ends = [
+                maze.intersections()[a].coordinates,
+                maze.intersections()[b].coordinates,
+            ];
+            ends.sort_by(|a, b| a.1.total_cmp(&b.1));
+            ends
+        };
+        // The player starts right on the intersection in the middle (nothing moves on the very first frame).
+        app.update();
+        app.update();
+        assert_eq!(current_path(&mut app), [(0.0, 0.0), (0.0, 0.0)]);
+        // Then heads forwards (towards negative z) down the corridor.
+        for _ in 0..30 {
+            app.update();
+        }
+        assert_eq!(current_path(&mut app), [(0.0, -5.0), (0.0, 0.0)]);
+        // And on past the next intersection.
+        for _ in 0..90 {
+            app.update();
+        }
+        assert_eq!(current_path(&mut app), [(0.0, -10.0), (0.0, -5.0)]);
+    }
+
+    #[test]
+    fn command_line() {
+        let parse = |args: &[&str]| parse_command_line(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(parse(&[]), CommandLine::default());
+        assert_eq!(
+            parse(&["--record", "game.replay", "level.txt"]),
+            CommandLine {
+                level: Some("level.txt".to_string()),
+                record: Some("game.replay".to_string()),
+                replay: None,
+                difficulty: None,
+                cell_size: None,
+                pellets: None,
+            }
+        );
+        assert_eq!(
+            parse(&["level.txt", "--replay", "game.replay"]),
+            CommandLine {
+                level: Some("level.txt".to_string()),
+                record: None,
+                replay: Some("game.replay".to_string()),
+                difficulty: None,
+                cell_size: None,
+                pellets: None,
+            }
+        );
+        assert_eq!(
+            parse(&["--difficulty", "hard"]).difficulty.as_deref(),
+            Some("hard")
+        );
+        assert_eq!(
+            parse(&["level.txt", "--cell-size", "4"])
+                .cell_size
+                .as_deref(),
+            Some("4")
+        );
+        assert_eq!(
+            parse(&["--pellets", "midpoints"]).pellets.as_deref(),
+            Some("midpoints")
+        );
+    }
+
+    #[test]
+    fn choosing_the_pellet_layout() {
+        assert_eq!(
+            choose_level(None, None, Some("midpoints")).pellets,
+            PelletLayout::Midpoints
+        );
+        assert_eq!(
+            choose_level(None, None, Some("density=2")).pellets,
+            PelletLayout::Density(2.0)
+        );
+        // Layouts which don't work with the level (or don't exist at all) leave it how it was.
+        let default = Level::default().pellets;
+        assert_eq!(choose_level(None, None, Some("spacing=0")).pellets, default);
+        assert_eq!(choose_level(None, None, Some("lots")).pellets, default);
+        assert_eq!(choose_level(None, None, None), Level::default());
+    }
+
+    #[test]
+    fn queued_turns_expire() {
+        let mut player = Player::default();
+        player.queue_turn(Direction::Left, 10.0);
+        player.expire_queued_turn(10.4, 0.5);
+        assert_eq!(player.queued_direction, Some(Direction::Left));
+        player.expire_queued_turn(10.6, 0.5);
+        assert_eq!(player.queued_direction, None);
+        // Queueing another turn starts the wait again.
+        player.queue_turn(Direction::Right, 10.6);
+        player.expire_queued_turn(11.0, 0.5);
+        assert_eq!(player.queued_direction, Some(Direction::Right));
+    }
+
+    #[test]
+    fn stops_at_dead_ends() {
+        let maze = Maze::new(&[((0.0, 0.0), (0.0, -10.0)), ((-5.0, -10.0), (5.0, -10.0))]);
+        let dead_end_reached = |direction, position| {
+            dead_end_reached(direction, position, maze.edge_at(position), &maze)
+        };
+        // Still on the way to the end.
+        assert_eq!(dead_end_reached(Direction::Forward, (0.0, -9.5)), None);
+        // There is nothing further forward, so that's as far as the player goes.
+        assert_eq!(
+            dead_end_reached(Direction::Forward, (0.0, -10.1)),
+            Some(index_of(&maze, (0.0, -10.0)))
+        );
+        // But they can still turn around or go along the other path.
+        assert_eq!(dead_end_reached(Direction::Backward, (0.0, -10.1)), None);
+        assert_eq!(dead_end_reached(Direction::Left, (0.0, -10.0)), None);
+        assert_eq!(
+            dead_end_reached(Direction::Left, (-5.0, -10.0)),
+            Some(index_of(&maze, (-5.0, -10.0)))
+        );
+        // Passing the middle of a path isn't a dead end.
+        assert_eq!(dead_end_reached(Direction::Forward, (0.0, -3.0)), None);
+    }
+
+    #[test]
+    fn dead_ends_on_other_paths_dont_count() {
+        // The end of the path on the right is nearer than the end of the one the player is on.
+        let maze = Maze::new(&[((0.0, 0.0), (0.0, -10.0)), ((2.0, 0.0), (2.0, -5.0))]);
+        let position = (0.0, -5.1);
+        assert_eq!(
+            dead_end_reached(Direction::Forward, position, maze.edge_at(position), &maze),
+            None
+        );
+    }
+
+    #[test]
+    fn queued_turns_are_found_ahead() {
+        let maze = Maze::new(&[((0.0, 0.0), (0.0, -10.0)), ((-5.0, -10.0), (5.0, -10.0))]);
+        let mut player = Player {
+            current_direction: Direction::Forward,
+            queued_direction: Some(Direction::Left),
+            ..Default::default()
+        };
+        let (index, distance) = queued_turn_ahead(&player, (0.0, -8.5), &maze).unwrap();
+        assert_eq!(maze.intersections()[index].coordinates, (0.0, -10.0));
+        assert_eq!(distance, 1.5);
+        // At 10fps the player goes 0.3 units in a frame, which is more than the sensor on the intersection is wide, so this is what stops them skipping over the turn.
+        let config = PlayerConfig::default();
+        let (_, distance) = queued_turn_ahead(&player, (0.0, -9.8), &maze).unwrap();
+        assert!(distance <= config.speed * 0.1);
+        // Nothing is found without a turn queued, or once we have gone past it.
+        assert_eq!(queued_turn_ahead(&player, (0.0, -10.6), &maze), None);
+        player.queued_direction = None;
+        assert_eq!(queued_turn_ahead(&player, (0.0, -8.5), &maze), None);
+    }
+
+    #[test]
+    fn turning_is_smooth() {
+        let left = Direction::Left.get_rotation();
+        // Half of a quarter turn gets half way there.
+        let rotation = rotate_towards(Quat::IDENTITY, left, PI / 4.0);
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_y(PI / 4.0), 0.0001));
+        // But we never go past it.
+        assert_eq!(rotate_towards(rotation, left, PI), left);
+        // Turning right from forward goes the short way round, rather than three quarters of the way to the left.
+        let rotation = rotate_towards(Quat::IDENTITY, Direction::Right.get_rotation(), 0.1);
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_y(-0.1), 0.0001));
+    }
+
+    #[test]
+    fn turning_only_snaps_across_the_new_path() {
+        let config = PlayerConfig::default();
+        let intersection = Intersection::new((5.0, -10.0));
+        // Coming up the vertical path, a little bit past the middle and slightly off to the side.
+        let mut transform = Transform::from_xyz(5.1, 1.0, -10.3);
+        snap_to_intersection(&mut transform, &intersection, Direction::Left, &config);
+        assert_eq!(transform.translation.z, -10.0);
+        assert!((transform.translation.x - 5.1).abs() < 0.05);
+        // Anything close enough to the middle is left alone.
+        let mut transform = Transform::from_xyz(5.01, 1.0, -10.02);
+        snap_to_intersection(&mut transform, &intersection, Direction::Left, &config);
+        assert_eq!(transform.translation, Vec3::new(5.01, 1.0, -10.02));
+    }
+
+    #[test]
+    fn turning_onto_diagonals() {
+        // A straight path with a diagonal going off it up and to the left.
+        let maze = Maze::new(&[((0.0, 0.0), (0.0, -10.0)), ((0.0, -5.0), (-5.0, -10.0))]);
+        let junction = &maze.intersections()[index_of(&maze, (0.0, -5.0))];
+        // There is nothing straight to the left, so turning left goes up the diagonal instead.
+        assert_eq!(
+            available_turn(junction, Direction::Forward, Direction::Left),
+            Some(Direction::ForwardLeft)
+        );
+        assert_eq!(
+            available_turn(junction, Direction::Forward, Direction::Right),
+            None
+        );
+        // Coming back down the diagonal, either way round gets back onto the straight path.
+        assert_eq!(
+            available_turn(
+                junction,
+                Direction::BackwardRight,
+                Direction::BackwardRight.rotate_right()
+            ),
+            Some(Direction::Backward)
+        );
+        assert_eq!(
+            available_turn(
+                junction,
+                Direction::BackwardRight,
+                Direction::BackwardRight.rotate_left()
+            ),
+            Some(Direction::Forward)
+        );
+        // Snapping onto the diagonal leaves the player on the line through the middle of it.
+        let config = PlayerConfig::default();
+        let mut transform = Transform::from_xyz(0.1, 1.0, -5.3);
+        snap_to_intersection(&mut transform, junction, Direction::ForwardLeft, &config);
+        let offset = transform.translation - Vec3::new(0.0, 1.0, -5.0);
+        assert!((offset.x - offset.z).abs() < 0.0001, "{}", offset);
+        assert!(offset.length() < 0.3);
+    }
+
+    #[test]
+    fn going_straight_at_an_intersection() {
+        let config = PlayerConfig::default();
+        // A T junction, coming in from the right (where there is a path straight ahead) and from the bottom (where there isn't).
+        let maze = Maze::new(&[((-5.0, 0.0), (5.0, 0.0)), ((0.0, 0.0), (0.0, 5.0))]);
+        let junction = &maze.intersections()[index_of(&maze, (0.0, 0.0))];
+        let mut player = Player {
+            current_direction: Direction::Left,
+            queued_direction: Some(Direction::Right),
+            ..Default::default()
+        };
+        let mut transform = Transform::from_xyz(0.1, 1.0, 0.2);
+        go_straight(&mut player, &mut transform, Some(junction), &config);
+        assert_eq!(player.current_direction, Direction::Left);
+        assert_eq!(player.queued_direction, None);
+        assert_eq!(transform.translation.z, 0.0);
+
+        // Going up from the bottom, there is a wall straight ahead.
+        let mut player = Player {
+            current_direction: Direction::Forward,
+            queued_direction: Some(Direction::Left),
+            ..Default::default()
+        };
+        let mut transform = Transform::from_xyz(0.1, 1.0, 0.2);
+        go_straight(&mut player, &mut transform, Some(junction), &config);
+        assert_eq!(player.current_direction, Direction::Forward);
+        assert_eq!(player.queued_direction, Some(Direction::Left));
+        assert_eq!(transform.translation, Vec3::new(0.1, 1.0, 0.2));
+    }
 }
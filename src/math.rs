@@ -0,0 +1,70 @@
+use bevy::prelude::Vec3;
+
+/// Anything which can be measured between, which is the 2D coordinates in the maze and the 3D positions in the world.
+pub trait Point: Copy {
+    /// How far apart two points are along each of the axes.
+    fn offsets(self, other: Self) -> [f32; 3];
+}
+
+impl Point for (f32, f32) {
+    fn offsets(self, other: Self) -> [f32; 3] {
+        [self.0 - other.0, self.1 - other.1, 0.0]
+    }
+}
+
+impl Point for Vec3 {
+    fn offsets(self, other: Self) -> [f32; 3] {
+        (self - other).to_array()
+    }
+}
+
+/// The distance going along each axis in turn (like a taxi driving around city blocks).
+/// For two points on the same straight path, this is exactly how long the path between them is.
+pub fn manhattan<P: Point>(a: P, b: P) -> f32 {
+    a.offsets(b).into_iter().map(f32::abs).sum()
+}
+
+/// The distance in a straight line.
+pub fn euclidean<P: Point>(a: P, b: P) -> f32 {
+    a.offsets(b)
+        .into_iter()
+        .map(|offset| offset * offset)
+        .sum::<f32>()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn along_the_axes() {
+        // The maze's paths all go along the axes, where both distances are the same.
+        assert_eq!(manhattan((0.0, 0.0), (5.0, 0.0)), 5.0);
+        assert_eq!(euclidean((0.0, 0.0), (5.0, 0.0)), 5.0);
+        assert_eq!(manhattan((-10.0, 5.0), (-10.0, -5.0)), 10.0);
+        assert_eq!(euclidean((-10.0, 5.0), (-10.0, -5.0)), 10.0);
+        assert_eq!(
+            manhattan(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, -3.0)),
+            6.0
+        );
+        assert_eq!(
+            euclidean(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, -3.0)),
+            6.0
+        );
+    }
+
+    #[test]
+    fn across_the_axes() {
+        assert_eq!(manhattan((0.0, 0.0), (3.0, -4.0)), 7.0);
+        assert_eq!(euclidean((0.0, 0.0), (3.0, -4.0)), 5.0);
+        assert_eq!(manhattan(Vec3::ZERO, Vec3::new(1.0, 2.0, 2.0)), 5.0);
+        assert_eq!(euclidean(Vec3::ZERO, Vec3::new(1.0, 2.0, 2.0)), 3.0);
+        // Both ways round are the same.
+        assert_eq!(
+            euclidean((2.0, 1.0), (-1.0, 5.0)),
+            euclidean((-1.0, 5.0), (2.0, 1.0))
+        );
+        assert_eq!(euclidean((2.0, 1.0), (2.0, 1.0)), 0.0);
+    }
+}
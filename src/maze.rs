@@ -1,20 +1,123 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap, HashMap},
+    f32::consts::PI,
+};
+
 use bevy::prelude::{Color, Quat, Resource, Vec3};
+use ordered_float::OrderedFloat;
 
-use crate::object::{GameObject, Mesh, Shape};
+use crate::{
+    math::{euclidean, manhattan},
+    object::{BoxExtents, GameObject, Mesh},
+    theme::MazeTheme,
+    Direction,
+};
 
 /// This is essentially a graph, with the nodes being the intersections and the edges being the paths between them.
 #[derive(Clone, Debug, PartialEq, Resource)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// The spatial index isn't saved, so it has to be made again from everything else whenever a maze is read back.
+#[cfg_attr(feature = "serde", serde(from = "SavedMaze"))]
 pub struct Maze {
     intersections: Vec<Intersection>,
+    /// Half of how wide the paths are (which is also how far the walls are from the middle of them).
+    half_path_width: f32,
+    /// This is worked out from everything else, so there's no point saving it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    spatial_index: SpatialIndex,
+    /// Where paths go over each other without joining (see `Maze::with_bridges`).
+    bridges: Vec<(f32, f32)>,
+}
+
+#[cfg(feature = "serde")]
+fn default_half_path_width() -> f32 {
+    DEFAULT_HALF_PATH_WIDTH
+}
+
+/// Everything in a `Maze` which actually gets saved.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SavedMaze {
+    intersections: Vec<Intersection>,
+    #[serde(default = "default_half_path_width")]
+    half_path_width: f32,
+    #[serde(default)]
+    bridges: Vec<(f32, f32)>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SavedMaze> for Maze {
+    fn from(saved: SavedMaze) -> Self {
+        let mut maze = Maze {
+            intersections: saved.intersections,
+            half_path_width: saved.half_path_width,
+            spatial_index: SpatialIndex::default(),
+            bridges: saved.bridges,
+        };
+        maze.rebuild_spatial_index();
+        maze
+    }
+}
+
+/// How far apart two angles (in radians) can be and still count as the same way out of an intersection.
+const ANGLE_TOLERANCE: f32 = 0.001;
+/// How close two points have to be to count as the same one.
+/// Coordinates which have been worked out (like where two paths cross) aren't always exactly what they would be if they had been written out by hand, so we can't just compare them with `==`.
+const COORDINATE_TOLERANCE: f32 = 0.0001;
+
+fn same_point(a: (f32, f32), b: (f32, f32)) -> bool {
+    (a.0 - b.0).abs() <= COORDINATE_TOLERANCE && (a.1 - b.1).abs() <= COORDINATE_TOLERANCE
+}
+
+/// The angle (in radians, anticlockwise from the x axis when y is drawn going up) of something facing this way.
+/// This is always more than -pi and no more than pi, so left is always pi rather than sometimes being -pi.
+pub fn angle_of(facing: (f32, f32)) -> f32 {
+    let angle = facing.1.atan2(facing.0);
+    if angle <= -PI + ANGLE_TOLERANCE {
+        angle + PI * 2.0
+    } else {
+        angle
+    }
+}
+
+/// Which way (in x and y) something at this angle is facing.
+/// The straight angles come out exactly, rather than being a tiny bit off along the other axis.
+pub fn facing_of(angle: f32) -> (f32, f32) {
+    let exact = |value: f32| {
+        if value.abs() < ANGLE_TOLERANCE {
+            0.0
+        } else {
+            value
+        }
+    };
+    let (sin, cos) = angle.sin_cos();
+    (exact(cos), exact(sin))
+}
+
+/// How far round (in radians) it is to go anticlockwise from one angle to the other, which is always between 0 and two pi.
+fn angle_between(from: f32, to: f32) -> f32 {
+    (to - from).rem_euclid(PI * 2.0)
+}
+
+fn same_angle(a: f32, b: f32) -> bool {
+    // Going round the short way, whichever that is.
+    let difference = angle_between(a, b);
+    difference.min(PI * 2.0 - difference) < ANGLE_TOLERANCE
+}
+
+/// Whether a path leaving at this angle goes diagonally, rather than along one of the axes.
+fn is_diagonal_angle(angle: f32) -> bool {
+    let (x, y) = facing_of(angle);
+    x != 0.0 && y != 0.0
 }
 
-/// For simplicity, we'll assume that all intersections are at right angles to each other. This means there are up to four paths leading out of each intersection: left, right, forward and backward.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Intersection {
-    pub left: Option<Path>,
-    pub right: Option<Path>,
-    pub forward: Option<Path>,
-    pub backward: Option<Path>,
+    /// Every path leading out of the intersection, along with the angle it leaves at (see `angle_of`), in order of their angles.
+    /// There is only ever one path at each angle.
+    pub paths: Vec<(f32, Path)>,
 
     pub coordinates: (f32, f32),
 }
@@ -22,45 +125,336 @@ pub struct Intersection {
 impl Intersection {
     pub fn new(coordinates: (f32, f32)) -> Self {
         Self {
-            left: None,
-            right: None,
-            forward: None,
-            backward: None,
+            paths: Vec::new(),
             coordinates,
         }
     }
-    pub fn with_paths(
-        left: Option<Path>,
-        right: Option<Path>,
-        forward: Option<Path>,
-        backward: Option<Path>,
-        coordinates: (f32, f32),
-    ) -> Self {
-        Self {
-            left,
-            right,
-            forward,
-            backward,
-            coordinates,
+
+    /// `Maze::new` makes intersections wherever two paths would cross if they were long enough, so some intersections aren't joined to anything.
+    pub fn has_paths(&self) -> bool {
+        !self.paths.is_empty()
+    }
+
+    /// The path leaving at this angle, if there is one.
+    pub fn path_at(&self, angle: f32) -> Option<&Path> {
+        self.paths
+            .iter()
+            .find(|(other_angle, _)| same_angle(*other_angle, angle))
+            .map(|(_, path)| path)
+    }
+
+    /// The path going this way (in x and y), if there is one.
+    pub fn path_facing(&self, facing: (f32, f32)) -> Option<&Path> {
+        self.path_at(angle_of(facing))
+    }
+
+    /// Adds a path leaving at this angle, unless there is already one there (in which case whichever path got there first wins).
+    /// Gives back whether it was added.
+    fn add_path(&mut self, angle: f32, path: Path) -> bool {
+        if self.path_at(angle).is_some() {
+            return false;
+        }
+        self.paths.push((angle, path));
+        // Keeping them in order means the maze doesn't depend on which order the paths were given in.
+        self.paths.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        true
+    }
+
+    pub fn left(&self) -> Option<&Path> {
+        self.path_facing((-1.0, 0.0))
+    }
+
+    pub fn right(&self) -> Option<&Path> {
+        self.path_facing((1.0, 0.0))
+    }
+
+    pub fn forward(&self) -> Option<&Path> {
+        self.path_facing((0.0, 1.0))
+    }
+
+    pub fn backward(&self) -> Option<&Path> {
+        self.path_facing((0.0, -1.0))
+    }
+
+    /// The four straight sides of the intersection, along with which way (in x and z) each one faces.
+    pub fn sides(&self) -> [(Option<&Path>, (f32, f32)); 4] {
+        [
+            (self.left(), (-1.0, 0.0)),
+            (self.right(), (1.0, 0.0)),
+            (self.forward(), (0.0, 1.0)),
+            (self.backward(), (0.0, -1.0)),
+        ]
+    }
+
+    /// Every path leading out of this intersection, along with which way (in x and z) it goes.
+    pub fn facings(&self) -> impl Iterator<Item = ((f32, f32), &Path)> {
+        self.paths
+            .iter()
+            .map(|(angle, path)| (facing_of(*angle), path))
+    }
+
+    /// How many paths lead out of this intersection (including tunnels and diagonals).
+    pub fn degree(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// What shape the paths make at this intersection.
+    pub fn kind(&self) -> IntersectionKind {
+        match self.degree() {
+            0 => IntersectionKind::Unjoined,
+            1 => IntersectionKind::DeadEnd,
+            2 => {
+                // The two ways out are opposite each other if they cancel out.
+                let (x, y) = self.facings().fold((0.0, 0.0), |total, (facing, _)| {
+                    (total.0 + facing.0, total.1 + facing.1)
+                });
+                if x.abs() < 0.001 && y.abs() < 0.001 {
+                    IntersectionKind::Corridor
+                } else {
+                    IntersectionKind::Corner
+                }
+            }
+            3 => IntersectionKind::Tee,
+            _ => IntersectionKind::Cross,
+        }
+    }
+
+    /// Every path leading out of this intersection, whichever way it goes.
+    pub fn all_paths(&self) -> impl Iterator<Item = &Path> {
+        self.paths.iter().map(|(_, path)| path)
+    }
+
+    /// How far along the path leaving at `angle` its wall on one side starts, so that it meets up with the walls around it.
+    /// `side` is 1 for the wall anticlockwise from the path (looking down with y going up), and -1 for the one clockwise from it.
+    fn wall_start(&self, angle: f32, side: f32, half_path_width: f32) -> f32 {
+        // The next path round on that side, if there is one.
+        let gap = self
+            .paths
+            .iter()
+            .map(|(other_angle, _)| angle_between(angle, *other_angle) * side)
+            .map(|gap| gap.rem_euclid(PI * 2.0))
+            .filter(|gap| *gap > ANGLE_TOLERANCE)
+            .fold(PI * 2.0, f32::min);
+        if (gap - PI / 4.0).abs() < ANGLE_TOLERANCE {
+            // The two paths share the space between them for a while, until their walls meet.
+            half_path_width / (PI / 8.0).tan()
+        } else if !is_diagonal_angle(angle) {
+            // Straight paths meet the sides of the intersection.
+            half_path_width
+        } else if (gap - PI / 2.0).abs() < ANGLE_TOLERANCE {
+            // Two diagonals at right angles to each other meet half way between them.
+            half_path_width
+        } else {
+            // Otherwise the diagonal meets the wall across the side of the intersection next to it.
+            half_path_width * (2.0f32.sqrt() - 1.0)
         }
     }
 }
 
+/// The shapes the paths can make at an intersection, going by how many of them there are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntersectionKind {
+    /// `Maze::new` makes intersections wherever paths would cross, even if they don't actually reach each other.
+    Unjoined,
+    DeadEnd,
+    /// Two paths which carry on in a straight line, so there isn't really a choice to make here.
+    Corridor,
+    /// Two paths at an angle to each other.
+    Corner,
+    Tee,
+    /// Four (or more) paths.
+    Cross,
+}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Path {
     pub end_index: usize,
     pub length: f32,
+    /// Whether this is a tunnel which goes out of the side of the maze and comes back in the other side (see `Maze::add_wrap`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub wrap: bool,
 }
 
 impl Path {
     pub fn new(end_index: usize, length: f32) -> Self {
-        Self { end_index, length }
+        Self {
+            end_index,
+            length,
+            wrap: false,
+        }
+    }
+}
+
+fn is_horizontal(path: ((f32, f32), (f32, f32))) -> bool {
+    (path.0 .1 - path.1 .1).abs() <= COORDINATE_TOLERANCE
+}
+
+fn is_vertical(path: ((f32, f32), (f32, f32))) -> bool {
+    (path.0 .0 - path.1 .0).abs() <= COORDINATE_TOLERANCE
+}
+
+fn is_diagonal(path: ((f32, f32), (f32, f32))) -> bool {
+    !is_horizontal(path) && !is_vertical(path)
+}
+
+pub fn is_straight_or_diagonal(path: ((f32, f32), (f32, f32))) -> bool {
+    let (start, end) = path;
+    !is_diagonal(path)
+        || ((end.0 - start.0).abs() - (end.1 - start.1).abs()).abs() <= COORDINATE_TOLERANCE
+}
+
+/// Finds where two paths cross, if they do.
+/// Unlike with two straight paths, we only count it if the point is actually on both of them.
+fn segment_crossing(
+    a: ((f32, f32), (f32, f32)),
+    b: ((f32, f32), (f32, f32)),
+) -> Option<(f32, f32)> {
+    let a_direction = (a.1 .0 - a.0 .0, a.1 .1 - a.0 .1);
+    let b_direction = (b.1 .0 - b.0 .0, b.1 .1 - b.0 .1);
+    let denominator = a_direction.0 * b_direction.1 - a_direction.1 * b_direction.0;
+    // Parallel paths never cross (and if they overlap, their ends will already be intersections).
+    if denominator == 0.0 {
+        return None;
+    }
+    let offset = (b.0 .0 - a.0 .0, b.0 .1 - a.0 .1);
+    // How far along each path the crossing is, as a fraction of its length.
+    let a_fraction = (offset.0 * b_direction.1 - offset.1 * b_direction.0) / denominator;
+    let b_fraction = (offset.0 * a_direction.1 - offset.1 * a_direction.0) / denominator;
+    if !(0.0..=1.0).contains(&a_fraction) || !(0.0..=1.0).contains(&b_fraction) {
+        return None;
+    }
+    let mut crossing = (
+        a.0 .0 + a_direction.0 * a_fraction,
+        a.0 .1 + a_direction.1 * a_fraction,
+    );
+    // Straight paths should have the crossing exactly on them (without any rounding errors), so that it lines up with everything else along them.
+    for path in [a, b] {
+        if is_vertical(path) {
+            crossing.0 = path.0 .0;
+        } else if is_horizontal(path) {
+            crossing.1 = path.0 .1;
+        }
+    }
+    Some(crossing)
+}
+
+/// Where two paths cross.
+/// For straight paths this is where they would cross if they were long enough, which `Maze::new` makes intersections at as well.
+fn crossing(a: ((f32, f32), (f32, f32)), b: ((f32, f32), (f32, f32))) -> Option<(f32, f32)> {
+    if is_diagonal(a) || is_diagonal(b) {
+        segment_crossing(a, b)
+    } else if is_horizontal(a) != is_horizontal(b) {
+        // Whichever one is vertical will supply the x coordinate of the intersection, and the horizontal one will supply the y.
+        let (vertical, horizontal) = if is_horizontal(a) { (b, a) } else { (a, b) };
+        Some((vertical.0 .0, horizontal.0 .1))
+    } else {
+        None
+    }
+}
+
+/// Whether the point is on a straight path, but not at either end of it.
+fn is_in_middle_of(path: ((f32, f32), (f32, f32)), point: (f32, f32)) -> bool {
+    let ((x0, y0), (x1, y1)) = path;
+    let is_between = |value: f32, a: f32, b: f32| {
+        value > a.min(b) + COORDINATE_TOLERANCE && value < a.max(b) - COORDINATE_TOLERANCE
+    };
+    if is_horizontal(path) {
+        (point.1 - y0).abs() <= COORDINATE_TOLERANCE && is_between(point.0, x0, x1)
+    } else {
+        (point.0 - x0).abs() <= COORDINATE_TOLERANCE && is_between(point.1, y0, y1)
     }
 }
 
+/// Joins up all of the intersections along a path (which are the places it crosses other paths, as well as its ends).
+fn join_path(intersections: &mut [Intersection], path: ((f32, f32), (f32, f32))) {
+    let (start, end) = path;
+    let length = euclidean(start, end);
+    let direction = ((end.0 - start.0) / length, (end.1 - start.1) / length);
+    // How far along the path (from the start) and how far off to the side of it something is.
+    let along = |coordinates: (f32, f32)| {
+        (coordinates.0 - start.0) * direction.0 + (coordinates.1 - start.1) * direction.1
+    };
+    let across = |coordinates: (f32, f32)| {
+        (coordinates.1 - start.1) * direction.0 - (coordinates.0 - start.0) * direction.1
+    };
+    let mut along_path = intersections
+        .iter()
+        .enumerate()
+        .filter(|(_, intersection)| {
+            let coordinates = intersection.coordinates;
+            across(coordinates).abs() <= COORDINATE_TOLERANCE
+                && along(coordinates) >= -COORDINATE_TOLERANCE
+                && along(coordinates) <= length + COORDINATE_TOLERANCE
+        })
+        .map(|(index, intersection)| (along(intersection.coordinates), index))
+        .collect::<Vec<_>>();
+    along_path.sort_by(|(a_distance, _), (b_distance, _)| a_distance.total_cmp(b_distance));
+    for pair in along_path.windows(2) {
+        let ((a_distance, a), (b_distance, b)) = (pair[0], pair[1]);
+        let length = b_distance - a_distance;
+        // Just like the straight paths, whichever path got there first wins.
+        // The angles come from which way the path goes rather than from the intersections' coordinates, so that they are exactly the same for every bit of it.
+        intersections[a].add_path(angle_of(direction), Path::new(b, length));
+        intersections[b].add_path(angle_of((-direction.0, -direction.1)), Path::new(a, length));
+    }
+}
+
+/// The intersections which a search went through to get to `index` (including it), from wherever it started.
+/// `predecessors` has where the search got to each intersection from, which is `None` where it started.
+fn route_to(predecessors: &[Option<usize>], index: usize) -> Vec<usize> {
+    let mut route = vec![index];
+    let mut current_index = index;
+    while let Some(previous_index) = predecessors[current_index] {
+        route.push(previous_index);
+        current_index = previous_index;
+    }
+    route.reverse();
+    route
+}
+
 impl Maze {
     #[allow(clippy::type_complexity)]
     pub fn new(paths: &[((f32, f32), (f32, f32))]) -> Self {
+        Self::build(paths, Vec::new())
+    }
+
+    /// Like `Maze::new`, except that each of the given pairs of paths (as indices into `paths`) go over each other where they cross rather than joining.
+    /// There is no intersection there, so nothing can turn from one onto the other.
+    /// Bridges have to be between a horizontal path and a vertical one, and can't be at the end of either of them.
+    /// Only the point where they cross is kept, so any other path going through that same point doesn't join them there either.
+    #[allow(clippy::type_complexity)]
+    pub fn with_bridges(
+        paths: &[((f32, f32), (f32, f32))],
+        bridges: &[(usize, usize)],
+    ) -> Result<Self, MazeBuildError> {
+        let bridges = bridges
+            .iter()
+            .map(|&(a, b)| {
+                let error = |error| MazeBuildError::Bridge {
+                    paths: (a, b),
+                    error,
+                };
+                let (Some(&first), Some(&second)) = (paths.get(a), paths.get(b)) else {
+                    return Err(error(BridgeError::NoSuchPath));
+                };
+                if is_diagonal(first) || is_diagonal(second) {
+                    return Err(error(BridgeError::Diagonal));
+                }
+                // `crossing` would find where they meet if they were long enough, which isn't much use for a bridge.
+                let point = segment_crossing(first, second)
+                    .ok_or_else(|| error(BridgeError::NotCrossing))?;
+                if !is_in_middle_of(first, point) || !is_in_middle_of(second, point) {
+                    return Err(error(BridgeError::AtEnd));
+                }
+                Ok(point)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::build(paths, bridges))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn build(paths: &[((f32, f32), (f32, f32))], bridges: Vec<(f32, f32)>) -> Self {
         let mut intersections: Vec<Intersection> = Vec::new();
         // We need to convert from a list of paths to a list of intersections and the paths connecting them (to form a graph of the maze).
         // We do this with a two stage process: first find all the intersections, then break up each path and sort out which intersections it passes through.
@@ -68,27 +462,25 @@ impl Maze {
         for (i, path) in paths.iter().enumerate() {
             let (start, end) = path;
             assert!(
-                start.0 == end.0 || start.1 == end.1,
-                "Paths must be either vertical or horizontal"
+                is_straight_or_diagonal(*path),
+                "Paths must be horizontal, vertical or at 45 degrees"
             );
-            let is_horizontal = start.1 == end.1;
+            let path_is_diagonal = is_diagonal(*path);
             // We need to create paths for the starts and ends of the path.
             // Because these paths are really edges in a graph, we actually need every path to be connected on both sides for it to register.
             let mut start_intersection_exists = intersections
                 .iter()
-                .any(|intersection| &intersection.coordinates == start);
+                .any(|intersection| same_point(intersection.coordinates, *start));
             let mut end_intersection_exists = intersections
                 .iter()
-                .any(|intersection| &intersection.coordinates == end);
+                .any(|intersection| same_point(intersection.coordinates, *end));
             // If we only check forward (i.e. we don't check paths covered in the outer loop), this will prevent us from checking the same pair of paths more than once.
             // We do this since it still covers all pairs, is a lot simpler and (hopefully) is faster to execute (although this isn't much of a concern).
             for other_path in paths.iter().skip(i + 1) {
-                let (other_start, other_end) = other_path;
                 assert!(
-                    other_start.0 == other_end.0 || other_start.1 == other_end.1,
-                    "Paths must be either vertical or horizontal"
+                    is_straight_or_diagonal(*other_path),
+                    "Paths must be horizontal, vertical or at 45 degrees"
                 );
-                let other_is_horizontal = other_start.1 == other_end.1;
                 // If they are parallel then they can't intersect, unless they are equal.
                 assert!(
                     path != other_path,
@@ -96,20 +488,24 @@ impl Maze {
                     path,
                     other_path
                 );
-                if is_horizontal != other_is_horizontal {
-                    // Whichever one is vertical will supply the x coordinate of the intersection, and the horizontal one will supply the y.
-                    let vertical_path = if is_horizontal { other_path } else { path };
-                    let horizontal_path = if is_horizontal { path } else { other_path };
-                    let intersection =
-                        Intersection::new((vertical_path.0 .0, horizontal_path.0 .1));
-                    if &intersection.coordinates == start {
+                // Paths going over each other don't join.
+                if let Some(coordinates) = crossing(*path, *other_path).filter(|coordinates| {
+                    !bridges
+                        .iter()
+                        .any(|bridge| same_point(*bridge, *coordinates))
+                }) {
+                    if same_point(coordinates, *start) {
                         start_intersection_exists = true;
                     }
-                    if &intersection.coordinates == end {
+                    if same_point(coordinates, *end) {
                         end_intersection_exists = true;
                     }
-                    if !intersections.contains(&intersection) {
-                        intersections.push(intersection);
+                    // When more than two paths cross at the same point, every pair of them finds it, but there should only be one intersection there.
+                    if !intersections
+                        .iter()
+                        .any(|intersection| same_point(intersection.coordinates, coordinates))
+                    {
+                        intersections.push(Intersection::new(coordinates));
                     }
                 }
             }
@@ -127,7 +523,12 @@ impl Maze {
                     intersections.push(Intersection::new(*end));
                 }
             };
-            let is_going_forward = start.0 < end.0 || start.1 < end.1;
+            // Diagonal paths always go along the x axis, but can go either way along the y axis, so only the x coordinates decide which way they are going.
+            let is_going_forward = if path_is_diagonal {
+                start.0 < end.0
+            } else {
+                start.0 < end.0 || start.1 < end.1
+            };
             if is_going_forward {
                 add_start_if_necessary(&mut intersections);
                 add_end_if_necessary(&mut intersections);
@@ -138,309 +539,2140 @@ impl Maze {
         }
 
         for path in paths {
-            let (start, end) = path;
-            let is_horizontal = start.1 == end.1;
-            // We need to be able to handle paths which go backwards (i.e. the start is greater than the end).
-            let is_moving_forward = start.0 < end.0 || start.1 < end.1;
-            // What we want is a list of all the intersections this path passes through, sorted by distance to the start.
-            // We can calculate distance using abs, rather than sqrt, because one of the terms in the distance formula should always be 0 (because the paths are always horizontal or vertical).
-            // For example, if the path is vertical, the intersection should have the same x value as the path.
-            // This means that (path.x-intersection.x) is 0, and therefore doesn't influence the distance. This leaves us with the distance as sqrt((path.y-intersection.y)^2), which is equal to abs(path.y-intersection.y).
-            let mut matching_intersections_with_distances = intersections
-                .iter_mut()
-                .enumerate()
-                .filter(|(_, intersection)| {
-                    // Unfortunately, the logic for this line segment intersection is different depending on the direction of the line.
-                    if is_moving_forward {
-                        if is_horizontal {
-                            intersection.coordinates.1 == start.1
-                                && intersection.coordinates.0 >= start.0
-                                && intersection.coordinates.0 <= end.0
-                        } else {
-                            intersection.coordinates.0 == start.0
-                                && intersection.coordinates.1 >= start.1
-                                && intersection.coordinates.1 <= end.1
-                        }
-                    } else if is_horizontal {
-                        intersection.coordinates.1 == start.1
-                            && intersection.coordinates.0 >= end.0
-                            && intersection.coordinates.0 <= start.0
-                    } else {
-                        intersection.coordinates.0 == start.0
-                            && intersection.coordinates.1 >= end.1
-                            && intersection.coordinates.1 <= start.1
-                    }
-                })
-                .map(|(index, intersection)| {
-                    (
-                        (intersection.coordinates.0 - start.0).abs()
-                            + (intersection.coordinates.1 - start.1).abs(),
-                        (index, intersection),
-                    )
-                })
-                .collect::<Vec<_>>();
-            matching_intersections_with_distances
-                .sort_by(|(a_distance, _), (b_distance, _)| a_distance.total_cmp(b_distance));
-            // Because of Rust's borrowing rules, we can't actually access different elements of the intersections Vec.
-            // However, we need to check the distances of the previous and next elements to be able to compute the lengths of the edges connecting them.
-            let distances_and_indexes = matching_intersections_with_distances
+            join_path(&mut intersections, *path);
+        }
+        let spatial_index = SpatialIndex::new(&intersections, DEFAULT_HALF_PATH_WIDTH);
+        Self {
+            intersections,
+            half_path_width: DEFAULT_HALF_PATH_WIDTH,
+            spatial_index,
+            bridges,
+        }
+    }
+
+    /// Saves the whole graph, so that it comes back exactly the same (intersection indices included).
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Mazes should always be serializable")
+    }
+
+    /// Reads back what `to_json` writes, which is how the editor loads a saved maze.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Maze, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn half_path_width(&self) -> f32 {
+        self.half_path_width
+    }
+
+    /// Makes the paths wider or narrower than `DEFAULT_HALF_PATH_WIDTH`.
+    /// Everything which needs to fit in the paths (the player, the ghosts and the intersection sensors) is sized from this.
+    pub fn with_half_path_width(mut self, half_path_width: f32) -> Self {
+        self.half_path_width = half_path_width;
+        self.rebuild_spatial_index();
+        self
+    }
+
+    fn rebuild_spatial_index(&mut self) {
+        self.spatial_index = SpatialIndex::new(&self.intersections, self.half_path_width);
+    }
+
+    pub fn intersections(&self) -> &Vec<Intersection> {
+        &self.intersections
+    }
+
+    /// Lists the maze as the segments which would be passed to `Maze::new` to build it again.
+    /// This gives one segment for every edge of the graph (rather than the long lines it might have been built from), but it makes the same maze.
+    #[allow(clippy::type_complexity)]
+    pub fn paths(&self) -> Vec<((f32, f32), (f32, f32))> {
+        let mut paths = Vec::new();
+        for (index, intersection) in self.intersections.iter().enumerate() {
+            // Like in create_game_object, every straight edge is the right or forward path of exactly one intersection.
+            // Diagonal paths are on both of their intersections, so we only take them from the one with the smaller index.
+            let diagonals = intersection
+                .paths
                 .iter()
-                .map(|(distance, (index, _))| (*distance, *index))
-                .collect::<Vec<_>>();
-            for (i, (distance, (_, intersection))) in
-                matching_intersections_with_distances.iter_mut().enumerate()
+                .filter(|(angle, path)| is_diagonal_angle(*angle) && path.end_index > index)
+                .map(|(_, path)| path);
+            // Tunnels aren't really segments (they go out of the maze), so they are listed separately by `wraps`.
+            for path in [intersection.forward(), intersection.right()]
+                .into_iter()
+                .flatten()
+                .chain(diagonals)
+                .filter(|path| !path.wrap)
             {
-                let path_to_previous = if i > 0 {
-                    Some(Path {
-                        end_index: distances_and_indexes[i - 1].1,
-                        length: *distance - distances_and_indexes[i - 1].0,
-                    })
-                } else {
-                    None
-                };
-                let path_to_next = if i + 1 < distances_and_indexes.len() {
-                    Some(Path {
-                        end_index: distances_and_indexes[i + 1].1,
-                        length: distances_and_indexes[i + 1].0 - *distance,
-                    })
-                } else {
-                    None
-                };
+                paths.push((
+                    intersection.coordinates,
+                    self.intersections[path.end_index].coordinates,
+                ));
+            }
+        }
+        paths
+    }
 
-                if is_moving_forward {
-                    if is_horizontal {
-                        intersection.left = intersection.left.clone().or(path_to_previous);
-                        intersection.right = intersection.right.clone().or(path_to_next);
-                    } else {
-                        intersection.backward = intersection.backward.clone().or(path_to_previous);
-                        intersection.forward = intersection.forward.clone().or(path_to_next);
-                    }
-                } else if is_horizontal {
-                    intersection.left = intersection.left.clone().or(path_to_next);
-                    intersection.right = intersection.right.clone().or(path_to_previous);
-                } else {
-                    intersection.backward = intersection.backward.clone().or(path_to_next);
-                    intersection.forward = intersection.forward.clone().or(path_to_previous);
+    /// Finds every intersection which can be reached from the given one by following paths.
+    /// The result has an entry for every intersection in the maze, which is true if it is reachable.
+    fn reachable_from(&self, start_index: usize) -> Vec<bool> {
+        let mut reachable = vec![false; self.intersections.len()];
+        reachable[start_index] = true;
+        let mut to_visit = vec![start_index];
+        while let Some(index) = to_visit.pop() {
+            for path in self.intersections[index].all_paths() {
+                if !reachable[path.end_index] {
+                    reachable[path.end_index] = true;
+                    to_visit.push(path.end_index);
                 }
             }
         }
-        Self { intersections }
+        reachable
     }
 
-    pub fn intersections(&self) -> &Vec<Intersection> {
-        &self.intersections
+    /// Groups the intersections into the bits of the maze which are joined together.
+    /// Intersections without any paths are left out, since they aren't really part of the maze (see `Intersection::has_paths`).
+    pub fn connected_components(&self) -> Vec<Vec<usize>> {
+        let mut components = Vec::new();
+        let mut seen = vec![false; self.intersections.len()];
+        for index in 0..self.intersections.len() {
+            if seen[index] || !self.intersections[index].has_paths() {
+                continue;
+            }
+            let component = self
+                .reachable_from(index)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, reachable)| *reachable)
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            for index in &component {
+                seen[*index] = true;
+            }
+            components.push(component);
+        }
+        components
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    pub fn is_fully_connected(&self) -> bool {
+        self.connected_components().len() <= 1
+    }
 
-    #[test]
-    fn create_maze_simple() {
-        let maze = Maze::new(&[((0.0, 1.0), (0.0, -1.0)), ((1.0, 0.0), (-1.0, 0.0))]);
-        assert_eq!(
-            maze.intersections,
-            vec![
-                Intersection::with_paths(
-                    Some(Path::new(3, 1.0)),
-                    Some(Path::new(4, 1.0)),
-                    Some(Path::new(2, 1.0)),
-                    Some(Path::new(1, 1.0)),
-                    (0.0, 0.0)
-                ),
-                Intersection::with_paths(None, None, Some(Path::new(0, 1.0)), None, (0.0, -1.0)),
-                Intersection::with_paths(None, None, None, Some(Path::new(0, 1.0)), (0.0, 1.0)),
-                Intersection::with_paths(None, Some(Path::new(0, 1.0)), None, None, (-1.0, 0.0)),
-                Intersection::with_paths(Some(Path::new(0, 1.0)), None, None, None, (1.0, 0.0)),
-            ]
-        );
-        let maze = Maze::new(&[
-            ((0.0, 1.0), (0.0, -1.0)),
-            ((1.0, 0.0), (-1.0, 0.0)),
-            ((1.0, 1.0), (1.0, -1.0)),
-        ]);
-        assert_eq!(
-            maze.intersections,
-            vec![
-                Intersection::with_paths(
-                    Some(Path::new(4, 1.0)),
-                    Some(Path::new(3, 1.0)),
-                    Some(Path::new(2, 1.0)),
-                    Some(Path::new(1, 1.0)),
-                    (0.0, 0.0)
-                ),
-                Intersection::with_paths(None, None, Some(Path::new(0, 1.0)), None, (0.0, -1.0)),
-                Intersection::with_paths(None, None, None, Some(Path::new(0, 1.0)), (0.0, 1.0)),
-                Intersection::with_paths(
-                    Some(Path::new(0, 1.0)),
-                    None,
-                    Some(Path::new(6, 1.0)),
-                    Some(Path::new(5, 1.0)),
-                    (1.0, 0.0)
-                ),
-                Intersection::with_paths(None, Some(Path::new(0, 1.0)), None, None, (-1.0, 0.0)),
-                Intersection::with_paths(None, None, Some(Path::new(3, 1.0)), None, (1.0, -1.0)),
-                Intersection::with_paths(None, None, None, Some(Path::new(3, 1.0)), (1.0, 1.0)),
-            ]
-        );
+    /// Checks whether something on the given edge could get to this point by following the paths of the maze.
+    /// Points which aren't on a path at all can't be reached.
+    pub fn is_reachable(&self, from_edge: (usize, usize), to_point: (f32, f32)) -> bool {
+        match self.edge_at(to_point) {
+            // Both ends of an edge are always connected to each other, so we only need to check one.
+            Some(to_edge) => self.reachable_from(from_edge.0)[to_edge.0],
+            None => false,
+        }
     }
 
-    #[test]
-    fn create_maze_complex_intersections() {
-        let maze1 = Maze::new(&[((0.0, 1.0), (0.0, -1.0)), ((1.0, 0.0), (-1.0, 0.0))]);
-        let maze2 = Maze::new(&[
-            ((0.0, 0.0), (0.0, -1.0)),
-            ((0.0, 1.0), (0.0, 0.0)),
-            ((1.0, 0.0), (-1.0, 0.0)),
-        ]);
-        assert_eq!(maze1, maze2);
+    /// Works out which way you have to go to get from one intersection to another one next to it.
+    /// Gives `None` if there isn't a path joining them.
+    pub fn direction_between(&self, a: usize, b: usize) -> Option<Direction> {
+        self.neighbors(a)
+            .find(|(_, path)| path.end_index == b)
+            .map(|(direction, _)| direction)
     }
 
-    #[test]
-    fn create_maze_backward_paths() {
-        let maze1 = Maze::new(&[((0.0, 1.0), (0.0, -1.0)), ((1.0, 0.0), (-1.0, 0.0))]);
-        let maze2 = Maze::new(&[((0.0, -1.0), (0.0, 1.0)), ((-1.0, 0.0), (1.0, 0.0))]);
-        assert_eq!(maze1, maze2);
+    /// Every path leading out of an intersection, along with which way it goes.
+    pub fn neighbors(&self, index: usize) -> impl Iterator<Item = (Direction, &Path)> {
+        let intersection = &self.intersections[index];
+        Direction::all_with_diagonals()
+            .into_iter()
+            .filter_map(move |direction| {
+                Some((direction, direction.intersection_path(intersection)?))
+            })
     }
-}
 
-pub const HALF_PATH_WIDTH: f32 = 0.5;
-pub const PATH_THICKNESS: f32 = 0.01;
+    /// How far it is to go along the paths through each of these intersections in turn (like the ones from `find_shortest_path`).
+    /// This is `None` if any two intersections next to each other in the list aren't joined by a path (or aren't in the maze at all).
+    pub fn route_length(&self, indices: &[usize]) -> Option<f32> {
+        indices
+            .windows(2)
+            .map(|pair| {
+                // There can be more than one path between two intersections (like a tunnel alongside a normal path), in which case we would take the shortest.
+                self.intersections
+                    .get(pair[0])?
+                    .all_paths()
+                    .filter(|path| path.end_index == pair[1])
+                    .map(|path| path.length)
+                    .min_by(f32::total_cmp)
+            })
+            .sum()
+    }
 
-impl Maze {
-    pub fn create_game_object(&self) -> GameObject {
-        let mut meshes: Vec<Mesh> = Vec::new();
-        for intersection in &self.intersections {
-            let mut paths: Vec<Path> = Vec::new();
-            // By only considering the up and right paths, we simplify the logic a lot.
-            // Every path has two intersections, which have the path on opposite edges. Therefore every path will always be either an up or a right of some intersection.
-            if let Some(path) = &intersection.right {
-                paths.push(path.clone());
-            }
-            if let Some(path) = &intersection.forward {
-                paths.push(path.clone());
-            }
-            for path in paths {
-                let target_intersection = &self.intersections[path.end_index];
-                let is_horizontal = intersection.coordinates.1 == target_intersection.coordinates.1;
-                let width = if is_horizontal {
-                    target_intersection.coordinates.0
-                        - intersection.coordinates.0
-                        - 2.0 * HALF_PATH_WIDTH
-                } else {
-                    PATH_THICKNESS
-                };
-                let depth = if is_horizontal {
-                    PATH_THICKNESS
-                } else {
-                    target_intersection.coordinates.1
-                        - intersection.coordinates.1
-                        - 2.0 * HALF_PATH_WIDTH
-                };
-                // Its important to note that the positions are actually teh positions of the centers of the shapes, so we have to add half of the width and depth.
-                // The logic for horizontal and vertical paths turns out to be exactly the same for position1.
-                let position1 = Vec3::new(
-                    intersection.coordinates.0 + HALF_PATH_WIDTH,
-                    HALF_PATH_WIDTH,
-                    intersection.coordinates.1 + HALF_PATH_WIDTH,
-                ) + Vec3::new(width, 0.0, depth) / 2.0;
-                let position2 = if is_horizontal {
-                    Vec3::new(
-                        intersection.coordinates.0 + HALF_PATH_WIDTH,
-                        HALF_PATH_WIDTH,
-                        intersection.coordinates.1 - HALF_PATH_WIDTH,
-                    )
-                } else {
-                    Vec3::new(
-                        intersection.coordinates.0 - HALF_PATH_WIDTH,
-                        HALF_PATH_WIDTH,
-                        intersection.coordinates.1 + HALF_PATH_WIDTH,
-                    )
-                } + Vec3::new(width, 0.0, depth) / 2.0;
-                meshes.push(Mesh {
-                    position: position1,
-                    rotation: Quat::default(),
-                    color: Color::GRAY,
-                    shape: Shape::Box {
-                        width,
-                        height: HALF_PATH_WIDTH * 2.0,
-                        depth,
-                    },
-                });
-                meshes.push(Mesh {
-                    position: position2,
-                    rotation: Quat::default(),
-                    color: Color::GRAY,
-                    shape: Shape::Box {
-                        width,
-                        height: HALF_PATH_WIDTH * 2.0,
-                        depth,
-                    },
-                });
+    /// The shortest way along the paths from one intersection to another, going by how long the paths are.
+    /// This includes both `from` and `to`, so it can be given straight to `route_length`.
+    /// Gives `None` if there isn't a way between them (or either of them isn't in the maze).
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        if from >= self.intersections.len() || to >= self.intersections.len() {
+            return None;
+        }
+        if from == to {
+            return Some(vec![from]);
+        }
+        self.search(&[(from, 0.0)], &[(to, 0.0)], |_| 0.0, None).0
+    }
+
+    /// Finds the shortest way along the paths from any of the `starts` to any of the `goals`, which is what both `shortest_path` and the ghosts are built on.
+    /// Each start and goal comes with how far it is from the real start or end, since those can be part way along a path.
+    /// A start only gets to a goal by going along a path to it, so starting on a goal has to be checked for before searching.
+    /// The heuristic guesses how far it is from the intersection at the given coordinates to the end (or is always zero for plain Dijkstra).
+    /// If there is a `came_from`, the way never turns straight back on itself, including going from the starts back to `came_from`.
+    /// Gives the intersections from the start to the goal (including both), and how many intersections had to be expanded to find them.
+    pub fn search(
+        &self,
+        starts: &[(usize, f32)],
+        goals: &[(usize, f32)],
+        heuristic: impl Fn((f32, f32)) -> f32,
+        came_from: Option<usize>,
+    ) -> (Option<Vec<usize>>, usize) {
+        let is_finite =
+            |coordinates: (f32, f32)| coordinates.0.is_finite() && coordinates.1.is_finite();
+        // This is Dijkstra's algorithm (or A* if there is a heuristic), starting from all of the starts at once.
+        // Rather than stopping at the goals, we go one step further to a made up goal node (with an index past the end of the intersections).
+        // That way the distance from each goal to the real end is taken into account, and the first time we reach the goal node we know it is the shortest way.
+        let goal = self.intersections.len();
+        let mut shortest_distances = vec![f32::INFINITY; goal + 1];
+        // Where we came from to get to each intersection the shortest way, so that we only have to build the route once at the end.
+        let mut predecessors: Vec<Option<usize>> = vec![None; goal + 1];
+        let mut expanded = vec![false; goal];
+        // The frontier is ordered by the distance so far plus the heuristic, but the actual distances are kept in `shortest_distances`.
+        let mut frontier = BinaryHeap::new();
+        for &(start_index, start_distance) in starts {
+            let start_coordinates = self.intersections[start_index].coordinates;
+            if !is_finite(start_coordinates) {
+                continue;
             }
-            let mut missing_path_position_width_depth = Vec::new();
-            if intersection.left.is_none() {
-                missing_path_position_width_depth.push((
-                    Vec3::new(
-                        intersection.coordinates.0 - HALF_PATH_WIDTH,
-                        HALF_PATH_WIDTH,
-                        intersection.coordinates.1,
-                    ),
-                    PATH_THICKNESS,
-                    HALF_PATH_WIDTH * 2.0,
-                ));
+            if start_distance < shortest_distances[start_index] {
+                shortest_distances[start_index] = start_distance;
+                frontier.push(Reverse((
+                    OrderedFloat(start_distance + heuristic(start_coordinates)),
+                    start_index,
+                )));
             }
-            if intersection.right.is_none() {
-                missing_path_position_width_depth.push((
-                    Vec3::new(
-                        intersection.coordinates.0 + HALF_PATH_WIDTH,
-                        HALF_PATH_WIDTH,
-                        intersection.coordinates.1,
-                    ),
-                    PATH_THICKNESS,
-                    HALF_PATH_WIDTH * 2.0,
-                ));
+        }
+        let mut expansions = 0;
+        while let Some(Reverse((_, current_index))) = frontier.pop() {
+            if current_index == goal {
+                break;
             }
-            if intersection.forward.is_none() {
-                missing_path_position_width_depth.push((
-                    Vec3::new(
-                        intersection.coordinates.0,
-                        HALF_PATH_WIDTH,
-                        intersection.coordinates.1 + HALF_PATH_WIDTH,
-                    ),
-                    HALF_PATH_WIDTH * 2.0,
-                    PATH_THICKNESS,
-                ));
+            // There may be older entries for intersections which we have since found a faster way to.
+            if expanded[current_index] {
+                continue;
             }
-            if intersection.backward.is_none() {
-                missing_path_position_width_depth.push((
-                    Vec3::new(
-                        intersection.coordinates.0,
-                        HALF_PATH_WIDTH,
-                        intersection.coordinates.1 - HALF_PATH_WIDTH,
-                    ),
-                    HALF_PATH_WIDTH * 2.0,
-                    PATH_THICKNESS,
-                ));
+            expanded[current_index] = true;
+            expansions += 1;
+            let current_distance = shortest_distances[current_index];
+            // Turning around isn't allowed anywhere along the way either, so we can't go back to wherever we got here from.
+            // The starts were got to from `came_from`.
+            let arrived_from = came_from.and(predecessors[current_index].or(came_from));
+            for joining_path in self.intersections[current_index].all_paths() {
+                let end_index = joining_path.end_index;
+                // Broken intersections are left out, as if they weren't there at all (otherwise the distances would all turn into NaN).
+                if Some(end_index) == arrived_from
+                    || !joining_path.length.is_finite()
+                    || !is_finite(self.intersections[end_index].coordinates)
+                {
+                    continue;
+                }
+                let new_distance = current_distance + joining_path.length;
+                if new_distance == shortest_distances[end_index] && !expanded[end_index] {
+                    // Two ways which are exactly as long as each other would otherwise be chosen between by whichever we happened to look at first.
+                    // Going by the indices means we always make the same choice.
+                    if route_to(&predecessors, current_index)
+                        < route_to(&predecessors, predecessors[end_index].unwrap_or(end_index))
+                    {
+                        predecessors[end_index] = Some(current_index);
+                    }
+                    continue;
+                }
+                if new_distance >= shortest_distances[end_index] {
+                    continue;
+                }
+                shortest_distances[end_index] = new_distance;
+                predecessors[end_index] = Some(current_index);
+                let joined_coordinates = self.intersections[end_index].coordinates;
+                if let Some((_, remaining)) = goals.iter().find(|(index, _)| *index == end_index) {
+                    // We have found a way to a goal, so we don't need to go any further than this intersection.
+                    let goal_distance = new_distance + remaining;
+                    if goal_distance == shortest_distances[goal] {
+                        if let Some(previous_end) = predecessors[goal] {
+                            if route_to(&predecessors, end_index)
+                                < route_to(&predecessors, previous_end)
+                            {
+                                predecessors[goal] = Some(end_index);
+                            }
+                        }
+                    } else if goal_distance < shortest_distances[goal] {
+                        shortest_distances[goal] = goal_distance;
+                        predecessors[goal] = Some(end_index);
+                        frontier.push(Reverse((OrderedFloat(goal_distance), goal)));
+                    }
+                } else {
+                    frontier.push(Reverse((
+                        OrderedFloat(new_distance + heuristic(joined_coordinates)),
+                        end_index,
+                    )));
+                }
             }
-            for (position, width, depth) in missing_path_position_width_depth {
-                meshes.push(Mesh {
-                    position,
-                    rotation: Quat::default(),
-                    color: Color::GRAY,
-                    shape: Shape::Box {
-                        width,
-                        height: HALF_PATH_WIDTH * 2.0,
-                        depth,
-                    },
-                });
+        }
+        let route = predecessors[goal].map(|last_index| route_to(&predecessors, last_index));
+        (route, expansions)
+    }
+
+    /// Adds another path to the maze, rebuilding the graph around it.
+    /// Note that this may change the indices of the intersections.
+    /// The bridges stay where they are, so the new path won't join anything it crosses at one of them.
+    pub fn add_path(&mut self, path: ((f32, f32), (f32, f32))) {
+        let mut paths = self.paths();
+        if paths.contains(&path) || paths.contains(&(path.1, path.0)) {
+            return;
+        }
+        paths.push(path);
+        self.rebuild(&paths);
+    }
+
+    /// Takes out the path between two intersections next to each other, rebuilding the graph without it.
+    /// Any tunnel which started or ended at an intersection which isn't there any more goes as well.
+    /// Like `add_path`, this may change the indices of the intersections.
+    pub fn remove_path(&mut self, a: usize, b: usize) {
+        let (Some(a), Some(b)) = (self.intersections.get(a), self.intersections.get(b)) else {
+            return;
+        };
+        let removed = (a.coordinates, b.coordinates);
+        let paths = self
+            .paths()
+            .into_iter()
+            .filter(|path| *path != removed && *path != (removed.1, removed.0))
+            .collect::<Vec<_>>();
+        self.rebuild(&paths);
+    }
+
+    /// Makes the maze again from a new set of paths, keeping the bridges, the width of the paths and whichever tunnels still have somewhere to go.
+    #[allow(clippy::type_complexity)]
+    fn rebuild(&mut self, paths: &[((f32, f32), (f32, f32))]) {
+        let wraps = self.wraps();
+        *self = Maze::build(paths, self.bridges.clone()).with_half_path_width(self.half_path_width);
+        for (a, b, length) in wraps {
+            let has_end = |end: (f32, f32)| {
+                self.intersections.iter().any(|intersection| {
+                    intersection.has_paths() && same_point(intersection.coordinates, end)
+                })
+            };
+            if has_end(a) && has_end(b) {
+                self.add_wrap(a, b, length);
+            }
+        }
+    }
+
+    /// Joins two intersections on the edge of the maze with a tunnel, so that going out of one comes back in the other (like the tunnel in the arcade game).
+    /// The tunnel leaves each intersection on the side facing away from the other one, so those sides can't already have paths.
+    pub fn add_wrap(&mut self, a: (f32, f32), b: (f32, f32), length: f32) {
+        let index_of = |coordinates: (f32, f32)| {
+            self.intersections
+                .iter()
+                .position(|intersection| same_point(intersection.coordinates, coordinates))
+                .expect("Tunnels have to start and end at intersections")
+        };
+        let (a_index, b_index) = (index_of(a), index_of(b));
+        // The tunnel goes along whichever axis the two ends are furthest apart on.
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let b_facing = if dx.abs() >= dy.abs() {
+            (dx.signum(), 0.0)
+        } else {
+            (0.0, dy.signum())
+        };
+        let a_facing = (-b_facing.0, -b_facing.1);
+        for (index, facing, end_index) in
+            [(a_index, a_facing, b_index), (b_index, b_facing, a_index)]
+        {
+            let added = self.intersections[index].add_path(
+                angle_of(facing),
+                Path {
+                    end_index,
+                    length,
+                    wrap: true,
+                },
+            );
+            assert!(
+                added,
+                "There is already a path where the tunnel at {:?} would go",
+                self.intersections[end_index].coordinates
+            );
+        }
+        self.rebuild_spatial_index();
+    }
+
+    /// Lists the tunnels in the maze, as the arguments to `add_wrap` which would make them again.
+    #[allow(clippy::type_complexity)]
+    pub fn wraps(&self) -> Vec<((f32, f32), (f32, f32), f32)> {
+        let mut wraps = Vec::new();
+        for (index, intersection) in self.intersections.iter().enumerate() {
+            for path in intersection.all_paths() {
+                if path.wrap && path.end_index > index {
+                    wraps.push((
+                        intersection.coordinates,
+                        self.intersections[path.end_index].coordinates,
+                        path.length,
+                    ));
+                }
+            }
+        }
+        wraps
+    }
+
+    pub fn has_wraps(&self) -> bool {
+        !self.wraps().is_empty()
+    }
+
+    /// If going from one intersection to the other means going through a tunnel, this is the way (in x and z) to go to get into it.
+    pub fn wrap_direction(&self, from: usize, to: usize) -> Option<(f32, f32)> {
+        self.intersections[from]
+            .facings()
+            .find(|(_, path)| path.wrap && path.end_index == to)
+            .map(|(facing, _)| facing)
+    }
+
+    /// Works out where something should be moved to if it has gone out of the mouth of a tunnel.
+    /// It comes out of the other end having gone just as far past the mouth, so nothing jumps forwards or backwards.
+    pub fn wrap_position(&self, position: (f32, f32)) -> Option<(f32, f32)> {
+        for (index, intersection) in self.intersections.iter().enumerate() {
+            for (facing, path) in intersection.facings() {
+                if !path.wrap {
+                    continue;
+                }
+                let offset = (
+                    position.0 - intersection.coordinates.0,
+                    position.1 - intersection.coordinates.1,
+                );
+                let past = offset.0 * facing.0 + offset.1 * facing.1;
+                let across = offset.0 * facing.1 - offset.1 * facing.0;
+                if across.abs() >= self.half_path_width || past <= self.half_path_width {
+                    continue;
+                }
+                let other_end = &self.intersections[path.end_index];
+                let other_facing = self.wrap_direction(path.end_index, index)?;
+                let distance_in = (self.half_path_width - (past - self.half_path_width)).max(0.0);
+                return Some((
+                    other_end.coordinates.0 + other_facing.0 * distance_in,
+                    other_end.coordinates.1 + other_facing.1 * distance_in,
+                ));
+            }
+        }
+        None
+    }
+
+    /// The corners of the box which the whole maze fits in, walls included (but not the tunnels going out of the sides).
+    /// A maze without any paths is just a point at the origin.
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let (min, max) = self
+            .intersections
+            .iter()
+            .filter(|intersection| intersection.has_paths())
+            .map(|intersection| {
+                Vec3::new(intersection.coordinates.0, 0.0, intersection.coordinates.1)
+            })
+            .fold(
+                (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+                |(min, max), point| (min.min(point), max.max(point)),
+            );
+        if !min.is_finite() {
+            return (Vec3::ZERO, Vec3::ZERO);
+        }
+        // The walls are as high as the paths are wide.
+        let margin = Vec3::new(self.half_path_width, 0.0, self.half_path_width);
+        (
+            min - margin,
+            max + margin + Vec3::Y * self.half_path_width * 2.0,
+        )
+    }
+
+    /// The dead ends or corners closest to each corner of `bounds`, which are where the ghosts go to scatter.
+    /// They are in the order top left, top right, bottom left, bottom right (the top being forward, which is the smallest y).
+    /// Mazes without any dead ends or corners (like a single corridor joined up by a tunnel) use whichever intersections are closest instead.
+    /// This is only `None` if the maze doesn't have any paths.
+    pub fn corners(&self) -> Option<[usize; 4]> {
+        let (min, max) = self.bounds();
+        let closest = |kinds: &[IntersectionKind], corner: (f32, f32)| {
+            self.intersections
+                .iter()
+                .enumerate()
+                .filter(|(_, intersection)| kinds.contains(&intersection.kind()))
+                .min_by(|(_, a), (_, b)| {
+                    euclidean(a.coordinates, corner).total_cmp(&euclidean(b.coordinates, corner))
+                })
+                .map(|(index, _)| index)
+        };
+        let mut corners = [0; 4];
+        for (index, corner) in [
+            (min.x, min.z),
+            (max.x, min.z),
+            (min.x, max.z),
+            (max.x, max.z),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            corners[index] = closest(
+                &[IntersectionKind::DeadEnd, IntersectionKind::Corner],
+                corner,
+            )
+            .or_else(|| {
+                closest(
+                    &[
+                        IntersectionKind::Corridor,
+                        IntersectionKind::Tee,
+                        IntersectionKind::Cross,
+                    ],
+                    corner,
+                )
+            })?;
+        }
+        Some(corners)
+    }
+
+    /// Finds the intersection closest to this position (in a straight line, ignoring the paths).
+    /// Intersections which aren't joined to any paths are skipped, since nothing can actually get to them.
+    /// This is only `None` if the maze doesn't have any paths.
+    pub fn nearest_intersection(&self, position: (f32, f32)) -> Option<usize> {
+        let distance_squared = |coordinates: (f32, f32)| {
+            (coordinates.0 - position.0).powi(2) + (coordinates.1 - position.1).powi(2)
+        };
+        let nearest = |candidates: Vec<usize>| {
+            candidates
+                .into_iter()
+                .filter(|index| self.intersections[*index].has_paths())
+                .min_by(|a, b| {
+                    distance_squared(self.intersections[*a].coordinates)
+                        .total_cmp(&distance_squared(self.intersections[*b].coordinates))
+                })
+        };
+        // Usually there is an intersection close by, in which case there is no need to look at all of the others.
+        // If there isn't one, we have to fall back to looking through all of them.
+        nearest(self.nearest_intersections(position, SPATIAL_INDEX_CELL_SIZE))
+            .or_else(|| nearest((0..self.intersections.len()).collect()))
+    }
+
+    /// Finds the intersections within `radius` of the position (in order of their indices).
+    pub fn nearest_intersections(&self, position: (f32, f32), radius: f32) -> Vec<usize> {
+        self.spatial_index
+            .near(position, radius)
+            .into_iter()
+            .filter(|index| {
+                let coordinates = self.intersections[*index].coordinates;
+                (coordinates.0 - position.0).powi(2) + (coordinates.1 - position.1).powi(2)
+                    <= radius * radius
+            })
+            .collect()
+    }
+
+    /// Finds the indices of the two intersections which this position is between.
+    /// If it is slightly off the path (i.e. within the width of the path), it will round to the nearest path.
+    /// Anything right on the edge of a path (or the end of one) still counts as being on it.
+    pub fn edge_at(&self, position: (f32, f32)) -> Option<(usize, usize)> {
+        // Something exactly on the edge of one path is often on the edge of another one too (like where it meets an intersection), so we only count the edges once nothing has it properly inside.
+        // Otherwise, a position exactly as far from a dead end as the path is wide wouldn't be anywhere at all, which made the ghosts panic.
+        self.edge_within(position, |distance, limit| distance < limit)
+            .or_else(|| self.edge_within(position, |distance, limit| distance <= limit))
+    }
+
+    /// Whether this position is on (or just off the middle of) one of the paths.
+    pub fn on_path(&self, position: (f32, f32)) -> bool {
+        self.edge_at(position).is_some()
+    }
+
+    /// The same as `edge_at` when the position is on a path, but anywhere else gives the path closest to it instead.
+    /// This is only `None` if the maze doesn't have any paths.
+    pub fn nearest_path(&self, position: (f32, f32)) -> Option<(usize, usize)> {
+        if let Some(edge) = self.edge_at(position) {
+            return Some(edge);
+        }
+        let mut nearest: Option<((usize, usize), f32)> = None;
+        for (intersection_index, intersection) in self.intersections.iter().enumerate() {
+            let (x, y) = intersection.coordinates;
+            // Tunnels go off the side of the maze, so it is the bit of them before they wrap around which counts.
+            for (facing, path) in intersection.facings() {
+                let end = if path.wrap {
+                    (x + facing.0 * path.length, y + facing.1 * path.length)
+                } else {
+                    self.intersections[path.end_index].coordinates
+                };
+                let distance = distance_to_segment(position, (x, y), end);
+                if nearest.map_or(true, |(_, nearest_distance)| distance < nearest_distance) {
+                    nearest = Some(((intersection_index, path.end_index), distance));
+                }
+            }
+        }
+        nearest.map(|(edge, _)| edge)
+    }
+
+    /// `edge_at`, with `inside` deciding whether being `distance` along or across something `limit` long counts as being on it.
+    fn edge_within(
+        &self,
+        position: (f32, f32),
+        inside: impl Fn(f32, f32) -> bool,
+    ) -> Option<(usize, usize)> {
+        let within_range = |a: f32, b: f32| inside((a - b).abs(), self.half_path_width);
+        // Only the intersections with something near the position can possibly match, which is a lot fewer than all of them on big mazes.
+        // They are still looked at in order, so that the same edge comes out as if we had looked at all of them.
+        let candidates = || {
+            self.spatial_index
+                .near(position, 0.0)
+                .into_iter()
+                .map(|index| (index, &self.intersections[index]))
+        };
+        // We need to ensure that if we are on an intersection, we prioritise that over being on a path.
+        // The easiest way I can think of is an initial pass which checks if we are on any of the intersections.
+        for (intersection_index, intersection) in candidates() {
+            if within_range(intersection.coordinates.0, position.0)
+                && within_range(intersection.coordinates.1, position.1)
+            {
+                return Some((intersection_index, intersection_index));
+            }
+        }
+
+        for (intersection_index, intersection) in candidates() {
+            if within_range(intersection.coordinates.0, position.0) {
+                if intersection.coordinates.1 < position.1 {
+                    let distance = position.1 - intersection.coordinates.1;
+                    if let Some(forward_path) = intersection
+                        .forward()
+                        .filter(|forward_path| inside(distance, forward_path.length))
+                    {
+                        return Some((intersection_index, forward_path.end_index));
+                    }
+                } else if intersection.coordinates.1 > position.1 {
+                    let distance = intersection.coordinates.1 - position.1;
+                    if let Some(backward_path) = intersection
+                        .backward()
+                        .filter(|backward_path| inside(distance, backward_path.length))
+                    {
+                        return Some((intersection_index, backward_path.end_index));
+                    }
+                }
+            }
+            // We don't do an else here because there is a chance that the player isn't on any path.
+            // This being the case, the first if might not return anything but this one will.
+            // We get panics if we don't do this.
+            if within_range(intersection.coordinates.1, position.1) {
+                if intersection.coordinates.0 < position.0 {
+                    let distance = position.0 - intersection.coordinates.0;
+                    if let Some(right_path) = intersection
+                        .right()
+                        .filter(|right_path| inside(distance, right_path.length))
+                    {
+                        return Some((intersection_index, right_path.end_index));
+                    }
+                } else if intersection.coordinates.0 > position.0 {
+                    let distance = intersection.coordinates.0 - position.0;
+                    if let Some(left_path) = intersection
+                        .left()
+                        .filter(|left_path| inside(distance, left_path.length))
+                    {
+                        return Some((intersection_index, left_path.end_index));
+                    }
+                }
             }
         }
-        let mut result = GameObject::default();
-        meshes.into_iter().for_each(|mesh| {
-            result.add_mesh(mesh);
+        // Diagonal paths can't be checked one coordinate at a time, so we work out how far the position is from each of them.
+        for (intersection_index, intersection) in candidates() {
+            for (angle, path) in intersection
+                .paths
+                .iter()
+                .filter(|(angle, _)| is_diagonal_angle(*angle))
+            {
+                let offset = (
+                    position.0 - intersection.coordinates.0,
+                    position.1 - intersection.coordinates.1,
+                );
+                let along = offset.0 * angle.cos() + offset.1 * angle.sin();
+                let across = offset.1 * angle.cos() - offset.0 * angle.sin();
+                if along > 0.0
+                    && inside(along, path.length)
+                    && inside(across.abs(), self.half_path_width)
+                {
+                    return Some((intersection_index, path.end_index));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// How far the point is from the closest part of the line between `a` and `b`.
+fn distance_to_segment(point: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let segment = (b.0 - a.0, b.1 - a.1);
+    let length_squared = segment.0 * segment.0 + segment.1 * segment.1;
+    let along = if length_squared > 0.0 {
+        (((point.0 - a.0) * segment.0 + (point.1 - a.1) * segment.1) / length_squared)
+            .clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    euclidean(point, (a.0 + segment.0 * along, a.1 + segment.1 * along))
+}
+
+const SPATIAL_INDEX_CELL_SIZE: f32 = 4.0;
+
+/// Splits the maze up into squares, remembering which intersections have something (themselves or one of their paths) in each square.
+/// That way, working out what is at a position only means looking at a few intersections rather than all of them.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SpatialIndex {
+    /// The intersections are in order of their indices, since they are added in that order.
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialIndex {
+    fn new(intersections: &[Intersection], half_path_width: f32) -> Self {
+        let mut index = Self::default();
+        for (intersection_index, intersection) in intersections.iter().enumerate() {
+            let (x, y) = intersection.coordinates;
+            index.insert(intersection_index, (x, y), (x, y), half_path_width);
+            // Tunnels go off the side of the maze rather than to the other end, so we go by which way the path faces rather than where it ends up.
+            for (facing, path) in intersection.facings() {
+                let end = (x + facing.0 * path.length, y + facing.1 * path.length);
+                index.insert(intersection_index, (x, y), end, half_path_width);
+            }
+        }
+        index
+    }
+
+    fn cell(position: (f32, f32)) -> (i32, i32) {
+        (
+            (position.0 / SPATIAL_INDEX_CELL_SIZE).floor() as i32,
+            (position.1 / SPATIAL_INDEX_CELL_SIZE).floor() as i32,
+        )
+    }
+
+    /// Every cell in the rectangle between the two corners, however far the margin goes past them.
+    fn cells_between(
+        a: (f32, f32),
+        b: (f32, f32),
+        margin: f32,
+    ) -> impl Iterator<Item = (i32, i32)> {
+        let (min, max) = if [a.0, a.1, b.0, b.1, margin].into_iter().all(f32::is_finite) {
+            (
+                Self::cell((a.0.min(b.0) - margin, a.1.min(b.1) - margin)),
+                Self::cell((a.0.max(b.0) + margin, a.1.max(b.1) + margin)),
+            )
+        } else {
+            // Broken (infinite or NaN) coordinates would cover billions of cells, but there isn't really anything there to find anyway.
+            ((0, 0), (-1, -1))
+        };
+        (min.0..=max.0).flat_map(move |x| (min.1..=max.1).map(move |y| (x, y)))
+    }
+
+    fn insert(&mut self, intersection_index: usize, a: (f32, f32), b: (f32, f32), margin: f32) {
+        for cell in Self::cells_between(a, b, margin) {
+            let intersections = self.cells.entry(cell).or_default();
+            if intersections.last() != Some(&intersection_index) {
+                intersections.push(intersection_index);
+            }
+        }
+    }
+
+    /// The intersections which might have something within `radius` of the position, in order of their indices.
+    fn near(&self, position: (f32, f32), radius: f32) -> Vec<usize> {
+        Self::cells_between(position, position, radius)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Something which was wrong with a segment given to a `MazeBuilder`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MazeBuildError {
+    /// Paths have to be horizontal, vertical or at 45 degrees.
+    SlantedPath(((f32, f32), (f32, f32))),
+    /// The same segment was added twice (possibly the other way around).
+    DuplicatePath(((f32, f32), (f32, f32))),
+    /// One of the bridges given to `Maze::with_bridges` (as indices into the paths) can't go where it was asked to.
+    Bridge {
+        paths: (usize, usize),
+        error: BridgeError,
+    },
+}
+
+/// Why two paths can't have a bridge between them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BridgeError {
+    /// At least one of the indices is past the end of the paths.
+    NoSuchPath,
+    Diagonal,
+    NotCrossing,
+    /// The paths meet at the end of one of them, which is somewhere to turn rather than something to go over.
+    AtEnd,
+}
+
+impl std::fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BridgeError::NoSuchPath => write!(f, "there aren't that many paths"),
+            BridgeError::Diagonal => write!(f, "diagonal paths can't have bridges"),
+            BridgeError::NotCrossing => write!(f, "the paths don't cross"),
+            BridgeError::AtEnd => write!(f, "they cross at the end of one of them"),
+        }
+    }
+}
+
+impl std::fmt::Display for MazeBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MazeBuildError::SlantedPath(path) => write!(
+                f,
+                "the path from {:?} to {:?} isn't horizontal, vertical or at 45 degrees",
+                path.0, path.1
+            ),
+            MazeBuildError::DuplicatePath(path) => write!(
+                f,
+                "the path from {:?} to {:?} is in the maze twice",
+                path.0, path.1
+            ),
+            MazeBuildError::Bridge { paths, error } => write!(
+                f,
+                "there can't be a bridge between paths {} and {}: {}",
+                paths.0, paths.1, error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MazeBuildError {}
+
+/// A more readable way to write out the segments for `Maze::new`, which also checks them as it goes rather than panicking.
+/// ```ignore
+/// let maze = MazeBuilder::new()
+///     .horizontal(0.0, -10.0, 10.0)
+///     .vertical(0.0, -10.0, 10.0)
+///     .build()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MazeBuilder {
+    #[allow(clippy::type_complexity)]
+    paths: Vec<((f32, f32), (f32, f32))>,
+    // The first thing which went wrong, if anything did.
+    // Keeping hold of it (rather than returning it straight away) means the calls can still be chained.
+    error: Option<MazeBuildError>,
+    bridges: Vec<(usize, usize)>,
+}
+
+impl MazeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A path along the x axis at `y`, from `x0` to `x1`.
+    pub fn horizontal(self, y: f32, x0: f32, x1: f32) -> Self {
+        self.segment((x0, y), (x1, y))
+    }
+
+    /// A path along the y axis at `x`, from `y0` to `y1`.
+    pub fn vertical(self, x: f32, y0: f32, y1: f32) -> Self {
+        self.segment((x, y0), (x, y1))
+    }
+
+    /// Any other path (which includes the diagonal ones).
+    pub fn segment(mut self, a: (f32, f32), b: (f32, f32)) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        let path = (a, b);
+        if !is_straight_or_diagonal(path) {
+            self.error = Some(MazeBuildError::SlantedPath(path));
+        } else if self.paths.contains(&path) || self.paths.contains(&(b, a)) {
+            self.error = Some(MazeBuildError::DuplicatePath(path));
+        } else {
+            self.paths.push(path);
+        }
+        self
+    }
+
+    /// Makes the `a`th and `b`th segments (counting from 0, in the order they were added) go over each other rather than joining (see `Maze::with_bridges`).
+    pub fn bridge(mut self, a: usize, b: usize) -> Self {
+        self.bridges.push((a, b));
+        self
+    }
+
+    /// The segments added so far, in the order they were added.
+    #[allow(clippy::type_complexity)]
+    pub fn into_paths(self) -> Result<Vec<((f32, f32), (f32, f32))>, MazeBuildError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.paths),
+        }
+    }
+
+    pub fn build(mut self) -> Result<Maze, MazeBuildError> {
+        let bridges = std::mem::take(&mut self.bridges);
+        Maze::with_bridges(&self.into_paths()?, &bridges)
+    }
+}
+
+pub const DEFAULT_HALF_PATH_WIDTH: f32 = 0.5;
+pub const PATH_THICKNESS: f32 = 0.01;
+
+/// Works out where the two walls running along the sides of a path go.
+/// The walls sit just outside the path, so the space between them is exactly the width of the path.
+/// `trims` has how far from `from` and from `to` each wall stops (see `Intersection::wall_start`), starting with the wall on the negative side.
+fn path_wall_extents(
+    from: (f32, f32),
+    to: (f32, f32),
+    half_path_width: f32,
+    trims: [(f32, f32); 2],
+) -> Vec<BoxExtents> {
+    let is_horizontal = is_horizontal((from, to));
+    let (low, high) = if is_horizontal {
+        (from.0.min(to.0), from.0.max(to.0))
+    } else {
+        (from.1.min(to.1), from.1.max(to.1))
+    };
+    let from_is_low = if is_horizontal {
+        from.0 <= to.0
+    } else {
+        from.1 <= to.1
+    };
+    [-1.0, 1.0]
+        .into_iter()
+        .zip(trims)
+        .filter_map(|(side, (from_trim, to_trim))| {
+            let (low_trim, high_trim) = if from_is_low {
+                (from_trim, to_trim)
+            } else {
+                (to_trim, from_trim)
+            };
+            // The walls stop where they meet the walls around the intersections at either end.
+            let (start, end) = (low + low_trim, high - high_trim);
+            // Paths which are too short to need walls don't get any.
+            if start >= end {
+                return None;
+            }
+            let inner = side * half_path_width;
+            let outer = side * (half_path_width + PATH_THICKNESS);
+            Some(if is_horizontal {
+                BoxExtents::from_corners(
+                    Vec3::new(start, 0.0, from.1 + inner),
+                    Vec3::new(end, half_path_width * 2.0, from.1 + outer),
+                )
+            } else {
+                BoxExtents::from_corners(
+                    Vec3::new(from.0 + inner, 0.0, start),
+                    Vec3::new(from.0 + outer, half_path_width * 2.0, end),
+                )
+            })
+        })
+        .collect()
+}
+
+/// How close two walls have to be to count as the same one.
+const DUPLICATE_WALL_EPSILON: f32 = 0.001;
+
+/// Where two bits of the maze both put a wall in the same place, the two copies flicker as they fight over which one is drawn in front.
+/// So we only keep the first of any walls which are the same shape and in the same place.
+fn remove_duplicate_walls(meshes: &mut Vec<Mesh>) {
+    let mut kept: Vec<Mesh> = Vec::with_capacity(meshes.len());
+    for mesh in meshes.drain(..) {
+        let is_duplicate = kept.iter().any(|other| {
+            other.shape == mesh.shape
+                && other
+                    .position
+                    .abs_diff_eq(mesh.position, DUPLICATE_WALL_EPSILON)
+                && other
+                    .rotation
+                    .abs_diff_eq(mesh.rotation, DUPLICATE_WALL_EPSILON)
         });
-        result
+        if !is_duplicate {
+            kept.push(mesh);
+        }
+    }
+    *meshes = kept;
+}
+
+/// The walls along a diagonal path, which have to be rotated to line up with it.
+/// `trims` is the same as for `path_wall_extents`, with the negative side being clockwise from the path (looking down with y going up).
+fn diagonal_wall_meshes(
+    from: (f32, f32),
+    angle: f32,
+    length: f32,
+    half_path_width: f32,
+    trims: [(f32, f32); 2],
+    color: Color,
+) -> Vec<Mesh> {
+    let direction = Vec3::new(angle.cos(), 0.0, angle.sin());
+    // At right angles to the path, along the ground.
+    let across = Vec3::new(-direction.z, 0.0, direction.x);
+    let from = Vec3::new(from.0, half_path_width, from.1);
+    [-1.0, 1.0]
+        .into_iter()
+        .zip(trims)
+        .filter(|(_, (from_trim, to_trim))| from_trim + to_trim < length)
+        .map(|(side, (from_trim, to_trim))| {
+            let wall_length = length - from_trim - to_trim;
+            let middle = from + direction * (from_trim + wall_length / 2.0);
+            let mut mesh = BoxExtents::new(
+                middle + across * side * (half_path_width + PATH_THICKNESS / 2.0),
+                Vec3::new(wall_length, half_path_width * 2.0, PATH_THICKNESS),
+            )
+            .to_mesh(color);
+            mesh.rotation = Quat::from_rotation_y(-angle);
+            mesh
+        })
+        .collect()
+}
+
+/// The corners of an intersection (in x and z), going round the same way as `Intersection::sides`.
+const CORNERS: [(f32, f32); 4] = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)];
+
+/// The walls across the missing sides of an intersection stop at its edges, so where two of them meet there is a tiny square gap in the corner between them.
+/// This fills one of those in, so that nothing can slip through the walls diagonally.
+fn corner_wall_mesh(
+    coordinates: (f32, f32),
+    (x_side, z_side): (f32, f32),
+    half_path_width: f32,
+    color: Color,
+) -> Mesh {
+    let offset = half_path_width + PATH_THICKNESS / 2.0;
+    BoxExtents::new(
+        Vec3::new(
+            coordinates.0 + x_side * offset,
+            half_path_width,
+            coordinates.1 + z_side * offset,
+        ),
+        Vec3::new(PATH_THICKNESS, half_path_width * 2.0, PATH_THICKNESS),
+    )
+    .to_mesh(color)
+}
+
+impl Maze {
+    pub fn create_game_object(&self, theme: &MazeTheme) -> GameObject {
+        GameObject::with_meshes(self.wall_meshes(theme.walls))
+    }
+
+    /// Splits a straight path up wherever it goes over (or under) another one.
+    /// The walls stop at the ends of each bit, which leaves a gap for the other path to go through.
+    #[allow(clippy::type_complexity)]
+    fn split_at_bridges(&self, from: (f32, f32), to: (f32, f32)) -> Vec<((f32, f32), (f32, f32))> {
+        let distance_from_start = |point: &(f32, f32)| manhattan(*point, from);
+        let mut points: Vec<(f32, f32)> = self
+            .bridges
+            .iter()
+            .copied()
+            .filter(|bridge| is_in_middle_of((from, to), *bridge))
+            .collect();
+        points.sort_by(|a, b| distance_from_start(a).total_cmp(&distance_from_start(b)));
+        points.insert(0, from);
+        points.push(to);
+        points.windows(2).map(|pair| (pair[0], pair[1])).collect()
+    }
+
+    /// How far from each end the walls on either side of the path from `from` to `to` stop, so that they meet up with the walls around the intersections.
+    /// `facing` is which way the path leaves `from`, and the walls are given in order of how far along `side` (in x and z) they are.
+    fn wall_trims(
+        &self,
+        from: usize,
+        to: usize,
+        facing: (f32, f32),
+        side: (f32, f32),
+    ) -> [(f32, f32); 2] {
+        let angle = angle_of(facing);
+        let back_angle = angle_of((-facing.0, -facing.1));
+        // Whether the wall on the positive `side` is anticlockwise from the path, going out of `from`.
+        let anticlockwise = (-facing.1 * side.0 + facing.0 * side.1).signum();
+        [-1.0, 1.0].map(|wall| {
+            let wall_side = wall * anticlockwise;
+            (
+                self.intersections[from].wall_start(angle, wall_side, self.half_path_width),
+                // Going back the other way, the same wall is on the other side.
+                self.intersections[to].wall_start(back_angle, -wall_side, self.half_path_width),
+            )
+        })
+    }
+
+    fn wall_meshes(&self, color: Color) -> Vec<Mesh> {
+        let mut meshes: Vec<Mesh> = Vec::new();
+        let diagonal_cut = self.half_path_width * (2.0f32.sqrt() - 1.0);
+        for (index, intersection) in self.intersections.iter().enumerate() {
+            for (angle, path) in &intersection.paths {
+                if is_diagonal_angle(*angle) && path.end_index > index {
+                    let facing = facing_of(*angle);
+                    meshes.extend(diagonal_wall_meshes(
+                        intersection.coordinates,
+                        *angle,
+                        path.length,
+                        self.half_path_width,
+                        self.wall_trims(index, path.end_index, facing, (-facing.1, facing.0)),
+                        color,
+                    ));
+                }
+            }
+            // By only considering the up and right paths, we simplify the logic a lot.
+            // Every path has two intersections, which have the path on opposite edges. Therefore every path will always be either an up or a right of some intersection.
+            for (facing, path) in [
+                ((0.0, 1.0), intersection.forward()),
+                ((1.0, 0.0), intersection.right()),
+            ] {
+                let Some(path) = path.filter(|path| !path.wrap) else {
+                    continue;
+                };
+                let target_intersection = &self.intersections[path.end_index];
+                // The walls are on either side of the path, which is along the other axis.
+                let side = (facing.1, facing.0);
+                let trims = self.wall_trims(index, path.end_index, facing, side);
+                let pieces = self
+                    .split_at_bridges(intersection.coordinates, target_intersection.coordinates);
+                let last = pieces.len() - 1;
+                for (piece, (from, to)) in pieces.into_iter().enumerate() {
+                    // Only the ends of the whole path meet other paths' walls; the gaps at bridges are the same as an ordinary crossing.
+                    let piece_trims = trims.map(|(from_trim, to_trim)| {
+                        (
+                            if piece == 0 {
+                                from_trim
+                            } else {
+                                self.half_path_width
+                            },
+                            if piece == last {
+                                to_trim
+                            } else {
+                                self.half_path_width
+                            },
+                        )
+                    });
+                    for wall in path_wall_extents(from, to, self.half_path_width, piece_trims) {
+                        meshes.push(wall.to_mesh(color));
+                    }
+                }
+            }
+            // Any side of the intersection without a path needs to be walled off (and tunnels are left open).
+            // Diagonal paths next to the side leave through part of it, so that part is left open as well.
+            for (_, facing) in intersection
+                .sides()
+                .into_iter()
+                .filter(|(path, _)| path.is_none())
+            {
+                let angle = angle_of(facing);
+                // How far along the side (anticlockwise from the middle) the wall goes.
+                let start = if intersection.path_at(angle - PI / 4.0).is_some() {
+                    diagonal_cut
+                } else {
+                    -self.half_path_width
+                };
+                let end = if intersection.path_at(angle + PI / 4.0).is_some() {
+                    -diagonal_cut
+                } else {
+                    self.half_path_width
+                };
+                if start >= end {
+                    continue;
+                }
+                let along = Vec3::new(-facing.1, 0.0, facing.0);
+                let offset = self.half_path_width + PATH_THICKNESS / 2.0;
+                let length = end - start;
+                let wall = BoxExtents::new(
+                    Vec3::new(
+                        intersection.coordinates.0 + facing.0 * offset,
+                        self.half_path_width,
+                        intersection.coordinates.1 + facing.1 * offset,
+                    ) + along * (start + end) / 2.0,
+                    Vec3::new(
+                        if facing.0 == 0.0 {
+                            length
+                        } else {
+                            PATH_THICKNESS
+                        },
+                        self.half_path_width * 2.0,
+                        if facing.1 == 0.0 {
+                            length
+                        } else {
+                            PATH_THICKNESS
+                        },
+                    ),
+                );
+                meshes.push(wall.to_mesh(color));
+            }
+            // A path on either side of a corner has its own wall going straight past it, so there's only a gap where the walls on both sides turn the corner.
+            // Diagonal paths go out through the corners, so those are left open, and unjoined intersections don't have anything to fill in.
+            if intersection.kind() == IntersectionKind::Unjoined {
+                continue;
+            }
+            for corner in CORNERS {
+                if intersection.path_facing(corner).is_none()
+                    && intersection.path_facing((corner.0, 0.0)).is_none()
+                    && intersection.path_facing((0.0, corner.1)).is_none()
+                {
+                    meshes.push(corner_wall_mesh(
+                        intersection.coordinates,
+                        corner,
+                        self.half_path_width,
+                        color,
+                    ));
+                }
+            }
+        }
+        remove_duplicate_walls(&mut meshes);
+        meshes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{index_of, object::Shape};
+
+    use super::*;
+
+    /// An intersection with these straight paths out of it.
+    fn with_paths(
+        left: Option<Path>,
+        right: Option<Path>,
+        forward: Option<Path>,
+        backward: Option<Path>,
+        coordinates: (f32, f32),
+    ) -> Intersection {
+        let mut intersection = Intersection::new(coordinates);
+        for (path, facing) in [
+            (left, (-1.0, 0.0)),
+            (right, (1.0, 0.0)),
+            (forward, (0.0, 1.0)),
+            (backward, (0.0, -1.0)),
+        ] {
+            if let Some(path) = path {
+                intersection.add_path(angle_of(facing), path);
+            }
+        }
+        intersection
+    }
+
+    #[test]
+    fn create_maze_simple() {
+        let maze = Maze::new(&[((0.0, 1.0), (0.0, -1.0)), ((1.0, 0.0), (-1.0, 0.0))]);
+        assert_eq!(
+            maze.intersections,
+            vec![
+                with_paths(
+                    Some(Path::new(3, 1.0)),
+                    Some(Path::new(4, 1.0)),
+                    Some(Path::new(2, 1.0)),
+                    Some(Path::new(1, 1.0)),
+                    (0.0, 0.0)
+                ),
+                with_paths(None, None, Some(Path::new(0, 1.0)), None, (0.0, -1.0)),
+                with_paths(None, None, None, Some(Path::new(0, 1.0)), (0.0, 1.0)),
+                with_paths(None, Some(Path::new(0, 1.0)), None, None, (-1.0, 0.0)),
+                with_paths(Some(Path::new(0, 1.0)), None, None, None, (1.0, 0.0)),
+            ]
+        );
+        let maze = Maze::new(&[
+            ((0.0, 1.0), (0.0, -1.0)),
+            ((1.0, 0.0), (-1.0, 0.0)),
+            ((1.0, 1.0), (1.0, -1.0)),
+        ]);
+        assert_eq!(
+            maze.intersections,
+            vec![
+                with_paths(
+                    Some(Path::new(4, 1.0)),
+                    Some(Path::new(3, 1.0)),
+                    Some(Path::new(2, 1.0)),
+                    Some(Path::new(1, 1.0)),
+                    (0.0, 0.0)
+                ),
+                with_paths(None, None, Some(Path::new(0, 1.0)), None, (0.0, -1.0)),
+                with_paths(None, None, None, Some(Path::new(0, 1.0)), (0.0, 1.0)),
+                with_paths(
+                    Some(Path::new(0, 1.0)),
+                    None,
+                    Some(Path::new(6, 1.0)),
+                    Some(Path::new(5, 1.0)),
+                    (1.0, 0.0)
+                ),
+                with_paths(None, Some(Path::new(0, 1.0)), None, None, (-1.0, 0.0)),
+                with_paths(None, None, Some(Path::new(3, 1.0)), None, (1.0, -1.0)),
+                with_paths(None, None, None, Some(Path::new(3, 1.0)), (1.0, 1.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn create_maze_complex_intersections() {
+        let maze1 = Maze::new(&[((0.0, 1.0), (0.0, -1.0)), ((1.0, 0.0), (-1.0, 0.0))]);
+        let maze2 = Maze::new(&[
+            ((0.0, 0.0), (0.0, -1.0)),
+            ((0.0, 1.0), (0.0, 0.0)),
+            ((1.0, 0.0), (-1.0, 0.0)),
+        ]);
+        assert_eq!(maze1, maze2);
+    }
+
+    #[test]
+    fn test_edge_at() {
+        let maze = Maze::new(&[((1.0, 0.0), (-1.0, 0.0)), ((0.0, 1.0), (0.0, -1.0))]);
+        let left_path = maze.edge_at((-0.5, 0.0));
+        assert_eq!(left_path, Some((0, 1)));
+        let right_path = maze.edge_at((0.5, 0.0));
+        assert_eq!(right_path, Some((0, 2)));
+        let forward_path = maze.edge_at((0.0, 0.5));
+        assert_eq!(forward_path, Some((0, 4)));
+        let backward_path = maze.edge_at((0.0, -0.5));
+        assert_eq!(backward_path, Some((0, 3)));
+    }
+
+    #[test]
+    fn edges_right_on_the_boundary() {
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((10.0, 0.0), (20.0, 10.0))]);
+        let half_path_width = maze.half_path_width();
+        // Which way round the ends come out doesn't matter here.
+        let edge_at =
+            |position: (f32, f32)| maze.edge_at(position).map(|(a, b)| (a.min(b), a.max(b)));
+        let between = |a: (f32, f32), b: (f32, f32)| {
+            Some((
+                index_of(&maze, a).min(index_of(&maze, b)),
+                index_of(&maze, a).max(index_of(&maze, b)),
+            ))
+        };
+        let dead_end = Some((index_of(&maze, (0.0, 0.0)), index_of(&maze, (0.0, 0.0))));
+        // Halfway along, and right on the side of the path.
+        assert_eq!(edge_at((5.0, 0.0)), between((0.0, 0.0), (10.0, 0.0)));
+        assert_eq!(
+            edge_at((5.0, half_path_width)),
+            between((0.0, 0.0), (10.0, 0.0))
+        );
+        assert_eq!(
+            edge_at((5.0, -half_path_width)),
+            between((0.0, 0.0), (10.0, 0.0))
+        );
+        assert_eq!(edge_at((5.0, half_path_width + 0.01)), None);
+        // Right on the end of the dead end, including its corners.
+        assert_eq!(edge_at((-half_path_width, 0.0)), dead_end);
+        assert_eq!(edge_at((-half_path_width, half_path_width)), dead_end);
+        assert_eq!(edge_at((0.0, -half_path_width)), dead_end);
+        assert_eq!(edge_at((-half_path_width - 0.01, 0.0)), None);
+        // Right on the far end of a path is the intersection there.
+        assert_eq!(
+            edge_at((10.0, 0.0)),
+            Some((index_of(&maze, (10.0, 0.0)), index_of(&maze, (10.0, 0.0))))
+        );
+        // The same goes for the sides of diagonal paths (as near as rounding lets us get to them).
+        let across = half_path_width / 2.0_f32.sqrt();
+        assert_eq!(
+            edge_at((15.0 - across * 0.999, 5.0 + across * 0.999)),
+            between((10.0, 0.0), (20.0, 10.0))
+        );
+        assert_eq!(edge_at((15.0 - across * 1.1, 5.0 + across * 1.1)), None);
+    }
+
+    #[test]
+    fn on_and_near_the_paths() {
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0)), ((10.0, 0.0), (10.0, 10.0))]);
+        let sorted = |edge: Option<(usize, usize)>| edge.map(|(a, b)| (a.min(b), a.max(b)));
+        let bottom = Some((
+            index_of(&maze, (0.0, 0.0)).min(index_of(&maze, (10.0, 0.0))),
+            index_of(&maze, (0.0, 0.0)).max(index_of(&maze, (10.0, 0.0))),
+        ));
+        let side = Some((
+            index_of(&maze, (10.0, 0.0)).min(index_of(&maze, (10.0, 10.0))),
+            index_of(&maze, (10.0, 0.0)).max(index_of(&maze, (10.0, 10.0))),
+        ));
+        // Inside a corridor.
+        assert!(maze.on_path((5.0, 0.3)));
+        assert_eq!(sorted(maze.nearest_path((5.0, 0.3))), bottom);
+        // On an intersection.
+        assert!(maze.on_path((10.0, 0.0)));
+        assert_eq!(
+            maze.nearest_path((10.0, 0.0)),
+            Some((index_of(&maze, (10.0, 0.0)), index_of(&maze, (10.0, 0.0))))
+        );
+        // Well outside the maze, where the closest path is still found.
+        assert!(!maze.on_path((4.0, -20.0)));
+        assert_eq!(sorted(maze.nearest_path((4.0, -20.0))), bottom);
+        assert!(!maze.on_path((30.0, 8.0)));
+        assert_eq!(sorted(maze.nearest_path((30.0, 8.0))), side);
+        // There isn't anything to be near in an empty maze.
+        assert!(!Maze::new(&[]).on_path((0.0, 0.0)));
+        assert_eq!(Maze::new(&[]).nearest_path((0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn unreachable_points() {
+        // Two separate loops which never meet.
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (2.0, 0.0)),
+            ((2.0, 0.0), (2.0, 2.0)),
+            ((2.0, 2.0), (0.0, 2.0)),
+            ((0.0, 2.0), (0.0, 0.0)),
+            ((10.0, 0.0), (12.0, 0.0)),
+            ((12.0, 0.0), (12.0, 2.0)),
+            ((12.0, 2.0), (10.0, 2.0)),
+            ((10.0, 2.0), (10.0, 0.0)),
+        ]);
+        let start_edge = maze.edge_at((1.0, 0.0)).unwrap();
+        assert!(maze.is_reachable(start_edge, (2.0, 1.0)));
+        assert!(maze.is_reachable(start_edge, (0.0, 2.0)));
+        assert!(!maze.is_reachable(start_edge, (11.0, 2.0)));
+        // This one isn't on a path at all.
+        assert!(!maze.is_reachable(start_edge, (5.0, 5.0)));
+    }
+
+    #[test]
+    fn test_direction_between() {
+        let maze = crate::sample_maze();
+        let centre = index_of(&maze, (0.0, 0.0));
+        // The paths going off the edges of the square make intersections half way along each side of it.
+        // Remember that forward is the negative z direction.
+        assert_eq!(
+            maze.direction_between(centre, index_of(&maze, (0.0, -5.0))),
+            Some(Direction::Forward)
+        );
+        assert_eq!(
+            maze.direction_between(centre, index_of(&maze, (0.0, 5.0))),
+            Some(Direction::Backward)
+        );
+        assert_eq!(
+            maze.direction_between(centre, index_of(&maze, (-5.0, 0.0))),
+            Some(Direction::Left)
+        );
+        assert_eq!(
+            maze.direction_between(index_of(&maze, (15.0, 5.0)), index_of(&maze, (10.0, 5.0))),
+            Some(Direction::Left)
+        );
+        assert_eq!(
+            maze.direction_between(index_of(&maze, (5.0, 20.0)), index_of(&maze, (10.0, 20.0))),
+            Some(Direction::Right)
+        );
+        // These are on the same line, but there is another intersection in between.
+        assert_eq!(
+            maze.direction_between(index_of(&maze, (0.0, 5.0)), index_of(&maze, (0.0, -5.0))),
+            None
+        );
+        assert_eq!(
+            maze.direction_between(centre, index_of(&maze, (10.0, 10.0))),
+            None
+        );
+    }
+
+    #[test]
+    fn create_maze_backward_paths() {
+        let maze1 = Maze::new(&[((0.0, 1.0), (0.0, -1.0)), ((1.0, 0.0), (-1.0, 0.0))]);
+        let maze2 = Maze::new(&[((0.0, -1.0), (0.0, 1.0)), ((-1.0, 0.0), (1.0, 0.0))]);
+        assert_eq!(maze1, maze2);
+    }
+
+    #[test]
+    fn diagonal_paths() {
+        // A square with a diagonal across it, going the "wrong way" to make sure that doesn't matter.
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (4.0, 0.0)),
+            ((4.0, 0.0), (4.0, 4.0)),
+            ((4.0, 4.0), (0.0, 4.0)),
+            ((0.0, 4.0), (0.0, 0.0)),
+            ((4.0, 4.0), (0.0, 0.0)),
+            ((0.0, 2.0), (4.0, 2.0)),
+        ]);
+        // The diagonal is split where it crosses the horizontal path across the middle.
+        let diagonals = |intersection: &Intersection| {
+            intersection
+                .paths
+                .iter()
+                .filter(|(angle, _)| is_diagonal_angle(*angle))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let corner = &maze.intersections()[index_of(&maze, (0.0, 0.0))];
+        assert_eq!(diagonals(corner).len(), 1);
+        let (angle, path) = &diagonals(corner)[0];
+        assert_eq!(*angle, std::f32::consts::FRAC_PI_4);
+        assert_eq!(path.end_index, index_of(&maze, (2.0, 2.0)));
+        assert_eq!(path.length, 8.0f32.sqrt());
+        // The straight paths are in there with it, in order of their angles.
+        assert_eq!(corner.degree(), 3);
+        assert!(corner.right().is_some() && corner.forward().is_some());
+        assert!(corner.paths.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let middle = &maze.intersections()[index_of(&maze, (2.0, 2.0))];
+        assert_eq!(diagonals(middle).len(), 2);
+        assert!(middle.left().is_some() && middle.right().is_some());
+        let (a, b) = maze.edge_at((3.0, 3.1)).unwrap();
+        let mut ends = [
+            maze.intersections()[a].coordinates,
+            maze.intersections()[b].coordinates,
+        ];
+        ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(ends, [(2.0, 2.0), (4.0, 4.0)]);
+        assert_eq!(maze.edge_at((3.0, 1.0)), None);
+        // Six edges around the outside, and two each for the paths across the middle.
+        assert_eq!(maze.paths().len(), 10);
+        // Building it with the diagonal going the other way makes the same maze.
+        let reversed = Maze::new(&[
+            ((0.0, 0.0), (4.0, 0.0)),
+            ((4.0, 0.0), (4.0, 4.0)),
+            ((4.0, 4.0), (0.0, 4.0)),
+            ((0.0, 4.0), (0.0, 0.0)),
+            ((0.0, 0.0), (4.0, 4.0)),
+            ((0.0, 2.0), (4.0, 2.0)),
+        ]);
+        assert_eq!(maze, reversed);
+        // The walls are turned to run along the path, on either side of it.
+        for wall in diagonal_wall_meshes(
+            (0.0, 0.0),
+            *angle,
+            path.length,
+            DEFAULT_HALF_PATH_WIDTH,
+            [(DEFAULT_HALF_PATH_WIDTH, DEFAULT_HALF_PATH_WIDTH); 2],
+            Color::GRAY,
+        ) {
+            assert!(
+                (wall.rotation * Vec3::X).abs_diff_eq(Vec3::new(1.0, 0.0, 1.0).normalize(), 0.0001)
+            );
+            assert!((wall.position.x - wall.position.z).abs() > DEFAULT_HALF_PATH_WIDTH);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn slanted_paths_panic() {
+        Maze::new(&[((0.0, 0.0), (1.0, 2.0))]);
+    }
+
+    #[test]
+    fn tunnels_wrap_around() {
+        let mut maze = Maze::new(&[((-10.0, 0.0), (10.0, 0.0)), ((0.0, -5.0), (0.0, 5.0))]);
+        let paths = maze.paths();
+        maze.add_wrap((-10.0, 0.0), (10.0, 0.0), 2.0);
+        // The tunnel isn't a real segment, so it doesn't show up with the others.
+        assert_eq!(maze.paths(), paths);
+        // Which end comes first depends on the order of the intersections.
+        let sorted_wraps = |maze: &Maze| {
+            maze.wraps()
+                .into_iter()
+                .map(|(a, b, length)| {
+                    if a < b {
+                        (a, b, length)
+                    } else {
+                        (b, a, length)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted_wraps(&maze), vec![((-10.0, 0.0), (10.0, 0.0), 2.0)]);
+        // Going out the left comes back in the right, having gone just as far.
+        let wrapped = maze.wrap_position((-10.6, 0.1)).unwrap();
+        assert!((wrapped.0 - 10.4).abs() < 0.0001);
+        assert_eq!(wrapped.1, 0.0);
+        assert_eq!(maze.wrap_position((-10.4, 0.0)), None);
+        assert_eq!(maze.wrap_position((-10.6, 1.0)), None);
+        // Editing the maze keeps the tunnel.
+        maze.add_path(((0.0, 5.0), (5.0, 5.0)));
+        assert_eq!(sorted_wraps(&maze), vec![((-10.0, 0.0), (10.0, 0.0), 2.0)]);
+        let left_end = index_of(&maze, (-10.0, 0.0));
+        let right_end = maze.intersections()[left_end].left().unwrap().end_index;
+        assert_eq!(maze.wrap_direction(left_end, right_end), Some((-1.0, 0.0)));
+        assert_eq!(maze.wrap_direction(right_end, left_end), Some((1.0, 0.0)));
+        // So does taking a path out again, as long as both ends of the tunnel are still there.
+        let (a, b) = (index_of(&maze, (0.0, 5.0)), index_of(&maze, (5.0, 5.0)));
+        maze.remove_path(a, b);
+        assert_eq!(maze.paths().len(), paths.len());
+        assert_eq!(sorted_wraps(&maze), vec![((-10.0, 0.0), (10.0, 0.0), 2.0)]);
+        let (a, b) = (index_of(&maze, (0.0, 0.0)), index_of(&maze, (10.0, 0.0)));
+        maze.remove_path(a, b);
+        assert_eq!(maze.paths().len(), paths.len() - 1);
+        assert!(!maze.has_wraps());
+    }
+
+    #[test]
+    fn builder_checks_segments() {
+        let maze = MazeBuilder::new()
+            .horizontal(0.0, 1.0, -1.0)
+            .vertical(0.0, 1.0, -1.0)
+            .segment((1.0, 1.0), (-1.0, -1.0))
+            .build()
+            .unwrap();
+        assert_eq!(
+            maze,
+            Maze::new(&[
+                ((1.0, 0.0), (-1.0, 0.0)),
+                ((0.0, 1.0), (0.0, -1.0)),
+                ((1.0, 1.0), (-1.0, -1.0)),
+            ])
+        );
+        // Only the first problem is reported, and nothing after it gets added.
+        assert_eq!(
+            MazeBuilder::new()
+                .segment((0.0, 0.0), (1.0, 2.0))
+                .horizontal(0.0, 0.0, 5.0)
+                .horizontal(0.0, 5.0, 0.0)
+                .build(),
+            Err(MazeBuildError::SlantedPath(((0.0, 0.0), (1.0, 2.0))))
+        );
+        assert_eq!(
+            MazeBuilder::new()
+                .horizontal(0.0, 0.0, 5.0)
+                .horizontal(0.0, 5.0, 0.0)
+                .build(),
+            Err(MazeBuildError::DuplicatePath(((5.0, 0.0), (0.0, 0.0))))
+        );
+    }
+
+    #[test]
+    fn walls_are_not_duplicated() {
+        // A 2x2 grid of squares.
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (4.0, 0.0)),
+            ((0.0, 2.0), (4.0, 2.0)),
+            ((0.0, 4.0), (4.0, 4.0)),
+            ((0.0, 0.0), (0.0, 4.0)),
+            ((2.0, 0.0), (2.0, 4.0)),
+            ((4.0, 0.0), (4.0, 4.0)),
+        ]);
+        let mut meshes = maze.wall_meshes(Color::GRAY);
+        for (i, mesh) in meshes.iter().enumerate() {
+            assert!(meshes[i + 1..]
+                .iter()
+                .all(|other| !other.position.abs_diff_eq(mesh.position, 0.001)));
+        }
+        // And if the same wall does turn up twice, only one of them is kept.
+        let wall = meshes[0].clone();
+        let count = meshes.len();
+        meshes.push(wall);
+        remove_duplicate_walls(&mut meshes);
+        assert_eq!(meshes.len(), count);
+    }
+
+    #[test]
+    fn wider_paths() {
+        let narrow = Maze::new(&[((0.0, 0.0), (10.0, 0.0))]);
+        let wide = narrow.clone().with_half_path_width(1.0);
+        assert_eq!(narrow.edge_at((5.0, 0.8)), None);
+        assert!(wide.edge_at((5.0, 0.8)).is_some());
+        // The walls move out to match.
+        let wall_distance = |maze: &Maze| {
+            maze.wall_meshes(Color::GRAY)
+                .iter()
+                .map(|wall| wall.position.z.abs())
+                .filter(|z| *z > 0.0)
+                .fold(f32::INFINITY, f32::min)
+        };
+        assert!(wall_distance(&wide) > 1.0);
+        assert!(wall_distance(&narrow) < 1.0);
+        // Editing the maze doesn't lose the width.
+        let mut edited = wide.clone();
+        edited.add_path(((5.0, 0.0), (5.0, 5.0)));
+        assert_eq!(edited.half_path_width(), 1.0);
+    }
+
+    #[test]
+    fn two_loops_are_two_components() {
+        // Two squares next to each other, without anything joining them.
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (2.0, 0.0)),
+            ((2.0, 0.0), (2.0, 2.0)),
+            ((2.0, 2.0), (0.0, 2.0)),
+            ((0.0, 2.0), (0.0, 0.0)),
+            ((5.0, 0.0), (7.0, 0.0)),
+            ((7.0, 0.0), (7.0, 2.0)),
+            ((7.0, 2.0), (5.0, 2.0)),
+            ((5.0, 2.0), (5.0, 0.0)),
+        ]);
+        let components = maze.connected_components();
+        assert_eq!(components.len(), 2);
+        for component in components {
+            assert_eq!(component.len(), 4);
+            let left_loop = maze.intersections()[component[0]].coordinates.0 < 3.0;
+            assert!(component
+                .iter()
+                .all(|index| (maze.intersections()[*index].coordinates.0 < 3.0) == left_loop));
+        }
+        assert!(!maze.is_fully_connected());
+        assert!(crate::sample_maze().is_fully_connected());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let maze = crate::sample_maze();
+        assert_eq!(Maze::from_json(&maze.to_json()).unwrap(), maze);
+        assert!(Maze::from_json("{\"intersections\": 5}").is_err());
+        // Mazes can be read as part of something else too (not just with `from_json`), and they still need their spatial index then.
+        let (read, _): (Maze, u32) =
+            serde_json::from_str(&format!("[{}, 1]", maze.to_json())).unwrap();
+        assert_eq!(read, maze);
+    }
+
+    #[test]
+    fn wall_meshes_match_colliders() {
+        let walls = path_wall_extents(
+            (0.0, 0.0),
+            (4.0, 0.0),
+            DEFAULT_HALF_PATH_WIDTH,
+            [(DEFAULT_HALF_PATH_WIDTH, DEFAULT_HALF_PATH_WIDTH); 2],
+        );
+        // The gap between the walls should be exactly the width of the path.
+        assert_eq!(
+            (walls[1].center.z - walls[1].half_extents.z)
+                - (walls[0].center.z + walls[0].half_extents.z),
+            DEFAULT_HALF_PATH_WIDTH * 2.0
+        );
+        assert_eq!(walls[0].size().x, 4.0 - DEFAULT_HALF_PATH_WIDTH * 2.0);
+        for wall in walls {
+            let mesh = wall.to_mesh(Color::GRAY);
+            let Shape::Box {
+                width,
+                height,
+                depth,
+            } = mesh.shape
+            else {
+                panic!("Walls should be boxes");
+            };
+            assert_eq!(mesh.position, wall.center);
+            let collider = mesh.get_collider();
+            let half_extents = collider.as_cuboid().unwrap().half_extents();
+            assert_eq!(half_extents, wall.half_extents);
+            assert_eq!(half_extents * 2.0, Vec3::new(width, height, depth));
+        }
+    }
+
+    #[test]
+    fn player_fits_between_the_walls() {
+        // Diagonal paths leave through the corners of the intersections, and meet the straight paths at 45 degrees.
+        let diagonals = Maze::new(&[
+            ((0.0, 0.0), (4.0, 0.0)),
+            ((0.0, 0.0), (0.0, 4.0)),
+            ((0.0, 4.0), (4.0, 4.0)),
+            ((4.0, 0.0), (4.0, 4.0)),
+            ((0.0, 0.0), (4.0, 4.0)),
+            ((0.0, 2.0), (4.0, 2.0)),
+            ((4.0, 0.0), (8.0, 4.0)),
+        ]);
+        for maze in [crate::sample_maze(), diagonals] {
+            assert_player_fits(&maze);
+        }
+    }
+
+    fn assert_player_fits(maze: &Maze) {
+        // The ghosts have to fit as well.
+        let size = crate::CharacterSize::default();
+        let radius = size.player_radius(maze).max(size.ghost_radius(maze));
+        let walls = maze.wall_meshes(Color::GRAY);
+        // How far a point is from the nearest part of a wall, looking down from above.
+        let distance_to_wall = |point: Vec3, wall: &Mesh| {
+            let Shape::Box { width, depth, .. } = wall.shape else {
+                panic!("Walls should be boxes");
+            };
+            let local = wall.rotation.inverse() * (point - wall.position);
+            let outside_x = (local.x.abs() - width / 2.0).max(0.0);
+            let outside_z = (local.z.abs() - depth / 2.0).max(0.0);
+            (outside_x * outside_x + outside_z * outside_z).sqrt()
+        };
+        // Going down the middle of every path (intersections included) in small steps, the player never touches a wall.
+        for (from, to) in maze.paths() {
+            let from = Vec3::new(from.0, 0.0, from.1);
+            let to = Vec3::new(to.0, 0.0, to.1);
+            let steps = (from.distance(to) / 0.1).ceil() as usize;
+            for step in 0..=steps {
+                let point = from.lerp(to, step as f32 / steps as f32);
+                for wall in &walls {
+                    assert!(
+                        distance_to_wall(point, wall) >= radius,
+                        "Something at {} would be inside the wall at {}",
+                        point,
+                        wall.position
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_real_corners_are_filled() {
+        let corner_offset = DEFAULT_HALF_PATH_WIDTH + PATH_THICKNESS / 2.0;
+        // Only the ones round the intersection at these coordinates, leaving out the dead ends at the ends of the paths.
+        let corner_pieces = |walls: &[Mesh], (x, z): (f32, f32)| {
+            walls
+                .iter()
+                .filter(|wall| {
+                    (wall.position.x - x).abs() < 1.0 && (wall.position.z - z).abs() < 1.0
+                })
+                .filter(|wall| {
+                    wall.shape
+                        == Shape::Box {
+                            width: PATH_THICKNESS,
+                            height: DEFAULT_HALF_PATH_WIDTH * 2.0,
+                            depth: PATH_THICKNESS,
+                        }
+                })
+                .map(|wall| wall.position)
+                .collect::<Vec<_>>()
+        };
+        let covered = |walls: &[Mesh], point: Vec3| {
+            walls.iter().any(|wall| {
+                let Shape::Box {
+                    width,
+                    height,
+                    depth,
+                } = wall.shape
+                else {
+                    return false;
+                };
+                let distance = (point - wall.position).abs();
+                distance.x <= width / 2.0 && distance.y <= height / 2.0 && distance.z <= depth / 2.0
+            })
+        };
+
+        // At a crossing, the walls along the paths already go past every corner, so nothing else is needed.
+        let crossing = Maze::new(&[((-5.0, 0.0), (5.0, 0.0)), ((0.0, -5.0), (0.0, 5.0))]);
+        let walls = crossing.wall_meshes(Color::GRAY);
+        assert!(corner_pieces(&walls, (0.0, 0.0)).is_empty());
+        for (x_side, z_side) in CORNERS {
+            let position = Vec3::new(
+                x_side * corner_offset,
+                DEFAULT_HALF_PATH_WIDTH,
+                z_side * corner_offset,
+            );
+            assert!(
+                covered(&walls, position),
+                "No wall in the corner at {}",
+                position
+            );
+        }
+
+        // Going round a bend, only the outside of it has walls turning a corner.
+        let bend = Maze::new(&[((0.0, 0.0), (5.0, 0.0)), ((0.0, 0.0), (0.0, -5.0))]);
+        let walls = bend.wall_meshes(Color::GRAY);
+        assert_eq!(
+            corner_pieces(&walls, (0.0, 0.0)),
+            vec![Vec3::new(
+                -corner_offset,
+                DEFAULT_HALF_PATH_WIDTH,
+                corner_offset
+            )]
+        );
+        // Intersections which nothing goes through don't get any.
+        let maze = crate::sample_maze();
+        let walls = maze.wall_meshes(Color::GRAY);
+        let unjoined = maze
+            .intersections()
+            .iter()
+            .find(|intersection| intersection.kind() == IntersectionKind::Unjoined)
+            .unwrap();
+        assert!(corner_pieces(&walls, unjoined.coordinates).is_empty());
+    }
+
+    #[test]
+    fn nearly_matching_ends_join() {
+        // The end of one path is a tiny bit off from the other one, which is what comes out of adding up floats.
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (0.3 + 0.6, 0.0)),
+            ((0.9, 0.0), (0.9, 5.0)),
+            ((0.0, 0.1 + 0.2), (5.0, 0.1 + 0.2)),
+            ((2.0, 0.3), (2.0, -5.0)),
+        ]);
+        let corner = &maze.intersections()[index_of(&maze, (0.9, 0.0))];
+        assert_eq!(corner.degree(), 2);
+        assert_eq!(corner.kind(), IntersectionKind::Corner);
+        let tee = &maze.intersections()[index_of(&maze, (2.0, 0.3))];
+        assert_eq!(tee.kind(), IntersectionKind::Tee);
+        assert!(maze.is_fully_connected());
+    }
+
+    #[test]
+    fn lines_crossing_at_one_point() {
+        let maze = Maze::new(&[
+            ((-5.0, 0.0), (5.0, 0.0)),
+            ((0.0, -5.0), (0.0, 5.0)),
+            ((-5.0, -5.0), (5.0, 5.0)),
+            ((-5.0, 5.0), (5.0, -5.0)),
+        ]);
+        let middles: Vec<_> = maze
+            .intersections()
+            .iter()
+            .filter(|intersection| intersection.coordinates == (0.0, 0.0))
+            .collect();
+        assert_eq!(middles.len(), 1);
+        assert_eq!(middles[0].all_paths().count(), 8);
+        // Each end of each line is an intersection as well.
+        assert_eq!(maze.intersections().len(), 9);
+    }
+
+    #[test]
+    fn bridges_dont_join() {
+        let paths = [
+            ((-5.0, 0.0), (5.0, 0.0)),
+            ((0.0, -5.0), (0.0, 5.0)),
+            ((-5.0, 5.0), (5.0, 5.0)),
+        ];
+        let maze = Maze::with_bridges(&paths, &[(0, 1)]).unwrap();
+        assert!(!maze
+            .intersections()
+            .iter()
+            .any(|intersection| intersection.coordinates == (0.0, 0.0)));
+        // The horizontal path goes straight across, so it is still one edge (and nothing can turn off it half way).
+        let left = &maze.intersections()[index_of(&maze, (-5.0, 0.0))];
+        assert_eq!(left.right().unwrap().length, 10.0);
+        // The vertical path still joins the top one, but not the horizontal one.
+        assert_eq!(maze.connected_components().len(), 2);
+        assert_eq!(maze, Maze::with_bridges(&paths, &[(1, 0)]).unwrap());
+
+        // The walls of each path leave a gap for the other one, so they end up just like they would round an intersection with paths on every side.
+        let walls = maze.wall_meshes(Color::GRAY);
+        let joined_walls = Maze::new(&paths).wall_meshes(Color::GRAY);
+        assert_eq!(walls.len(), joined_walls.len());
+        for wall in &walls {
+            assert!(joined_walls
+                .iter()
+                .any(|other| other.shape == wall.shape && other.position == wall.position));
+        }
+
+        // Adding more paths keeps the bridge.
+        let mut maze = maze;
+        maze.add_path(((5.0, 0.0), (5.0, 5.0)));
+        assert!(!maze
+            .intersections()
+            .iter()
+            .any(|intersection| intersection.coordinates == (0.0, 0.0)));
+        assert!(maze.is_fully_connected());
+
+        // Bridges which can't go where they are asked to are errors rather than panics, since they can come from level files.
+        let bridge_error = |paths: &[_], bridge| match Maze::with_bridges(paths, &[bridge]) {
+            Err(MazeBuildError::Bridge { error, .. }) => Some(error),
+            _ => None,
+        };
+        assert_eq!(bridge_error(&paths, (0, 3)), Some(BridgeError::NoSuchPath));
+        assert_eq!(bridge_error(&paths, (0, 2)), Some(BridgeError::NotCrossing));
+        // The vertical path ends on the top one.
+        assert_eq!(bridge_error(&paths, (1, 2)), Some(BridgeError::AtEnd));
+        let diagonal = [paths[0], ((-5.0, -5.0), (5.0, 5.0))];
+        assert_eq!(bridge_error(&diagonal, (0, 1)), Some(BridgeError::Diagonal));
+    }
+
+    #[test]
+    fn maze_bounds() {
+        // The sample maze goes from -15 to 15 across and -20 to 20 down.
+        assert_eq!(
+            crate::sample_maze().bounds(),
+            (Vec3::new(-15.5, 0.0, -20.5), Vec3::new(15.5, 1.0, 20.5))
+        );
+        let maze = Maze::new(&[((0.0, 0.0), (4.0, 0.0))]).with_half_path_width(1.0);
+        assert_eq!(
+            maze.bounds(),
+            (Vec3::new(-1.0, 0.0, -1.0), Vec3::new(5.0, 2.0, 1.0))
+        );
+        assert_eq!(Maze::new(&[]).bounds(), (Vec3::ZERO, Vec3::ZERO));
+    }
+
+    #[test]
+    fn route_lengths() {
+        let maze = crate::sample_maze();
+        let route = |coordinates: &[(f32, f32)]| {
+            coordinates
+                .iter()
+                .map(|coordinates| index_of(&maze, *coordinates))
+                .collect::<Vec<_>>()
+        };
+        // From Blinky's spawn point to the middle of the maze.
+        assert_eq!(
+            maze.route_length(&route(&[
+                (5.0, 20.0),
+                (5.0, 10.0),
+                (0.0, 10.0),
+                (0.0, 5.0),
+                (0.0, 0.0)
+            ])),
+            Some(25.0)
+        );
+        // Going nowhere is no distance at all.
+        assert_eq!(maze.route_length(&route(&[(0.0, 0.0)])), Some(0.0));
+        assert_eq!(maze.route_length(&[]), Some(0.0));
+        // Indices which aren't in the maze don't go anywhere.
+        assert_eq!(maze.route_length(&[maze.intersections().len(), 0]), None);
+        // There isn't a path straight across from the middle to the corner of the loop.
+        assert_eq!(
+            maze.route_length(&route(&[(0.0, 0.0), (0.0, 5.0), (5.0, 20.0)])),
+            None
+        );
+    }
+
+    #[test]
+    fn kinds_of_intersection() {
+        let maze = crate::sample_maze();
+        let count = |kind| {
+            maze.intersections()
+                .iter()
+                .filter(|intersection| intersection.kind() == kind)
+                .count()
+        };
+        assert_eq!(count(IntersectionKind::Unjoined), 6);
+        assert_eq!(count(IntersectionKind::DeadEnd), 0);
+        assert_eq!(count(IntersectionKind::Corridor), 14);
+        assert_eq!(count(IntersectionKind::Corner), 12);
+        assert_eq!(count(IntersectionKind::Tee), 16);
+        assert_eq!(count(IntersectionKind::Cross), 1);
+        let middle = &maze.intersections()[index_of(&maze, (0.0, 0.0))];
+        assert_eq!(middle.degree(), 4);
+
+        // Diagonals count too.
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (5.0, 5.0)),
+            ((5.0, 5.0), (10.0, 10.0)),
+            ((10.0, 10.0), (15.0, 10.0)),
+        ]);
+        let kind_at = |coordinates| maze.intersections()[index_of(&maze, coordinates)].kind();
+        assert_eq!(kind_at((0.0, 0.0)), IntersectionKind::DeadEnd);
+        assert_eq!(kind_at((5.0, 5.0)), IntersectionKind::Corridor);
+        assert_eq!(kind_at((10.0, 10.0)), IntersectionKind::Corner);
+        assert_eq!(kind_at((15.0, 10.0)), IntersectionKind::DeadEnd);
+    }
+
+    #[test]
+    fn corners_of_the_sample_maze() {
+        let maze = crate::sample_maze();
+        let corners = maze
+            .corners()
+            .unwrap()
+            .map(|index| maze.intersections()[index].coordinates);
+        let quadrants = corners.map(|(x, y)| (x.signum(), y.signum()));
+        assert_eq!(
+            quadrants,
+            [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+        );
+        assert_eq!(
+            corners,
+            [(-15.0, -20.0), (15.0, -20.0), (-15.0, 20.0), (15.0, 20.0)]
+        );
+        assert_eq!(Maze::new(&[]).corners(), None);
+    }
+
+    #[test]
+    fn shortest_paths_across_the_sample_maze() {
+        let maze = crate::sample_maze();
+        let coordinates_of = |path: Vec<usize>| {
+            path.into_iter()
+                .map(|index| maze.intersections()[index].coordinates)
+                .collect::<Vec<_>>()
+        };
+        let middle = index_of(&maze, (0.0, 0.0));
+        assert_eq!(maze.shortest_path(middle, middle), Some(vec![middle]));
+        // Up the middle and round into the bottom right loop, which is the only way that short.
+        let to_ghosts = maze
+            .shortest_path(middle, index_of(&maze, (5.0, 20.0)))
+            .unwrap();
+        assert_eq!(maze.route_length(&to_ghosts), Some(25.0));
+        assert_eq!(
+            coordinates_of(to_ghosts.clone()),
+            vec![
+                (0.0, 0.0),
+                (0.0, 5.0),
+                (0.0, 10.0),
+                (5.0, 10.0),
+                (5.0, 20.0)
+            ]
+        );
+        // The same way back.
+        let mut back = maze
+            .shortest_path(index_of(&maze, (5.0, 20.0)), middle)
+            .unwrap();
+        back.reverse();
+        assert_eq!(back, to_ghosts);
+        // There are lots of ways between opposite corners which are just as short (as long as they never go back on themselves), but we always get the same one.
+        let across = maze
+            .shortest_path(
+                index_of(&maze, (-15.0, -20.0)),
+                index_of(&maze, (15.0, 20.0)),
+            )
+            .unwrap();
+        assert_eq!(maze.route_length(&across), Some(70.0));
+        // Which means it only ever goes right or down.
+        assert!(coordinates_of(across.clone())
+            .windows(2)
+            .all(|pair| pair[1].0 >= pair[0].0 && pair[1].1 >= pair[0].1));
+        assert_eq!(
+            maze.shortest_path(
+                index_of(&maze, (-15.0, -20.0)),
+                index_of(&maze, (15.0, 20.0))
+            ),
+            Some(across)
+        );
+    }
+
+    #[test]
+    fn shortest_path_with_no_way_there() {
+        let maze = Maze::new(&[((0.0, 0.0), (5.0, 0.0)), ((0.0, 10.0), (5.0, 10.0))]);
+        assert_eq!(
+            maze.shortest_path(index_of(&maze, (0.0, 0.0)), index_of(&maze, (5.0, 0.0))),
+            Some(vec![
+                index_of(&maze, (0.0, 0.0)),
+                index_of(&maze, (5.0, 0.0))
+            ])
+        );
+        assert_eq!(
+            maze.shortest_path(index_of(&maze, (0.0, 0.0)), index_of(&maze, (5.0, 10.0))),
+            None
+        );
+        assert_eq!(maze.shortest_path(0, maze.intersections().len()), None);
+    }
+
+    #[test]
+    fn neighbors_of_the_middle() {
+        let maze = crate::sample_maze();
+        let middle = index_of(&maze, (0.0, 0.0));
+        let neighbors: Vec<_> = maze
+            .neighbors(middle)
+            .map(|(direction, path)| {
+                (
+                    direction,
+                    maze.intersections()[path.end_index].coordinates,
+                    path.length,
+                )
+            })
+            .collect();
+        assert_eq!(
+            neighbors,
+            // The loops have paths along 5 and -5, so `Maze::new` splits the middle lines up where those would cross them.
+            vec![
+                (Direction::Forward, (0.0, -5.0), 5.0),
+                (Direction::Backward, (0.0, 5.0), 5.0),
+                (Direction::Left, (-5.0, 0.0), 5.0),
+                (Direction::Right, (5.0, 0.0), 5.0),
+            ]
+        );
+        // Diagonal paths come back as the diagonal directions, after the straight ones.
+        let maze = Maze::new(&[((0.0, 0.0), (5.0, 5.0)), ((0.0, 0.0), (5.0, 0.0))]);
+        let corner = index_of(&maze, (0.0, 0.0));
+        assert_eq!(
+            maze.neighbors(corner)
+                .map(|(direction, _)| direction)
+                .collect::<Vec<_>>(),
+            vec![Direction::Right, Direction::BackwardRight]
+        );
+    }
+
+    #[test]
+    fn spatial_index_finds_everything() {
+        let maze = crate::sample_maze();
+        for x in -25..=25 {
+            for y in -25..=25 {
+                let position = (x as f32 * 0.9, y as f32 * 0.9);
+                let expected: Vec<usize> = (0..maze.intersections().len())
+                    .filter(|index| {
+                        let coordinates = maze.intersections()[*index].coordinates;
+                        (coordinates.0 - position.0).powi(2) + (coordinates.1 - position.1).powi(2)
+                            <= 9.0
+                    })
+                    .collect();
+                assert_eq!(maze.nearest_intersections(position, 3.0), expected);
+            }
+        }
+
+        // Paths much longer than the squares of the index still have to be found in the middle.
+        let mut maze = Maze::new(&[((0.0, 0.0), (100.0, 0.0)), ((0.0, 0.0), (0.0, 100.0))]);
+        let corner = index_of(&maze, (0.0, 0.0));
+        let right = index_of(&maze, (100.0, 0.0));
+        let top = index_of(&maze, (0.0, 100.0));
+        assert_eq!(maze.edge_at((50.0, 0.2)), Some((corner, right)));
+        assert_eq!(maze.edge_at((0.3, 99.0)), Some((corner, top)));
+        assert_eq!(maze.edge_at((50.0, 50.0)), None);
+        // So do tunnels, which go off the side of the maze.
+        maze.add_wrap((100.0, 0.0), (0.0, 100.0), 10.0);
+        assert_eq!(maze.edge_at((105.0, 0.0)), Some((right, top)));
+        assert_eq!(maze.edge_at((-9.0, 100.0)), Some((top, right)));
     }
 }
@@ -0,0 +1,318 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::{
+        Assets, Camera, Camera3d, Camera3dBundle, Color, Commands, Component, DespawnRecursiveExt,
+        Entity, OrthographicProjection, Quat, Query, Res, ResMut, StandardMaterial, Transform,
+        UVec2, UiCameraConfig, Vec3, With, Without,
+    },
+    render::{
+        camera::{ScalingMode, Viewport},
+        view::RenderLayers,
+    },
+};
+
+use crate::{
+    ghost::{Ghost, GhostHouse},
+    maze::Maze,
+    object::{Mesh, Shape},
+    Player,
+};
+
+/// The minimap is drawn on its own layer, so that only the minimap camera sees it (and it doesn't see anything else).
+const MINIMAP_LAYER: u8 = 1;
+/// How big the minimap is on the screen (in pixels), and how far it is from the top left corner.
+pub const MINIMAP_SIZE: u32 = 200;
+pub const MINIMAP_MARGIN: u32 = 10;
+/// The markers sit just above the paths so that they are always drawn on top of them.
+const MARKER_HEIGHT: f32 = 0.01;
+const MINIMAP_PATH_COLOR: Color = Color::GRAY;
+const MINIMAP_PLAYER_COLOR: Color = Color::YELLOW;
+const MINIMAP_GHOST_HOUSE_COLOR: Color = Color::PINK;
+
+#[derive(Component)]
+pub struct MinimapCamera;
+
+/// One of the paths drawn on the minimap.
+#[derive(Component)]
+pub struct MinimapPath;
+
+/// A dot on the minimap following the player or one of the ghosts around.
+#[derive(Component)]
+pub struct MinimapMarker(pub Entity);
+
+/// How many units of the world fit across the minimap.
+fn minimap_scale(bounds: (Vec3, Vec3)) -> f32 {
+    let size = bounds.1 - bounds.0;
+    // A maze without any paths has no size, but we still don't want to divide by zero.
+    size.x.max(size.z).max(f32::EPSILON)
+}
+
+/// Works out where something in the world goes on the minimap.
+/// The whole maze fits into a square of size 1 centered on the origin, with the top of the maze at the top.
+fn to_minimap(position: Vec3, bounds: (Vec3, Vec3)) -> Vec3 {
+    let center = (bounds.0 + bounds.1) / 2.0;
+    let scale = minimap_scale(bounds);
+    Vec3::new(
+        (position.x - center.x) / scale,
+        0.0,
+        (position.z - center.z) / scale,
+    )
+}
+
+/// The minimap is a second camera looking straight down on a schematic of the maze, drawn in the corner of the screen.
+pub fn setup_minimap(mut commands: Commands) {
+    commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                // It has to be drawn after the main camera, or the main camera would draw over it.
+                order: 1,
+                viewport: Some(Viewport {
+                    physical_position: UVec2::splat(MINIMAP_MARGIN),
+                    physical_size: UVec2::splat(MINIMAP_SIZE),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            camera_3d: Camera3d {
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..Default::default()
+            },
+            projection: OrthographicProjection {
+                // A little bit more than the maze, so that the edges aren't right up against the sides.
+                scaling_mode: ScalingMode::Fixed {
+                    width: 1.1,
+                    height: 1.1,
+                },
+                ..Default::default()
+            }
+            .into(),
+            // Forward (-z) is the top of the minimap, just like it is the top of the maze.
+            transform: Transform::from_xyz(0.0, 1.0, 0.0).looking_to(-Vec3::Y, -Vec3::Z),
+            ..Default::default()
+        },
+        RenderLayers::layer(MINIMAP_LAYER),
+        // Otherwise the HUD would be drawn a second time, squashed into the minimap.
+        UiCameraConfig { show_ui: false },
+        MinimapCamera,
+    ));
+}
+
+/// The paths of the maze as they are drawn on the minimap.
+fn minimap_paths(maze: &Maze) -> Vec<Mesh> {
+    let bounds = maze.bounds();
+    let path_width = maze.half_path_width() * 2.0 / minimap_scale(bounds);
+    maze.paths()
+        .into_iter()
+        .map(|(start, end)| {
+            let start = to_minimap(Vec3::new(start.0, 0.0, start.1), bounds);
+            let end = to_minimap(Vec3::new(end.0, 0.0, end.1), bounds);
+            let along = end - start;
+            Mesh {
+                shape: Shape::Box {
+                    // Going past the ends by half the width of the path fills in the corners.
+                    width: along.length() + path_width,
+                    height: 0.0,
+                    depth: path_width,
+                },
+                color: MINIMAP_PATH_COLOR,
+                position: (start + end) / 2.0,
+                rotation: Quat::from_rotation_y(-along.z.atan2(along.x)),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// A square over each of the ghost house's intersections, so that it stands out from the rest of the paths.
+fn minimap_ghost_house(maze: &Maze, house: &GhostHouse) -> Vec<Mesh> {
+    let bounds = maze.bounds();
+    let size = maze.half_path_width() * 2.0 / minimap_scale(bounds);
+    // The house is worked out when the game starts, so the editor could have taken some of its intersections away since then.
+    house
+        .intersections
+        .iter()
+        .filter_map(|index| maze.intersections().get(*index))
+        .map(|intersection| {
+            let (x, z) = intersection.coordinates;
+            Mesh {
+                shape: Shape::Box {
+                    width: size,
+                    height: 0.0,
+                    depth: size,
+                },
+                color: MINIMAP_GHOST_HOUSE_COLOR,
+                // Halfway between the paths and the markers, so that it covers the paths but the ghosts can still be seen on top.
+                position: to_minimap(Vec3::new(x, 0.0, z), bounds) + Vec3::Y * MARKER_HEIGHT / 2.0,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Draws the maze on the minimap again whenever it changes.
+pub fn draw_minimap_maze(
+    maze: Res<Maze>,
+    house: Option<Res<GhostHouse>>,
+    old_paths: Query<Entity, With<MinimapPath>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<bevy::prelude::Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for entity in old_paths.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let house = house
+        .map(|house| minimap_ghost_house(&maze, &house))
+        .unwrap_or_default();
+    for path in minimap_paths(&maze).into_iter().chain(house) {
+        path.to_entity(&mut commands, &mut meshes, &mut materials)
+            .insert((MinimapPath, RenderLayers::layer(MINIMAP_LAYER)));
+    }
+}
+
+/// Moves the markers to wherever the player and the ghosts are.
+/// The player and the ghosts come and go (like when the game restarts), so markers are added and removed to match.
+#[allow(clippy::type_complexity)]
+pub fn update_minimap_markers(
+    maze: Res<Maze>,
+    players: Query<(Entity, &Transform), With<Player>>,
+    ghosts: Query<(Entity, &Transform, &Ghost), Without<Player>>,
+    mut markers: Query<(Entity, &MinimapMarker, &mut Transform), (Without<Player>, Without<Ghost>)>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<bevy::prelude::Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let bounds = maze.bounds();
+    let targets = players
+        .iter()
+        .map(|(entity, transform)| (entity, transform, MINIMAP_PLAYER_COLOR))
+        .chain(ghosts.iter().map(|(entity, transform, ghost)| {
+            (entity, transform, ghost.personality.default_color())
+        }))
+        .collect::<Vec<_>>();
+    let marker_position =
+        |transform: &Transform| to_minimap(transform.translation, bounds) + Vec3::Y * MARKER_HEIGHT;
+    let mut has_marker = Vec::new();
+    for (marker_entity, marker, mut marker_transform) in markers.iter_mut() {
+        match targets.iter().find(|(entity, ..)| *entity == marker.0) {
+            Some((entity, transform, _)) => {
+                marker_transform.translation = marker_position(transform);
+                has_marker.push(*entity);
+            }
+            None => commands.entity(marker_entity).despawn_recursive(),
+        }
+    }
+    // The markers are a bit bigger than the paths, so that they stand out.
+    let marker_size = maze.half_path_width() * 3.0 / minimap_scale(bounds);
+    for (entity, transform, color) in targets {
+        if has_marker.contains(&entity) {
+            continue;
+        }
+        Mesh {
+            shape: Shape::Box {
+                width: marker_size,
+                height: 0.0,
+                depth: marker_size,
+            },
+            color,
+            position: marker_position(transform),
+            ..Default::default()
+        }
+        .to_entity(&mut commands, &mut meshes, &mut materials)
+        .insert((MinimapMarker(entity), RenderLayers::layer(MINIMAP_LAYER)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{
+        asset::{AddAsset, AssetPlugin},
+        prelude::{App, MinimalPlugins, Update},
+    };
+
+    use super::*;
+    use crate::ghost::GhostPersonality;
+
+    #[test]
+    fn positions_on_the_minimap() {
+        // The sample maze is 31 across and 41 down (walls included), so the height decides the scale.
+        let bounds = crate::sample_maze().bounds();
+        assert_eq!(to_minimap(Vec3::ZERO, bounds), Vec3::ZERO);
+        assert_eq!(
+            to_minimap(Vec3::new(0.0, 1.0, -20.5), bounds),
+            Vec3::new(0.0, 0.0, -0.5)
+        );
+        assert!(to_minimap(Vec3::new(4.1, 0.0, 8.2), bounds)
+            .abs_diff_eq(Vec3::new(0.1, 0.0, 0.2), 0.0001));
+        // Mazes without any paths don't break it.
+        assert!(to_minimap(Vec3::ONE, (Vec3::ZERO, Vec3::ZERO)).is_finite());
+    }
+
+    #[test]
+    fn ghost_house_on_the_minimap() {
+        let maze = crate::sample_maze();
+        let home = crate::index_of(&maze, (0.0, 0.0));
+        let squares = minimap_ghost_house(
+            &maze,
+            &GhostHouse::new(vec![home, maze.intersections().len()], Vec::new()),
+        );
+        // The second one isn't in the maze any more, so only the first is drawn.
+        assert_eq!(squares.len(), 1);
+        assert_eq!(squares[0].color, MINIMAP_GHOST_HOUSE_COLOR);
+        assert_eq!(
+            squares[0].position,
+            Vec3::new(0.0, MARKER_HEIGHT / 2.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn markers_follow_the_player_and_ghosts() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<bevy::prelude::Mesh>()
+            .add_asset::<StandardMaterial>()
+            .insert_resource(crate::sample_maze())
+            .add_systems(Update, update_minimap_markers);
+        let player = app
+            .world
+            .spawn((Player::default(), Transform::default()))
+            .id();
+        let ghost = app
+            .world
+            .spawn((
+                Ghost::new(GhostPersonality::Blinky),
+                Transform::from_xyz(4.1, 1.0, 8.2),
+            ))
+            .id();
+        let markers = |app: &mut App| {
+            let mut markers = app
+                .world
+                .query::<(&MinimapMarker, &Transform)>()
+                .iter(&app.world)
+                .map(|(marker, transform)| (marker.0, transform.translation))
+                .collect::<Vec<_>>();
+            markers.sort_by_key(|(entity, _)| *entity);
+            markers
+        };
+
+        app.update();
+        let first_markers = markers(&mut app);
+        assert_eq!(first_markers.len(), 2);
+        assert_eq!(
+            first_markers[0],
+            (player, Vec3::new(0.0, MARKER_HEIGHT, 0.0))
+        );
+        assert_eq!(first_markers[1].0, ghost);
+        assert!(first_markers[1]
+            .1
+            .abs_diff_eq(Vec3::new(0.1, MARKER_HEIGHT, 0.2), 0.0001));
+
+        app.world.get_mut::<Transform>(player).unwrap().translation = Vec3::new(0.0, 1.0, -20.5);
+        app.world.despawn(ghost);
+        app.update();
+        assert_eq!(
+            markers(&mut app),
+            vec![(player, Vec3::new(0.0, MARKER_HEIGHT, -0.5))]
+        );
+    }
+}
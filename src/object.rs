@@ -1,61 +1,280 @@
+use std::f32::consts::TAU;
+
 use bevy::{
-    ecs::{component::Component, system::EntityCommands},
+    ecs::{component::Component, entity::Entity, system::EntityCommands},
     prelude::{
-        shape, Assets, BuildChildren, Color, Commands, ComputedVisibility, GlobalTransform,
-        PbrBundle, Quat, ResMut, Transform, Vec3, Visibility,
+        shape, Assets, BuildChildren, Color, Commands, ComputedVisibility, GlobalTransform, Handle,
+        Image, PbrBundle, Quat, ResMut, StandardMaterial, Transform, Vec3, Visibility,
+    },
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::PrimitiveTopology,
     },
 };
-use bevy_rapier3d::prelude::{Collider, RigidBody, Velocity};
+use bevy_rapier3d::prelude::{Collider, RigidBody, Sensor, Velocity};
 
 #[derive(Clone, Default, Debug, Component)]
 pub struct GameObject {
     meshes: Vec<Mesh>,
+    batched: bool,
+    sensor: bool,
+    collider_strategy: ColliderStrategy,
+}
+
+/// The different ways the collider can be made out of the meshes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColliderStrategy {
+    /// Each mesh gets its own simple shape (like a box or a ball), and they are all put together.
+    /// These are exact and cheap to test against, but there is one for every mesh.
+    #[default]
+    Compound,
+    /// One big collider made from the triangles of all of the meshes.
+    /// Anything inside of it isn't counted as touching it, so this is only any good for things like walls.
+    TriMesh,
 }
 
 impl GameObject {
-    pub fn add_mesh(&mut self, mesh: Mesh) -> &mut Self {
-        self.meshes.push(mesh);
+    /// Makes an object out of just this mesh, which is most of them.
+    pub fn with_mesh(mesh: Mesh) -> Self {
+        Self::with_meshes(vec![mesh])
+    }
+
+    pub fn with_meshes(meshes: Vec<Mesh>) -> Self {
+        Self {
+            meshes,
+            ..Default::default()
+        }
+    }
+
+    /// Merges all the meshes which look the same into one big mesh when this is spawned, so that they get drawn all at once.
+    /// This is much faster for things made of lots of pieces (like the maze), but it does mean the pieces can't be changed separately afterwards.
+    /// The collider is still made of the separate shapes either way.
+    pub fn set_batched(&mut self, batched: bool) -> &mut Self {
+        self.batched = batched;
+        self
+    }
+
+    /// Sensors detect when things are touching them without getting in their way, so things go straight through them.
+    /// They aren't drawn either; the meshes just give them their shape.
+    pub fn set_sensor(&mut self, sensor: bool) -> &mut Self {
+        self.sensor = sensor;
         self
     }
 
+    pub fn set_collider_strategy(&mut self, collider_strategy: ColliderStrategy) -> &mut Self {
+        self.collider_strategy = collider_strategy;
+        self
+    }
+
+    /// All of the triangles of all of the meshes, in the object's own space.
+    fn triangles(&self) -> (Vec<Vec3>, Vec<[u32; 3]>) {
+        let combined = combine_meshes(&self.meshes.iter().collect::<Vec<_>>());
+        let vertices = match combined.attribute(bevy::prelude::Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => {
+                positions.iter().copied().map(Vec3::from).collect()
+            }
+            _ => Vec::new(),
+        };
+        let indices = combined
+            .indices()
+            .map(|indices| indices.iter().map(|index| index as u32).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        (vertices, indices)
+    }
+
+    fn collider(&self) -> Collider {
+        match self.collider_strategy {
+            ColliderStrategy::Compound => Collider::compound(
+                self.meshes
+                    .iter()
+                    .map(|mesh| (mesh.position, mesh.rotation, mesh.get_collider()))
+                    .collect(),
+            ),
+            ColliderStrategy::TriMesh => {
+                let (vertices, indices) = self.triangles();
+                Collider::trimesh(vertices, indices)
+            }
+        }
+    }
+
+    /// Groups the meshes by their material, keeping the first one of each group in the same order as they were added.
+    fn batches(&self) -> Vec<Vec<&Mesh>> {
+        let mut batches: Vec<Vec<&Mesh>> = Vec::new();
+        for mesh in &self.meshes {
+            match batches
+                .iter_mut()
+                .find(|batch| batch[0].has_same_material(mesh))
+            {
+                Some(batch) => batch.push(mesh),
+                None => batches.push(vec![mesh]),
+            }
+        }
+        batches
+    }
+
     pub fn spawn<'w, 's, 'a>(
         self,
         initial_transform: Transform,
         rigid_body: RigidBody,
         commands: &'a mut Commands<'w, 's>,
         meshes: &mut ResMut<Assets<bevy::prelude::Mesh>>,
-        materials: &mut ResMut<Assets<bevy::prelude::StandardMaterial>>,
+        materials: &mut ResMut<Assets<StandardMaterial>>,
     ) -> EntityCommands<'w, 's, 'a> {
-        let colliders = self
-            .meshes
-            .iter()
-            .map(|mesh| (mesh.position, mesh.rotation, mesh.get_collider()))
-            .collect::<Vec<_>>();
+        let collider = self.collider();
         let mut children = Vec::with_capacity(self.meshes.len());
-        for mesh in self.meshes.iter() {
-            children.push(mesh.to_entity(commands, meshes, materials).id());
+        if self.sensor {
+            // Nothing to draw.
+        } else if self.batched {
+            for batch in self.batches() {
+                children.push(
+                    commands
+                        .spawn((
+                            MeshComponent,
+                            PbrBundle {
+                                mesh: meshes.add(combine_meshes(&batch)),
+                                material: materials.add(batch[0].material()),
+                                ..Default::default()
+                            },
+                        ))
+                        .id(),
+                );
+            }
+        } else {
+            for mesh in self.meshes.iter() {
+                children.push(mesh.to_entity(commands, meshes, materials).id());
+            }
         }
         let mut entity_commands = commands.spawn(rigid_body);
         children.into_iter().for_each(|child| {
             entity_commands.add_child(child);
         });
         entity_commands
-            .insert(Collider::compound(colliders))
+            .insert(collider)
             .insert(initial_transform)
             .insert(GlobalTransform::default())
             .insert(Visibility::default())
             .insert(ComputedVisibility::default())
             .insert(Velocity::default());
+        if self.sensor {
+            entity_commands.insert(Sensor);
+        }
         entity_commands.insert(self);
         entity_commands
     }
+
+    /// The same as `spawn`, but also gives back the entity, for when we need to hold on to it and still add more components.
+    pub fn spawn_with_id<'w, 's, 'a>(
+        self,
+        initial_transform: Transform,
+        rigid_body: RigidBody,
+        commands: &'a mut Commands<'w, 's>,
+        meshes: &mut ResMut<Assets<bevy::prelude::Mesh>>,
+        materials: &mut ResMut<Assets<StandardMaterial>>,
+    ) -> (Entity, EntityCommands<'w, 's, 'a>) {
+        let entity_commands =
+            self.spawn(initial_transform, rigid_body, commands, meshes, materials);
+        (entity_commands.id(), entity_commands)
+    }
+
+    /// Spawns something which never moves (like the ground), when there is nothing else to add to it.
+    pub fn spawn_static(
+        self,
+        initial_transform: Transform,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<bevy::prelude::Mesh>>,
+        materials: &mut ResMut<Assets<StandardMaterial>>,
+    ) -> Entity {
+        self.spawn(
+            initial_transform,
+            RigidBody::Fixed,
+            commands,
+            meshes,
+            materials,
+        )
+        .id()
+    }
 }
 
-#[derive(Clone, Debug)]
+/// Describes a box by its center and half extents.
+/// Positions are always the centers of shapes, and colliders want half sizes, so it is very easy to get the maths subtly wrong if it is repeated everywhere.
+/// Both the box meshes and their colliders are calculated from this so that they can never disagree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxExtents {
+    pub center: Vec3,
+    pub half_extents: Vec3,
+}
+
+impl BoxExtents {
+    pub fn new(center: Vec3, size: Vec3) -> Self {
+        Self {
+            center,
+            half_extents: size / 2.0,
+        }
+    }
+
+    pub fn from_corners(a: Vec3, b: Vec3) -> Self {
+        Self::new((a + b) / 2.0, (a - b).abs())
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.half_extents * 2.0
+    }
+
+    pub fn shape(&self) -> Shape {
+        let size = self.size();
+        Shape::Box {
+            width: size.x,
+            height: size.y,
+            depth: size.z,
+        }
+    }
+
+    pub fn collider(&self) -> Collider {
+        Collider::cuboid(
+            self.half_extents.x,
+            self.half_extents.y,
+            self.half_extents.z,
+        )
+    }
+
+    pub fn to_mesh(self, color: Color) -> Mesh {
+        Mesh {
+            shape: self.shape(),
+            color,
+            position: self.center,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Shape {
-    Box { width: f32, height: f32, depth: f32 },
-    Sphere { radius: f32 },
-    Cylinder { radius: f32, height: f32 },
+    Box {
+        width: f32,
+        height: f32,
+        depth: f32,
+    },
+    Cylinder {
+        radius: f32,
+        height: f32,
+    },
+    /// A cone with its point at the top.
+    Cone {
+        radius: f32,
+        height: f32,
+    },
+    /// A cylinder with rounded ends, standing up.
+    /// The height includes the ends, so it should be at least twice the radius.
+    Capsule {
+        radius: f32,
+        height: f32,
+    },
+    Sphere {
+        radius: f32,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -64,55 +283,388 @@ pub struct Mesh {
     pub color: Color,
     pub position: Vec3,
     pub rotation: Quat,
+    /// An image to draw on the shape, which gets tinted by `color` (so use white to see it as it is).
+    pub texture: Option<Handle<Image>>,
+    /// These are all passed straight on to the `StandardMaterial`, and leaving them out gives Bevy's defaults.
+    pub metallic: Option<f32>,
+    pub perceptual_roughness: Option<f32>,
+    /// The colour the mesh glows, even in the dark.
+    pub emissive: Option<Color>,
+}
+
+/// A white box a unit across, sitting on the origin with nothing else special about it.
+/// Most meshes only change the shape and the colour, so the rest can be left to this.
+impl Default for Mesh {
+    fn default() -> Self {
+        Self {
+            shape: Shape::Box {
+                width: 1.0,
+                height: 1.0,
+                depth: 1.0,
+            },
+            color: Color::WHITE,
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            texture: None,
+            metallic: None,
+            perceptual_roughness: None,
+            emissive: None,
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct MeshComponent;
 
 impl Mesh {
-    fn to_entity<'w, 's, 'a>(
+    pub fn to_entity<'w, 's, 'a>(
         &self,
         commands: &'a mut Commands<'w, 's>,
         meshes: &mut ResMut<Assets<bevy::prelude::Mesh>>,
-        materials: &mut ResMut<Assets<bevy::prelude::StandardMaterial>>,
+        materials: &mut ResMut<Assets<StandardMaterial>>,
     ) -> EntityCommands<'w, 's, 'a> {
         commands.spawn((
             MeshComponent,
             PbrBundle {
-                mesh: meshes.add(match self.shape {
-                    Shape::Box {
-                        width,
-                        height,
-                        depth,
-                    } => shape::Box::new(width, height, depth).into(),
-                    Shape::Cylinder { radius, height } => shape::Cylinder {
-                        height,
-                        radius,
-                        ..Default::default()
-                    }
-                    .into(),
-                    Shape::Sphere { radius } => shape::UVSphere {
-                        radius,
-                        ..Default::default()
-                    }
-                    .into(),
-                }),
-                material: materials.add(self.color.into()),
+                mesh: meshes.add(self.bevy_mesh()),
+                material: materials.add(self.material()),
                 transform: Transform::from_translation(self.position).with_rotation(self.rotation),
                 ..Default::default()
             },
         ))
     }
 
-    fn get_collider(&self) -> Collider {
+    /// Makes the mesh for Bevy to draw, centered on the origin (without the position or rotation).
+    fn bevy_mesh(&self) -> bevy::prelude::Mesh {
         match self.shape {
             Shape::Box {
                 width,
                 height,
                 depth,
-            } => Collider::cuboid(width / 2.0, height / 2.0, depth / 2.0),
+            } => shape::Box::new(width, height, depth).into(),
+            Shape::Cylinder { radius, height } => shape::Cylinder {
+                height,
+                radius,
+                ..Default::default()
+            }
+            .into(),
+            // Bevy doesn't have a cone shape, so we have to make our own.
+            Shape::Cone { radius, height } => cone_mesh(radius, height),
+            Shape::Capsule { radius, height } => shape::Capsule {
+                radius,
+                // Bevy only wants the length of the straight bit in the middle.
+                depth: capsule_depth(radius, height),
+                ..Default::default()
+            }
+            .into(),
+            Shape::Sphere { radius } => shape::UVSphere {
+                radius,
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+
+    fn has_same_material(&self, other: &Mesh) -> bool {
+        self.color == other.color
+            && self.texture == other.texture
+            && self.metallic == other.metallic
+            && self.perceptual_roughness == other.perceptual_roughness
+            && self.emissive == other.emissive
+    }
+
+    pub fn material(&self) -> StandardMaterial {
+        let mut material: StandardMaterial = self.color.into();
+        material.base_color_texture = self.texture.clone();
+        if let Some(metallic) = self.metallic {
+            material.metallic = metallic;
+        }
+        if let Some(perceptual_roughness) = self.perceptual_roughness {
+            material.perceptual_roughness = perceptual_roughness;
+        }
+        if let Some(emissive) = self.emissive {
+            material.emissive = emissive;
+        }
+        material
+    }
+
+    pub fn get_collider(&self) -> Collider {
+        match self.shape {
+            Shape::Box {
+                width,
+                height,
+                depth,
+            } => BoxExtents::new(Vec3::ZERO, Vec3::new(width, height, depth)).collider(),
             Shape::Cylinder { radius, height } => Collider::cylinder(height / 2.0, radius),
+            Shape::Cone { radius, height } => Collider::cone(height / 2.0, radius),
+            Shape::Capsule { radius, height } => {
+                Collider::capsule_y(capsule_depth(radius, height) / 2.0, radius)
+            }
             Shape::Sphere { radius } => Collider::ball(radius),
         }
     }
 }
+
+/// The length of the straight part of a capsule, between the two rounded ends.
+fn capsule_depth(radius: f32, height: f32) -> f32 {
+    (height - radius * 2.0).max(0.0)
+}
+
+/// Puts a lot of meshes together into one, with their positions and rotations built in.
+fn combine_meshes(meshes: &[&Mesh]) -> bevy::prelude::Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    for mesh in meshes {
+        let bevy_mesh = mesh.bevy_mesh();
+        let offset = positions.len() as u32;
+        let transform = Transform::from_translation(mesh.position).with_rotation(mesh.rotation);
+        // All of our shapes have these attributes, and Bevy always makes them with these types.
+        if let Some(VertexAttributeValues::Float32x3(mesh_positions)) =
+            bevy_mesh.attribute(bevy::prelude::Mesh::ATTRIBUTE_POSITION)
+        {
+            positions.extend(
+                mesh_positions
+                    .iter()
+                    .map(|position| transform.transform_point(Vec3::from(*position)).to_array()),
+            );
+        }
+        if let Some(VertexAttributeValues::Float32x3(mesh_normals)) =
+            bevy_mesh.attribute(bevy::prelude::Mesh::ATTRIBUTE_NORMAL)
+        {
+            normals.extend(
+                mesh_normals
+                    .iter()
+                    .map(|normal| (mesh.rotation * Vec3::from(*normal)).to_array()),
+            );
+        }
+        if let Some(VertexAttributeValues::Float32x2(mesh_uvs)) =
+            bevy_mesh.attribute(bevy::prelude::Mesh::ATTRIBUTE_UV_0)
+        {
+            uvs.extend(mesh_uvs.iter().copied());
+        }
+        match bevy_mesh.indices() {
+            Some(mesh_indices) => {
+                indices.extend(mesh_indices.iter().map(|index| index as u32 + offset))
+            }
+            None => indices.extend(offset..positions.len() as u32),
+        }
+    }
+    let mut combined = bevy::prelude::Mesh::new(PrimitiveTopology::TriangleList);
+    combined.insert_attribute(bevy::prelude::Mesh::ATTRIBUTE_POSITION, positions);
+    combined.insert_attribute(bevy::prelude::Mesh::ATTRIBUTE_NORMAL, normals);
+    combined.insert_attribute(bevy::prelude::Mesh::ATTRIBUTE_UV_0, uvs);
+    combined.set_indices(Some(Indices::U32(indices)));
+    combined
+}
+
+/// How many flat sides the round part of a cone is made out of.
+const CONE_SEGMENTS: u32 = 32;
+
+/// Makes a cone centered on the origin, with the point at the top (which is the same way round as Rapier's cones).
+fn cone_mesh(radius: f32, height: f32) -> bevy::prelude::Mesh {
+    let half_height = height / 2.0;
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    // The sides get a separate point at the top for each segment, so that each one can have its own normal.
+    for i in 0..=CONE_SEGMENTS {
+        let fraction = i as f32 / CONE_SEGMENTS as f32;
+        let (sin, cos) = (fraction * TAU).sin_cos();
+        // The sides lean in, so the normals point up a bit as well as out.
+        let normal = Vec3::new(cos * height, radius, sin * height).normalize();
+        positions.push([cos * radius, -half_height, sin * radius]);
+        positions.push([0.0, half_height, 0.0]);
+        normals.extend([normal.to_array(); 2]);
+        uvs.push([fraction, 1.0]);
+        uvs.push([fraction, 0.0]);
+    }
+    for i in 0..CONE_SEGMENTS {
+        let base = i * 2;
+        indices.extend([base, base + 1, base + 2]);
+    }
+    // Then the flat circle on the bottom, as a fan around the middle.
+    let center = positions.len() as u32;
+    positions.push([0.0, -half_height, 0.0]);
+    normals.push([0.0, -1.0, 0.0]);
+    uvs.push([0.5, 0.5]);
+    for i in 0..=CONE_SEGMENTS {
+        let (sin, cos) = (i as f32 / CONE_SEGMENTS as f32 * TAU).sin_cos();
+        positions.push([cos * radius, -half_height, sin * radius]);
+        normals.push([0.0, -1.0, 0.0]);
+        uvs.push([(cos + 1.0) / 2.0, (sin + 1.0) / 2.0]);
+    }
+    for i in 0..CONE_SEGMENTS {
+        indices.extend([center, center + 1 + i, center + 2 + i]);
+    }
+    let mut mesh = bevy::prelude::Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(bevy::prelude::Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(bevy::prelude::Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(bevy::prelude::Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{
+        asset::{AddAsset, AssetPlugin},
+        prelude::{App, Children, MinimalPlugins, Update},
+    };
+
+    use super::*;
+
+    #[test]
+    fn sensors() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<bevy::prelude::Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_systems(
+                Update,
+                |mut commands: Commands,
+                 mut meshes: ResMut<Assets<bevy::prelude::Mesh>>,
+                 mut materials: ResMut<Assets<StandardMaterial>>| {
+                    let mesh = BoxExtents::new(Vec3::ZERO, Vec3::ONE).to_mesh(Color::RED);
+                    GameObject::with_mesh(mesh.clone()).spawn_static(
+                        Transform::default(),
+                        &mut commands,
+                        &mut meshes,
+                        &mut materials,
+                    );
+                    GameObject::with_mesh(mesh)
+                        .set_sensor(true)
+                        .clone()
+                        .spawn_static(
+                            Transform::default(),
+                            &mut commands,
+                            &mut meshes,
+                            &mut materials,
+                        );
+                },
+            );
+        app.update();
+        let mut objects = app
+            .world
+            .query::<(&GameObject, Option<&Sensor>, Option<&Children>)>();
+        let objects = objects.iter(&app.world).collect::<Vec<_>>();
+        assert_eq!(objects.len(), 2);
+        for (object, sensor, children) in objects {
+            assert_eq!(object.sensor, sensor.is_some());
+            // Only the solid one has a mesh to draw.
+            assert_eq!(object.sensor, children.is_none());
+        }
+    }
+
+    #[test]
+    fn collider_strategies() {
+        let mut object =
+            GameObject::with_mesh(BoxExtents::new(Vec3::ZERO, Vec3::ONE).to_mesh(Color::RED));
+        assert!(object.collider().as_compound().is_some());
+        object.set_collider_strategy(ColliderStrategy::TriMesh);
+        // Each of the 6 sides of a box is 2 triangles.
+        assert_eq!(
+            object.collider().as_trimesh().unwrap().raw.num_triangles(),
+            12
+        );
+    }
+
+    #[test]
+    fn built_in_one_go() {
+        let mesh = BoxExtents::new(Vec3::ZERO, Vec3::ONE).to_mesh(Color::RED);
+        let built = GameObject::with_meshes(vec![mesh.clone(), mesh.clone()]);
+        assert_eq!(built.meshes.len(), 2);
+        assert!(!built.batched);
+        assert_eq!(GameObject::with_mesh(mesh).meshes.len(), 1);
+    }
+
+    #[test]
+    fn textures_are_tinted() {
+        let mut mesh = BoxExtents::new(Vec3::ZERO, Vec3::ONE).to_mesh(Color::RED);
+        let material = mesh.material();
+        assert_eq!(material.base_color, Color::RED);
+        assert_eq!(material.base_color_texture, None);
+        let texture = Handle::<Image>::weak(bevy::asset::HandleId::random::<Image>());
+        mesh.texture = Some(texture.clone());
+        let material = mesh.material();
+        assert_eq!(material.base_color, Color::RED);
+        assert_eq!(material.base_color_texture, Some(texture));
+    }
+
+    #[test]
+    fn batching_combines_meshes() {
+        let boxes = [Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0)]
+            .map(|center| BoxExtents::new(center, Vec3::ONE).to_mesh(Color::GRAY));
+        let combined = combine_meshes(&[&boxes[0], &boxes[1]]);
+        // Each box has 24 vertices (four for each face, so that the corners can have different normals) and 36 indices.
+        assert_eq!(combined.count_vertices(), 48);
+        assert_eq!(combined.indices().unwrap().len(), 72);
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            combined.attribute(bevy::prelude::Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("The combined mesh should have positions");
+        };
+        assert!(positions[..24]
+            .iter()
+            .all(|position| position[0].abs() == 0.5));
+        assert!(positions[24..]
+            .iter()
+            .all(|position| position[0] == 1.5 || position[0] == 2.5));
+
+        // The whole maze is one colour, so all 208 walls (little bits in the corners of the intersections included) get drawn at once.
+        let maze = crate::sample_maze().create_game_object(&crate::theme::MazeTheme::default());
+        assert_eq!(maze.meshes.len(), 208);
+        assert_eq!(maze.batches().len(), 1);
+    }
+
+    #[test]
+    fn material_properties() {
+        let mut mesh = BoxExtents::new(Vec3::ZERO, Vec3::ONE).to_mesh(Color::RED);
+        // Leaving everything out gives exactly what the colour on its own would.
+        let default_material: StandardMaterial = Color::RED.into();
+        let material = mesh.material();
+        assert_eq!(material.metallic, default_material.metallic);
+        assert_eq!(
+            material.perceptual_roughness,
+            default_material.perceptual_roughness
+        );
+        assert_eq!(material.emissive, default_material.emissive);
+        mesh.metallic = Some(0.5);
+        mesh.perceptual_roughness = Some(0.25);
+        mesh.emissive = Some(Color::WHITE);
+        let material = mesh.material();
+        assert_eq!(material.metallic, 0.5);
+        assert_eq!(material.perceptual_roughness, 0.25);
+        assert_eq!(material.emissive, Color::WHITE);
+    }
+
+    #[test]
+    fn cone_and_capsule_colliders() {
+        let mesh = |shape| Mesh {
+            shape,
+            color: Color::WHITE,
+            ..Default::default()
+        };
+        let collider = mesh(Shape::Cone {
+            radius: 0.5,
+            height: 2.0,
+        })
+        .get_collider();
+        let cone = collider.as_cone().unwrap();
+        assert_eq!((cone.radius(), cone.half_height()), (0.5, 1.0));
+        let collider = mesh(Shape::Capsule {
+            radius: 0.5,
+            height: 2.0,
+        })
+        .get_collider();
+        let capsule = collider.as_capsule().unwrap();
+        // The rounded ends take up half a unit each, leaving one unit of straight side.
+        assert_eq!((capsule.radius(), capsule.half_height()), (0.5, 0.5));
+
+        let cone = cone_mesh(0.5, 2.0);
+        assert_eq!(cone.count_vertices(), (CONE_SEGMENTS as usize + 1) * 3 + 1);
+        assert_eq!(cone.indices().unwrap().len(), CONE_SEGMENTS as usize * 6);
+    }
+}
@@ -0,0 +1,132 @@
+use std::{collections::HashMap, ops::DerefMut};
+
+use bevy::prelude::{Entity, Input, KeyCode, Query, Res, ResMut, Resource};
+use bevy_rapier3d::prelude::Velocity;
+
+/// Whether the game is paused.
+/// Nothing moves while it is, and the ghosts stop thinking.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub struct Paused(pub bool);
+
+/// What everything was doing when the game was paused, so that it can carry on the same way afterwards.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct PausedVelocities(HashMap<Entity, Velocity>);
+
+/// Stops everything, giving back how fast each thing was going.
+pub fn freeze_velocities<V: DerefMut<Target = Velocity>>(
+    velocities: impl IntoIterator<Item = (Entity, V)>,
+) -> HashMap<Entity, Velocity> {
+    velocities
+        .into_iter()
+        .map(|(entity, mut velocity)| {
+            let stored = *velocity;
+            *velocity = Velocity::zero();
+            (entity, stored)
+        })
+        .collect()
+}
+
+/// Starts everything going again, as fast as it was going when it was frozen.
+/// Anything which wasn't around then (like a ghost which has respawned since) is left alone.
+pub fn restore_velocities<V: DerefMut<Target = Velocity>>(
+    stored: &HashMap<Entity, Velocity>,
+    velocities: impl IntoIterator<Item = (Entity, V)>,
+) {
+    for (entity, mut velocity) in velocities {
+        if let Some(stored) = stored.get(&entity) {
+            *velocity = *stored;
+        }
+    }
+}
+
+/// The run condition for everything which should stop while the game is paused.
+pub fn not_paused(paused: Res<Paused>) -> bool {
+    !paused.0
+}
+
+pub fn toggle_pause(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut paused: ResMut<Paused>,
+    mut paused_velocities: ResMut<PausedVelocities>,
+    mut velocities: Query<(Entity, &mut Velocity)>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    paused.0 = !paused.0;
+    if paused.0 {
+        paused_velocities.0 = freeze_velocities(velocities.iter_mut());
+    } else {
+        restore_velocities(&paused_velocities.0, velocities.iter_mut());
+        paused_velocities.0.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, Update, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn velocities_come_back() {
+        let ghost = Entity::from_raw(1);
+        let player = Entity::from_raw(2);
+        let mut ghost_velocity = Velocity::linear(Vec3::new(0.0, 0.0, -3.0));
+        let mut player_velocity = Velocity::linear(Vec3::new(5.0, 0.0, 0.0));
+        let stored =
+            freeze_velocities([(ghost, &mut ghost_velocity), (player, &mut player_velocity)]);
+        assert_eq!(ghost_velocity, Velocity::zero());
+        assert_eq!(player_velocity, Velocity::zero());
+
+        // Something new which turned up while paused doesn't get a velocity from anything else.
+        let mut new_velocity = Velocity::linear(Vec3::X);
+        restore_velocities(
+            &stored,
+            [
+                (ghost, &mut ghost_velocity),
+                (player, &mut player_velocity),
+                (Entity::from_raw(3), &mut new_velocity),
+            ],
+        );
+        assert_eq!(ghost_velocity, Velocity::linear(Vec3::new(0.0, 0.0, -3.0)));
+        assert_eq!(player_velocity, Velocity::linear(Vec3::new(5.0, 0.0, 0.0)));
+        assert_eq!(new_velocity, Velocity::linear(Vec3::X));
+    }
+
+    #[test]
+    fn escape_pauses_and_unpauses() {
+        let mut app = App::new();
+        app.init_resource::<Input<KeyCode>>()
+            .init_resource::<Paused>()
+            .init_resource::<PausedVelocities>()
+            .add_systems(Update, toggle_pause);
+        let ghost = app
+            .world
+            .spawn(Velocity::linear(Vec3::new(0.0, 0.0, -3.0)))
+            .id();
+
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Escape);
+        app.update();
+        assert_eq!(*app.world.resource::<Paused>(), Paused(true));
+        assert_eq!(*app.world.get::<Velocity>(ghost).unwrap(), Velocity::zero());
+
+        // Holding it down doesn't unpause.
+        app.world.resource_mut::<Input<KeyCode>>().clear();
+        app.update();
+        assert_eq!(*app.world.resource::<Paused>(), Paused(true));
+
+        let mut keyboard_input = app.world.resource_mut::<Input<KeyCode>>();
+        keyboard_input.release(KeyCode::Escape);
+        keyboard_input.clear();
+        keyboard_input.press(KeyCode::Escape);
+        app.update();
+        assert_eq!(*app.world.resource::<Paused>(), Paused(false));
+        assert_eq!(
+            *app.world.get::<Velocity>(ghost).unwrap(),
+            Velocity::linear(Vec3::new(0.0, 0.0, -3.0))
+        );
+    }
+}
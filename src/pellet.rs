@@ -0,0 +1,474 @@
+use std::fmt;
+
+use bevy::{
+    prelude::{
+        shape, Added, Assets, Color, Commands, Component, DespawnRecursiveExt, DetectChanges,
+        Entity, Event, EventWriter, Query, RemovedComponents, Res, ResMut, SpatialBundle,
+        Transform, TransformBundle, With,
+    },
+    render::view::NoFrustumCulling,
+};
+use bevy_rapier3d::prelude::{Collider, RapierContext, Sensor};
+
+use crate::{
+    instancing::{Instance, Instances},
+    math::euclidean,
+    maze::Maze,
+    theme::MazeTheme,
+    Player,
+};
+
+#[derive(Component)]
+pub struct Pellet;
+
+/// Power pellets are also `Pellet`s, since they need to be eaten to finish the level too.
+#[derive(Component)]
+pub struct PowerPellet;
+
+/// How many points each normal pellet is worth.
+const PELLET_POINTS: u32 = 10;
+
+/// Sent whenever the player eats a normal pellet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct PelletEaten {
+    pub points: u32,
+}
+
+/// Sent whenever the player eats a power pellet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct PowerPelletEaten;
+
+const PELLET_RADIUS: f32 = 0.15;
+const POWER_PELLET_RADIUS: f32 = 0.3;
+/// Pellets float at the height of the middle of the player, so that the player runs straight through them.
+const PELLET_HEIGHT: f32 = 1.0;
+/// How much the gaps between pellets can be stretched or squashed so that every edge gets evenly spaced pellets (as a fraction of the requested spacing).
+const MAX_SPACING_STRETCH: f32 = 0.25;
+
+/// The built in level only uses one of these, but the others are there for custom levels (and `--pellets` on the command line).
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PelletLayout {
+    /// Pellets this far apart along every path, with one on each intersection.
+    Spacing(f32),
+    /// This many pellets per unit of path (which is really just another way of giving the spacing).
+    Density(f32),
+    /// A single pellet in the middle of each path, for a more minimalist maze.
+    Midpoints,
+}
+
+impl PelletLayout {
+    /// The layout written like this on the command line, which is `midpoints`, `spacing=<units>` or `density=<pellets per unit>`.
+    /// Whether the numbers make any sense is left to `pellet_positions`.
+    pub fn from_name(name: &str) -> Option<PelletLayout> {
+        let name = name.to_lowercase();
+        if name == "midpoints" {
+            return Some(PelletLayout::Midpoints);
+        }
+        let (kind, amount) = name.split_once('=')?;
+        let amount = amount.trim().parse().ok()?;
+        match kind.trim() {
+            "spacing" => Some(PelletLayout::Spacing(amount)),
+            "density" => Some(PelletLayout::Density(amount)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PelletLayoutError {
+    /// The spacing (or density) has to be a positive number.
+    InvalidSpacing(f32),
+    /// The spacing would have to change too much to fit evenly on this path.
+    UnevenSpacing {
+        path: ((f32, f32), (f32, f32)),
+        spacing: f32,
+    },
+}
+
+impl fmt::Display for PelletLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PelletLayoutError::InvalidSpacing(spacing) => {
+                write!(f, "pellet spacing must be positive, not {}", spacing)
+            }
+            PelletLayoutError::UnevenSpacing { path, spacing } => write!(
+                f,
+                "pellets {} apart don't fit evenly on the path from {:?} to {:?}",
+                spacing, path.0, path.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PelletLayoutError {}
+
+/// Works out where all of the pellets go.
+/// Paths share intersections with each other, so those get their pellets separately to make sure there is only ever one on each.
+pub fn pellet_positions(
+    maze: &Maze,
+    layout: PelletLayout,
+) -> Result<Vec<(f32, f32)>, PelletLayoutError> {
+    let spacing = match layout {
+        PelletLayout::Spacing(spacing) => spacing,
+        PelletLayout::Density(density) => 1.0 / density,
+        PelletLayout::Midpoints => {
+            return Ok(maze
+                .paths()
+                .into_iter()
+                .map(|(from, to)| ((from.0 + to.0) / 2.0, (from.1 + to.1) / 2.0))
+                .collect());
+        }
+    };
+    // This also catches NaN, since any comparison with it is false.
+    if !(spacing > 0.0 && spacing.is_finite()) {
+        return Err(PelletLayoutError::InvalidSpacing(spacing));
+    }
+    // Some intersections aren't joined to any paths, and those are somewhere the player can't get to.
+    let mut positions = maze
+        .intersections()
+        .iter()
+        .filter(|intersection| intersection.has_paths())
+        .map(|intersection| intersection.coordinates)
+        .collect::<Vec<_>>();
+    for path in maze.paths() {
+        let (from, to) = path;
+        let length = euclidean(from, to);
+        // We adjust the spacing a little so that the last pellet lands right on the far intersection.
+        let gaps = (length / spacing).round().max(1.0);
+        if ((length / gaps) - spacing).abs() > spacing * MAX_SPACING_STRETCH {
+            return Err(PelletLayoutError::UnevenSpacing { path, spacing });
+        }
+        // Both ends already have a pellet from their intersections.
+        for i in 1..gaps as usize {
+            let fraction = i as f32 / gaps;
+            positions.push((
+                from.0 + (to.0 - from.0) * fraction,
+                from.1 + (to.1 - from.1) * fraction,
+            ));
+        }
+    }
+    Ok(positions)
+}
+
+/// Finds the intersections in the corners of the maze, which is where the power pellets go.
+/// These are the intersections with the fewest paths joining them, and out of those we take the one furthest towards each corner.
+fn power_pellet_intersections(maze: &Maze) -> Vec<usize> {
+    let intersections = maze.intersections();
+    let path_count = |index: usize| intersections[index].all_paths().count();
+    // Intersections which aren't joined to anything don't count.
+    let Some(fewest_paths) = (0..intersections.len())
+        .map(path_count)
+        .filter(|count| *count > 0)
+        .min()
+    else {
+        return Vec::new();
+    };
+    let mut corners = Vec::new();
+    for (x_sign, y_sign) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+        let how_far_into_corner = |index: &usize| {
+            let coordinates = intersections[*index].coordinates;
+            coordinates.0 * x_sign + coordinates.1 * y_sign
+        };
+        let corner = (0..intersections.len())
+            .filter(|index| path_count(*index) == fewest_paths)
+            .max_by(|a, b| how_far_into_corner(a).total_cmp(&how_far_into_corner(b)));
+        // Tiny mazes might have the same intersection in more than one corner.
+        if let Some(corner) = corner.filter(|corner| !corners.contains(corner)) {
+            corners.push(corner);
+        }
+    }
+    corners
+}
+
+/// All of the pellets are drawn together by this one entity (see `update_pellet_instances`), rather than each of them being drawn separately.
+#[derive(Component)]
+pub struct PelletInstances;
+
+/// How many slices the pellet spheres are made of around the middle and from top to bottom.
+/// The pellets are tiny, so they don't need nearly as many as Bevy would give them.
+const PELLET_SECTORS: usize = 12;
+const PELLET_STACKS: usize = 8;
+
+pub fn spawn_pellets(
+    maze: &Maze,
+    layout: PelletLayout,
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<bevy::prelude::Mesh>>,
+) {
+    let positions = match pellet_positions(maze, layout) {
+        Ok(positions) => positions,
+        Err(error) => {
+            bevy::log::error!("Couldn't place the pellets: {}", error);
+            return;
+        }
+    };
+    let power_pellet_positions = power_pellet_intersections(maze)
+        .into_iter()
+        .map(|index| maze.intersections()[index].coordinates)
+        .collect::<Vec<_>>();
+    let transform = |position: &(f32, f32)| {
+        TransformBundle::from_transform(Transform::from_xyz(position.0, PELLET_HEIGHT, position.1))
+    };
+    for position in &power_pellet_positions {
+        commands.spawn((
+            transform(position),
+            Collider::ball(POWER_PELLET_RADIUS),
+            Sensor,
+            Pellet,
+            PowerPellet,
+        ));
+    }
+    // The power pellets replace the normal pellets in the corners.
+    for position in positions
+        .iter()
+        .filter(|position| !power_pellet_positions.contains(position))
+    {
+        // Like the intersections, pellets are sensors so that the player can go straight through them.
+        commands.spawn((
+            transform(position),
+            Collider::ball(PELLET_RADIUS),
+            Sensor,
+            Pellet,
+        ));
+    }
+    // Every pellet is a copy of the same sphere, just moved and scaled.
+    commands.spawn((
+        meshes.add(
+            shape::UVSphere {
+                radius: 1.0,
+                sectors: PELLET_SECTORS,
+                stacks: PELLET_STACKS,
+            }
+            .into(),
+        ),
+        SpatialBundle::default(),
+        Instances::default(),
+        // The sphere is only at the origin, but the copies are all over the maze.
+        NoFrustumCulling,
+        PelletInstances,
+    ));
+}
+
+/// How each pellet looks when it is drawn.
+fn pellet_instance(transform: &Transform, power_pellet: bool, theme: &MazeTheme) -> Instance {
+    Instance {
+        position: transform.translation,
+        scale: if power_pellet {
+            POWER_PELLET_RADIUS
+        } else {
+            PELLET_RADIUS
+        },
+        color: theme.pellets,
+        // Power pellets glow, so that they stand out from the normal ones.
+        emissive: if power_pellet {
+            theme.pellets
+        } else {
+            Color::BLACK
+        },
+    }
+}
+
+/// Keeps the pellets being drawn the same as the pellets which are actually there, which only needs doing when some are added or eaten.
+pub fn update_pellet_instances(
+    mut removed: RemovedComponents<Pellet>,
+    added: Query<(), Added<Pellet>>,
+    pellets: Query<(&Transform, Option<&PowerPellet>), With<Pellet>>,
+    mut batches: Query<&mut Instances, With<PelletInstances>>,
+    theme: Res<MazeTheme>,
+) {
+    let pellets_changed = removed.iter().count() > 0 || !added.is_empty() || theme.is_changed();
+    for mut instances in batches.iter_mut() {
+        // A new batch (like after the level is rebuilt) needs filling in, even if the pellets went in before it did.
+        if !pellets_changed && !instances.is_added() {
+            continue;
+        }
+        instances.0 = pellets
+            .iter()
+            .map(|(transform, power_pellet)| {
+                pellet_instance(transform, power_pellet.is_some(), &theme)
+            })
+            .collect();
+    }
+}
+
+/// Eats any pellets which the player is touching.
+pub fn pellet_collection(
+    player: Query<Entity, With<Player>>,
+    pellets: Query<(Entity, Option<&PowerPellet>), With<Pellet>>,
+    rapier_context: Res<RapierContext>,
+    mut commands: Commands,
+    mut eaten: EventWriter<PelletEaten>,
+    mut power_eaten: EventWriter<PowerPelletEaten>,
+) {
+    for player in player.iter() {
+        for (pellet, power_pellet) in pellets.iter() {
+            if rapier_context
+                .intersection_pair(player, pellet)
+                .unwrap_or(false)
+            {
+                commands.entity(pellet).despawn_recursive();
+                if power_pellet.is_some() {
+                    power_eaten.send(PowerPelletEaten);
+                } else {
+                    eaten.send(PelletEaten {
+                        points: PELLET_POINTS,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Whether the player has eaten every pellet in the maze (power pellets included).
+pub fn all_pellets_collected(pellets: &Query<(), With<Pellet>>) -> bool {
+    pellets.is_empty()
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{
+        asset::{AddAsset, AssetPlugin},
+        prelude::{App, MinimalPlugins, Startup, Update},
+    };
+
+    use super::*;
+
+    #[test]
+    fn layout_names() {
+        assert_eq!(
+            PelletLayout::from_name("Midpoints"),
+            Some(PelletLayout::Midpoints)
+        );
+        assert_eq!(
+            PelletLayout::from_name("spacing=1.5"),
+            Some(PelletLayout::Spacing(1.5))
+        );
+        assert_eq!(
+            PelletLayout::from_name("density=2"),
+            Some(PelletLayout::Density(2.0))
+        );
+        assert_eq!(PelletLayout::from_name("spacing"), None);
+        assert_eq!(PelletLayout::from_name("spacing=lots"), None);
+        assert_eq!(PelletLayout::from_name("everywhere=1"), None);
+    }
+
+    #[test]
+    fn test_pellet_positions() {
+        let maze = Maze::new(&[((0.0, 0.0), (10.0, 0.0))]);
+        // One at each end and four in between.
+        let positions = pellet_positions(&maze, PelletLayout::Spacing(2.0)).unwrap();
+        assert_eq!(positions.len(), 6);
+        assert!(positions.contains(&(4.0, 0.0)));
+        assert_eq!(
+            pellet_positions(&maze, PelletLayout::Density(0.5)).unwrap(),
+            positions
+        );
+        // Close enough to fit evenly.
+        assert_eq!(
+            pellet_positions(&maze, PelletLayout::Spacing(2.2))
+                .unwrap()
+                .len(),
+            6
+        );
+        assert!(matches!(
+            pellet_positions(&maze, PelletLayout::Spacing(7.0)),
+            Err(PelletLayoutError::UnevenSpacing { .. })
+        ));
+        assert_eq!(
+            pellet_positions(&maze, PelletLayout::Spacing(0.0)),
+            Err(PelletLayoutError::InvalidSpacing(0.0))
+        );
+
+        let maze = crate::sample_maze();
+        let positions = pellet_positions(&maze, PelletLayout::Midpoints).unwrap();
+        assert_eq!(positions.len(), maze.paths().len());
+        // Shared intersections only get one pellet.
+        let positions = pellet_positions(&maze, PelletLayout::Spacing(1.0)).unwrap();
+        for (i, a) in positions.iter().enumerate() {
+            assert!(!positions[i + 1..].contains(a), "Two pellets at {:?}", a);
+        }
+    }
+
+    #[test]
+    fn power_pellets_in_corners() {
+        let maze = crate::sample_maze();
+        let corners = power_pellet_intersections(&maze)
+            .into_iter()
+            .map(|index| maze.intersections()[index].coordinates)
+            .collect::<Vec<_>>();
+        assert_eq!(corners.len(), 4);
+        // There should be one in each quarter of the maze.
+        for (x_sign, y_sign) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            assert!(
+                corners
+                    .iter()
+                    .any(|corner| corner.0 * x_sign > 0.0 && corner.1 * y_sign > 0.0),
+                "{:?}",
+                corners
+            );
+        }
+    }
+
+    #[test]
+    fn eaten_pellets_stop_being_drawn() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<bevy::prelude::Mesh>()
+            .init_resource::<MazeTheme>()
+            .add_systems(
+                Startup,
+                |mut commands: Commands, mut meshes: ResMut<Assets<bevy::prelude::Mesh>>| {
+                    let maze = crate::sample_maze();
+                    spawn_pellets(
+                        &maze,
+                        PelletLayout::Spacing(1.0),
+                        &mut commands,
+                        &mut meshes,
+                    );
+                },
+            )
+            .add_systems(Update, update_pellet_instances);
+        let instances = |app: &mut App| {
+            app.world
+                .query_filtered::<&Instances, With<PelletInstances>>()
+                .single(&app.world)
+                .0
+                .clone()
+        };
+        let pellets = |app: &mut App| {
+            app.world
+                .query_filtered::<(Entity, Option<&PowerPellet>), With<Pellet>>()
+                .iter(&app.world)
+                .map(|(entity, power_pellet)| (entity, power_pellet.is_some()))
+                .collect::<Vec<_>>()
+        };
+
+        app.update();
+        let all_pellets = pellets(&mut app);
+        let drawn = instances(&mut app);
+        assert_eq!(drawn.len(), all_pellets.len());
+        // Only the four power pellets glow.
+        assert_eq!(
+            drawn
+                .iter()
+                .filter(|instance| instance.emissive != Color::BLACK)
+                .count(),
+            4
+        );
+
+        let (power_pellet, _) = *all_pellets.iter().find(|(_, power)| *power).unwrap();
+        app.world.despawn(power_pellet);
+        app.world.despawn(all_pellets[all_pellets.len() - 1].0);
+        app.update();
+        let drawn = instances(&mut app);
+        assert_eq!(drawn.len(), all_pellets.len() - 2);
+        assert_eq!(
+            drawn
+                .iter()
+                .filter(|instance| instance.emissive != Color::BLACK)
+                .count(),
+            3
+        );
+    }
+}
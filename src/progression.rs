@@ -0,0 +1,120 @@
+use bevy::prelude::Resource;
+
+/// How fast the ghosts go on each level, as a percentage of the fastest anything goes in the arcade game.
+/// Everything after the last entry is the same as it.
+const GHOST_SPEEDS: [f32; 4] = [75.0, 85.0, 85.0, 85.0];
+const LATER_GHOST_SPEED: f32 = 95.0;
+/// How long (in seconds) the ghosts stay frightened on each level in the arcade game, which mostly gets shorter but jumps back up every now and then.
+/// Past the end of the table they aren't frightened at all.
+const FRIGHTENED_TIMES: [f32; 18] = [
+    6.0, 5.0, 4.0, 3.0, 2.0, 5.0, 2.0, 2.0, 1.0, 5.0, 2.0, 1.0, 1.0, 3.0, 1.0, 1.0, 0.0, 1.0,
+];
+
+/// Which level the player is on, starting from 1 and going up every time all of the pellets are eaten.
+/// (`Level` is already the layout of the maze, which stays the same from one level to the next.)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Resource)]
+pub struct LevelNumber(pub u32);
+
+impl Default for LevelNumber {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+/// How much harder a level is than the first one.
+/// These go on top of the `Difficulty` multipliers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LevelMultipliers {
+    pub ghost_speed: f32,
+    pub frightened_duration: f32,
+}
+
+impl LevelNumber {
+    /// Goes by the same table as the arcade game, compared to how it is on level 1.
+    pub fn multipliers(self) -> LevelMultipliers {
+        // Level 0 isn't a real level, but it can't hurt to treat it as the first one.
+        let index = self.0.max(1) as usize - 1;
+        let ghost_speed = GHOST_SPEEDS
+            .get(index)
+            .copied()
+            .unwrap_or(LATER_GHOST_SPEED);
+        let frightened_time = FRIGHTENED_TIMES.get(index).copied().unwrap_or(0.0);
+        LevelMultipliers {
+            ghost_speed: ghost_speed / GHOST_SPEEDS[0],
+            frightened_duration: frightened_time / FRIGHTENED_TIMES[0],
+        }
+    }
+
+    pub fn next(self) -> LevelNumber {
+        LevelNumber(self.0 + 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, Update};
+
+    use super::*;
+    use crate::{
+        difficulty::Difficulty,
+        ghost::{frighten_ghosts, FrightenedConfig, GhostState, PathCache},
+        ghost_speed_with,
+        pellet::PowerPelletEaten,
+    };
+
+    #[test]
+    fn progression_table() {
+        assert_eq!(
+            LevelNumber::default().multipliers(),
+            LevelMultipliers {
+                ghost_speed: 1.0,
+                frightened_duration: 1.0
+            }
+        );
+        let multipliers = |level| LevelNumber(level).multipliers();
+        assert!(multipliers(2).frightened_duration < multipliers(1).frightened_duration);
+        // Level 6 gives the player a break.
+        assert!(multipliers(6).frightened_duration > multipliers(5).frightened_duration);
+        assert_eq!(multipliers(4).ghost_speed, multipliers(2).ghost_speed);
+        assert!(multipliers(5).ghost_speed > multipliers(4).ghost_speed);
+        assert_eq!(multipliers(100).ghost_speed, multipliers(5).ghost_speed);
+        assert_eq!(multipliers(17).frightened_duration, 0.0);
+        assert_eq!(multipliers(100).frightened_duration, 0.0);
+    }
+
+    #[test]
+    fn level_two_ghosts_are_faster() {
+        let ghost_speed = |level| ghost_speed_with(Difficulty::default(), level);
+        let (first, second) = (ghost_speed(LevelNumber(1)), ghost_speed(LevelNumber(2)));
+        assert!(first > 0.0);
+        assert!(first < second, "{} {}", first, second);
+    }
+
+    #[test]
+    fn late_levels_dont_frighten_ghosts() {
+        let ghost_state_after_power_pellet = |level| {
+            let mut app = App::new();
+            app.add_event::<PowerPelletEaten>()
+                .insert_resource(level)
+                .init_resource::<Difficulty>()
+                .init_resource::<FrightenedConfig>()
+                .add_systems(Update, frighten_ghosts);
+            let ghost = app
+                .world
+                .spawn((GhostState::Normal, PathCache::default()))
+                .id();
+            app.world.send_event(PowerPelletEaten);
+            app.update();
+            app.world.get::<GhostState>(ghost).unwrap().clone()
+        };
+        assert!(matches!(
+            ghost_state_after_power_pellet(LevelNumber(1)),
+            GhostState::Frightened { .. }
+        ));
+        // Not even for a moment, since they would still stop and turn around.
+        assert!(matches!(
+            ghost_state_after_power_pellet(LevelNumber(17)),
+            GhostState::Normal
+        ));
+    }
+}
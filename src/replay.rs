@@ -0,0 +1,305 @@
+use std::{fmt, path::Path};
+
+use bevy::prelude::{Res, Resource};
+
+use crate::{difficulty::Difficulty, input::PlayerAction};
+
+/// What the game was started with, which has to be the same again for a replay to play out the same way.
+/// This goes at the top of the replay file, before the steps.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayHeader {
+    /// The level file given on the command line, or `None` for the built in level.
+    pub level: Option<String>,
+    pub difficulty: Difficulty,
+}
+
+/// Records what the player did on every step of the game, so that it can be played back exactly the same way later.
+/// There isn't anything random in the game (even the ghosts always make the same choices), so the player's actions (and what the game was started with) are all it takes.
+#[derive(Clone, Debug, Default, PartialEq, Resource)]
+pub struct ReplayRecorder {
+    header: ReplayHeader,
+    steps: Vec<Vec<PlayerAction>>,
+}
+
+impl ReplayRecorder {
+    pub fn new(header: ReplayHeader) -> Self {
+        Self {
+            header,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Adds the next step to the recording.
+    pub fn record(&mut self, actions: &[PlayerAction]) {
+        self.steps.push(actions.to_vec());
+    }
+
+    /// Writes the recording to a file, with the header and then one line for each step.
+    pub fn save_replay(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(
+            path,
+            header_to_text(&self.header) + &steps_to_text(&self.steps),
+        )
+    }
+}
+
+/// Plays a recorded game back, giving the actions from the recording instead of whatever is being pressed.
+#[derive(Clone, Debug, Default, PartialEq, Resource)]
+pub struct ReplayPlayer {
+    steps: Vec<Vec<PlayerAction>>,
+    next_step: usize,
+}
+
+impl ReplayPlayer {
+    /// Loads a recording, as long as it was recorded with the same level and difficulty as `current`.
+    /// Otherwise it would go completely differently, so there's no point playing it back.
+    pub fn load_replay(
+        path: impl AsRef<Path>,
+        current: &ReplayHeader,
+    ) -> Result<ReplayPlayer, ReplayError> {
+        let text = std::fs::read_to_string(path).map_err(ReplayError::Io)?;
+        let (recorded, steps) = header_from_text(&text)?;
+        if recorded != *current {
+            return Err(ReplayError::DifferentGame {
+                recorded,
+                current: current.clone(),
+            });
+        }
+        Ok(ReplayPlayer {
+            steps: steps_from_text(steps, HEADER_LINES)?,
+            next_step: 0,
+        })
+    }
+
+    /// What the player did on the next step of the recording.
+    /// Once the recording runs out the player just stops doing anything, the same as if they had let go of everything.
+    pub fn next_actions(&mut self) -> Vec<PlayerAction> {
+        let actions = self.steps.get(self.next_step).cloned().unwrap_or_default();
+        self.next_step += 1;
+        actions
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    /// The file doesn't start with a header saying what the game was started with.
+    MissingHeader,
+    /// The replay was recorded with a different level or difficulty to this game.
+    DifferentGame {
+        recorded: ReplayHeader,
+        current: ReplayHeader,
+    },
+    /// Something in the file which isn't one of the player's actions.
+    UnknownAction {
+        line: usize,
+        word: String,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "couldn't read the replay: {}", error),
+            ReplayError::MissingHeader => write!(
+                f,
+                "the replay doesn't say which level and difficulty it was recorded with"
+            ),
+            ReplayError::DifferentGame { recorded, current } => write!(
+                f,
+                "the replay was recorded on {} ({:?}), but this game is {} ({:?})",
+                level_name(&recorded.level),
+                recorded.difficulty,
+                level_name(&current.level),
+                current.difficulty
+            ),
+            ReplayError::UnknownAction { line, word } => write!(
+                f,
+                "{:?} on line {} of the replay isn't something the player can do",
+                word, line
+            ),
+        }
+    }
+}
+
+fn action_name(action: PlayerAction) -> &'static str {
+    match action {
+        PlayerAction::GoStraight => "straight",
+        PlayerAction::TurnLeft => "left",
+        PlayerAction::TurnRight => "right",
+        PlayerAction::Reverse => "reverse",
+    }
+}
+
+fn parse_action(word: &str) -> Option<PlayerAction> {
+    [
+        PlayerAction::GoStraight,
+        PlayerAction::TurnLeft,
+        PlayerAction::TurnRight,
+        PlayerAction::Reverse,
+    ]
+    .into_iter()
+    .find(|action| action_name(*action) == word)
+}
+
+/// How many lines the header takes up at the top of the file.
+const HEADER_LINES: usize = 2;
+
+fn level_name(level: &Option<String>) -> &str {
+    level.as_deref().unwrap_or("the built in level")
+}
+
+/// The level goes on the first line (which is just `level` on its own for the built in one), and the difficulty on the second.
+fn header_to_text(header: &ReplayHeader) -> String {
+    let level = match &header.level {
+        Some(level) => format!("level {}\n", level),
+        None => "level\n".to_string(),
+    };
+    level + &format!("difficulty {}\n", header.difficulty.name())
+}
+
+/// Reads the header, and gives back the rest of the text (which is the steps) along with it.
+fn header_from_text(text: &str) -> Result<(ReplayHeader, &str), ReplayError> {
+    let mut lines = text.splitn(HEADER_LINES + 1, '\n');
+    let (Some(level), Some(difficulty)) = (lines.next(), lines.next()) else {
+        return Err(ReplayError::MissingHeader);
+    };
+    let level = match level.strip_prefix("level") {
+        Some("") => None,
+        Some(level) if level.starts_with(' ') => Some(level[1..].to_string()),
+        _ => return Err(ReplayError::MissingHeader),
+    };
+    let difficulty = difficulty
+        .strip_prefix("difficulty ")
+        .and_then(Difficulty::from_name)
+        .ok_or(ReplayError::MissingHeader)?;
+    Ok((
+        ReplayHeader { level, difficulty },
+        lines.next().unwrap_or_default(),
+    ))
+}
+
+/// Most steps don't have anything happening in them, so they are just empty lines.
+fn steps_to_text(steps: &[Vec<PlayerAction>]) -> String {
+    steps
+        .iter()
+        .map(|actions| {
+            let names = actions
+                .iter()
+                .map(|action| action_name(*action))
+                .collect::<Vec<_>>();
+            names.join(" ") + "\n"
+        })
+        .collect()
+}
+
+/// `first_line` is how many lines of the file came before these, so that errors point at the right line.
+fn steps_from_text(text: &str, first_line: usize) -> Result<Vec<Vec<PlayerAction>>, ReplayError> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| {
+            line.split_whitespace()
+                .map(|word| {
+                    parse_action(word).ok_or_else(|| ReplayError::UnknownAction {
+                        line: first_line + index + 1,
+                        word: word.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Where the recording is saved once the game is over.
+#[derive(Clone, Debug, Resource)]
+pub struct ReplaySavePath(pub std::path::PathBuf);
+
+pub fn save_recording(recorder: Res<ReplayRecorder>, path: Res<ReplaySavePath>) {
+    if let Err(error) = recorder.save_replay(&path.0) {
+        bevy::log::error!("Couldn't save the replay to {:?}: {}", path.0, error);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replays_as_text() {
+        let steps = vec![
+            vec![],
+            vec![PlayerAction::TurnLeft],
+            vec![],
+            vec![PlayerAction::GoStraight, PlayerAction::Reverse],
+            vec![PlayerAction::TurnRight],
+        ];
+        let text = steps_to_text(&steps);
+        assert_eq!(text, "\nleft\n\nstraight reverse\nright\n");
+        assert_eq!(steps_from_text(&text, 0).unwrap(), steps);
+        assert!(matches!(
+            steps_from_text("left\n\njump", HEADER_LINES),
+            Err(ReplayError::UnknownAction { line: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn headers_as_text() {
+        let header = ReplayHeader {
+            level: Some("levels/big maze.txt".to_string()),
+            difficulty: Difficulty::Hard,
+        };
+        let text = header_to_text(&header) + "left\n";
+        assert_eq!(text, "level levels/big maze.txt\ndifficulty hard\nleft\n");
+        let (read, steps) = header_from_text(&text).unwrap();
+        assert_eq!((read, steps), (header, "left\n"));
+        let built_in = header_to_text(&ReplayHeader::default());
+        assert_eq!(built_in, "level\ndifficulty normal\n");
+        assert_eq!(
+            header_from_text(&built_in).unwrap(),
+            (ReplayHeader::default(), "")
+        );
+        // Replays from before there was a header are just steps.
+        assert!(matches!(
+            header_from_text("\nleft\n"),
+            Err(ReplayError::MissingHeader)
+        ));
+        assert!(matches!(
+            header_from_text("levels\ndifficulty normal\n"),
+            Err(ReplayError::MissingHeader)
+        ));
+    }
+
+    #[test]
+    fn saving_and_loading() {
+        let header = ReplayHeader {
+            level: Some("level.txt".to_string()),
+            difficulty: Difficulty::Easy,
+        };
+        let mut recorder = ReplayRecorder::new(header.clone());
+        recorder.record(&[]);
+        recorder.record(&[PlayerAction::TurnRight]);
+        let path = std::env::temp_dir().join(format!("pacman3d-replay-{}", std::process::id()));
+        recorder.save_replay(&path).unwrap();
+        // It would go differently on any other level or difficulty.
+        assert!(matches!(
+            ReplayPlayer::load_replay(&path, &ReplayHeader::default()),
+            Err(ReplayError::DifferentGame { .. })
+        ));
+        assert!(matches!(
+            ReplayPlayer::load_replay(
+                &path,
+                &ReplayHeader {
+                    difficulty: Difficulty::Normal,
+                    ..header.clone()
+                }
+            ),
+            Err(ReplayError::DifferentGame { .. })
+        ));
+        let mut player = ReplayPlayer::load_replay(&path, &header).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(player.next_actions(), vec![]);
+        assert_eq!(player.next_actions(), vec![PlayerAction::TurnRight]);
+        // There's nothing left, so nothing else happens.
+        assert_eq!(player.next_actions(), vec![]);
+    }
+}
@@ -0,0 +1,165 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::{
+    Assets, Color, Commands, Component, DespawnRecursiveExt, Entity, Quat, Query, Res, ResMut,
+    Resource, StandardMaterial, Vec3, With,
+};
+
+use crate::{
+    maze::Maze,
+    object::{Mesh, Shape},
+    Direction,
+};
+
+/// A route through the maze (as intersection indices) which should be marked out on the floor, for things like tutorial levels.
+/// The intersections don't have to be next to each other, since the route goes the shortest way between them (so a tutorial only has to say where it wants the player to go).
+#[derive(Clone, Debug, Default, Resource)]
+pub struct RouteGuide(pub Vec<usize>);
+
+#[derive(Component)]
+pub struct RouteArrow;
+
+/// The top of the ground, which the arrows sit just above.
+const FLOOR_HEIGHT: f32 = 0.5;
+const ARROW_WIDTH: f32 = 0.2;
+const ARROW_THICKNESS: f32 = 0.01;
+/// How much of the arrow is taken up by its head.
+const ARROW_HEAD_FRACTION: f32 = 0.3;
+
+/// Each direction gets its own colour so that it is obvious which way to go, even from a distance.
+fn direction_color(direction: Direction) -> Color {
+    match direction {
+        Direction::Forward => Color::GREEN,
+        Direction::Backward => Color::RED,
+        Direction::Left => Color::BLUE,
+        Direction::Right => Color::PURPLE,
+        Direction::ForwardLeft => Color::TEAL,
+        Direction::ForwardRight => Color::OLIVE,
+        Direction::BackwardLeft => Color::FUCHSIA,
+        Direction::BackwardRight => Color::ORANGE,
+    }
+}
+
+/// Fills in the gaps between intersections in the route which aren't next to each other, going the shortest way between them.
+/// If there isn't any way from one to the next, the route just skips ahead to it.
+fn fill_in_route(maze: &Maze, route: &[usize]) -> Vec<usize> {
+    let mut filled = route.first().copied().into_iter().collect::<Vec<_>>();
+    for pair in route.windows(2) {
+        match maze.shortest_path(pair[0], pair[1]) {
+            Some(way) => filled.extend(&way[1..]),
+            None => filled.push(pair[1]),
+        }
+    }
+    filled
+}
+
+/// Makes an arrow for each edge along the route, pointing the way to go.
+/// Each one is a flat shaft with a cone on the front for its head, so that it can't be read backwards.
+/// Any steps in the route which there is no way along are skipped, and so are tunnels (since the middle of one is off the side of the maze).
+/// So are any intersections which aren't in the maze (which happens if it has changed since the route was made).
+fn route_arrows(maze: &Maze, route: &[usize]) -> Vec<[Mesh; 2]> {
+    fill_in_route(maze, route)
+        .windows(2)
+        .filter_map(|pair| {
+            let from = maze.intersections().get(pair[0])?;
+            let to = maze.intersections().get(pair[1])?;
+            let direction = maze.direction_between(pair[0], pair[1])?;
+            let path = direction
+                .intersection_path(from)
+                .filter(|path| !path.wrap)?;
+            let length = path.length / 2.0;
+            let head_length = length * ARROW_HEAD_FRACTION;
+            let middle = Vec3::new(
+                (from.coordinates.0 + to.coordinates.0) / 2.0,
+                FLOOR_HEIGHT + ARROW_THICKNESS / 2.0,
+                (from.coordinates.1 + to.coordinates.1) / 2.0,
+            );
+            let forward = Vec3::new(direction.x_velocity(), 0.0, direction.z_velocity());
+            Some([
+                Mesh {
+                    // The shaft is long in the z direction, which the rotation then turns to point along the edge.
+                    shape: Shape::Box {
+                        width: ARROW_WIDTH,
+                        height: ARROW_THICKNESS,
+                        depth: length - head_length,
+                    },
+                    color: direction_color(direction),
+                    position: middle - forward * head_length / 2.0,
+                    rotation: direction.get_rotation(),
+                    ..Default::default()
+                },
+                Mesh {
+                    // Cones point up, so this one gets tipped over to point forward before it is turned along the edge.
+                    // The bottom half of it is under the floor, which leaves a low point sticking out of the shaft.
+                    shape: Shape::Cone {
+                        radius: ARROW_WIDTH,
+                        height: head_length,
+                    },
+                    color: direction_color(direction),
+                    position: middle + forward * (length - head_length) / 2.0,
+                    rotation: direction.get_rotation() * Quat::from_rotation_x(-PI / 2.0),
+                    ..Default::default()
+                },
+            ])
+        })
+        .collect()
+}
+
+pub fn spawn_route_guides(
+    route: Res<RouteGuide>,
+    maze: Res<Maze>,
+    old_arrows: Query<Entity, With<RouteArrow>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<bevy::prelude::Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for entity in old_arrows.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    // These are just decoration, so they don't need colliders like the rest of the game objects.
+    for arrow in route_arrows(&maze, &route.0).into_iter().flatten() {
+        arrow
+            .to_entity(&mut commands, &mut meshes, &mut materials)
+            .insert(RouteArrow);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index_of;
+
+    #[test]
+    fn test_route_arrows() {
+        let maze = Maze::new(&[
+            ((0.0, 0.0), (4.0, 0.0)),
+            ((4.0, 0.0), (4.0, -4.0)),
+            ((4.0, -4.0), (0.0, -4.0)),
+        ]);
+        let route = [(0.0, 0.0), (4.0, 0.0), (4.0, -4.0), (0.0, -4.0)]
+            .map(|coordinates| index_of(&maze, coordinates));
+        let arrows = route_arrows(&maze, &route);
+        assert_eq!(arrows.len(), route.len() - 1);
+        let expected = [
+            (Vec3::new(2.0, 0.0, 0.0), Direction::Right),
+            (Vec3::new(4.0, 0.0, -2.0), Direction::Forward),
+            (Vec3::new(2.0, 0.0, -4.0), Direction::Left),
+        ];
+        for ([shaft, head], (position, direction)) in arrows.iter().zip(expected) {
+            // The shaft is behind the middle of the edge, and the head is in front of it.
+            let forward = Vec3::new(direction.x_velocity(), 0.0, direction.z_velocity());
+            assert!((shaft.position - position).dot(forward) < 0.0);
+            assert!((head.position - position).dot(forward) > 0.0);
+            assert_eq!(shaft.rotation, direction.get_rotation());
+            // The point of the head faces the way to go.
+            assert!((head.rotation * Vec3::Y).abs_diff_eq(forward, 0.0001));
+        }
+        // Intersections which aren't next to each other get arrows the whole way between them.
+        let arrows = route_arrows(&maze, &[route[0], route[3]]);
+        assert_eq!(arrows.len(), 3);
+        assert_eq!(arrows[1][0].rotation, Direction::Forward.get_rotation());
+        // A route left over from a different maze doesn't get arrows where it goes off the end of this one.
+        let arrows = route_arrows(&maze, &[route[0], route[1], 100]);
+        assert_eq!(arrows.len(), 1);
+    }
+}
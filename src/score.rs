@@ -0,0 +1,99 @@
+use bevy::prelude::{EventReader, ResMut, Resource};
+
+use crate::{
+    ghost::GhostEaten,
+    pellet::{PelletEaten, PowerPelletEaten},
+};
+
+const POWER_PELLET_POINTS: u32 = 50;
+const STARTING_LIVES: u8 = 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Resource)]
+pub struct Score(pub u32);
+
+impl Score {
+    pub fn current(&self) -> u32 {
+        self.0
+    }
+
+    pub fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
+
+/// How many more times the player can be caught before the game is over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Resource)]
+pub struct Lives(pub u8);
+
+impl Default for Lives {
+    fn default() -> Self {
+        Self(STARTING_LIVES)
+    }
+}
+
+impl Lives {
+    /// Takes away a life, returning whether that was the last one.
+    pub fn lose(&mut self) -> bool {
+        self.0 = self.0.saturating_sub(1);
+        self.0 == 0
+    }
+}
+
+pub fn update_score(
+    mut score: ResMut<Score>,
+    mut pellets: EventReader<PelletEaten>,
+    mut power_pellets: EventReader<PowerPelletEaten>,
+    mut ghosts: EventReader<GhostEaten>,
+) {
+    for pellet in pellets.iter() {
+        score.0 += pellet.points;
+    }
+    for _ in power_pellets.iter() {
+        score.0 += POWER_PELLET_POINTS;
+    }
+    for ghost in ghosts.iter() {
+        score.0 += ghost.points;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, Entity, Update};
+
+    use super::*;
+
+    #[test]
+    fn pellets_add_to_score() {
+        let mut app = App::new();
+        app.add_event::<PelletEaten>()
+            .add_event::<PowerPelletEaten>()
+            .add_event::<GhostEaten>()
+            .init_resource::<Score>()
+            .add_systems(Update, update_score);
+        app.world.send_event(PelletEaten { points: 10 });
+        app.world.send_event(PelletEaten { points: 10 });
+        app.update();
+        assert_eq!(app.world.resource::<Score>().current(), 20);
+        app.world.send_event(GhostEaten {
+            ghost: Entity::from_raw(1),
+            points: 200,
+        });
+        app.world.send_event(PowerPelletEaten);
+        app.update();
+        assert_eq!(app.world.resource::<Score>().current(), 270);
+        app.world.resource_mut::<Score>().reset();
+        assert_eq!(app.world.resource::<Score>().current(), 0);
+    }
+
+    #[test]
+    fn losing_lives() {
+        let mut lives = Lives::default();
+        assert!(!lives.lose());
+        assert!(!lives.lose());
+        assert_eq!(lives, Lives(1));
+        assert!(lives.lose());
+        // Getting caught again (on the same frame, say) can't go below nothing.
+        assert!(lives.lose());
+        assert_eq!(lives, Lives(0));
+    }
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use bevy::prelude::{Component, FixedTime, Query, Res, ResMut, Resource, Transform, Vec3};
+
+/// How long each step of the game is (in seconds).
+/// The player, the ghosts and the physics all move on these steps (in `FixedUpdate`), however often the frames are actually drawn.
+pub const STEP: f32 = 1.0 / 60.0;
+/// Frames longer than this many steps (less than 15fps) slow the game down instead of it having to do lots of steps all at once.
+/// Otherwise one slow frame would make the next one even slower (with even more steps to catch up on), and the game would never catch up.
+const MAX_STEPS_PER_FRAME: u32 = 4;
+
+/// Anything which moves further than this in one step has been put somewhere else (like going through a tunnel or respawning), rather than actually moving there.
+const TELEPORT_DISTANCE: f32 = 1.0;
+
+/// The game's own clock, which goes forward by exactly one step every time `FixedUpdate` runs.
+/// Bevy's `Time` still goes by how long the frames took, even in `FixedUpdate`, so the gameplay uses this instead.
+/// It has the same methods as `Time`, so that it reads the same way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Resource)]
+pub struct StepTime {
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl StepTime {
+    pub fn advance(&mut self, delta: Duration) {
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+}
+
+pub fn advance_step_time(fixed_time: Res<FixedTime>, mut step_time: ResMut<StepTime>) {
+    step_time.advance(fixed_time.period);
+}
+
+/// Throws away whatever time would take this frame past `MAX_STEPS_PER_FRAME` steps.
+/// This has to run in `FixedUpdate`, where the frame's time has already been added and the step which is running has already been taken off.
+pub fn limit_steps_per_frame(mut fixed_time: ResMut<FixedTime>) {
+    let limit = fixed_time.period * (MAX_STEPS_PER_FRAME - 1);
+    while fixed_time.accumulated() >= fixed_time.period + limit {
+        // This can't actually fail, since there is more than a step left.
+        let _ = fixed_time.expend();
+    }
+}
+
+/// Something which moves on the steps, but is drawn smoothly in between them.
+/// Without this, anything moving would jitter whenever the frame rate doesn't match up with the steps.
+/// The real position is put back before each step, so the physics never sees where it was drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component)]
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+    /// Where it was last drawn, which tells us if anything else has moved it since.
+    drawn: Vec3,
+}
+
+impl Interpolated {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            previous: position,
+            current: position,
+            drawn: position,
+        }
+    }
+
+    /// Something outside the steps (like the editor) moved it, so it should just be there from now on.
+    fn moved_elsewhere(&mut self, position: Vec3) -> bool {
+        if position == self.drawn {
+            return false;
+        }
+        *self = Self::new(position);
+        true
+    }
+
+    /// Where to draw it when `fraction` of the way from the last step to the next.
+    fn position(&self, fraction: f32) -> Vec3 {
+        self.previous.lerp(self.current, fraction.clamp(0.0, 1.0))
+    }
+}
+
+/// Runs at the start of each step, putting everything back where the last step left it.
+pub fn restore_positions(mut objects: Query<(&mut Transform, &mut Interpolated)>) {
+    for (mut transform, mut interpolated) in objects.iter_mut() {
+        if !interpolated.moved_elsewhere(transform.translation) {
+            transform.translation = interpolated.current;
+        }
+    }
+}
+
+/// Runs at the end of each step (after the physics), remembering where everything got to.
+pub fn record_positions(mut objects: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in objects.iter_mut() {
+        let position = transform.translation;
+        if position.distance(interpolated.current) > TELEPORT_DISTANCE {
+            *interpolated = Interpolated::new(position);
+        } else {
+            interpolated.previous = interpolated.current;
+            interpolated.current = position;
+            interpolated.drawn = position;
+        }
+    }
+}
+
+/// Moves everything to where it should be drawn this frame, going by how far it is to the next step.
+/// This is always a step behind, but that's a lot less noticeable than the jitter.
+pub fn interpolate_positions(
+    fixed_time: Res<FixedTime>,
+    mut objects: Query<(&mut Transform, &mut Interpolated)>,
+) {
+    let fraction = fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32();
+    for (mut transform, mut interpolated) in objects.iter_mut() {
+        if interpolated.moved_elsewhere(transform.translation) {
+            continue;
+        }
+        transform.translation = interpolated.position(fraction);
+        interpolated.drawn = transform.translation;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, FixedUpdate, IntoSystemConfigs, PostUpdate};
+
+    use super::*;
+
+    #[test]
+    fn clock_goes_by_steps() {
+        let mut step_time = StepTime::default();
+        step_time.advance(Duration::from_secs_f32(STEP));
+        step_time.advance(Duration::from_secs_f32(STEP));
+        assert_eq!(step_time.delta_seconds(), STEP);
+        assert!((step_time.elapsed_seconds() - STEP * 2.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn slow_frames_are_capped() {
+        let steps_in_a_frame = |frame: Duration| {
+            let mut app = App::new();
+            let mut fixed_time = FixedTime::new_from_secs(STEP);
+            fixed_time.tick(frame);
+            app.insert_resource(fixed_time)
+                .init_resource::<StepTime>()
+                .add_systems(
+                    FixedUpdate,
+                    (limit_steps_per_frame, advance_step_time).chain(),
+                );
+            // This is what Bevy does with `FixedUpdate` every frame.
+            while app.world.resource_mut::<FixedTime>().expend().is_ok() {
+                app.world.run_schedule(FixedUpdate);
+            }
+            (app.world.resource::<StepTime>().elapsed_seconds() / STEP).round() as u32
+        };
+        let step = Duration::from_secs_f32(STEP);
+        assert_eq!(steps_in_a_frame(step * 2), 2);
+        assert_eq!(
+            steps_in_a_frame(step * MAX_STEPS_PER_FRAME),
+            MAX_STEPS_PER_FRAME
+        );
+        // A whole second all at once only goes as far as the cap.
+        assert_eq!(
+            steps_in_a_frame(Duration::from_secs(1)),
+            MAX_STEPS_PER_FRAME
+        );
+    }
+
+    #[test]
+    fn drawn_between_steps() {
+        let mut app = App::new();
+        app.insert_resource(FixedTime::new_from_secs(1.0))
+            .add_systems(
+                FixedUpdate,
+                (
+                    restore_positions,
+                    |mut objects: Query<&mut Transform>| {
+                        for mut transform in objects.iter_mut() {
+                            transform.translation.x += 0.5;
+                        }
+                    },
+                    record_positions,
+                )
+                    .chain(),
+            )
+            .add_systems(PostUpdate, interpolate_positions);
+        let object = app
+            .world
+            .spawn((Transform::default(), Interpolated::new(Vec3::ZERO)))
+            .id();
+        let step = |app: &mut App, accumulated: f32| {
+            app.world.run_schedule(FixedUpdate);
+            // Nothing else is ticking it, so this is how far into the next step we are.
+            *app.world.resource_mut::<FixedTime>() = FixedTime::new_from_secs(1.0);
+            app.world
+                .resource_mut::<FixedTime>()
+                .tick(Duration::from_secs_f32(accumulated));
+            app.world.run_schedule(PostUpdate);
+            app.world.get::<Transform>(object).unwrap().translation
+        };
+
+        assert_eq!(step(&mut app, 0.5), Vec3::new(0.25, 0.0, 0.0));
+        assert_eq!(step(&mut app, 0.0), Vec3::new(0.5, 0.0, 0.0));
+        // Each step starts from where it really is, rather than where it was drawn.
+        assert_eq!(step(&mut app, 1.0), Vec3::new(1.5, 0.0, 0.0));
+
+        // Anything else moving it just puts it there.
+        app.world.get_mut::<Transform>(object).unwrap().translation = Vec3::new(10.0, 0.0, 0.0);
+        app.world.run_schedule(PostUpdate);
+        assert_eq!(
+            app.world.get::<Transform>(object).unwrap().translation,
+            Vec3::new(10.0, 0.0, 0.0)
+        );
+        assert_eq!(step(&mut app, 0.0), Vec3::new(10.0, 0.0, 0.0));
+        assert_eq!(step(&mut app, 1.0), Vec3::new(11.0, 0.0, 0.0));
+    }
+}
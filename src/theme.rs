@@ -0,0 +1,155 @@
+use bevy::prelude::{
+    Assets, Children, ClearColor, Color, Handle, Input, KeyCode, Query, Res, ResMut, Resource,
+    StandardMaterial, With,
+};
+
+use crate::{Floor, MazeWalls};
+
+/// The colours the maze is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq, Resource)]
+pub struct MazeTheme {
+    pub walls: Color,
+    /// The ground which the maze sits on.
+    pub floor: Color,
+    /// Power pellets glow in this colour as well.
+    pub pellets: Color,
+    /// What is seen past the edges of the floor.
+    pub background: Color,
+}
+
+impl MazeTheme {
+    /// How the maze has always looked.
+    pub const ORIGINAL: MazeTheme = MazeTheme {
+        walls: Color::GRAY,
+        floor: Color::YELLOW,
+        pellets: Color::WHITE,
+        // This is what Bevy clears the screen to if nothing else is given.
+        background: Color::rgb(0.4, 0.4, 0.4),
+    };
+    /// Blue walls on black, like the arcade game.
+    pub const CLASSIC: MazeTheme = MazeTheme {
+        walls: Color::rgb(0.13, 0.13, 0.87),
+        floor: Color::BLACK,
+        pellets: Color::rgb(1.0, 0.72, 0.59),
+        background: Color::BLACK,
+    };
+    pub const NEON: MazeTheme = MazeTheme {
+        walls: Color::rgb(1.0, 0.1, 0.8),
+        floor: Color::rgb(0.05, 0.0, 0.1),
+        pellets: Color::rgb(0.3, 1.0, 1.0),
+        background: Color::rgb(0.02, 0.0, 0.05),
+    };
+    /// The themes which `cycle_theme` goes through, in order.
+    pub const PRESETS: [MazeTheme; 3] = [Self::ORIGINAL, Self::CLASSIC, Self::NEON];
+
+    /// The preset after this one, going back to the start after the last one.
+    fn next_preset(&self) -> MazeTheme {
+        let next_index = Self::PRESETS
+            .iter()
+            .position(|preset| preset == self)
+            .map_or(0, |index| (index + 1) % Self::PRESETS.len());
+        Self::PRESETS[next_index]
+    }
+}
+
+impl Default for MazeTheme {
+    fn default() -> Self {
+        Self::ORIGINAL
+    }
+}
+
+pub fn cycle_theme(keyboard_input: Res<Input<KeyCode>>, mut theme: ResMut<MazeTheme>) {
+    if keyboard_input.just_pressed(KeyCode::T) {
+        *theme = theme.next_preset();
+    }
+}
+
+/// Colours in everything which has already been spawned, for when the theme changes.
+/// Anything spawned after that (like the walls when the editor rebuilds them) already uses the new theme.
+pub fn apply_theme(
+    theme: Res<MazeTheme>,
+    walls: Query<&Children, With<MazeWalls>>,
+    floors: Query<&Children, With<Floor>>,
+    material_handles: Query<&Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    for (children, color) in walls
+        .iter()
+        .map(|children| (children, theme.walls))
+        .chain(floors.iter().map(|children| (children, theme.floor)))
+    {
+        for handle in material_handles.iter_many(children) {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color = color;
+            }
+        }
+    }
+    clear_color.0 = theme.background;
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{
+        asset::{AddAsset, AssetPlugin},
+        prelude::{App, BuildWorldChildren, MinimalPlugins, Update},
+    };
+
+    use super::*;
+
+    #[test]
+    fn cycling_through_the_presets() {
+        assert_eq!(MazeTheme::ORIGINAL.next_preset(), MazeTheme::CLASSIC);
+        assert_eq!(MazeTheme::CLASSIC.next_preset(), MazeTheme::NEON);
+        assert_eq!(MazeTheme::NEON.next_preset(), MazeTheme::ORIGINAL);
+        // A theme of our own goes back to the first one.
+        let custom = MazeTheme {
+            walls: Color::RED,
+            ..MazeTheme::CLASSIC
+        };
+        assert_eq!(custom.next_preset(), MazeTheme::ORIGINAL);
+    }
+
+    #[test]
+    fn walls_change_colour() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<StandardMaterial>()
+            .init_resource::<MazeTheme>()
+            .init_resource::<ClearColor>()
+            .add_systems(Update, apply_theme);
+        let mut materials = app.world.resource_mut::<Assets<StandardMaterial>>();
+        let wall_material = materials.add(MazeTheme::ORIGINAL.walls.into());
+        let floor_material = materials.add(MazeTheme::ORIGINAL.floor.into());
+        app.world.spawn(MazeWalls).with_children(|walls| {
+            walls.spawn(wall_material.clone());
+        });
+        app.world.spawn(Floor).with_children(|floor| {
+            floor.spawn(floor_material.clone());
+        });
+        let colors = |app: &App| {
+            let materials = app.world.resource::<Assets<StandardMaterial>>();
+            (
+                materials.get(&wall_material).unwrap().base_color,
+                materials.get(&floor_material).unwrap().base_color,
+                app.world.resource::<ClearColor>().0,
+            )
+        };
+
+        app.update();
+        assert_eq!(
+            colors(&app),
+            (Color::GRAY, Color::YELLOW, MazeTheme::ORIGINAL.background)
+        );
+        *app.world.resource_mut::<MazeTheme>() = MazeTheme::NEON;
+        app.update();
+        assert_eq!(
+            colors(&app),
+            (
+                MazeTheme::NEON.walls,
+                MazeTheme::NEON.floor,
+                MazeTheme::NEON.background
+            )
+        );
+    }
+}